@@ -1,12 +1,15 @@
 use std::fs::{self, File};
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::time::UNIX_EPOCH;
 
 use clap::{Parser, Subcommand};
 use time::{OffsetDateTime, PrimitiveDateTime};
 
-use cab::{Cabinet, CabinetBuilder, CompressionType, FileEntry, FolderEntry};
+use cab::{
+    Cabinet, CabinetBuilder, CabinetPlan, CompressionType, DumpFormat,
+    FileEntry, FolderEntry,
+};
 
 #[derive(Parser, Debug)]
 #[command(author, about, version)]
@@ -21,14 +24,33 @@ enum Command {
     Cat { path: PathBuf, files: Vec<String> },
     /// Creates a new cabinet
     Create {
-        /// Sets compression type
-        #[clap(short, long, default_value_t = String::from("mszip"))]
-        compress: String,
+        /// Sets compression type (e.g. "none" or "mszip"); with a manifest,
+        /// this is used for files listed before any `.folder` directive
+        #[clap(short, long, default_value_t = CompressionType::MsZip)]
+        compress: CompressionType,
         /// Sets output path
         #[clap(short, long)]
         output: Option<PathBuf>,
+        /// Reads the list of files (and folder/cabinet layout) from a
+        /// manifest file (or a makecab .ddf file) instead of the command line
+        #[clap(short, long, conflicts_with = "files")]
+        manifest: Option<PathBuf>,
         files: Vec<String>,
     },
+    /// Dumps the cabinet's full low-level structure
+    Dump {
+        /// Prints JSON instead of text
+        #[clap(short, long)]
+        json: bool,
+        path: PathBuf,
+    },
+    /// Prints the cabinet's header fields and folder table
+    Info {
+        /// Also lists each folder's data blocks
+        #[clap(short, long)]
+        blocks: bool,
+        path: PathBuf,
+    },
     /// Lists files in the cabinet
     Ls {
         /// Lists in long format
@@ -48,22 +70,35 @@ fn main() {
                 io::copy(&mut file_reader, &mut io::stdout()).unwrap();
             }
         }
-        Command::Create { compress, output, files } => {
-            let compress = match compress.as_str() {
-                "none" => CompressionType::None,
-                "mszip" => CompressionType::MsZip,
-                _ => panic!("Invalid compression type: {}", compress),
+        Command::Create {
+            compress, manifest: Some(manifest), output, ..
+        } => {
+            let text = fs::read_to_string(&manifest).unwrap();
+            // Paths in the manifest are relative to its own directory.
+            let base = manifest.parent().unwrap_or(Path::new(""));
+            let size_of =
+                |path: &Path| Ok(fs::metadata(base.join(path))?.len());
+            let is_ddf = manifest.extension().is_some_and(|ext| ext == "ddf");
+            let mut plan = if is_ddf {
+                cab::ddf::parse_with_sizes(&text, size_of).unwrap()
+            } else {
+                CabinetPlan::from_manifest_with(&text, compress, size_of)
+                    .unwrap()
             };
-
-            let output = output.unwrap_or_else(|| {
-                let mut path = PathBuf::from("out.cab");
-                let mut index: i32 = 0;
-                while path.exists() {
-                    index += 1;
-                    path = PathBuf::from(format!("out{}.cab", index));
+            plan.resolve_sources(base);
+            for cabinet in plan.cabinets() {
+                let path = match cabinet.name() {
+                    Some(name) => base.join(name),
+                    None => output.clone().unwrap_or_else(default_output),
+                };
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent).unwrap();
                 }
-                path
-            });
+                cabinet.write(File::create(&path).unwrap()).unwrap();
+            }
+        }
+        Command::Create { compress, output, files, .. } => {
+            let output = output.unwrap_or_else(default_output);
             let mut builder = CabinetBuilder::new();
             let mut file_index: usize = 0;
             while file_index < files.len() {
@@ -97,17 +132,100 @@ fn main() {
             }
             cabinet.finish().unwrap();
         }
+        Command::Dump { path, json } => {
+            let mut cabinet = Cabinet::new(File::open(path).unwrap()).unwrap();
+            let format =
+                if json { DumpFormat::Json } else { DumpFormat::Text };
+            cabinet.dump_structure(io::stdout().lock(), format).unwrap();
+        }
+        Command::Info { path, blocks } => {
+            let mut cabinet = Cabinet::new(File::open(path).unwrap()).unwrap();
+            print_info(&mut cabinet, blocks);
+        }
         Command::Ls { path, long } => {
             let cabinet = Cabinet::new(File::open(path).unwrap()).unwrap();
             for (index, folder) in cabinet.folder_entries().enumerate() {
                 for file in folder.file_entries() {
-                    list_file(index, &folder, file, long);
+                    list_file(index, folder, file, long);
                 }
             }
         }
     }
 }
 
+fn default_output() -> PathBuf {
+    let mut path = PathBuf::from("out.cab");
+    let mut index: i32 = 0;
+    while path.exists() {
+        index += 1;
+        path = PathBuf::from(format!("out{}.cab", index));
+    }
+    path
+}
+
+fn print_info(cabinet: &mut Cabinet<File>, blocks: bool) {
+    let (major, minor) = cabinet.version();
+    println!("Version:              {}.{}", major, minor);
+    println!("Total size:           {}", cabinet.total_size());
+    println!("Set ID:               {}", cabinet.cabinet_set_id());
+    println!("Set index:            {}", cabinet.cabinet_set_index());
+    if let Some(name) = cabinet.prev_cabinet_name() {
+        let disk = cabinet.prev_disk_name().unwrap_or("");
+        println!("Previous cabinet:     {} (disk {:?})", name, disk);
+    }
+    if let Some(name) = cabinet.next_cabinet_name() {
+        let disk = cabinet.next_disk_name().unwrap_or("");
+        println!("Next cabinet:         {} (disk {:?})", name, disk);
+    }
+    println!("Header reserve size:  {}", cabinet.reserve_data().len());
+    println!("Folder reserve size:  {}", cabinet.folder_reserve_size());
+    println!("Data reserve size:    {}", cabinet.data_reserve_size());
+    println!("First file offset:    {}", cabinet.first_file_offset());
+    let num_files: usize =
+        cabinet.folder_entries().map(|f| f.file_entries().len()).sum();
+    println!("Files:                {}", num_files);
+    println!("Folders:              {}", cabinet.folder_entries().len());
+    for warning in cabinet.warnings() {
+        println!("Warning: {}", warning);
+    }
+    let folders: Vec<(CompressionType, u32, u16, usize)> = cabinet
+        .folder_entries()
+        .map(|folder| {
+            (
+                folder.compression_type(),
+                folder.first_data_block_offset(),
+                folder.num_data_blocks(),
+                folder.file_entries().len(),
+            )
+        })
+        .collect();
+    for (index, (ctype, offset, num_blocks, num_files)) in
+        folders.into_iter().enumerate()
+    {
+        println!(
+            "  Folder {}: {}, first block at {}, {} blocks, {} files",
+            index, ctype, offset, num_blocks, num_files
+        );
+        if !blocks {
+            continue;
+        }
+        for (block_index, block) in
+            cabinet.data_blocks(index).unwrap().enumerate()
+        {
+            let block = block.unwrap();
+            println!(
+                "    Block {:>5} at {:>10}: checksum {:08x}, \
+                 csize {:>5}, usize {:>5}",
+                block_index,
+                block.offset(),
+                block.checksum(),
+                block.compressed_size(),
+                block.uncompressed_size()
+            );
+        }
+    }
+}
+
 fn list_file(
     folder_index: usize,
     folder: &FolderEntry,
@@ -123,6 +241,8 @@ fn list_file(
         CompressionType::MsZip => "MsZip".to_string(),
         CompressionType::Quantum(v, m) => format!("Q{}/{}", v, m),
         CompressionType::Lzx(w) => format!("Lzx{:?}", w),
+        CompressionType::Unknown(bits) => format!("?{:04x}", bits),
+        other => other.to_string(),
     };
     let file_size = if file.uncompressed_size() >= 100_000_000 {
         format!("{} MB", file.uncompressed_size() / (1 << 20))
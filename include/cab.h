@@ -0,0 +1,133 @@
+#ifndef CAB_H
+#define CAB_H
+
+/* Generated by cbindgen from src/capi.rs; do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// The status codes returned by the C API.
+typedef enum CabStatus {
+  // The call succeeded.
+  CabOk = 0,
+  // An argument was invalid (e.g. a null pointer, or a string that isn't
+  // UTF-8).
+  CabErrorInvalidArgument = -1,
+  // The named file (or file within the cabinet) was not found.
+  CabErrorNotFound = -2,
+  // The cabinet file is malformed, or uses an unsupported feature.
+  CabErrorInvalidData = -3,
+  // Some other I/O error occurred.
+  CabErrorIo = -4,
+} CabStatus;
+
+// The compression types supported by [`cab_builder_add_folder`].
+typedef enum CabCompression {
+  // No compression.
+  CabCompressionNone = 0,
+  // MSZIP compression.
+  CabCompressionMsZip = 1,
+} CabCompression;
+
+// A cabinet file being built.  Create with [`cab_builder_new`], and free
+// with [`cab_builder_free`].
+typedef struct CabBuilder CabBuilder;
+
+// An open cabinet file.  Create with [`cab_open`], and free with
+// [`cab_close`].
+typedef struct CabCabinet CabCabinet;
+
+// A callback for [`cab_list`], called with the name and uncompressed size
+// of each file, and the `user_data` passed to `cab_list`.  Returning
+// nonzero stops the listing.  Passing a null callback to `cab_list` is an
+// error.
+typedef int (*CabListCallback)(const char *name, uint32_t size, void *user_data);
+
+// Returns a description of the last error that occurred on this thread, or
+// null if there hasn't been one.  The returned string remains valid until
+// the next failing call on this thread.
+const char *cab_last_error(void);
+
+// Opens the cabinet file at the given path.  Returns null on failure.
+//
+// # Safety
+//
+// `path` must be a NUL-terminated string.
+struct CabCabinet *cab_open(const char *path);
+
+// Closes a cabinet opened with [`cab_open`].  Does nothing if `cabinet` is
+// null.
+//
+// # Safety
+//
+// `cabinet` must be null or a pointer returned by [`cab_open`] that hasn't
+// already been closed.
+void cab_close(struct CabCabinet *cabinet);
+
+// Calls `callback` for each file in the cabinet, in order, until it
+// returns nonzero.
+//
+// # Safety
+//
+// `cabinet` must be a valid pointer returned by [`cab_open`], and
+// `callback` must be null or a valid function pointer.  The name passed to
+// `callback` is only valid for the duration of that call.
+enum CabStatus cab_list(const struct CabCabinet *cabinet,
+                        CabListCallback callback,
+                        void *user_data);
+
+// Extracts the named file from the cabinet, writing it to `out_path`
+// (which is created or truncated).
+//
+// # Safety
+//
+// `cabinet` must be a valid pointer returned by [`cab_open`], and `name`
+// and `out_path` must be NUL-terminated strings.
+enum CabStatus cab_extract_file(struct CabCabinet *cabinet, const char *name, const char *out_path);
+
+// Creates a new, empty cabinet builder.
+struct CabBuilder *cab_builder_new(void);
+
+// Frees a builder created with [`cab_builder_new`].  Does nothing if
+// `builder` is null.
+//
+// # Safety
+//
+// `builder` must be null or a pointer returned by [`cab_builder_new`] that
+// hasn't already been freed.
+void cab_builder_free(struct CabBuilder *builder);
+
+// Adds a new folder to the cabinet being built.  Files added afterwards
+// with [`cab_builder_add_file`] go in this folder.
+//
+// # Safety
+//
+// `builder` must be a valid pointer returned by [`cab_builder_new`].
+enum CabStatus cab_builder_add_folder(struct CabBuilder *builder, enum CabCompression compression);
+
+// Adds a file to the most recently added folder, to be stored in the
+// cabinet as `name` and read from `source_path` when the cabinet is
+// written.
+//
+// # Safety
+//
+// `builder` must be a valid pointer returned by [`cab_builder_new`], and
+// `name` and `source_path` must be NUL-terminated strings.
+enum CabStatus cab_builder_add_file(struct CabBuilder *builder,
+                                    const char *name,
+                                    const char *source_path);
+
+// Writes the cabinet to `out_path` (which is created or truncated), reading
+// each file's data from its source path.  The builder is left unchanged,
+// and must still be freed with [`cab_builder_free`].
+//
+// # Safety
+//
+// `builder` must be a valid pointer returned by [`cab_builder_new`], and
+// `out_path` must be a NUL-terminated string.
+enum CabStatus cab_builder_write(const struct CabBuilder *builder, const char *out_path);
+
+#endif  /* CAB_H */
@@ -0,0 +1,147 @@
+//! Wrappers for the Microsoft compression API, so that on Windows the `cab`
+//! crate's tests can check MSZIP interop with the system implementation.
+//! This code comes from https://github.com/luser/rust-makecab; thanks to Ted
+//! Mielczarek for sharing it.
+//!
+//! This is a separate, dev-only crate so that the `cab` library itself needs
+//! no unsafe code.  On targets other than MSVC, it's empty.
+
+#![cfg(target_env = "msvc")]
+#![allow(non_camel_case_types)]
+
+use std::mem;
+use std::ptr;
+
+use winapi::shared::basetsd::{PSIZE_T, SIZE_T};
+use winapi::shared::minwindef::{BOOL, DWORD, FALSE, LPVOID, TRUE};
+use winapi::um::winnt::{HANDLE, PVOID};
+
+const MSZIP_BLOCK_LEN: usize = 0x8000;
+const COMPRESS_ALGORITHM_MSZIP: DWORD = 2;
+const COMPRESS_RAW: DWORD = 1 << 29;
+type PCOMPRESS_ALLOCATION_ROUTINES = LPVOID;
+type COMPRESSOR_HANDLE = HANDLE;
+type DECOMPRESSOR_HANDLE = HANDLE;
+type PCOMPRESSOR_HANDLE = *mut COMPRESSOR_HANDLE;
+type PDECOMPRESSOR_HANDLE = *mut DECOMPRESSOR_HANDLE;
+
+#[rustfmt::skip]
+#[link(name = "cabinet")]
+extern "system" {
+    fn CreateCompressor(
+        Algorithm: DWORD,
+        AllocationRoutines: LPVOID,
+        CompressorHandle: PCOMPRESSOR_HANDLE)
+        -> BOOL;
+    fn CloseCompressor(
+        CompressorHandle: COMPRESSOR_HANDLE)
+        -> BOOL;
+    fn Compress(
+        CompressorHandle: COMPRESSOR_HANDLE,
+        UncompressedData: PVOID,
+        UncompressedDataSize: SIZE_T,
+        CompressedBuffer: PVOID,
+        CompressedBufferSize: SIZE_T,
+        CompressedDataSize: PSIZE_T)
+        -> BOOL;
+
+    fn CreateDecompressor(
+        Algorithm: DWORD,
+        AllocationRoutines: PCOMPRESS_ALLOCATION_ROUTINES,
+        DecompressorHandle: PDECOMPRESSOR_HANDLE)
+        -> BOOL;
+    fn CloseDecompressor(
+        DecompressorHandle: DECOMPRESSOR_HANDLE)
+        -> BOOL;
+    fn Decompress(
+        DecompressorHandle: DECOMPRESSOR_HANDLE,
+        CompressedData: PVOID,
+        CompressedDataSize: SIZE_T,
+        UncompressedBuffer: PVOID,
+        UncompressedBufferSize: SIZE_T,
+        UncompressedDataSize: PSIZE_T)
+        -> BOOL;
+}
+
+/// Compresses `data` into MSZIP blocks with the Microsoft compression
+/// API, returning `(uncompressed_size, compressed_block)` pairs.
+pub fn mszip_compress(data: &[u8]) -> Vec<(usize, Vec<u8>)> {
+    let handle = unsafe {
+        let mut handle = mem::MaybeUninit::uninit();
+        if CreateCompressor(
+            COMPRESS_ALGORITHM_MSZIP | COMPRESS_RAW,
+            ptr::null_mut(),
+            handle.as_mut_ptr(),
+        ) != TRUE
+        {
+            panic!("CreateCompressor failed");
+        }
+        handle.assume_init()
+    };
+    let mut blocks = Vec::<(usize, Vec<u8>)>::new();
+    for slice in data.chunks(MSZIP_BLOCK_LEN) {
+        let mut buffer = vec![0; 0xffff];
+        unsafe {
+            let mut compressed_size = mem::MaybeUninit::uninit();
+            if Compress(
+                handle,
+                slice.as_ptr() as PVOID,
+                slice.len() as SIZE_T,
+                buffer.as_ptr() as PVOID,
+                buffer.len() as SIZE_T,
+                compressed_size.as_mut_ptr(),
+            ) == FALSE
+            {
+                panic!("Compress failed");
+            }
+            buffer.resize(compressed_size.assume_init() as usize, 0);
+        }
+        blocks.push((slice.len(), buffer));
+    }
+    unsafe {
+        CloseCompressor(handle);
+    }
+    blocks
+}
+
+/// Decompresses `(uncompressed_size, compressed_block)` MSZIP block pairs
+/// with the Microsoft compression API.
+pub fn mszip_decompress(blocks: Vec<(usize, Vec<u8>)>) -> Vec<u8> {
+    let handle = unsafe {
+        let mut handle = mem::MaybeUninit::uninit();
+        if CreateDecompressor(
+            COMPRESS_ALGORITHM_MSZIP | COMPRESS_RAW,
+            ptr::null_mut(),
+            handle.as_mut_ptr(),
+        ) != TRUE
+        {
+            panic!("CreateDecompressor failed");
+        }
+        handle.assume_init()
+    };
+    let mut buffer = Vec::<u8>::new();
+    // Decompress each chunk in turn.
+    for (original_size, ref block) in blocks.into_iter() {
+        assert!(original_size <= MSZIP_BLOCK_LEN);
+        // Make space in the output buffer.
+        let last = buffer.len();
+        buffer.resize(last + original_size, 0);
+        unsafe {
+            if Decompress(
+                handle,
+                block.as_ptr() as PVOID,
+                block.len() as SIZE_T,
+                buffer[last..].as_mut_ptr() as PVOID,
+                original_size as SIZE_T,
+                ptr::null_mut(),
+            ) == FALSE
+            {
+                panic!("Decompress failed");
+            }
+        }
+    }
+    unsafe {
+        CloseDecompressor(handle);
+    }
+    buffer
+}
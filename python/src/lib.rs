@@ -0,0 +1,539 @@
+//! Python bindings for the [`cab`] crate, built with
+//! [PyO3](https://pyo3.rs).
+//!
+//! To build the `cab` Python module and install it into the current Python
+//! environment, run `maturin develop --release` from this directory.  The
+//! API loosely follows the standard library's `zipfile` module:
+//!
+//! ```python
+//! import cab
+//!
+//! with cab.Cabinet("example.cab") as cabinet:
+//!     for info in cabinet.infolist():
+//!         print(info.name, info.size, info.date_time)
+//!     data = cabinet.read("hi.txt")
+//!     with cabinet.open("lorem_ipsum.txt") as entry:
+//!         first_line = entry.readline()
+//!
+//! builder = cab.CabinetBuilder()
+//! builder.add_folder("mszip")
+//! builder.add_file("hi.txt", b"Hello, world!\n")
+//! builder.write("out.cab")
+//! ```
+
+// The code generated by `#[pymethods]` trips this lint for methods returning
+// `PyResult`.
+#![allow(clippy::useless_conversion)]
+
+use std::fs::File;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::path::PathBuf;
+
+use pyo3::exceptions::{PyKeyError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyBytes;
+use time::PrimitiveDateTime;
+
+/// Converts an I/O error from the `cab` crate into a Python exception.
+/// Malformed or unsupported cabinets raise `ValueError`; everything else
+/// maps to the corresponding `OSError` subclass.
+fn to_py_err(error: io::Error) -> PyErr {
+    match error.kind() {
+        io::ErrorKind::InvalidData | io::ErrorKind::InvalidInput => {
+            PyValueError::new_err(error.to_string())
+        }
+        _ => PyErr::from(error),
+    }
+}
+
+fn missing_file(name: &str) -> PyErr {
+    PyKeyError::new_err(format!(
+        "There is no item named {:?} in the cabinet",
+        name
+    ))
+}
+
+/// A `(year, month, day, hour, minute, second)` tuple, as used by
+/// `zipfile.ZipInfo.date_time`.
+type DateTimeTuple = (i32, u8, u8, u8, u8, u8);
+
+fn datetime_tuple(datetime: PrimitiveDateTime) -> DateTimeTuple {
+    (
+        datetime.year(),
+        datetime.month() as u8,
+        datetime.day(),
+        datetime.hour(),
+        datetime.minute(),
+        datetime.second(),
+    )
+}
+
+fn tuple_datetime(tuple: DateTimeTuple) -> PyResult<PrimitiveDateTime> {
+    let (year, month, day, hour, minute, second) = tuple;
+    let invalid = |error: time::error::ComponentRange| {
+        PyValueError::new_err(error.to_string())
+    };
+    let month = time::Month::try_from(month).map_err(invalid)?;
+    let date =
+        time::Date::from_calendar_date(year, month, day).map_err(invalid)?;
+    let time = time::Time::from_hms(hour, minute, second).map_err(invalid)?;
+    Ok(PrimitiveDateTime::new(date, time))
+}
+
+// ========================================================================= //
+
+/// Metadata about one file in a cabinet.
+#[pyclass(frozen, get_all, module = "cab")]
+#[derive(Clone)]
+struct FileInfo {
+    /// The file's name within the cabinet.
+    name: String,
+    /// The file's uncompressed size, in bytes.
+    size: u32,
+    /// The index of the folder that the file is stored in.
+    folder_index: usize,
+    /// The file's modification time, as a `(year, month, day, hour,
+    /// minute, second)` tuple, or `None` if the stored value is invalid.
+    date_time: Option<DateTimeTuple>,
+    /// The file's raw attribute bits (see the `ATTR_*` constants).
+    attributes: u16,
+}
+
+#[pymethods]
+impl FileInfo {
+    fn __repr__(&self) -> String {
+        format!("<FileInfo name={:?} size={}>", self.name, self.size)
+    }
+}
+
+// ========================================================================= //
+
+/// A cabinet file opened for reading.
+#[pyclass(unsendable, module = "cab")]
+struct Cabinet {
+    path: PathBuf,
+    cabinet: cab::Cabinet<File>,
+}
+
+#[pymethods]
+impl Cabinet {
+    #[new]
+    fn new(path: PathBuf) -> PyResult<Cabinet> {
+        let file = File::open(&path)?;
+        let cabinet = cab::Cabinet::new(file).map_err(to_py_err)?;
+        Ok(Cabinet { path, cabinet })
+    }
+
+    /// The cabinet's format version, as a `(major, minor)` tuple.
+    #[getter]
+    fn version(&self) -> (u8, u8) {
+        self.cabinet.version()
+    }
+
+    /// The cabinet's set ID.
+    #[getter]
+    fn set_id(&self) -> u16 {
+        self.cabinet.cabinet_set_id()
+    }
+
+    /// The cabinet's index within its set.
+    #[getter]
+    fn set_index(&self) -> u16 {
+        self.cabinet.cabinet_set_index()
+    }
+
+    /// Returns the names of the files in the cabinet, in order.
+    fn namelist(&self) -> Vec<String> {
+        self.cabinet
+            .folder_entries()
+            .flat_map(|folder| folder.file_entries())
+            .map(|file| file.name().to_string())
+            .collect()
+    }
+
+    /// Returns a `FileInfo` for each file in the cabinet, in order.
+    fn infolist(&self) -> Vec<FileInfo> {
+        let mut infos = Vec::new();
+        for (index, folder) in self.cabinet.folder_entries().enumerate() {
+            for file in folder.file_entries() {
+                infos.push(file_info(index, file));
+            }
+        }
+        infos
+    }
+
+    /// Returns the `FileInfo` for the named file, raising `KeyError` if
+    /// there is no such file.
+    fn getinfo(&self, name: &str) -> PyResult<FileInfo> {
+        for (index, folder) in self.cabinet.folder_entries().enumerate() {
+            for file in folder.file_entries() {
+                if file.name() == name {
+                    return Ok(file_info(index, file));
+                }
+            }
+        }
+        Err(missing_file(name))
+    }
+
+    /// Decompresses and returns the entire contents of the named file.
+    fn read<'py>(
+        &mut self,
+        py: Python<'py>,
+        name: &str,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        if !self.cabinet.contains(name) {
+            return Err(missing_file(name));
+        }
+        let mut data = Vec::new();
+        let mut reader = self.cabinet.read_file(name).map_err(to_py_err)?;
+        reader.read_to_end(&mut data).map_err(to_py_err)?;
+        Ok(PyBytes::new_bound(py, &data))
+    }
+
+    /// Opens the named file for reading, returning a binary file-like
+    /// object.  The returned object reads from its own handle on the
+    /// cabinet file, so it stays usable after this cabinet is closed.
+    fn open(&self, name: &str) -> PyResult<EntryReader> {
+        if !self.cabinet.contains(name) {
+            return Err(missing_file(name));
+        }
+        let file = File::open(&self.path)?;
+        let cabinet = cab::Cabinet::new(file).map_err(to_py_err)?;
+        let reader = cabinet.into_file_reader(name).map_err(to_py_err)?;
+        Ok(EntryReader { reader: Some(reader) })
+    }
+
+    /// Does nothing; provided for symmetry with `zipfile.ZipFile`.
+    fn close(&self) {}
+
+    fn __contains__(&self, name: &str) -> bool {
+        self.cabinet.contains(name)
+    }
+
+    fn __len__(&self) -> usize {
+        self.cabinet.folder_entries().map(|f| f.file_entries().len()).sum()
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(
+        &self,
+        _exc_type: PyObject,
+        _exc_value: PyObject,
+        _traceback: PyObject,
+    ) -> bool {
+        false
+    }
+}
+
+fn file_info(folder_index: usize, file: &cab::FileEntry) -> FileInfo {
+    FileInfo {
+        name: file.name().to_string(),
+        size: file.uncompressed_size(),
+        folder_index,
+        date_time: file.datetime().map(datetime_tuple),
+        attributes: file.attributes(),
+    }
+}
+
+// ========================================================================= //
+
+/// A binary file-like object for reading one file from a cabinet, returned
+/// by `Cabinet.open`.
+#[pyclass(unsendable, module = "cab")]
+struct EntryReader {
+    reader: Option<cab::FileReader<'static, File>>,
+}
+
+impl EntryReader {
+    fn reader(&mut self) -> PyResult<&mut cab::FileReader<'static, File>> {
+        match self.reader.as_mut() {
+            Some(reader) => Ok(reader),
+            None => Err(PyValueError::new_err("I/O operation on closed file")),
+        }
+    }
+}
+
+#[pymethods]
+impl EntryReader {
+    /// Reads and returns up to `size` bytes, or everything up to the end of
+    /// the file if `size` is negative or omitted.
+    #[pyo3(signature = (size = -1))]
+    fn read<'py>(
+        &mut self,
+        py: Python<'py>,
+        size: i64,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let reader = self.reader()?;
+        let mut data = Vec::new();
+        if size < 0 {
+            reader.read_to_end(&mut data).map_err(to_py_err)?;
+        } else {
+            reader
+                .take(size as u64)
+                .read_to_end(&mut data)
+                .map_err(to_py_err)?;
+        }
+        Ok(PyBytes::new_bound(py, &data))
+    }
+
+    /// Reads and returns one line, including the trailing newline (if any).
+    fn readline<'py>(
+        &mut self,
+        py: Python<'py>,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let reader = self.reader()?;
+        let mut line = Vec::new();
+        let mut byte = [0u8];
+        while reader.read(&mut byte).map_err(to_py_err)? == 1 {
+            line.push(byte[0]);
+            if byte[0] == b'\n' {
+                break;
+            }
+        }
+        Ok(PyBytes::new_bound(py, &line))
+    }
+
+    /// Moves to the given position (relative to the start of the file if
+    /// `whence` is 0, the current position if 1, or the end if 2), and
+    /// returns the new position.
+    #[pyo3(signature = (offset, whence = 0))]
+    fn seek(&mut self, offset: i64, whence: i32) -> PyResult<u64> {
+        let position = match whence {
+            0 if offset >= 0 => SeekFrom::Start(offset as u64),
+            0 => return Err(PyValueError::new_err("negative seek position")),
+            1 => SeekFrom::Current(offset),
+            2 => SeekFrom::End(offset),
+            _ => {
+                let message = format!("invalid whence ({})", whence);
+                return Err(PyValueError::new_err(message));
+            }
+        };
+        self.reader()?.seek(position).map_err(to_py_err)
+    }
+
+    /// Returns the current position within the file.
+    fn tell(&mut self) -> PyResult<u64> {
+        self.reader()?.stream_position().map_err(to_py_err)
+    }
+
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn seekable(&self) -> bool {
+        true
+    }
+
+    fn writable(&self) -> bool {
+        false
+    }
+
+    /// Closes the reader; further reads will raise `ValueError`.
+    fn close(&mut self) {
+        self.reader = None;
+    }
+
+    /// True if the reader has been closed.
+    #[getter]
+    fn closed(&self) -> bool {
+        self.reader.is_none()
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        _exc_type: PyObject,
+        _exc_value: PyObject,
+        _traceback: PyObject,
+    ) -> bool {
+        self.close();
+        false
+    }
+}
+
+// ========================================================================= //
+
+struct PendingFile {
+    name: String,
+    data: Vec<u8>,
+    datetime: Option<PrimitiveDateTime>,
+}
+
+/// Builds a new cabinet file from in-memory file contents.
+#[pyclass(module = "cab")]
+#[derive(Default)]
+struct CabinetBuilder {
+    folders: Vec<(cab::CompressionType, Vec<PendingFile>)>,
+}
+
+#[pymethods]
+impl CabinetBuilder {
+    #[new]
+    fn new() -> CabinetBuilder {
+        CabinetBuilder::default()
+    }
+
+    /// Starts a new folder, compressed with the given compression type
+    /// (e.g. `"none"` or `"mszip"`).  Files added afterwards go in this
+    /// folder.
+    #[pyo3(signature = (compression = "mszip"))]
+    fn add_folder(&mut self, compression: &str) -> PyResult<()> {
+        let ctype = compression.parse().map_err(to_py_err)?;
+        self.folders.push((ctype, Vec::new()));
+        Ok(())
+    }
+
+    /// Adds a file with the given name and contents to the most recently
+    /// added folder.  `date_time`, if given, is a `(year, month, day, hour,
+    /// minute, second)` tuple; otherwise the current time is used.
+    #[pyo3(signature = (name, data, date_time = None))]
+    fn add_file(
+        &mut self,
+        name: String,
+        data: Vec<u8>,
+        date_time: Option<DateTimeTuple>,
+    ) -> PyResult<()> {
+        let datetime = date_time.map(tuple_datetime).transpose()?;
+        match self.folders.last_mut() {
+            Some((_, files)) => {
+                files.push(PendingFile { name, data, datetime });
+                Ok(())
+            }
+            None => Err(PyValueError::new_err(
+                "add_folder() must be called before add_file()",
+            )),
+        }
+    }
+
+    /// Writes the cabinet to the file at the given path.
+    fn write(&self, path: PathBuf) -> PyResult<()> {
+        let file = File::create(path)?;
+        self.build(file)?;
+        Ok(())
+    }
+
+    /// Returns the cabinet's contents as `bytes`.
+    fn to_bytes<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        let cursor = self.build(Cursor::new(Vec::new()))?;
+        Ok(PyBytes::new_bound(py, &cursor.into_inner()))
+    }
+}
+
+impl CabinetBuilder {
+    fn build<W: io::Write + Seek>(&self, writer: W) -> PyResult<W> {
+        let mut builder = cab::CabinetBuilder::new();
+        for (ctype, files) in self.folders.iter() {
+            let folder = builder.add_folder(*ctype);
+            for pending in files.iter() {
+                let file = folder.add_file(pending.name.as_str());
+                if let Some(datetime) = pending.datetime {
+                    file.set_datetime(datetime);
+                }
+            }
+        }
+        let mut cabinet = builder.build(writer).map_err(to_py_err)?;
+        let mut pending = self.folders.iter().flat_map(|(_, files)| files);
+        while let Some(mut writer) = cabinet.next_file().map_err(to_py_err)? {
+            let file = pending.next().expect("file count mismatch");
+            io::Write::write_all(&mut writer, &file.data)
+                .map_err(to_py_err)?;
+        }
+        cabinet.finish().map_err(to_py_err)
+    }
+}
+
+// ========================================================================= //
+
+/// Read/write Windows cabinet (CAB) files.
+#[pymodule]
+#[pyo3(name = "cab")]
+fn pycab(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<Cabinet>()?;
+    module.add_class::<CabinetBuilder>()?;
+    module.add_class::<EntryReader>()?;
+    module.add_class::<FileInfo>()?;
+    module.add("ATTR_READ_ONLY", 0x01)?;
+    module.add("ATTR_HIDDEN", 0x02)?;
+    module.add("ATTR_SYSTEM", 0x04)?;
+    module.add("ATTR_ARCH", 0x20)?;
+    module.add("ATTR_EXEC", 0x40)?;
+    module.add("ATTR_NAME_IS_UTF", 0x80)?;
+    Ok(())
+}
+
+// ========================================================================= //
+
+#[cfg(test)]
+mod tests {
+    use pyo3::prelude::*;
+    use pyo3::types::PyDict;
+
+    use super::pycab;
+
+    #[test]
+    fn build_and_read_from_python() {
+        pyo3::append_to_inittab!(pycab);
+        pyo3::prepare_freethreaded_python();
+        let path = std::env::temp_dir()
+            .join(format!("cab-python-test-{}.cab", std::process::id()));
+        Python::with_gil(|py| {
+            let locals = PyDict::new_bound(py);
+            locals.set_item("path", path.to_str().unwrap()).unwrap();
+            py.run_bound(
+                r#"
+import cab
+
+builder = cab.CabinetBuilder()
+builder.add_folder("mszip")
+builder.add_file("hi.txt", b"Hello, world!\n",
+                 date_time=(2018, 1, 6, 15, 19, 42))
+builder.add_file("lines.txt", b"one\ntwo\nthree\n")
+builder.add_folder("none")
+builder.add_file("empty.txt", b"")
+builder.write(path)
+assert cab.Cabinet(path).read("hi.txt") == b"Hello, world!\n"
+
+with cab.Cabinet(path) as cabinet:
+    assert cabinet.namelist() == ["hi.txt", "lines.txt", "empty.txt"]
+    assert len(cabinet) == 3
+    assert "lines.txt" in cabinet
+    info = cabinet.getinfo("hi.txt")
+    assert info.size == 14
+    assert info.folder_index == 0
+    assert info.date_time == (2018, 1, 6, 15, 19, 42)
+    assert cabinet.infolist()[2].folder_index == 1
+    entry = cabinet.open("lines.txt")
+
+with entry:
+    assert entry.readline() == b"one\n"
+    assert entry.tell() == 4
+    assert entry.read(3) == b"two"
+    entry.seek(-6, 2)
+    assert entry.read() == b"three\n"
+assert entry.closed
+
+try:
+    cab.Cabinet(path).read("missing.txt")
+    raise AssertionError("expected KeyError")
+except KeyError:
+    pass
+
+data = builder.to_bytes()
+assert data[:4] == b"MSCF"
+"#,
+                None,
+                Some(&locals),
+            )
+            .unwrap();
+        });
+        std::fs::remove_file(&path).unwrap();
+    }
+}
+
+// ========================================================================= //
@@ -0,0 +1,194 @@
+//! Format-neutral traits for reading and writing archives.
+//!
+//! [`ArchiveReader`] and [`ArchiveWriter`] are a minimal interface that
+//! generic backup or packaging code can be written against, so that a
+//! cabinet backend can be swapped in for a zip or tar one (by implementing
+//! the same traits for those crates' types) with little code.  Cabinets
+//! implement them via [`Cabinet`] and [`CabArchiveWriter`]:
+//!
+//! ```no_run
+//! use cab::archive::{ArchiveReader, ArchiveWriter, CabArchiveWriter};
+//!
+//! let input = std::fs::File::open("in.cab")?;
+//! let mut reader = cab::Cabinet::new(input)?;
+//! let output = std::fs::File::create("out.cab")?;
+//! let mut writer =
+//!     CabArchiveWriter::new(output, cab::CompressionType::MsZip);
+//! cab::archive::copy_archive(&mut reader, &mut writer)?;
+//! writer.finish()?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use time::PrimitiveDateTime;
+
+use crate::builder::CabinetBuilder;
+use crate::cabinet::Cabinet;
+use crate::ctype::CompressionType;
+use crate::file::FileReader;
+use crate::spool::{Spool, SpoolOptions};
+
+/// Metadata about one entry (file) in an archive.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ArchiveEntry {
+    /// The entry's name (path) within the archive.
+    pub name: String,
+    /// The entry's uncompressed size, in bytes.
+    pub size: u64,
+    /// The entry's modification time, if known.
+    pub modified: Option<PrimitiveDateTime>,
+}
+
+/// An archive whose entries can be listed and read.
+pub trait ArchiveReader {
+    /// The reader type returned by [`open_entry`](Self::open_entry).
+    type EntryReader<'a>: Read
+    where
+        Self: 'a;
+
+    /// Returns the metadata for each entry in the archive, in order.
+    fn entries(&self) -> Vec<ArchiveEntry>;
+
+    /// Opens the named entry for reading.  Returns an error of kind
+    /// `NotFound` if there is no such entry.
+    fn open_entry(&mut self, name: &str) -> io::Result<Self::EntryReader<'_>>;
+}
+
+/// An archive that can be built up one entry at a time.
+pub trait ArchiveWriter {
+    /// The value returned by [`finish`](Self::finish), typically the
+    /// underlying writer.
+    type Output;
+
+    /// Adds an entry with the given name and modification time, whose
+    /// contents are read from `data`; returns the size of the entry.
+    fn add_entry(
+        &mut self,
+        name: &str,
+        modified: Option<PrimitiveDateTime>,
+        data: &mut dyn Read,
+    ) -> io::Result<u64>;
+
+    /// Finishes writing the archive.
+    fn finish(self) -> io::Result<Self::Output>
+    where
+        Self: Sized;
+}
+
+/// Copies every entry of `reader` into `writer`, in order, and returns the
+/// number of entries copied.  Does not call [`ArchiveWriter::finish`].
+pub fn copy_archive<R: ArchiveReader, W: ArchiveWriter>(
+    reader: &mut R,
+    writer: &mut W,
+) -> io::Result<usize> {
+    let entries = reader.entries();
+    for entry in entries.iter() {
+        let mut entry_reader = reader.open_entry(&entry.name)?;
+        writer.add_entry(&entry.name, entry.modified, &mut entry_reader)?;
+    }
+    Ok(entries.len())
+}
+
+impl<R: Read + Seek> ArchiveReader for Cabinet<R> {
+    type EntryReader<'a>
+        = FileReader<'a, R>
+    where
+        R: 'a;
+
+    fn entries(&self) -> Vec<ArchiveEntry> {
+        self.folder_entries()
+            .flat_map(|folder| folder.file_entries())
+            .map(|file| ArchiveEntry {
+                name: file.name().to_string(),
+                size: file.uncompressed_size() as u64,
+                modified: file.datetime(),
+            })
+            .collect()
+    }
+
+    fn open_entry(&mut self, name: &str) -> io::Result<FileReader<'_, R>> {
+        self.read_file(name)
+    }
+}
+
+struct PendingEntry {
+    name: String,
+    modified: Option<PrimitiveDateTime>,
+    data: Spool,
+}
+
+/// An [`ArchiveWriter`] that builds a cabinet, with all entries compressed
+/// together in a single folder.
+///
+/// Since a cabinet's file names must all be known before any data can be
+/// written, each entry's contents are buffered in a [`Spool`] (in memory, or
+/// in a temporary file once large enough) until [`finish`](Self::finish) is
+/// called.
+pub struct CabArchiveWriter<W: Write + Seek> {
+    writer: W,
+    compression_type: CompressionType,
+    spool_options: SpoolOptions,
+    entries: Vec<PendingEntry>,
+}
+
+impl<W: Write + Seek> CabArchiveWriter<W> {
+    /// Creates a writer that will write a cabinet to `writer` once
+    /// finished, compressing its entries with the given compression type.
+    pub fn new(
+        writer: W,
+        compression_type: CompressionType,
+    ) -> CabArchiveWriter<W> {
+        CabArchiveWriter {
+            writer,
+            compression_type,
+            spool_options: SpoolOptions::new(),
+            entries: Vec::new(),
+        }
+    }
+
+    /// Sets the options used to create the spool that buffers each entry's
+    /// contents.
+    pub fn set_spool_options(&mut self, options: SpoolOptions) {
+        self.spool_options = options;
+    }
+}
+
+impl<W: Write + Seek> ArchiveWriter for CabArchiveWriter<W> {
+    type Output = W;
+
+    fn add_entry(
+        &mut self,
+        name: &str,
+        modified: Option<PrimitiveDateTime>,
+        data: &mut dyn Read,
+    ) -> io::Result<u64> {
+        let mut spool = self.spool_options.create();
+        let size = io::copy(data, &mut spool)?;
+        spool.seek(SeekFrom::Start(0))?;
+        self.entries.push(PendingEntry {
+            name: name.to_string(),
+            modified,
+            data: spool,
+        });
+        Ok(size)
+    }
+
+    fn finish(self) -> io::Result<W> {
+        let mut builder = CabinetBuilder::new();
+        let folder = builder.add_folder(self.compression_type);
+        for entry in self.entries.iter() {
+            let file = folder.add_file(entry.name.as_str());
+            if let Some(modified) = entry.modified {
+                file.set_datetime(modified);
+            }
+        }
+        let mut cabinet = builder.build(self.writer)?;
+        let mut entries = self.entries.into_iter();
+        while let Some(mut file_writer) = cabinet.next_file()? {
+            let mut entry = entries.next().unwrap();
+            io::copy(&mut entry.data, &mut file_writer)?;
+        }
+        cabinet.finish()
+    }
+}
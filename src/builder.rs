@@ -1,42 +1,139 @@
+use crate::cabinet::Cabinet;
+use crate::cancel::CancelToken;
 use crate::checksum::Checksum;
 use crate::consts;
-use crate::ctype::CompressionType;
+use crate::ctype::{CompressionEffort, CompressionType, Decompressor};
+use crate::datetime::datetime_bits_from_system_time;
+#[cfg(feature = "time")]
 use crate::datetime::datetime_to_bits;
 use crate::mszip::MsZipCompressor;
-use byteorder::{LittleEndian, WriteBytesExt};
-use std::io::{self, Seek, SeekFrom, Write};
+use crate::report::{CabinetReport, FileSpan, FolderReport};
+use crate::spool::Spool;
+use crate::throttle::Throttle;
+use crate::transform::BlockTransform;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::Hasher;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
 use std::mem;
+use std::sync::Arc;
+use std::time::SystemTime;
+#[cfg(feature = "time")]
 use time::PrimitiveDateTime;
 
 const MAX_UNCOMPRESSED_BLOCK_SIZE: usize = 0x8000;
+/// How many data blocks to compress before deciding whether a folder set to
+/// store incompressible data should be stored uncompressed.
+const INCOMPRESSIBLE_PROBE_BLOCKS: usize = 8;
+/// The compression ratio at or above which a file is considered
+/// incompressible by folders with
+/// [`FolderBuilder::set_split_incompressible`] enabled.
+const INCOMPRESSIBLE_RATIO: f64 = 0.95;
+/// How much of a file's data must be measured before it can be judged
+/// incompressible (or compressible).
+const MIN_MEASURED_SIZE: u64 = 2 * MAX_UNCOMPRESSED_BLOCK_SIZE as u64;
+const CHECKPOINT_SIGNATURE: &[u8; 8] = b"CABCKPT2";
 
 /// A structure for building a file within a new cabinet.
 pub struct FileBuilder {
     name: String,
     attributes: u16,
-    datetime: PrimitiveDateTime,
+    /// The datetime, as the date and time bits to store in the file entry.
+    datetime_bits: (u16, u16),
     entry_offset: u64,
     uncompressed_size: u32,
+    expected_size: Option<u64>,
     offset_within_folder: u32,
+    duplicate_of: Option<String>,
+    original_name: Option<String>,
 }
 
 impl FileBuilder {
     fn new(name: String) -> FileBuilder {
         let name_is_utf = name.bytes().any(|byte| byte > 0x7f);
-        let now = time::OffsetDateTime::now_utc();
-
         let mut builder = FileBuilder {
             name,
             attributes: consts::ATTR_ARCH,
-            datetime: time::PrimitiveDateTime::new(now.date(), now.time()),
+            datetime_bits: datetime_bits_from_system_time(SystemTime::now()),
             entry_offset: 0, // filled in later by CabinetWriter
             uncompressed_size: 0, // filled in later by FileWriter
+            expected_size: None,
             offset_within_folder: 0, // filled in later by CabinetWriter
+            duplicate_of: None,
+            original_name: None,
         };
         builder.set_attribute(consts::ATTR_NAME_IS_UTF, name_is_utf);
         builder
     }
 
+    /// Returns the name of this file.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the name that this file was originally added with, if it
+    /// has since been renamed to avoid a duplicate name (see
+    /// [`DuplicateNames::Rename`]).
+    pub fn original_name(&self) -> Option<&str> {
+        self.original_name.as_deref()
+    }
+
+    /// Returns the number of bytes of data written for this file so far.
+    pub fn uncompressed_size(&self) -> u32 {
+        self.uncompressed_size
+    }
+
+    /// Declares the size of this file, for files in a folder whose data is
+    /// supplied precompressed (see [`FolderBuilder::set_precompressed`]).
+    /// For other files, the size is instead determined by the data written
+    /// to them, and any value set here is only used as the expected size by
+    /// [`CabinetBuilder::validate`].
+    pub fn set_uncompressed_size(&mut self, size: u32) {
+        self.uncompressed_size = size;
+    }
+
+    /// Returns the size that this file's data is expected to have, if one
+    /// was set with [`set_expected_size`](FileBuilder::set_expected_size).
+    pub fn expected_size(&self) -> Option<u64> {
+        self.expected_size
+    }
+
+    /// Declares the exact size of this file's data ahead of writing it.
+    /// Writing more data than this to the file fails, as does moving on to
+    /// the next file (or finishing the cabinet) before this much has been
+    /// written, which catches data sources that get cut short.
+    ///
+    /// If every file in a folder has an expected size (other than those
+    /// added with [`FolderBuilder::add_duplicate_file`]), their final file
+    /// entries are written along with the cabinet header, rather than being
+    /// patched in once the folder is finished; this isn't possible for
+    /// folders that may be split or that deduplicate their files.  The
+    /// expected size is also used by [`CabinetBuilder::validate`] and
+    /// [`CabinetBuilder::estimate_total_size`], in place of any size set
+    /// with [`set_uncompressed_size`](FileBuilder::set_uncompressed_size).
+    ///
+    /// Declaring sizes does not let a cabinet be written to a non-seekable
+    /// sink: the cabinet header still records the total cabinet size and
+    /// each data block's compressed size, which aren't known until the data
+    /// has been compressed.  To write to a non-seekable sink, build into a
+    /// [`Spool`] and use [`CabinetWriter::finish_to`].
+    pub fn set_expected_size(&mut self, size: u64) {
+        self.expected_size = Some(size);
+    }
+
+    /// Returns the size declared for this file ahead of writing it, if any
+    /// (or else zero).
+    fn declared_size(&self) -> u64 {
+        self.expected_size.unwrap_or(self.uncompressed_size as u64)
+    }
+
+    /// Returns the name of the file whose data this file shares, if it was
+    /// added with [`FolderBuilder::add_duplicate_file`].
+    pub fn duplicate_of(&self) -> Option<&str> {
+        self.duplicate_of.as_deref()
+    }
+
     /// Sets the datetime for this file.  According to the CAB spec, this "is
     /// typically considered the 'last modified' time in local time, but the
     /// actual definition is application-defined".
@@ -47,9 +144,26 @@ impl FileBuilder {
     /// clamped/rounded to the nearest legal value.
     ///
     /// By default, the datetime of a new `FileBuilder` is the current UTC
-    /// date/time.
+    /// date/time.  Requires the `time` feature (enabled by default).
+    #[cfg(feature = "time")]
     pub fn set_datetime(&mut self, datetime: PrimitiveDateTime) {
-        self.datetime = datetime;
+        self.datetime_bits = datetime_to_bits(datetime);
+    }
+
+    /// Sets the datetime for this file to the given system time (such as a
+    /// source file's modification time), as a UTC date/time, clamped and
+    /// rounded as for [`set_datetime`](FileBuilder::set_datetime).  Unlike
+    /// `set_datetime`, this doesn't require the `time` feature.
+    pub fn set_system_time(&mut self, time: SystemTime) {
+        self.datetime_bits = datetime_bits_from_system_time(time);
+    }
+
+    /// Sets the raw date and time bits to store for this file, exactly as
+    /// given, even if they don't form a valid datetime.  This allows a file
+    /// copied from another cabinet to keep its stored datetime byte-for-byte
+    /// (see [`FileEntry::raw_datetime`](crate::FileEntry::raw_datetime)).
+    pub fn set_raw_datetime(&mut self, date: u16, time: u16) {
+        self.datetime_bits = (date, time);
     }
 
     /// Sets whether this file has the "read-only" attribute set.  This
@@ -94,18 +208,42 @@ impl FileBuilder {
 /// A structure for building a folder within a new cabinet.
 pub struct FolderBuilder {
     compression_type: CompressionType,
+    compression_effort: CompressionEffort,
+    mszip_shared_history: bool,
+    store_if_incompressible: bool,
+    split_incompressible: bool,
+    /// For folders split off to hold a run of incompressible files, the
+    /// compression type to return to once the run ends.
+    incompressible_run_of: Option<CompressionType>,
     files: Vec<FileBuilder>,
+    raw_compression_bits: Option<u16>,
     reserve_data: Vec<u8>,
+    max_compressed_size: Option<u32>,
+    deduplicate: bool,
+    precompressed: bool,
     entry_offset: u32,
+    /// True if the file entries were written with their final sizes and
+    /// offsets up front (see `FileBuilder::set_expected_size`).
+    entries_declared: bool,
 }
 
 impl FolderBuilder {
     fn new(ctype: CompressionType) -> FolderBuilder {
         FolderBuilder {
             compression_type: ctype,
+            compression_effort: CompressionEffort::default(),
+            mszip_shared_history: true,
+            store_if_incompressible: false,
+            split_incompressible: false,
+            incompressible_run_of: None,
             files: Vec::new(),
+            raw_compression_bits: None,
             reserve_data: Vec::new(),
+            max_compressed_size: None,
+            deduplicate: false,
+            precompressed: false,
             entry_offset: 0, // filled in later by CabinetWriter
+            entries_declared: false,
         }
     }
 
@@ -116,23 +254,365 @@ impl FolderBuilder {
         self.files.last_mut().unwrap()
     }
 
+    /// Adds a new file to the folder whose contents are identical to those
+    /// of an earlier file in the same folder, named `original`.  No data is
+    /// written for the new file; instead, its entry refers to the same range
+    /// of the folder's uncompressed data as the original.  Such files are
+    /// skipped by [`CabinetWriter::next_file`].
+    ///
+    /// Writing the cabinet will fail if `original` is not an earlier file in
+    /// this folder, or if the folder has a compressed size limit (since the
+    /// two files could then end up in different folders).
+    pub fn add_duplicate_file<S: Into<String>>(
+        &mut self,
+        name: S,
+        original: &str,
+    ) -> &mut FileBuilder {
+        let mut file = FileBuilder::new(name.into());
+        file.duplicate_of = Some(original.to_string());
+        self.files.push(file);
+        self.files.last_mut().unwrap()
+    }
+
+    /// Sets whether to detect files in this folder with identical contents
+    /// while writing, and store their data only once.  When enabled, the
+    /// data for each file is buffered in memory until the file is finished
+    /// (that is, until the next call to [`CabinetWriter::next_file`]), and
+    /// files matching an earlier file in the folder are stored as references
+    /// to that file's data.  Files whose contents hash the same are compared
+    /// byte for byte, so the contents of each distinct file are also kept in
+    /// memory until the folder is finished.  By default, this is disabled.
+    pub fn set_deduplicate(&mut self, deduplicate: bool) {
+        self.deduplicate = deduplicate;
+    }
+
+    /// Sets whether this folder's data will be supplied as already-compressed
+    /// data blocks (see [`CabinetWriter::write_precompressed_block`]), rather
+    /// than written file by file.  This allows folders to be compressed
+    /// elsewhere (e.g. in parallel), with this crate only writing the
+    /// cabinet's directory structure around them.  The folder's compression
+    /// type must match the format of the supplied blocks, and each file's
+    /// size must be declared with [`FileBuilder::set_uncompressed_size`];
+    /// the files' data is taken to be laid out in the order they were added.
+    ///
+    /// [`CabinetWriter::next_file`] skips over the files in such folders.
+    /// By default, this is disabled.
+    pub fn set_precompressed(&mut self, precompressed: bool) {
+        self.precompressed = precompressed;
+    }
+
+    /// Sets how hard to work at compressing this folder's data; see
+    /// [`CompressionEffort`] for what each level means for each compression
+    /// type.  By default, this is [`CompressionEffort::Max`].
+    pub fn set_compression_effort(&mut self, effort: CompressionEffort) {
+        self.compression_effort = effort;
+    }
+
+    /// Sets whether MSZIP compression carries the previous 32K of history
+    /// across data blocks, so that each block can refer back to data in
+    /// earlier blocks.  This gives better compression and is what Windows
+    /// does, but some third-party extractors mishandle it; when disabled,
+    /// each block is compressed independently.  Either way, the result is a
+    /// valid MSZIP folder.  This has no effect on other compression types.
+    /// By default, this is enabled.
+    pub fn set_mszip_shared_history(&mut self, shared_history: bool) {
+        self.mszip_shared_history = shared_history;
+    }
+
+    /// Sets whether to store this folder's data uncompressed if compressing
+    /// it doesn't help (e.g. because the files are already compressed).
+    /// When enabled, the first 256 KiB of the folder's data is compressed
+    /// and held in memory before anything is written; if the compressed
+    /// blocks are no smaller than the original data, the folder is written
+    /// with [`CompressionType::None`] instead, saving both space and the
+    /// time it would take to decompress.  The compression type actually used
+    /// is reported by [`FolderReport::compression_type`].
+    ///
+    /// Even without this, individual MSZIP blocks that don't compress are
+    /// stored uncompressed within the MSZIP stream (see
+    /// [`FolderReport::stored_blocks`]).  This only applies to MSZIP
+    /// folders that aren't precompressed.  By default, this is disabled.
+    pub fn set_store_if_incompressible(&mut self, enable: bool) {
+        self.store_if_incompressible = enable;
+    }
+
+    /// Sets whether to start a new, uncompressed folder when a run of
+    /// incompressible files (e.g. images or archives) is found in this
+    /// folder, and to go back to a compressed folder when the run ends.
+    /// This saves the time spent compressing (and later decompressing) data
+    /// that doesn't get any smaller, without having to sort files into
+    /// folders by hand.
+    ///
+    /// Since a file's data isn't known until it is written, each file is
+    /// judged once it is finished, and only affects where the files after
+    /// it go: after an incompressible file in a compressed folder, the rest
+    /// of the folder's files are moved into a new folder with
+    /// [`CompressionType::None`]; in such a folder, each block is also
+    /// compressed quickly (and then discarded) to measure how compressible
+    /// the data is, and after a compressible file, the remaining files are
+    /// moved into a new folder with this folder's original compression type.
+    /// Files smaller than 64 KiB are never judged.  Like other folder
+    /// splits, this reserves room in the cabinet header for one folder
+    /// entry per file.
+    ///
+    /// This only applies to MSZIP folders, and can't be used for folders
+    /// containing duplicate files (see
+    /// [`add_duplicate_file`](FolderBuilder::add_duplicate_file)), nor when
+    /// appending to an existing cabinet.  By default, this is disabled.
+    pub fn set_split_incompressible(&mut self, enable: bool) {
+        self.split_incompressible = enable;
+    }
+
+    /// Sets the exact value to write for the folder's compression type
+    /// field, e.g. to preserve the reserved bits of a folder being copied
+    /// from another cabinet (see
+    /// [`FolderEntry::raw_compression_bits`](crate::FolderEntry::raw_compression_bits)).
+    /// The bits must describe the folder's compression type, or building
+    /// the cabinet will fail.  If the folder ends up being written with a
+    /// different compression type (see
+    /// [`set_store_if_incompressible`](FolderBuilder::set_store_if_incompressible)),
+    /// the bits are ignored.  By default, the field is derived from the
+    /// compression type, with the reserved bits cleared.
+    pub fn set_raw_compression_bits(&mut self, bits: Option<u16>) {
+        self.raw_compression_bits = bits;
+    }
+
     /// Sets the folder's reserve data.  The meaning of this data is
     /// application-defined.  The data must be no more than 255 bytes long.
     pub fn set_reserve_data(&mut self, data: Vec<u8>) {
         self.reserve_data = data;
     }
+
+    /// Caps the compressed size of the folder.  Once the compressed data
+    /// written for this folder reaches `max_size` bytes, the remaining files
+    /// are moved into a new folder (with the same settings), which may in
+    /// turn be split again.  This is useful when targeting media size limits.
+    ///
+    /// The limit is only checked between files (since a file cannot be split
+    /// across folders), so a folder may still exceed the limit by up to one
+    /// file's worth of compressed data.  By default, there is no limit.
+    ///
+    /// Folders with a limit (or with
+    /// [`set_split_incompressible`](FolderBuilder::set_split_incompressible)
+    /// enabled) are also split automatically once their uncompressed data
+    /// grows past 4 GiB, the furthest into a folder that a file can start;
+    /// writing that much data to any other folder fails.
+    pub fn set_max_compressed_size(&mut self, max_size: Option<u32>) {
+        self.max_compressed_size = max_size;
+    }
+
+    /// Checks that the folder's settings can be written.
+    fn validate(&self) -> io::Result<()> {
+        self.compression_type.validate()?;
+        if let Some(bits) = self.raw_compression_bits {
+            let ctype = CompressionType::from_bitfield(bits).ok();
+            if ctype != Some(self.compression_type) {
+                invalid_input!(
+                    "Raw compression bits 0x{:04x} don't match the folder's \
+                     compression type ({})",
+                    bits,
+                    self.compression_type
+                );
+            }
+        }
+        if self.precompressed {
+            if self.max_compressed_size.is_some() {
+                invalid_input!(
+                    "Precompressed folders cannot have a compressed size limit"
+                );
+            }
+            for file in self.files.iter() {
+                if file.uncompressed_size > consts::MAX_FILE_SIZE {
+                    invalid_input!(
+                        "File {:?} is too large ({} bytes; max is {} bytes)",
+                        file.name,
+                        file.uncompressed_size,
+                        consts::MAX_FILE_SIZE
+                    );
+                }
+            }
+        }
+        for (index, file) in self.files.iter().enumerate() {
+            let original = match file.duplicate_of {
+                Some(ref original) => original,
+                None => continue,
+            };
+            if self.max_compressed_size.is_some() {
+                invalid_input!(
+                    "Duplicate file {:?} is in a folder with a compressed \
+                     size limit",
+                    file.name
+                );
+            }
+            if self.split_incompressible {
+                invalid_input!(
+                    "Duplicate file {:?} is in a folder that may be split \
+                     on incompressible files",
+                    file.name
+                );
+            }
+            if !self.files[..index].iter().any(|file| &file.name == original) {
+                invalid_input!(
+                    "Duplicate file {:?} refers to {:?}, which is not an \
+                     earlier file in the same folder",
+                    file.name,
+                    original
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the value to write for the folder's compression type field:
+    /// the raw bits, if set and still applicable, or else the bits for the
+    /// folder's compression type.
+    fn compression_bits(&self) -> u16 {
+        match self.raw_compression_bits {
+            Some(bits)
+                if CompressionType::from_bitfield(bits).ok()
+                    == Some(self.compression_type) =>
+            {
+                bits
+            }
+            _ => self.compression_type.to_bitfield(),
+        }
+    }
+
+    /// Returns the maximum number of folders that this folder could be split
+    /// into when writing.
+    fn max_num_splits(&self) -> usize {
+        if self.max_compressed_size.is_some() || self.split_incompressible {
+            self.files.len().max(1)
+        } else {
+            1
+        }
+    }
+
+    /// If every file in the folder has an expected size, and the folder
+    /// can't be split or deduplicated, fills in each file's size and offset
+    /// within the folder from those sizes, so that their file entries can be
+    /// written in final form up front.
+    fn declare_entries(&mut self) -> io::Result<()> {
+        if self.precompressed
+            || self.deduplicate
+            || self.max_num_splits() > 1
+            || self.files.iter().any(|file| {
+                file.duplicate_of.is_none() && file.expected_size.is_none()
+            })
+        {
+            return Ok(());
+        }
+        let mut offset: u64 = 0;
+        for index in 0..self.files.len() {
+            let (earlier, later) = self.files.split_at_mut(index);
+            let file = &mut later[0];
+            if let Some(ref name) = file.duplicate_of {
+                let original =
+                    earlier.iter().find(|earlier| &earlier.name == name);
+                if let Some(original) = original {
+                    file.offset_within_folder = original.offset_within_folder;
+                    file.uncompressed_size = original.uncompressed_size;
+                }
+                continue;
+            }
+            let size = file.expected_size.unwrap();
+            if size > consts::MAX_FILE_SIZE as u64 {
+                invalid_input!(
+                    "File {:?} is too large ({} bytes; max is {} bytes)",
+                    file.name,
+                    size,
+                    consts::MAX_FILE_SIZE
+                );
+            }
+            if offset > u32::MAX as u64 {
+                invalid_input!(
+                    "File {:?} starts too far into its folder \
+                     (offset of {} bytes, max is {} bytes)",
+                    file.name,
+                    offset,
+                    u32::MAX
+                );
+            }
+            file.offset_within_folder = offset as u32;
+            file.uncompressed_size = size as u32;
+            offset += size;
+        }
+        self.entries_declared = true;
+        Ok(())
+    }
+
+    /// Moves the files from `index` onwards into a new folder with the same
+    /// settings.
+    fn split_off(&mut self, index: usize) -> FolderBuilder {
+        FolderBuilder {
+            compression_type: self.compression_type,
+            compression_effort: self.compression_effort,
+            mszip_shared_history: self.mszip_shared_history,
+            store_if_incompressible: self.store_if_incompressible,
+            split_incompressible: self.split_incompressible,
+            incompressible_run_of: self.incompressible_run_of,
+            files: self.files.split_off(index),
+            raw_compression_bits: self.raw_compression_bits,
+            reserve_data: self.reserve_data.clone(),
+            max_compressed_size: self.max_compressed_size,
+            deduplicate: self.deduplicate,
+            precompressed: self.precompressed,
+            entry_offset: 0, // filled in later by CabinetWriter
+            entries_declared: false,
+        }
+    }
+}
+
+/// How [`CabinetBuilder::build`] handles two or more files in the cabinet
+/// with the same name.  Names are compared case-insensitively, since that is
+/// how Windows compares them when extracting.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum DuplicateNames {
+    /// Fail with an error listing the duplicated names.
+    #[default]
+    Error,
+    /// Write the files as-is, duplicate names and all.
+    Allow,
+    /// Keep the first file with each name, and rename later ones by adding
+    /// a numbered suffix before the extension (e.g. `foo.txt` becomes
+    /// `foo (2).txt`).  The renamed files are listed by
+    /// [`CabinetWriter::renamed_files`].
+    Rename,
 }
 
 /// A structure for building a new cabinet.
 pub struct CabinetBuilder {
     folders: Vec<FolderBuilder>,
     reserve_data: Vec<u8>,
+    version: (u8, u8),
+    cabinet_set_id: u16,
+    cabinet_set_index: u16,
+    prev_cabinet: Option<(String, String)>,
+    next_cabinet: Option<(String, String)>,
+    block_transform: Option<Arc<dyn BlockTransform>>,
+    verify_blocks: bool,
+    duplicate_names: DuplicateNames,
+    cancel: Option<CancelToken>,
+    throttle: Option<Arc<dyn Throttle>>,
 }
 
 impl CabinetBuilder {
     /// Creates a new, empty `CabinetBuilder`.
     pub fn new() -> CabinetBuilder {
-        CabinetBuilder { folders: Vec::new(), reserve_data: Vec::new() }
+        CabinetBuilder {
+            folders: Vec::new(),
+            reserve_data: Vec::new(),
+            version: (consts::VERSION_MAJOR, consts::VERSION_MINOR),
+            cabinet_set_id: 0,
+            cabinet_set_index: 0,
+            prev_cabinet: None,
+            next_cabinet: None,
+            block_transform: None,
+            verify_blocks: false,
+            duplicate_names: DuplicateNames::Error,
+            cancel: None,
+            throttle: None,
+        }
     }
 
     /// Adds a new folder to the cabinet.  Use the returned `FolderBuilder` to
@@ -145,6 +625,31 @@ impl CabinetBuilder {
         self.folders.last_mut().unwrap()
     }
 
+    /// Adds a new folder to the cabinet, and calls `setup` to add files to
+    /// it and change its settings.  Returns this builder, so that several
+    /// folders can be added in one expression:
+    ///
+    /// ```
+    /// use cab::{CabinetBuilder, CompressionType};
+    /// let mut builder = CabinetBuilder::new();
+    /// builder
+    ///     .folder(CompressionType::None, |folder| {
+    ///         folder.add_file("img/foo.jpg");
+    ///     })
+    ///     .folder(CompressionType::MsZip, |folder| {
+    ///         folder.add_file("documents/README.txt");
+    ///         folder.add_file("documents/hidden.txt").set_is_hidden(true);
+    ///     });
+    /// ```
+    pub fn folder<F: FnOnce(&mut FolderBuilder)>(
+        &mut self,
+        ctype: CompressionType,
+        setup: F,
+    ) -> &mut CabinetBuilder {
+        setup(self.add_folder(ctype));
+        self
+    }
+
     /// Sets the cabinet file's header reserve data.  The meaning of this data
     /// is application-defined.  The data must be no more than 60,000 bytes
     /// long.
@@ -152,6 +657,100 @@ impl CabinetBuilder {
         self.reserve_data = data;
     }
 
+    /// Sets the format version to record in the cabinet header, for the
+    /// benefit of old consumers that only accept earlier versions.  The
+    /// major version must be 1, and the minor version from 1 to 3; the
+    /// default is 1.3.
+    ///
+    /// Versions before 1.3 don't support reserve data, LZX or Quantum
+    /// compression, or the attribute marking a file name as UTF-8 (which is
+    /// set for any name that isn't plain ASCII), so
+    /// [`build`](CabinetBuilder::build) fails if any of those are used.
+    pub fn set_version(&mut self, major: u8, minor: u8) {
+        self.version = (major, minor);
+    }
+
+    /// Sets the cabinet set ID (an arbitrary number shared by all the
+    /// cabinets in a set) and this cabinet's (zero-based) index within the
+    /// set.  Both default to zero.
+    pub fn set_cabinet_set(&mut self, set_id: u16, set_index: u16) {
+        self.cabinet_set_id = set_id;
+        self.cabinet_set_index = set_index;
+    }
+
+    /// Sets the file name of the previous cabinet in the set, and the name
+    /// of the disk it is on, to record in the cabinet header.  Each name
+    /// must be no more than 255 bytes long.  By default, there is no
+    /// previous cabinet.
+    ///
+    /// This only links the cabinets together; files and folders still can't
+    /// be continued from one cabinet to the next.
+    pub fn set_prev_cabinet<S: Into<String>, T: Into<String>>(
+        &mut self,
+        cabinet_name: S,
+        disk_name: T,
+    ) {
+        self.prev_cabinet = Some((cabinet_name.into(), disk_name.into()));
+    }
+
+    /// Sets the file name of the next cabinet in the set, and the name of
+    /// the disk it is on, to record in the cabinet header (see
+    /// [`set_prev_cabinet`](CabinetBuilder::set_prev_cabinet)).  By default,
+    /// there is no next cabinet.
+    pub fn set_next_cabinet<S: Into<String>, T: Into<String>>(
+        &mut self,
+        cabinet_name: S,
+        disk_name: T,
+    ) {
+        self.next_cabinet = Some((cabinet_name.into(), disk_name.into()));
+    }
+
+    /// Sets a transformation to apply to each data block's payload after
+    /// compressing it (see [`BlockTransform`]).  By default, no
+    /// transformation is applied.
+    pub fn set_block_transform(
+        &mut self,
+        transform: Option<Arc<dyn BlockTransform>>,
+    ) {
+        self.block_transform = transform;
+    }
+
+    /// Sets whether to check each data block as it is written, by decoding
+    /// and decompressing it again and comparing the result with the data
+    /// that went in.  If a block fails to round-trip, writing fails with an
+    /// error rather than producing a corrupt cabinet.  This roughly doubles
+    /// the CPU cost of writing.  By default, this is disabled.
+    ///
+    /// This only checks blocks one at a time, as they are written; it never
+    /// reads anything back from the underlying writer, so
+    /// [`CabinetWriter::finish`] doesn't re-read the finished cabinet.  To do
+    /// that as well, use [`CabinetWriter::finish_and_verify`].
+    pub fn set_verify_blocks(&mut self, verify: bool) {
+        self.verify_blocks = verify;
+    }
+
+    /// Sets how to handle files with the same name.  By default,
+    /// [`build`](CabinetBuilder::build) returns an error if any two files in
+    /// the cabinet have the same name.
+    pub fn set_duplicate_names(&mut self, duplicate_names: DuplicateNames) {
+        self.duplicate_names = duplicate_names;
+    }
+
+    /// Sets a token that can be used to cancel writing the cabinet while it
+    /// is in progress (see [`CancelToken`]).  Once the token is cancelled,
+    /// writing fails with a [`Cancelled`](crate::Cancelled) error before the
+    /// next data block is written, leaving the output incomplete.  By
+    /// default, writing can't be cancelled.
+    pub fn set_cancel_token(&mut self, token: Option<CancelToken>) {
+        self.cancel = token;
+    }
+
+    /// Sets a hook to call for each data block written (see [`Throttle`]),
+    /// e.g. to rate-limit cabinet creation.  By default, there is no hook.
+    pub fn set_throttle(&mut self, throttle: Option<Arc<dyn Throttle>>) {
+        self.throttle = throttle;
+    }
+
     /// Locks in the cabinet settings and returns a `CabinetWriter` object that
     /// will write the cabinet file into the given writer.
     pub fn build<W: Write + Seek>(
@@ -160,45 +759,163 @@ impl CabinetBuilder {
     ) -> io::Result<CabinetWriter<W>> {
         CabinetWriter::start(writer, self)
     }
-}
 
-impl Default for CabinetBuilder {
-    fn default() -> Self {
-        CabinetBuilder::new()
+    /// Resumes writing a cabinet file whose creation was interrupted, given
+    /// the partially-written output and a token from
+    /// [`CabinetWriter::checkpoint`].  This builder must be set up exactly
+    /// the same way as the one originally used to create the cabinet.  The
+    /// returned `CabinetWriter` continues with the first folder that had not
+    /// been completed at the time of the checkpoint.
+    ///
+    /// Any data in the output past [`CheckpointToken::data_offset`] will be
+    /// overwritten; if the output is a file, it may be worth truncating it to
+    /// that length first, since otherwise stale data from the interrupted
+    /// run could remain at the end of the file.
+    pub fn resume<W: Write + Seek>(
+        self,
+        writer: W,
+        token: &CheckpointToken,
+    ) -> io::Result<CabinetWriter<W>> {
+        CabinetWriter::resume(writer, self, token)
     }
-}
-
-/// A structure for writing file data into a new cabinet file.
-pub struct CabinetWriter<W: Write + Seek> {
-    writer: InnerCabinetWriter<W>,
-    builder: CabinetBuilder,
-    current_folder_index: usize,
-    next_file_index: usize,
-    offset_within_folder: u64,
-}
 
-enum InnerCabinetWriter<W: Write + Seek> {
-    Raw(W),
-    Folder(FolderWriter<W>),
-    None,
-}
+    /// Checks that this builder can be written as a cabinet, without writing
+    /// anything.  This runs the same checks as
+    /// [`build`](CabinetBuilder::build) (except that duplicate names are
+    /// only an error under [`DuplicateNames::Error`]), and also checks the
+    /// sizes declared with [`FileBuilder::set_uncompressed_size`] against
+    /// the format's limits: an error is returned listing any files larger
+    /// than the maximum file size, or that would start too far into a
+    /// folder that can't be split (see
+    /// [`FolderBuilder::set_max_compressed_size`]).  Files whose sizes
+    /// haven't been declared are taken to be empty.
+    ///
+    /// Since a file can't be split across folders, and this crate doesn't
+    /// yet write cabinet sets, such files can't be written at all; this
+    /// allows finding them before streaming any data through a
+    /// [`CabinetWriter`].
+    pub fn validate(&self) -> io::Result<()> {
+        if self.duplicate_names == DuplicateNames::Error {
+            let (_, duplicates) = self.find_duplicate_names();
+            if !duplicates.is_empty() {
+                invalid_input!(
+                    "Cabinet has duplicate file names: {}",
+                    quoted_list(&duplicates)
+                );
+            }
+        }
+        self.check_limits()?;
+        let too_large: Vec<&str> = self
+            .folders
+            .iter()
+            .flat_map(|folder| folder.files.iter())
+            .filter(|file| file.declared_size() > consts::MAX_FILE_SIZE as u64)
+            .map(|file| file.name.as_str())
+            .collect();
+        if !too_large.is_empty() {
+            invalid_input!(
+                "Files are too large (max is {} bytes): {}",
+                consts::MAX_FILE_SIZE,
+                quoted_list(&too_large)
+            );
+        }
+        let mut overflowing = Vec::<&str>::new();
+        for folder in self.folders.iter() {
+            if folder.max_num_splits() > 1 {
+                continue;
+            }
+            let mut offset: u64 = 0;
+            for file in folder.files.iter() {
+                if offset > u32::MAX as u64 {
+                    overflowing.push(&file.name);
+                }
+                if file.duplicate_of.is_none() {
+                    offset += file.declared_size();
+                }
+            }
+        }
+        if !overflowing.is_empty() {
+            invalid_input!(
+                "Files start too far into their folders \
+                 (max offset is {} bytes): {}",
+                u32::MAX,
+                quoted_list(&overflowing)
+            );
+        }
+        Ok(())
+    }
 
-impl<W: Write + Seek> InnerCabinetWriter<W> {
-    fn is_none(&self) -> bool {
-        matches!(*self, InnerCabinetWriter::None)
+    /// Returns the number of bytes that the cabinet header, folder entries,
+    /// and file entries will take up, i.e. the offset at which the first
+    /// data block will be written.  This includes room for the extra folder
+    /// entries reserved for folders that may be split while writing (see
+    /// [`FolderBuilder::set_max_compressed_size`]).  It can only be an
+    /// estimate if any files will be renamed under
+    /// [`DuplicateNames::Rename`].
+    pub fn estimated_header_size(&self) -> u64 {
+        let header_reserve_size = self.reserve_data.len() as u64;
+        let folder_reserve_size = self
+            .folders
+            .iter()
+            .map(|folder| folder.reserve_data.len() as u64)
+            .max()
+            .unwrap_or(0);
+        let mut size: u64 = 36;
+        if header_reserve_size > 0 || folder_reserve_size > 0 {
+            size += 4 + header_reserve_size;
+        }
+        size += self.cabinet_links_size() as u64;
+        let num_folder_slots: usize =
+            self.folders.iter().map(FolderBuilder::max_num_splits).sum();
+        let num_folder_slots = num_folder_slots.min(consts::MAX_NUM_FOLDERS);
+        size += num_folder_slots as u64 * (8 + folder_reserve_size);
+        for file in self.folders.iter().flat_map(|folder| folder.files.iter())
+        {
+            size += 17 + file.name.len() as u64;
+        }
+        size
     }
 
-    fn take(&mut self) -> InnerCabinetWriter<W> {
-        mem::replace(self, InnerCabinetWriter::None)
+    /// Estimates the total size of the cabinet file, given the sizes
+    /// declared with [`FileBuilder::set_uncompressed_size`] (files whose
+    /// sizes haven't been declared are taken to be empty), and assuming
+    /// that compressed folders will compress to `assumed_ratio` times their
+    /// uncompressed size (e.g. 0.5 for data that halves in size).  Folders
+    /// with [`CompressionType::None`] are always counted at their full size.
+    ///
+    /// This is meant for checking ahead of time whether a cabinet is likely
+    /// to fit on size-constrained media (see also
+    /// [`estimated_header_size`](CabinetBuilder::estimated_header_size)),
+    /// before streaming the files' data through a [`CabinetWriter`]; the
+    /// actual size depends on how well the data compresses.
+    pub fn estimate_total_size(&self, assumed_ratio: f64) -> u64 {
+        let mut size = self.estimated_header_size();
+        for folder in self.folders.iter() {
+            let uncompressed_size: u64 = folder
+                .files
+                .iter()
+                .filter(|file| file.duplicate_of.is_none())
+                .map(FileBuilder::declared_size)
+                .sum();
+            let num_blocks =
+                uncompressed_size.div_ceil(MAX_UNCOMPRESSED_BLOCK_SIZE as u64);
+            let compressed_size = match folder.compression_type {
+                CompressionType::None => uncompressed_size,
+                _ => (uncompressed_size as f64 * assumed_ratio).ceil() as u64,
+            };
+            size += 8 * num_blocks + compressed_size;
+        }
+        size
     }
-}
 
-impl<W: Write + Seek> CabinetWriter<W> {
-    fn start(
-        mut writer: W,
-        mut builder: CabinetBuilder,
-    ) -> io::Result<CabinetWriter<W>> {
-        let num_folders = builder.folders.len();
+    /// Checks that the number of folders and files, and the sizes of the
+    /// reserve data, are within the format's limits, and that each folder's
+    /// settings can be written.
+    fn check_limits(&self) -> io::Result<()> {
+        for folder in self.folders.iter() {
+            folder.validate()?;
+        }
+        let num_folders = self.folders.len();
         if num_folders > consts::MAX_NUM_FOLDERS {
             invalid_input!(
                 "Cabinet has too many folders ({}; max is {})",
@@ -206,9 +923,8 @@ impl<W: Write + Seek> CabinetWriter<W> {
                 consts::MAX_NUM_FOLDERS
             );
         }
-
         let num_files: usize =
-            builder.folders.iter().map(|folder| folder.files.len()).sum();
+            self.folders.iter().map(|folder| folder.files.len()).sum();
         if num_files > consts::MAX_NUM_FILES {
             invalid_input!(
                 "Cabinet has too many files ({}; max is {})",
@@ -216,8 +932,28 @@ impl<W: Write + Seek> CabinetWriter<W> {
                 consts::MAX_NUM_FILES
             );
         }
-
-        let header_reserve_size = builder.reserve_data.len();
+        for (cabinet_name, disk_name) in
+            self.prev_cabinet.iter().chain(self.next_cabinet.iter())
+        {
+            for name in [cabinet_name, disk_name] {
+                if name.len() > consts::MAX_STRING_SIZE {
+                    invalid_input!(
+                        "Cabinet or disk name {:?} is too long ({} bytes; \
+                         max is {} bytes)",
+                        name,
+                        name.len(),
+                        consts::MAX_STRING_SIZE
+                    );
+                }
+                if name.contains('\0') {
+                    invalid_input!(
+                        "Cabinet or disk name {:?} contains a NUL character",
+                        name
+                    );
+                }
+            }
+        }
+        let header_reserve_size = self.reserve_data.len();
         if header_reserve_size > consts::MAX_HEADER_RESERVE_SIZE {
             invalid_input!(
                 "Cabinet header reserve data is too large \
@@ -226,8 +962,7 @@ impl<W: Write + Seek> CabinetWriter<W> {
                 consts::MAX_HEADER_RESERVE_SIZE
             );
         }
-
-        let folder_reserve_size = builder
+        let folder_reserve_size = self
             .folders
             .iter()
             .map(|folder| folder.reserve_data.len())
@@ -241,79 +976,632 @@ impl<W: Write + Seek> CabinetWriter<W> {
                 consts::MAX_FOLDER_RESERVE_SIZE
             );
         }
+        self.check_version()
+    }
 
-        let mut flags: u16 = 0;
-        if header_reserve_size > 0 || folder_reserve_size > 0 {
-            flags |= consts::FLAG_RESERVE_PRESENT;
+    /// Checks that the cabinet's version is supported, and that the cabinet
+    /// doesn't use any features that its version doesn't allow.
+    fn check_version(&self) -> io::Result<()> {
+        let (major, minor) = self.version;
+        if major != consts::VERSION_MAJOR
+            || !(1..=consts::VERSION_MINOR).contains(&minor)
+        {
+            invalid_input!(
+                "Version {}.{} cabinet files are not supported",
+                major,
+                minor
+            );
+        }
+        if minor >= 3 {
+            return Ok(());
+        }
+        if !self.reserve_data.is_empty()
+            || self
+                .folders
+                .iter()
+                .any(|folder| !folder.reserve_data.is_empty())
+        {
+            invalid_input!(
+                "Version {}.{} cabinet files can't have reserve data",
+                major,
+                minor
+            );
+        }
+        for folder in self.folders.iter() {
+            if let CompressionType::Lzx(_) | CompressionType::Quantum(_, _) =
+                folder.compression_type
+            {
+                invalid_input!(
+                    "Version {}.{} cabinet files can't use {} compression",
+                    major,
+                    minor,
+                    folder.compression_type
+                );
+            }
+        }
+        let utf_names: Vec<&str> = self
+            .folders
+            .iter()
+            .flat_map(|folder| folder.files.iter())
+            .filter(|file| file.attributes & consts::ATTR_NAME_IS_UTF != 0)
+            .map(|file| file.name.as_str())
+            .collect();
+        if !utf_names.is_empty() {
+            invalid_input!(
+                "Version {}.{} cabinet files can't have UTF-8 file names: {}",
+                major,
+                minor,
+                quoted_list(&utf_names)
+            );
         }
+        Ok(())
+    }
 
-        let mut first_folder_offset = 36;
-        if (flags & consts::FLAG_RESERVE_PRESENT) != 0 {
-            first_folder_offset += 4 + header_reserve_size as u32;
+    /// Returns the (lowercased) names of all files in the builder, along
+    /// with the names of any files whose names were already taken by an
+    /// earlier file.
+    fn find_duplicate_names(&self) -> (HashSet<String>, Vec<&str>) {
+        let mut taken = HashSet::<String>::new();
+        let mut duplicates = Vec::<&str>::new();
+        for file in self.folders.iter().flat_map(|folder| folder.files.iter())
+        {
+            if !taken.insert(file.name.to_lowercase()) {
+                duplicates.push(&file.name);
+            }
         }
-        let folder_entry_size = 8 + folder_reserve_size as u32;
-        let first_file_offset =
-            first_folder_offset + (num_folders as u32) * folder_entry_size;
+        (taken, duplicates)
+    }
 
-        // Write cabinet header:
-        writer.write_u32::<LittleEndian>(consts::FILE_SIGNATURE)?;
-        writer.write_u32::<LittleEndian>(0)?; // reserved1
-        writer.write_u32::<LittleEndian>(0)?; // total size, filled later
-        writer.write_u32::<LittleEndian>(0)?; // reserved2
-        writer.write_u32::<LittleEndian>(first_file_offset)?;
-        writer.write_u32::<LittleEndian>(0)?; // reserved3
-        writer.write_u8(consts::VERSION_MINOR)?;
-        writer.write_u8(consts::VERSION_MAJOR)?;
-        writer.write_u16::<LittleEndian>(num_folders as u16)?;
-        writer.write_u16::<LittleEndian>(num_files as u16)?;
-        writer.write_u16::<LittleEndian>(flags)?;
-        writer.write_u16::<LittleEndian>(0)?; // cabinet set ID
-        writer.write_u16::<LittleEndian>(0)?; // cabinet set index
-        if (flags & consts::FLAG_RESERVE_PRESENT) != 0 {
-            writer.write_u16::<LittleEndian>(header_reserve_size as u16)?;
-            writer.write_u8(folder_reserve_size as u8)?;
-            writer.write_u8(0)?; // data reserve size
-            writer.write_all(&builder.reserve_data)?;
+    /// Checks for files with duplicate names, handling them according to
+    /// `self.duplicate_names`, and returns the list of files renamed (as
+    /// `(original, renamed)` pairs).
+    fn resolve_duplicate_names(
+        &mut self,
+    ) -> io::Result<Vec<(String, String)>> {
+        if self.duplicate_names == DuplicateNames::Allow {
+            return Ok(Vec::new());
         }
-        if (flags & consts::FLAG_PREV_CABINET) != 0 {
-            invalid_input!("Prev-cabinet feature not yet supported");
+        let (mut taken, duplicates) = self.find_duplicate_names();
+        if duplicates.is_empty() {
+            return Ok(Vec::new());
         }
-        if (flags & consts::FLAG_NEXT_CABINET) != 0 {
-            invalid_input!("Next-cabinet feature not yet supported");
+        if self.duplicate_names == DuplicateNames::Error {
+            invalid_input!(
+                "Cabinet has duplicate file names: {}",
+                quoted_list(&duplicates)
+            );
         }
-
-        // Write structs for folders:
-        for (index, folder) in builder.folders.iter_mut().enumerate() {
-            folder.entry_offset =
-                first_folder_offset + (index as u32) * folder_entry_size;
-            writer.write_u32::<LittleEndian>(0)?; // first data, filled later
-            writer.write_u16::<LittleEndian>(0)?; // num data, filled later
-            let ctype_bits = folder.compression_type.to_bitfield();
-            writer.write_u16::<LittleEndian>(ctype_bits)?;
-            debug_assert!(folder.reserve_data.len() <= folder_reserve_size);
-            if folder_reserve_size > 0 {
-                writer.write_all(&folder.reserve_data)?;
-                let padding = folder_reserve_size - folder.reserve_data.len();
-                if padding > 0 {
-                    writer.write_all(&vec![0; padding])?;
+        let mut seen = HashSet::<String>::new();
+        let mut renamed = Vec::new();
+        for folder in self.folders.iter_mut() {
+            for index in 0..folder.files.len() {
+                let (earlier, later) = folder.files.split_at_mut(index);
+                let (file, later) = later.split_first_mut().unwrap();
+                if seen.insert(file.name.to_lowercase()) {
+                    continue;
+                }
+                let (stem, extension) = split_extension(&file.name);
+                let new_name = (2..)
+                    .map(|number| {
+                        format!("{} ({}){}", stem, number, extension)
+                    })
+                    .find(|name| !taken.contains(&name.to_lowercase()))
+                    .unwrap();
+                taken.insert(new_name.to_lowercase());
+                seen.insert(new_name.to_lowercase());
+                // Later duplicate files in this folder that referred to this
+                // file (rather than to an earlier file of the same name) must
+                // follow it to its new name.
+                if !earlier.iter().any(|other| other.name == file.name) {
+                    for other in later.iter_mut() {
+                        if other.duplicate_of.as_ref() == Some(&file.name) {
+                            other.duplicate_of = Some(new_name.clone());
+                        }
+                    }
                 }
+                renamed.push((file.name.clone(), new_name.clone()));
+                let name_is_utf = new_name.bytes().any(|byte| byte > 0x7f);
+                file.set_attribute(consts::ATTR_NAME_IS_UTF, name_is_utf);
+                file.original_name =
+                    Some(mem::replace(&mut file.name, new_name));
             }
         }
+        Ok(renamed)
+    }
 
-        // Write structs for files:
-        let mut current_offset = first_file_offset as u64;
-        for (folder_index, folder) in builder.folders.iter_mut().enumerate() {
+    /// Returns the number of bytes taken up in the cabinet header by the
+    /// names of the previous and next cabinets (and their disks), if any.
+    fn cabinet_links_size(&self) -> usize {
+        self.prev_cabinet
+            .iter()
+            .chain(self.next_cabinet.iter())
+            .map(|(cabinet_name, disk_name)| {
+                cabinet_name.len() + disk_name.len() + 2
+            })
+            .sum()
+    }
+
+    /// Returns a hash of the layout described by this builder (its header
+    /// settings, folders and files), used to make sure that a cabinet is
+    /// resumed with a matching builder.  File datetimes aren't included,
+    /// since they default to the time at which each file was added.
+    fn fingerprint(&self) -> u64 {
+        let mut hasher = LayoutHasher::new();
+        hasher.update(&[self.version.0, self.version.1]);
+        hasher.update_bytes(&self.reserve_data);
+        hasher.update(&self.cabinet_set_id.to_le_bytes());
+        hasher.update(&self.cabinet_set_index.to_le_bytes());
+        for link in [&self.prev_cabinet, &self.next_cabinet] {
+            match link {
+                Some((cabinet_name, disk_name)) => {
+                    hasher.update(&[1]);
+                    hasher.update_bytes(cabinet_name.as_bytes());
+                    hasher.update_bytes(disk_name.as_bytes());
+                }
+                None => hasher.update(&[0]),
+            }
+        }
+        hasher.update(&(self.folders.len() as u32).to_le_bytes());
+        for folder in self.folders.iter() {
+            hasher.update(&folder.compression_bits().to_le_bytes());
+            hasher.update(
+                &folder.compression_effort.deflate_level().to_le_bytes(),
+            );
+            hasher.update(&[
+                folder.mszip_shared_history as u8,
+                folder.store_if_incompressible as u8,
+                folder.split_incompressible as u8,
+                folder.deduplicate as u8,
+                folder.precompressed as u8,
+            ]);
+            hasher.update_bytes(&folder.reserve_data);
+            let max_size = folder.max_compressed_size.map_or(0, |size| size);
+            hasher.update(&max_size.to_le_bytes());
+            hasher.update(&(folder.files.len() as u32).to_le_bytes());
+            for file in folder.files.iter() {
+                hasher.update_bytes(file.name.as_bytes());
+                hasher.update(&file.attributes.to_le_bytes());
+                hasher.update(&file.uncompressed_size.to_le_bytes());
+                let expected_size =
+                    file.expected_size.map_or(u64::MAX, |size| size);
+                hasher.update(&expected_size.to_le_bytes());
+                match file.duplicate_of {
+                    Some(ref original) => {
+                        hasher.update(&[1]);
+                        hasher.update_bytes(original.as_bytes());
+                    }
+                    None => hasher.update(&[0]),
+                }
+            }
+        }
+        hasher.finish()
+    }
+}
+
+/// A 64-bit FNV-1a hash.  Unlike `DefaultHasher`, its output is the same on
+/// every platform and Rust version, so it can be stored in checkpoint tokens.
+struct LayoutHasher {
+    value: u64,
+}
+
+impl LayoutHasher {
+    fn new() -> LayoutHasher {
+        LayoutHasher { value: 0xcbf29ce484222325 }
+    }
+
+    fn update(&mut self, buf: &[u8]) {
+        for &byte in buf {
+            self.value ^= byte as u64;
+            self.value = self.value.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    /// Hashes a variable-length byte string, prefixed with its length so that
+    /// adjacent strings can't run into each other.
+    fn update_bytes(&mut self, buf: &[u8]) {
+        self.update(&(buf.len() as u64).to_le_bytes());
+        self.update(buf);
+    }
+
+    fn finish(&self) -> u64 {
+        self.value
+    }
+}
+
+/// Formats a list of names as a comma-separated list of quoted strings.
+fn quoted_list(names: &[&str]) -> String {
+    names
+        .iter()
+        .map(|name| format!("{:?}", name))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Splits a file name into the part before the extension of its last path
+/// component, and the extension (including the dot, or empty if none).
+pub(crate) fn split_extension(name: &str) -> (&str, &str) {
+    let base_start = name.rfind(['/', '\\']).map_or(0, |index| index + 1);
+    match name[base_start..].rfind('.') {
+        Some(dot) if dot > 0 => name.split_at(base_start + dot),
+        _ => (name, ""),
+    }
+}
+
+impl Default for CabinetBuilder {
+    fn default() -> Self {
+        CabinetBuilder::new()
+    }
+}
+
+/// A structure for writing file data into a new cabinet file.
+///
+/// If a `CabinetWriter` is dropped without calling
+/// [`finish`](CabinetWriter::finish), it finishes the cabinet as best it
+/// can, writing any files whose data hasn't been written yet as empty files
+/// (see [`set_require_finish`](CabinetWriter::set_require_finish) to catch
+/// that happening by mistake).
+#[must_use = "the cabinet isn't complete until `finish` is called"]
+pub struct CabinetWriter<W: Write + Seek> {
+    writer: InnerCabinetWriter<W>,
+    builder: CabinetBuilder,
+    current_folder_index: usize,
+    next_file_index: usize,
+    offset_within_folder: u64,
+    append: Option<AppendState>,
+    folder_layout: FolderLayout,
+    dedup: DedupState,
+    report: CabinetReport,
+    renamed_files: Vec<(String, String)>,
+    /// Whether writing has failed with an error, after which the writer may
+    /// be dropped without being finished.
+    failed: bool,
+    /// Whether to panic (in debug builds) if the writer is dropped with
+    /// files not yet written (see `set_require_finish`).
+    require_finish: bool,
+}
+
+/// A cabinet file that has been completely written, as returned by
+/// [`CabinetWriter::finalize`].
+#[must_use = "dropping a `FinishedCabinet` drops the underlying writer"]
+pub struct FinishedCabinet<W> {
+    writer: W,
+    report: CabinetReport,
+    total_size: u64,
+}
+
+/// Tracks which file contents have already been written to the current
+/// folder, for folders with deduplication enabled.
+#[derive(Default)]
+struct DedupState {
+    /// The buffered contents of the file currently being written.
+    buffer: Vec<u8>,
+    /// The contents of each distinct file written so far, along with its
+    /// offset within the folder, grouped by a hash of the contents.
+    contents: HashMap<u64, Vec<(u32, Vec<u8>)>>,
+}
+
+impl DedupState {
+    /// Returns the offset within the folder of earlier contents identical
+    /// to `buffer`, if any.  Files with the same hash are compared byte by
+    /// byte, so a hash collision can never alias two different files.
+    fn find(&self, hash: u64) -> Option<u32> {
+        let candidates = self.contents.get(&hash)?;
+        candidates
+            .iter()
+            .find(|(_, data)| *data == self.buffer)
+            .map(|&(offset, _)| offset)
+    }
+}
+
+/// Hashes a file's contents, for finding candidate duplicates.
+fn content_hash(data: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(data);
+    hasher.finish()
+}
+
+/// Where folder entries live in the cabinet file being written.
+struct FolderLayout {
+    first_folder_offset: u32,
+    folder_entry_size: u32,
+    folder_reserve_size: usize,
+    first_file_offset: u32,
+    /// The fingerprint of the original `CabinetBuilder`.
+    fingerprint: u64,
+    /// True if any folder has been split while writing, in which case the
+    /// folder count in the cabinet header needs updating at the end.
+    any_split: bool,
+}
+
+/// A record of how far a `CabinetWriter` has gotten, which can be used to
+/// resume writing the cabinet if it is interrupted.  See
+/// [`CabinetWriter::checkpoint`] and [`CabinetBuilder::resume`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CheckpointToken {
+    fingerprint: u64,
+    first_folder_offset: u32,
+    folder_entry_size: u32,
+    folder_reserve_size: u32,
+    first_file_offset: u32,
+    data_offset: u64,
+    /// The number of files in each completed folder (which, due to splits,
+    /// may not match the folders in the original builder).
+    folder_file_counts: Vec<u32>,
+}
+
+impl CheckpointToken {
+    /// Returns the offset within the cabinet file at which writing will
+    /// continue when resuming from this checkpoint.  All data before this
+    /// offset has already been written.
+    pub fn data_offset(&self) -> u64 {
+        self.data_offset
+    }
+
+    /// Returns the number of folders that were complete at the time of the
+    /// checkpoint.
+    pub fn num_completed_folders(&self) -> usize {
+        self.folder_file_counts.len()
+    }
+
+    /// Serializes this token so that it can be saved (e.g. to disk) and later
+    /// restored with [`CheckpointToken::from_bytes`].
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = CHECKPOINT_SIGNATURE.to_vec();
+        bytes.extend_from_slice(&self.fingerprint.to_le_bytes());
+        bytes.extend_from_slice(&self.first_folder_offset.to_le_bytes());
+        bytes.extend_from_slice(&self.folder_entry_size.to_le_bytes());
+        bytes.extend_from_slice(&self.folder_reserve_size.to_le_bytes());
+        bytes.extend_from_slice(&self.first_file_offset.to_le_bytes());
+        bytes.extend_from_slice(&self.data_offset.to_le_bytes());
+        let num_folders = self.folder_file_counts.len() as u32;
+        bytes.extend_from_slice(&num_folders.to_le_bytes());
+        for &count in self.folder_file_counts.iter() {
+            bytes.extend_from_slice(&count.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// Deserializes a token previously serialized with
+    /// [`CheckpointToken::to_bytes`].
+    pub fn from_bytes(mut bytes: &[u8]) -> io::Result<CheckpointToken> {
+        let mut signature = [0u8; 8];
+        bytes.read_exact(&mut signature)?;
+        if &signature != CHECKPOINT_SIGNATURE {
+            invalid_data!("Not a cabinet checkpoint token");
+        }
+        let fingerprint = bytes.read_u64::<LittleEndian>()?;
+        let first_folder_offset = bytes.read_u32::<LittleEndian>()?;
+        let folder_entry_size = bytes.read_u32::<LittleEndian>()?;
+        let folder_reserve_size = bytes.read_u32::<LittleEndian>()?;
+        let first_file_offset = bytes.read_u32::<LittleEndian>()?;
+        let data_offset = bytes.read_u64::<LittleEndian>()?;
+        let num_folders = bytes.read_u32::<LittleEndian>()? as usize;
+        if num_folders > consts::MAX_NUM_FOLDERS {
+            invalid_data!("Checkpoint token has too many folders");
+        }
+        let mut folder_file_counts = Vec::with_capacity(num_folders);
+        for _ in 0..num_folders {
+            folder_file_counts.push(bytes.read_u32::<LittleEndian>()?);
+        }
+        if !bytes.is_empty() {
+            invalid_data!("Checkpoint token has trailing data");
+        }
+        Ok(CheckpointToken {
+            fingerprint,
+            first_folder_offset,
+            folder_entry_size,
+            folder_reserve_size,
+            first_file_offset,
+            data_offset,
+            folder_file_counts,
+        })
+    }
+}
+
+/// State for a `CabinetWriter` that is appending folders to an existing
+/// cabinet, whose file entries will all be written out at the end.
+struct AppendState {
+    old_file_entries: Vec<u8>,
+    old_num_folders: usize,
+    old_num_files: usize,
+}
+
+/// Returns the total size in bytes of the `num_files` file entries starting
+/// at `first_file_offset`.
+fn measure_file_entries<R: Read + Seek>(
+    reader: &mut R,
+    first_file_offset: u32,
+    num_files: usize,
+) -> io::Result<u64> {
+    reader.seek(SeekFrom::Start(first_file_offset as u64))?;
+    let mut reader = BufReader::new(reader);
+    let mut size = 0u64;
+    let mut name = Vec::new();
+    for _ in 0..num_files {
+        let mut fields = [0u8; 16];
+        reader.read_exact(&mut fields)?;
+        name.clear();
+        let name_len = reader.read_until(0, &mut name)?;
+        if name.last() != Some(&0) {
+            invalid_data!("Unterminated file name in existing file entries");
+        }
+        size += 16 + name_len as u64;
+    }
+    Ok(size)
+}
+
+/// Information about an existing cabinet file, needed to append to it.
+pub(crate) struct AppendInfo {
+    pub(crate) total_size: u32,
+    pub(crate) first_folder_offset: u32,
+    pub(crate) first_file_offset: u32,
+    /// The total size of the existing file entries, or `None` if it needs
+    /// to be measured (because the entries were never parsed).
+    pub(crate) file_entries_size: Option<u64>,
+    pub(crate) num_folders: usize,
+    pub(crate) num_files: usize,
+    pub(crate) folder_reserve_size: u8,
+    pub(crate) data_reserve_size: u8,
+    /// The first data block offset and number of data blocks for each
+    /// existing folder.
+    pub(crate) folder_data_offsets: Vec<(u32, u16)>,
+}
+
+/// A structure for adding new folders to the end of an existing cabinet file.
+///
+/// Created by [`Cabinet::into_appender`](crate::Cabinet::into_appender).
+/// Once all the new folders and files have been added, call
+/// [`build`](CabinetAppender::build) to get a `CabinetWriter` for writing the
+/// new files' data, just as when creating a new cabinet.
+pub struct CabinetAppender<W> {
+    writer: W,
+    info: AppendInfo,
+    builder: CabinetBuilder,
+}
+
+impl<W: Read + Write + Seek> CabinetAppender<W> {
+    pub(crate) fn new(writer: W, info: AppendInfo) -> CabinetAppender<W> {
+        CabinetAppender { writer, info, builder: CabinetBuilder::new() }
+    }
+
+    /// Adds a new folder to the end of the cabinet.  Use the returned
+    /// `FolderBuilder` to add files to the folder or to change other settings
+    /// on the folder.
+    pub fn add_folder(
+        &mut self,
+        ctype: CompressionType,
+    ) -> &mut FolderBuilder {
+        self.builder.add_folder(ctype)
+    }
+
+    /// Sets a transformation to apply to each new data block's payload after
+    /// compressing it (see [`BlockTransform`]).  This should generally match
+    /// the transform used for the rest of the cabinet.
+    pub fn set_block_transform(
+        &mut self,
+        transform: Option<Arc<dyn BlockTransform>>,
+    ) {
+        self.builder.set_block_transform(transform);
+    }
+
+    /// Locks in the new folders and returns a `CabinetWriter` object that
+    /// will write their data into the cabinet file.
+    ///
+    /// The existing file entries are moved to the end of the cabinet file to
+    /// make room for the new folder entries.  If that still doesn't free up
+    /// enough room, the existing data blocks are shifted forward as well.
+    pub fn build(self) -> io::Result<CabinetWriter<W>> {
+        CabinetWriter::start_append(self.writer, self.builder, self.info)
+    }
+}
+
+enum InnerCabinetWriter<W: Write + Seek> {
+    Raw(W),
+    Folder(Box<FolderWriter<W>>),
+    None,
+}
+
+impl<W: Write + Seek> InnerCabinetWriter<W> {
+    fn is_none(&self) -> bool {
+        matches!(*self, InnerCabinetWriter::None)
+    }
+
+    fn take(&mut self) -> InnerCabinetWriter<W> {
+        mem::replace(self, InnerCabinetWriter::None)
+    }
+}
+
+impl<W: Write + Seek> CabinetWriter<W> {
+    fn start(
+        mut writer: W,
+        mut builder: CabinetBuilder,
+    ) -> io::Result<CabinetWriter<W>> {
+        let renamed_files = builder.resolve_duplicate_names()?;
+        builder.check_limits()?;
+        let fingerprint = builder.fingerprint();
+        let num_folders = builder.folders.len();
+        // Room for extra folder entries, in case some folders get split:
+        let num_folder_slots: usize =
+            builder.folders.iter().map(FolderBuilder::max_num_splits).sum();
+        let num_folder_slots = num_folder_slots.min(consts::MAX_NUM_FOLDERS);
+        let num_files: usize =
+            builder.folders.iter().map(|folder| folder.files.len()).sum();
+        let header_reserve_size = builder.reserve_data.len();
+        let folder_reserve_size = builder
+            .folders
+            .iter()
+            .map(|folder| folder.reserve_data.len())
+            .max()
+            .unwrap_or(0);
+
+        let mut flags: u16 = 0;
+        if header_reserve_size > 0 || folder_reserve_size > 0 {
+            flags |= consts::FLAG_RESERVE_PRESENT;
+        }
+        if builder.prev_cabinet.is_some() {
+            flags |= consts::FLAG_PREV_CABINET;
+        }
+        if builder.next_cabinet.is_some() {
+            flags |= consts::FLAG_NEXT_CABINET;
+        }
+
+        let mut first_folder_offset = 36;
+        if (flags & consts::FLAG_RESERVE_PRESENT) != 0 {
+            first_folder_offset += 4 + header_reserve_size as u32;
+        }
+        first_folder_offset += builder.cabinet_links_size() as u32;
+        let folder_entry_size = 8 + folder_reserve_size as u32;
+        let first_file_offset = first_folder_offset
+            + (num_folder_slots as u32) * folder_entry_size;
+
+        // Write cabinet header:
+        writer.write_u32::<LittleEndian>(consts::FILE_SIGNATURE)?;
+        writer.write_u32::<LittleEndian>(0)?; // reserved1
+        writer.write_u32::<LittleEndian>(0)?; // total size, filled later
+        writer.write_u32::<LittleEndian>(0)?; // reserved2
+        writer.write_u32::<LittleEndian>(first_file_offset)?;
+        writer.write_u32::<LittleEndian>(0)?; // reserved3
+        writer.write_u8(builder.version.1)?;
+        writer.write_u8(builder.version.0)?;
+        writer.write_u16::<LittleEndian>(num_folders as u16)?;
+        writer.write_u16::<LittleEndian>(num_files as u16)?;
+        writer.write_u16::<LittleEndian>(flags)?;
+        writer.write_u16::<LittleEndian>(builder.cabinet_set_id)?;
+        writer.write_u16::<LittleEndian>(builder.cabinet_set_index)?;
+        if (flags & consts::FLAG_RESERVE_PRESENT) != 0 {
+            writer.write_u16::<LittleEndian>(header_reserve_size as u16)?;
+            writer.write_u8(folder_reserve_size as u8)?;
+            writer.write_u8(0)?; // data reserve size
+            writer.write_all(&builder.reserve_data)?;
+        }
+        for (cabinet_name, disk_name) in
+            builder.prev_cabinet.iter().chain(builder.next_cabinet.iter())
+        {
+            writer.write_all(cabinet_name.as_bytes())?;
+            writer.write_u8(0)?;
+            writer.write_all(disk_name.as_bytes())?;
+            writer.write_u8(0)?;
+        }
+
+        // Write structs for folders:
+        for (index, folder) in builder.folders.iter_mut().enumerate() {
+            folder.entry_offset =
+                first_folder_offset + (index as u32) * folder_entry_size;
+            write_folder_entry(&mut writer, folder, folder_reserve_size)?;
+        }
+        let num_spare_slots = num_folder_slots - num_folders;
+        if num_spare_slots > 0 {
+            let spare_size = num_spare_slots * folder_entry_size as usize;
+            writer.write_all(&vec![0; spare_size])?;
+        }
+
+        // Write structs for files:
+        let mut current_offset = first_file_offset as u64;
+        for (folder_index, folder) in builder.folders.iter_mut().enumerate() {
+            folder.declare_entries()?;
             for file in folder.files.iter_mut() {
                 file.entry_offset = current_offset;
-                writer.write_u32::<LittleEndian>(0)?; // size, filled later
-                writer.write_u32::<LittleEndian>(0)?; // offset, filled later
-                writer.write_u16::<LittleEndian>(folder_index as u16)?;
-                let (date, time) = datetime_to_bits(file.datetime);
-                writer.write_u16::<LittleEndian>(date)?;
-                writer.write_u16::<LittleEndian>(time)?;
-                writer.write_u16::<LittleEndian>(file.attributes)?;
-                writer.write_all(file.name.as_bytes())?;
-                writer.write_u8(0)?;
+                write_file_entry(&mut writer, file, folder_index)?;
                 current_offset += 17 + file.name.len() as u64;
             }
         }
@@ -324,41 +1612,56 @@ impl<W: Write + Seek> CabinetWriter<W> {
             current_folder_index: 0,
             next_file_index: 0,
             offset_within_folder: 0,
+            append: None,
+            folder_layout: FolderLayout {
+                first_folder_offset,
+                folder_entry_size,
+                folder_reserve_size,
+                first_file_offset,
+                fingerprint,
+                any_split: false,
+            },
+            dedup: DedupState::default(),
+            report: CabinetReport::default(),
+            renamed_files,
+            failed: false,
+            require_finish: false,
         })
     }
 
     /// Returns a `FileWriter` for the next file within that cabinet that needs
     /// data to be written, or `None` if all files are now complete.
-    pub fn next_file(&mut self) -> io::Result<Option<FileWriter<W>>> {
-        let num_folders = self.builder.folders.len();
-        while self.current_folder_index < num_folders {
+    pub fn next_file(&mut self) -> io::Result<Option<FileWriter<'_, W>>> {
+        // Until we return successfully, assume that an error has occurred.
+        self.failed = true;
+        // Note that folders may be split (adding more folders) as we go.
+        while self.current_folder_index < self.builder.folders.len() {
+            if self.builder.folders[self.current_folder_index].precompressed {
+                self.end_precompressed_folder()?;
+                continue;
+            }
             if self.next_file_index > 0 {
-                // End previous file:
-                let folder = &self.builder.folders[self.current_folder_index];
-                let file = &folder.files[self.next_file_index - 1];
-                self.offset_within_folder += file.uncompressed_size as u64;
+                self.end_file()?;
+                self.split_folder_if_full()?;
+                self.split_folder_if_incompressible()?;
+            }
+            if self.next_file_index == 0
+                && matches!(self.writer, InnerCabinetWriter::Raw(_))
+            {
+                // Begin the folder even if it has no files, so that it still
+                // gets a (zero-block) folder entry:
+                self.begin_folder()?;
             }
             let num_files =
                 self.builder.folders[self.current_folder_index].files.len();
+            if self.next_file_index < num_files && self.skip_duplicate_file() {
+                continue;
+            }
             if self.next_file_index < num_files {
+                // Begin next file:
                 let folder =
                     &mut self.builder.folders[self.current_folder_index];
-                if self.next_file_index == 0 {
-                    // Begin folder:
-                    match self.writer.take() {
-                        InnerCabinetWriter::Raw(writer) => {
-                            let folder_writer = FolderWriter::new(
-                                writer,
-                                folder.compression_type,
-                                folder.entry_offset,
-                            )?;
-                            self.writer =
-                                InnerCabinetWriter::Folder(folder_writer);
-                        }
-                        _ => unreachable!(),
-                    }
-                }
-                // Begin next file:
+                let deduplicate = folder.deduplicate;
                 let file = &mut folder.files[self.next_file_index];
                 if self.offset_within_folder > (u32::MAX as u64) {
                     invalid_data!(
@@ -369,95 +1672,1108 @@ impl<W: Write + Seek> CabinetWriter<W> {
                     );
                 }
                 file.offset_within_folder = self.offset_within_folder as u32;
+                file.uncompressed_size = 0;
+                self.failed = false;
                 let file_writer = match self.writer {
                     InnerCabinetWriter::Folder(ref mut folder_writer) => {
-                        FileWriter::new(folder_writer, file)
+                        let buffer = if deduplicate {
+                            Some(&mut self.dedup.buffer)
+                        } else {
+                            None
+                        };
+                        folder_writer.mark_file_start();
+                        FileWriter::new(
+                            folder_writer,
+                            file,
+                            buffer,
+                            &mut self.failed,
+                        )
                     }
                     _ => unreachable!(),
                 };
                 self.next_file_index += 1;
                 return Ok(Some(file_writer));
             }
-            // End folder:
-            match self.writer.take() {
-                InnerCabinetWriter::Folder(folder_writer) => {
-                    let folder =
-                        &self.builder.folders[self.current_folder_index];
-                    let writer = folder_writer.finish(&folder.files)?;
-                    self.writer = InnerCabinetWriter::Raw(writer);
+            self.end_folder()?;
+        }
+        self.failed = false;
+        Ok(None)
+    }
+
+    fn begin_folder(&mut self) -> io::Result<()> {
+        let folder = &mut self.builder.folders[self.current_folder_index];
+        if self.folder_layout.any_split {
+            let layout = &self.folder_layout;
+            folder.entry_offset = layout.first_folder_offset
+                + (self.current_folder_index as u32)
+                    * layout.folder_entry_size;
+        }
+        match self.writer.take() {
+            InnerCabinetWriter::Raw(mut writer) => {
+                if self.folder_layout.any_split {
+                    let offset = writer.stream_position()?;
+                    writer
+                        .seek(SeekFrom::Start(folder.entry_offset as u64))?;
+                    write_folder_entry(
+                        &mut writer,
+                        folder,
+                        self.folder_layout.folder_reserve_size,
+                    )?;
+                    writer.seek(SeekFrom::Start(offset))?;
+                }
+                let folder_index = self.current_folder_index
+                    + self
+                        .append
+                        .as_ref()
+                        .map_or(0, |append| append.old_num_folders);
+                let folder_writer = FolderWriter::new(
+                    writer,
+                    folder,
+                    folder_index,
+                    self.builder.block_transform.clone(),
+                    self.builder.verify_blocks,
+                    self.builder.cancel.clone(),
+                    self.builder.throttle.clone(),
+                )?;
+                self.writer =
+                    InnerCabinetWriter::Folder(Box::new(folder_writer));
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    /// Writes an already-compressed data block into the next folder, which
+    /// must have been marked with [`FolderBuilder::set_precompressed`].
+    /// `payload` must be the block's data exactly as it should be stored in
+    /// the cabinet (before any [`BlockTransform`] is applied), and
+    /// `uncompressed_size` the number of bytes it decompresses to (from 1 to
+    /// 32768).
+    ///
+    /// All of a precompressed folder's blocks must be written, in order,
+    /// before calling [`next_file`](CabinetWriter::next_file) or
+    /// [`finish`](CabinetWriter::finish), which complete the folder and move
+    /// on past it.  Blocks written this way are not checked by
+    /// [`CabinetBuilder::set_verify_blocks`].
+    pub fn write_precompressed_block(
+        &mut self,
+        payload: &[u8],
+        uncompressed_size: u16,
+    ) -> io::Result<()> {
+        if uncompressed_size == 0
+            || uncompressed_size as usize > MAX_UNCOMPRESSED_BLOCK_SIZE
+        {
+            invalid_input!(
+                "Invalid uncompressed size for data block ({} bytes; must \
+                 be from 1 to {} bytes)",
+                uncompressed_size,
+                MAX_UNCOMPRESSED_BLOCK_SIZE
+            );
+        }
+        // If every file in the current (ordinary) folder has been started,
+        // complete that folder so as to move on to the next one.
+        if let Some(folder) =
+            self.builder.folders.get(self.current_folder_index)
+        {
+            if !folder.precompressed
+                && self.next_file_index > 0
+                && self.next_file_index == folder.files.len()
+            {
+                self.end_file()?;
+                self.end_folder()?;
+            }
+        }
+        match self.builder.folders.get(self.current_folder_index) {
+            Some(folder) if folder.precompressed => {}
+            _ => invalid_input!(
+                "The next folder to be written is not a precompressed folder"
+            ),
+        }
+        if let InnerCabinetWriter::Raw(_) = self.writer {
+            self.begin_folder()?;
+        }
+        match self.writer {
+            InnerCabinetWriter::Folder(ref mut folder_writer) => folder_writer
+                .write_block(payload.to_vec(), uncompressed_size, None),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Completes a precompressed folder, after checking that its data blocks
+    /// hold exactly as much data as its files declare.
+    fn end_precompressed_folder(&mut self) -> io::Result<()> {
+        if let InnerCabinetWriter::Raw(_) = self.writer {
+            self.begin_folder()?;
+        }
+        let folder = &mut self.builder.folders[self.current_folder_index];
+        let mut folder_size: u64 = 0;
+        for index in 0..folder.files.len() {
+            let (earlier, later) = folder.files.split_at_mut(index);
+            let file = &mut later[0];
+            match file.duplicate_of {
+                Some(ref name) => {
+                    let original = earlier
+                        .iter()
+                        .find(|file| &file.name == name)
+                        .unwrap();
+                    file.offset_within_folder = original.offset_within_folder;
+                    file.uncompressed_size = original.uncompressed_size;
+                }
+                None => {
+                    if folder_size > (u32::MAX as u64) {
+                        invalid_data!(
+                            "Folder is overfull \
+                             (file offset of {} bytes, max is {} bytes)",
+                            folder_size,
+                            u32::MAX
+                        );
+                    }
+                    file.offset_within_folder = folder_size as u32;
+                    folder_size += file.uncompressed_size as u64;
+                }
+            }
+        }
+        let written_size = match self.writer {
+            InnerCabinetWriter::Folder(ref folder_writer) => {
+                folder_writer.uncompressed_size
+            }
+            _ => unreachable!(),
+        };
+        if written_size != folder_size {
+            invalid_input!(
+                "Precompressed folder {} has {} bytes of data in its data \
+                 blocks, but its files total {} bytes",
+                self.current_folder_index,
+                written_size,
+                folder_size
+            );
+        }
+        self.next_file_index = folder.files.len();
+        self.offset_within_folder = folder_size;
+        self.end_folder()
+    }
+
+    /// If the next file was added with
+    /// [`FolderBuilder::add_duplicate_file`], points it at its original's
+    /// data and moves past it, returning true.
+    fn skip_duplicate_file(&mut self) -> bool {
+        let folder = &mut self.builder.folders[self.current_folder_index];
+        let (earlier, later) = folder.files.split_at_mut(self.next_file_index);
+        let file = &mut later[0];
+        let original = match file.duplicate_of {
+            Some(ref name) => {
+                earlier.iter().find(|earlier| &earlier.name == name).unwrap()
+            }
+            None => return false,
+        };
+        file.offset_within_folder = original.offset_within_folder;
+        file.uncompressed_size = original.uncompressed_size;
+        self.next_file_index += 1;
+        true
+    }
+
+    fn end_file(&mut self) -> io::Result<()> {
+        let folder = &mut self.builder.folders[self.current_folder_index];
+        let deduplicate = folder.deduplicate;
+        let file = &mut folder.files[self.next_file_index - 1];
+        if file.duplicate_of.is_some() {
+            return Ok(());
+        }
+        if let Some(expected_size) = file.expected_size {
+            if file.uncompressed_size as u64 != expected_size {
+                invalid_input!(
+                    "File {:?} was expected to be {} bytes, but only {} \
+                     bytes were written",
+                    file.name,
+                    expected_size,
+                    file.uncompressed_size
+                );
+            }
+        }
+        if deduplicate {
+            let hash = content_hash(&self.dedup.buffer);
+            if let Some(offset) = self.dedup.find(hash) {
+                file.offset_within_folder = offset;
+                self.dedup.buffer.clear();
+                return Ok(());
+            }
+            match self.writer {
+                InnerCabinetWriter::Folder(ref mut folder_writer) => {
+                    folder_writer.write_all(&self.dedup.buffer)?;
                 }
                 _ => unreachable!(),
             }
-            self.current_folder_index += 1;
-            self.next_file_index = 0;
-            self.offset_within_folder = 0;
+            let data = std::mem::take(&mut self.dedup.buffer);
+            let offset = file.offset_within_folder;
+            self.dedup.contents.entry(hash).or_default().push((offset, data));
+        }
+        self.offset_within_folder += file.uncompressed_size as u64;
+        Ok(())
+    }
+
+    fn end_folder(&mut self) -> io::Result<()> {
+        match self.writer.take() {
+            InnerCabinetWriter::Folder(mut folder_writer) => {
+                let folder = &self.builder.folders[self.current_folder_index];
+                folder_writer.finish_blocks()?;
+                self.report.folders.push(FolderReport {
+                    index: folder_writer.folder_index,
+                    compression_type: folder_writer.compression_type,
+                    uncompressed_size: self.offset_within_folder,
+                    compressed_size: folder_writer.compressed_size(),
+                    num_data_blocks: folder_writer.num_data_blocks,
+                    stored_blocks: folder_writer.stored_blocks,
+                    files: folder
+                        .files
+                        .iter()
+                        .map(|file| FileSpan {
+                            name: file.name.clone(),
+                            offset: file.offset_within_folder,
+                            size: file.uncompressed_size,
+                        })
+                        .collect(),
+                });
+                // When appending, file entries are written out in full at the
+                // very end, rather than patched here.  And entries written
+                // up front are already final, unless an earlier folder was
+                // split (changing this folder's index).
+                let files: &[FileBuilder] = if self.append.is_some()
+                    || folder.entries_declared && !self.folder_layout.any_split
+                {
+                    &[]
+                } else {
+                    &folder.files
+                };
+                let writer = folder_writer
+                    .finish(files, self.current_folder_index as u16)?;
+                self.writer = InnerCabinetWriter::Raw(writer);
+            }
+            _ => unreachable!(),
+        }
+        self.current_folder_index += 1;
+        self.next_file_index = 0;
+        self.offset_within_folder = 0;
+        self.dedup.contents.clear();
+        Ok(())
+    }
+
+    /// Flushes all completed folders to the underlying writer, and returns a
+    /// token recording the progress so far, which can later be passed to
+    /// [`CabinetBuilder::resume`] if writing the cabinet gets interrupted.
+    ///
+    /// If all the files in the current folder have been started, that folder
+    /// is completed first.  Otherwise, the checkpoint refers to the start of
+    /// the current folder, which will be rewritten in full when resuming.
+    /// Checkpoints are not supported when appending to an existing cabinet.
+    pub fn checkpoint(&mut self) -> io::Result<CheckpointToken> {
+        if self.append.is_some() {
+            invalid_input!(
+                "Checkpoints are not supported when appending to a cabinet"
+            );
+        }
+        if let InnerCabinetWriter::Folder(_) = self.writer {
+            // A precompressed folder is only complete once moved past; until
+            // then, resuming restarts it from its first data block.
+            let folder = &self.builder.folders[self.current_folder_index];
+            if !folder.precompressed
+                && self.next_file_index > 0
+                && self.next_file_index == folder.files.len()
+            {
+                self.end_file()?;
+                self.end_folder()?;
+            }
+        }
+        let data_offset = match self.writer {
+            InnerCabinetWriter::Raw(ref mut writer) => {
+                writer.flush()?;
+                writer.stream_position()?
+            }
+            InnerCabinetWriter::Folder(ref mut folder_writer) => {
+                folder_writer.flush()?;
+                folder_writer.first_data_block_offset as u64
+            }
+            InnerCabinetWriter::None => unreachable!(),
+        };
+        let layout = &self.folder_layout;
+        Ok(CheckpointToken {
+            fingerprint: layout.fingerprint,
+            first_folder_offset: layout.first_folder_offset,
+            folder_entry_size: layout.folder_entry_size,
+            folder_reserve_size: layout.folder_reserve_size as u32,
+            first_file_offset: layout.first_file_offset,
+            data_offset,
+            folder_file_counts: self.builder.folders
+                [..self.current_folder_index]
+                .iter()
+                .map(|folder| folder.files.len() as u32)
+                .collect(),
+        })
+    }
+
+    fn resume(
+        mut writer: W,
+        mut builder: CabinetBuilder,
+        token: &CheckpointToken,
+    ) -> io::Result<CabinetWriter<W>> {
+        let renamed_files = builder.resolve_duplicate_names()?;
+        if builder.fingerprint() != token.fingerprint {
+            invalid_input!(
+                "Cabinet builder does not match the checkpoint token"
+            );
+        }
+        // Re-apply any folder splits that happened before the checkpoint:
+        let mut any_split = false;
+        for (index, &count) in token.folder_file_counts.iter().enumerate() {
+            let count = count as usize;
+            let num_files = match builder.folders.get(index) {
+                Some(folder) => folder.files.len(),
+                None => invalid_input!(
+                    "Checkpoint token has more folders than the builder"
+                ),
+            };
+            if count > num_files || builder.folders[index].files.is_empty() {
+                invalid_input!(
+                    "Cabinet builder does not match the checkpoint token"
+                );
+            }
+            if count < num_files {
+                let new_folder = builder.folders[index].split_off(count);
+                builder.folders.insert(index + 1, new_folder);
+                any_split = true;
+            }
+        }
+        // Recompute where each folder and file entry lives:
+        let mut file_offset = token.first_file_offset as u64;
+        for (index, folder) in builder.folders.iter_mut().enumerate() {
+            folder.entry_offset = token.first_folder_offset
+                + (index as u32) * token.folder_entry_size;
+            for file in folder.files.iter_mut() {
+                file.entry_offset = file_offset;
+                file_offset += 17 + file.name.len() as u64;
+            }
+        }
+        writer.seek(SeekFrom::Start(token.data_offset))?;
+        Ok(CabinetWriter {
+            writer: InnerCabinetWriter::Raw(writer),
+            builder,
+            current_folder_index: token.folder_file_counts.len(),
+            next_file_index: 0,
+            offset_within_folder: 0,
+            append: None,
+            folder_layout: FolderLayout {
+                first_folder_offset: token.first_folder_offset,
+                folder_entry_size: token.folder_entry_size,
+                folder_reserve_size: token.folder_reserve_size as usize,
+                first_file_offset: token.first_file_offset,
+                fingerprint: token.fingerprint,
+                any_split,
+            },
+            dedup: DedupState::default(),
+            report: CabinetReport::default(),
+            renamed_files,
+            failed: false,
+            require_finish: false,
+        })
+    }
+
+    /// Returns the index of the folder currently being written (or that will
+    /// be written next).  Note that if folders get split (see
+    /// [`FolderBuilder::set_max_compressed_size`]), later folders' indices
+    /// shift accordingly.
+    pub fn current_folder_index(&self) -> usize {
+        self.current_folder_index
+    }
+
+    /// Returns the file most recently returned by
+    /// [`next_file`](CabinetWriter::next_file), or `None` if `next_file`
+    /// hasn't been called yet or all files are complete.
+    pub fn current_file(&self) -> Option<&FileBuilder> {
+        if self.next_file_index == 0 {
+            return None;
+        }
+        self.builder
+            .folders
+            .get(self.current_folder_index)
+            .and_then(|folder| folder.files.get(self.next_file_index - 1))
+    }
+
+    /// Returns the number of files that have been started so far (that is,
+    /// returned by [`next_file`](CabinetWriter::next_file)).
+    pub fn files_started(&self) -> usize {
+        let earlier: usize = self.builder.folders[..self.current_folder_index]
+            .iter()
+            .map(|folder| folder.files.len())
+            .sum();
+        earlier + self.next_file_index
+    }
+
+    /// Returns the number of files that have yet to be returned by
+    /// [`next_file`](CabinetWriter::next_file).
+    pub fn files_remaining(&self) -> usize {
+        self.total_num_files() - self.files_started()
+    }
+
+    fn total_num_files(&self) -> usize {
+        self.builder.folders.iter().map(|folder| folder.files.len()).sum()
+    }
+
+    /// Returns an iterator over all files in the cabinet, in the order that
+    /// they will be returned by [`next_file`](CabinetWriter::next_file),
+    /// along with the index of the folder that each file is in.
+    pub fn planned_files(
+        &self,
+    ) -> impl Iterator<Item = (usize, &FileBuilder)> + '_ {
+        self.builder.folders.iter().enumerate().flat_map(|(index, folder)| {
+            folder.files.iter().map(move |file| (index, file))
+        })
+    }
+
+    /// Returns the files that were renamed to avoid duplicate names (see
+    /// [`DuplicateNames::Rename`]), as `(original, renamed)` pairs, in the
+    /// order they will be written.
+    pub fn renamed_files(&self) -> &[(String, String)] {
+        &self.renamed_files
+    }
+
+    /// Sets whether, in debug builds, dropping this writer while files
+    /// remain to be written (without an error having occurred while writing)
+    /// should panic, rather than quietly writing those files as empty
+    /// files.  This catches callers that forget to finish the cabinet, but
+    /// it also catches callers that return early (e.g. with `?`) after an
+    /// error of their own, so it's off by default.
+    pub fn set_require_finish(&mut self, require_finish: bool) {
+        self.require_finish = require_finish;
+    }
+
+    /// If the current folder has reached its compressed size limit (or the
+    /// largest uncompressed size whose end a file entry can point to) and
+    /// still has files left to write, moves those files into a new folder.
+    fn split_folder_if_full(&mut self) -> io::Result<()> {
+        let folder = &self.builder.folders[self.current_folder_index];
+        // Only folders with room reserved for splitting can be split.
+        if folder.max_num_splits() <= 1
+            || self.next_file_index >= folder.files.len()
+        {
+            return Ok(());
+        }
+        let compressed_size = match self.writer {
+            InnerCabinetWriter::Folder(ref folder_writer) => {
+                folder_writer.compressed_size()
+            }
+            _ => unreachable!(),
+        };
+        let over_limit = folder
+            .max_compressed_size
+            .is_some_and(|max_size| compressed_size >= max_size as u64);
+        // A file can't start past the largest offset a file entry can hold,
+        // so continue in a new folder instead:
+        let overfull = self.offset_within_folder > u32::MAX as u64;
+        if !over_limit && !overfull {
+            return Ok(());
+        }
+        self.split_current_folder()?;
+        Ok(())
+    }
+
+    /// If the current folder is set to split on incompressible files, and
+    /// the file just finished marks the start or end of a run of
+    /// incompressible files, moves the folder's remaining files into a new
+    /// folder with the appropriate compression type.
+    fn split_folder_if_incompressible(&mut self) -> io::Result<()> {
+        let folder = &self.builder.folders[self.current_folder_index];
+        if !folder.split_incompressible
+            || self.next_file_index >= folder.files.len()
+        {
+            return Ok(());
+        }
+        // Only compressed (or monitored) data is measured, so this is `None`
+        // for folders of other compression types:
+        let ratio = match self.writer {
+            InnerCabinetWriter::Folder(ref folder_writer) => {
+                folder_writer.file_ratio()
+            }
+            _ => unreachable!(),
+        };
+        let incompressible = match ratio {
+            Some(ratio) => ratio >= INCOMPRESSIBLE_RATIO,
+            None => return Ok(()),
+        };
+        match folder.incompressible_run_of {
+            None if incompressible => {
+                let ctype = folder.compression_type;
+                let new_folder = self.split_current_folder()?;
+                new_folder.compression_type = CompressionType::None;
+                new_folder.incompressible_run_of = Some(ctype);
+            }
+            Some(ctype) if !incompressible => {
+                let new_folder = self.split_current_folder()?;
+                new_folder.compression_type = ctype;
+                new_folder.incompressible_run_of = None;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Moves the current folder's remaining files into a new folder with the
+    /// same settings, which is returned.
+    fn split_current_folder(&mut self) -> io::Result<&mut FolderBuilder> {
+        if self.builder.folders.len() >= consts::MAX_NUM_FOLDERS {
+            invalid_input!(
+                "Cabinet has too many folders (max is {})",
+                consts::MAX_NUM_FOLDERS
+            );
+        }
+        let folder = &mut self.builder.folders[self.current_folder_index];
+        let new_folder = folder.split_off(self.next_file_index);
+        self.builder.folders.insert(self.current_folder_index + 1, new_folder);
+        self.folder_layout.any_split = true;
+        Ok(&mut self.builder.folders[self.current_folder_index + 1])
+    }
+
+    /// Writes the data for every remaining file, copying it from the
+    /// reader that `provider` returns when given the file's name (as it was
+    /// added to the builder, i.e. its
+    /// [`original_name`](FileWriter::original_name)), and then finishes
+    /// writing the cabinet file and returns the underlying writer.  This
+    /// takes the place of the usual loop over
+    /// [`next_file`](CabinetWriter::next_file) followed by
+    /// [`finish`](CabinetWriter::finish).
+    ///
+    /// ```no_run
+    /// use std::fs::File;
+    /// let mut builder = cab::CabinetBuilder::new();
+    /// let folder = builder.add_folder(cab::CompressionType::MsZip);
+    /// folder.add_file("README.txt");
+    /// folder.add_file("license.txt");
+    /// let cab_writer = builder.build(File::create("docs.cab")?)?;
+    /// cab_writer.write_all_files(|name| File::open(name))?;
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn write_all_files<F, S>(mut self, mut provider: F) -> io::Result<W>
+    where
+        F: FnMut(&str) -> io::Result<S>,
+        S: Read,
+    {
+        while let Some(mut file_writer) = self.next_file()? {
+            let result = provider(file_writer.original_name()).and_then(
+                |mut reader| io::copy(&mut reader, &mut file_writer),
+            );
+            if let Err(error) = result {
+                self.failed = true;
+                return Err(error);
+            }
+        }
+        self.finish()
+    }
+
+    /// Finishes writing the cabinet file, and returns the underlying writer.
+    pub fn finish(self) -> io::Result<W> {
+        self.finalize().map(FinishedCabinet::into_inner)
+    }
+
+    /// Finishes writing the cabinet file, and returns the underlying writer
+    /// along with a report of the uncompressed and compressed sizes of each
+    /// folder written, and where each file lies within its folder.
+    pub fn finish_with_report(self) -> io::Result<(W, CabinetReport)> {
+        self.finalize().map(FinishedCabinet::into_parts)
+    }
+
+    /// Finishes writing the cabinet file, like
+    /// [`finish`](CabinetWriter::finish), but returns the underlying writer
+    /// wrapped in a [`FinishedCabinet`], along with the cabinet's total size
+    /// and a report of the folders written.
+    pub fn finalize(mut self) -> io::Result<FinishedCabinet<W>> {
+        let total_size = self.shutdown()?;
+        let report = mem::take(&mut self.report);
+        match self.writer.take() {
+            InnerCabinetWriter::Raw(writer) => {
+                Ok(FinishedCabinet { writer, report, total_size })
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the underlying writer without finishing the cabinet file,
+    /// leaving it incomplete: the files not yet written, and the folder
+    /// entries and cabinet header fields that are filled in at the end, are
+    /// left out.  This is for abandoning a cabinet on purpose; unlike
+    /// dropping the `CabinetWriter`, it never writes anything more.
+    pub fn into_inner_unfinished(mut self) -> W {
+        match self.writer.take() {
+            InnerCabinetWriter::Raw(writer) => writer,
+            InnerCabinetWriter::Folder(folder_writer) => folder_writer.writer,
+            InnerCabinetWriter::None => unreachable!(),
+        }
+    }
+
+    /// Finishes writing the cabinet file, returning its total size.
+    fn shutdown(&mut self) -> io::Result<u64> {
+        while (self.next_file()?).is_some() {}
+        match self.writer {
+            InnerCabinetWriter::Raw(ref mut writer) => {
+                if let Some(append) = self.append.take() {
+                    finish_append(writer, &self.builder, append)?;
+                } else if self.folder_layout.any_split {
+                    writer.seek(SeekFrom::Start(26))?;
+                    let num_folders = self.builder.folders.len() as u16;
+                    writer.write_u16::<LittleEndian>(num_folders)?;
+                    writer.seek(SeekFrom::End(0))?;
+                }
+                let cabinet_file_size = writer.stream_position()?;
+                if cabinet_file_size > (consts::MAX_TOTAL_CAB_SIZE as u64) {
+                    invalid_data!(
+                        "Cabinet file is too large \
+                         ({} bytes; max is {} bytes)",
+                        cabinet_file_size,
+                        consts::MAX_TOTAL_CAB_SIZE
+                    );
+                }
+                writer.seek(SeekFrom::Start(8))?;
+                writer.write_u32::<LittleEndian>(cabinet_file_size as u32)?;
+                writer.seek(SeekFrom::End(0))?;
+                writer.flush()?;
+                Ok(cabinet_file_size)
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl CabinetWriter<Spool> {
+    /// Finishes writing a cabinet file that was built into a [`Spool`], then
+    /// copies the whole cabinet into the given (possibly non-seekable) sink,
+    /// and returns the sink.  Any temporary file used by the spool is
+    /// deleted afterwards.
+    pub fn finish_to<S: Write>(self, mut sink: S) -> io::Result<S> {
+        let mut spool = self.finish()?;
+        spool.copy_to(&mut sink)?;
+        sink.flush()?;
+        Ok(sink)
+    }
+}
+
+impl<W: Read + Write + Seek> CabinetWriter<W> {
+    /// Finishes writing the cabinet file, then reads it back from the
+    /// underlying writer to check that it parses correctly, that every data
+    /// block's checksum matches and decompresses successfully, and that every
+    /// file lies within its folder's data.  Returns the underlying writer,
+    /// positioned at the end of the cabinet.
+    pub fn finish_and_verify(self) -> io::Result<W> {
+        let transform = self.builder.block_transform.clone();
+        let mut writer = self.finish()?;
+        writer.seek(SeekFrom::Start(0))?;
+        let mut cabinet = Cabinet::new(&mut writer)?;
+        cabinet.set_block_transform(transform);
+        verify_cabinet(&mut cabinet)?;
+        drop(cabinet);
+        writer.seek(SeekFrom::End(0))?;
+        Ok(writer)
+    }
+
+    fn start_append(
+        mut writer: W,
+        mut builder: CabinetBuilder,
+        info: AppendInfo,
+    ) -> io::Result<CabinetWriter<W>> {
+        for folder in builder.folders.iter() {
+            folder.validate()?;
+        }
+        if info.data_reserve_size != 0 {
+            invalid_input!(
+                "Appending to cabinets with data block reserve data is not \
+                 supported"
+            );
+        }
+        if !builder.reserve_data.is_empty() {
+            invalid_input!(
+                "Cannot change header reserve data when appending to a cabinet"
+            );
+        }
+        let num_folders = info.num_folders + builder.folders.len();
+        if num_folders > consts::MAX_NUM_FOLDERS {
+            invalid_input!(
+                "Cabinet has too many folders ({}; max is {})",
+                num_folders,
+                consts::MAX_NUM_FOLDERS
+            );
+        }
+        let num_files = info.num_files
+            + builder
+                .folders
+                .iter()
+                .map(|folder| folder.files.len())
+                .sum::<usize>();
+        if num_files > consts::MAX_NUM_FILES {
+            invalid_input!(
+                "Cabinet has too many files ({}; max is {})",
+                num_files,
+                consts::MAX_NUM_FILES
+            );
+        }
+        let folder_reserve_size = info.folder_reserve_size as usize;
+        // Only one folder entry is reserved per new folder, so folders that
+        // might be split (by a size limit or on incompressible data) can't be
+        // appended.
+        if builder.folders.iter().any(|f| f.max_num_splits() > 1) {
+            invalid_input!(
+                "Folder compressed size limits and incompressible splitting \
+                 are not supported when appending to a cabinet"
+            );
+        }
+        for folder in builder.folders.iter() {
+            if folder.reserve_data.len() > folder_reserve_size {
+                invalid_input!(
+                    "Folder reserve data is too large for existing cabinet \
+                     ({} bytes; max is {} bytes)",
+                    folder.reserve_data.len(),
+                    folder_reserve_size
+                );
+            }
+        }
+
+        // Save the existing file entries, which we'll rewrite at the end:
+        let file_entries_size = match info.file_entries_size {
+            Some(size) => size,
+            None => measure_file_entries(
+                &mut writer,
+                info.first_file_offset,
+                info.num_files,
+            )?,
+        };
+        writer.seek(SeekFrom::Start(info.first_file_offset as u64))?;
+        let mut old_file_entries = vec![0u8; file_entries_size as usize];
+        writer.read_exact(&mut old_file_entries)?;
+
+        // Figure out where there's room for the new folder entries:
+        let folder_entry_size = 8 + folder_reserve_size as u64;
+        let free_start = info.first_folder_offset as u64
+            + (info.num_folders as u64) * folder_entry_size;
+        let data_start = info
+            .folder_data_offsets
+            .iter()
+            .filter(|&&(_, num_blocks)| num_blocks > 0)
+            .map(|&(offset, _)| offset as u64)
+            .min()
+            .unwrap_or(info.total_size as u64);
+        if data_start < free_start {
+            invalid_data!("Cabinet data blocks overlap folder entries");
+        }
+        let required = (builder.folders.len() as u64) * folder_entry_size;
+        let shift = required.saturating_sub(data_start - free_start);
+        if shift > 0 {
+            // Work out the shifted data offsets before moving anything, so
+            // that an overflow leaves the cabinet untouched.
+            let mut shifted_offsets = Vec::new();
+            for (index, &(offset, num_blocks)) in
+                info.folder_data_offsets.iter().enumerate()
+            {
+                if num_blocks == 0 || (offset as u64) < data_start {
+                    continue;
+                }
+                let shifted = match u32::try_from(shift)
+                    .ok()
+                    .and_then(|shift| offset.checked_add(shift))
+                {
+                    Some(shifted) => shifted,
+                    None => invalid_data!(
+                        "Data for folder {} would be moved past the maximum \
+                         cabinet size",
+                        index
+                    ),
+                };
+                shifted_offsets.push((index, shifted));
+            }
+            shift_forward(
+                &mut writer,
+                data_start,
+                info.total_size as u64,
+                shift,
+            )?;
+            for (index, shifted) in shifted_offsets {
+                let entry_offset = info.first_folder_offset as u64
+                    + (index as u64) * folder_entry_size;
+                writer.seek(SeekFrom::Start(entry_offset))?;
+                writer.write_u32::<LittleEndian>(shifted)?;
+            }
+        }
+
+        // Write structs for new folders, and clear any leftover space:
+        writer.seek(SeekFrom::Start(free_start))?;
+        for (index, folder) in builder.folders.iter_mut().enumerate() {
+            folder.entry_offset =
+                (free_start + (index as u64) * folder_entry_size) as u32;
+            write_folder_entry(&mut writer, folder, folder_reserve_size)?;
+        }
+        let padding = (data_start + shift) - (free_start + required);
+        if padding > 0 {
+            io::copy(&mut io::repeat(0).take(padding), &mut writer)?;
+        }
+        writer.seek(SeekFrom::Start(info.total_size as u64 + shift))?;
+
+        Ok(CabinetWriter {
+            writer: InnerCabinetWriter::Raw(writer),
+            builder,
+            current_folder_index: 0,
+            next_file_index: 0,
+            offset_within_folder: 0,
+            append: Some(AppendState {
+                old_file_entries,
+                old_num_folders: info.num_folders,
+                old_num_files: info.num_files,
+            }),
+            folder_layout: FolderLayout {
+                first_folder_offset: free_start as u32,
+                folder_entry_size: folder_entry_size as u32,
+                folder_reserve_size,
+                first_file_offset: 0, // unused when appending
+                fingerprint: 0,       // unused when appending
+                any_split: false,
+            },
+            dedup: DedupState::default(),
+            report: CabinetReport::default(),
+            renamed_files: Vec::new(),
+            failed: false,
+            require_finish: false,
+        })
+    }
+}
+
+impl<W: Write + Seek> Drop for CabinetWriter<W> {
+    fn drop(&mut self) {
+        if !self.writer.is_none() {
+            let unwritten = self.files_remaining();
+            let failed = self.failed;
+            let result = self.shutdown();
+            // Writing the remaining files as empty files is almost never
+            // what the caller wanted, unless something already went wrong.
+            debug_assert!(
+                !self.require_finish
+                    || failed
+                    || unwritten == 0
+                    || result.is_err()
+                    || std::thread::panicking(),
+                "CabinetWriter dropped with {} file(s) not yet written; \
+                 call finish() to complete the cabinet",
+                unwritten
+            );
         }
-        Ok(None)
     }
+}
 
-    /// Finishes writing the cabinet file, and returns the underlying writer.
-    pub fn finish(mut self) -> io::Result<W> {
-        self.shutdown()?;
-        match self.writer.take() {
-            InnerCabinetWriter::Raw(writer) => Ok(writer),
-            _ => unreachable!(),
+impl<W> FinishedCabinet<W> {
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Returns the underlying writer, along with the report of the folders
+    /// written.
+    pub fn into_parts(self) -> (W, CabinetReport) {
+        (self.writer, self.report)
+    }
+
+    /// Returns a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    /// Returns a mutable reference to the underlying writer.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.writer
+    }
+
+    /// Returns a report of the uncompressed and compressed sizes of each
+    /// folder written, and where each file lies within its folder.
+    pub fn report(&self) -> &CabinetReport {
+        &self.report
+    }
+
+    /// Returns the total size of the cabinet file, in bytes.
+    pub fn total_size(&self) -> u64 {
+        self.total_size
+    }
+}
+
+fn write_folder_entry<W: Write>(
+    writer: &mut W,
+    folder: &FolderBuilder,
+    folder_reserve_size: usize,
+) -> io::Result<()> {
+    writer.write_u32::<LittleEndian>(0)?; // first data, filled later
+    writer.write_u16::<LittleEndian>(0)?; // num data, filled later
+    let ctype_bits = folder.compression_bits();
+    writer.write_u16::<LittleEndian>(ctype_bits)?;
+    debug_assert!(folder.reserve_data.len() <= folder_reserve_size);
+    if folder_reserve_size > 0 {
+        writer.write_all(&folder.reserve_data)?;
+        let padding = folder_reserve_size - folder.reserve_data.len();
+        if padding > 0 {
+            writer.write_all(&vec![0; padding])?;
         }
     }
+    Ok(())
+}
 
-    fn shutdown(&mut self) -> io::Result<()> {
-        while (self.next_file()?).is_some() {}
-        match self.writer {
-            InnerCabinetWriter::Raw(ref mut writer) => {
-                let cabinet_file_size = writer.stream_position()?;
-                if cabinet_file_size > (consts::MAX_TOTAL_CAB_SIZE as u64) {
-                    invalid_data!(
-                        "Cabinet file is too large \
-                         ({} bytes; max is {} bytes)",
-                        cabinet_file_size,
-                        consts::MAX_TOTAL_CAB_SIZE
-                    );
-                }
-                writer.seek(SeekFrom::Start(8))?;
-                writer.write_u32::<LittleEndian>(cabinet_file_size as u32)?;
-                writer.seek(SeekFrom::End(0))?;
-                writer.flush()?;
-            }
-            _ => unreachable!(),
-        };
-        Ok(())
+fn write_file_entry<W: Write>(
+    writer: &mut W,
+    file: &FileBuilder,
+    folder_index: usize,
+) -> io::Result<()> {
+    writer.write_u32::<LittleEndian>(file.uncompressed_size)?;
+    writer.write_u32::<LittleEndian>(file.offset_within_folder)?;
+    writer.write_u16::<LittleEndian>(folder_index as u16)?;
+    let (date, time) = file.datetime_bits;
+    writer.write_u16::<LittleEndian>(date)?;
+    writer.write_u16::<LittleEndian>(time)?;
+    writer.write_u16::<LittleEndian>(file.attributes)?;
+    writer.write_all(file.name.as_bytes())?;
+    writer.write_u8(0)?;
+    Ok(())
+}
+
+/// Writes out all file entries (old and new) at the current end of an
+/// appended-to cabinet, and updates the header to match.
+fn finish_append<W: Write + Seek>(
+    writer: &mut W,
+    builder: &CabinetBuilder,
+    append: AppendState,
+) -> io::Result<()> {
+    let first_file_offset = writer.stream_position()?;
+    if first_file_offset > (consts::MAX_TOTAL_CAB_SIZE as u64) {
+        invalid_data!(
+            "Cabinet file is too large \
+             (already {} bytes; max is {} bytes)",
+            first_file_offset,
+            consts::MAX_TOTAL_CAB_SIZE
+        );
     }
+    writer.write_all(&append.old_file_entries)?;
+    let mut num_files = append.old_num_files;
+    for (index, folder) in builder.folders.iter().enumerate() {
+        for file in folder.files.iter() {
+            write_file_entry(writer, file, append.old_num_folders + index)?;
+            num_files += 1;
+        }
+    }
+    let end = writer.stream_position()?;
+    let num_folders = append.old_num_folders + builder.folders.len();
+    writer.seek(SeekFrom::Start(16))?;
+    writer.write_u32::<LittleEndian>(first_file_offset as u32)?;
+    writer.seek(SeekFrom::Start(26))?;
+    writer.write_u16::<LittleEndian>(num_folders as u16)?;
+    writer.write_u16::<LittleEndian>(num_files as u16)?;
+    writer.seek(SeekFrom::Start(end))?;
+    Ok(())
 }
 
-impl<W: Write + Seek> Drop for CabinetWriter<W> {
-    fn drop(&mut self) {
-        if !self.writer.is_none() {
-            let _ = self.shutdown();
+/// Reads through all the data in a cabinet, returning an error if anything
+/// fails to decode or if any file extends past the end of its folder.
+fn verify_cabinet<R: Read + Seek>(cabinet: &mut Cabinet<R>) -> io::Result<()> {
+    let folder_files: Vec<Vec<(String, u64)>> = cabinet
+        .folder_entries()
+        .map(|folder| {
+            folder
+                .file_entries()
+                .map(|file| {
+                    let end = file.uncompressed_offset as u64
+                        + file.uncompressed_size() as u64;
+                    (file.name().to_string(), end)
+                })
+                .collect()
+        })
+        .collect();
+    for (index, files) in folder_files.into_iter().enumerate() {
+        let mut reader = cabinet.read_folder(index)?;
+        let folder_size = io::copy(&mut reader, &mut io::sink())?;
+        for (name, end) in files {
+            if end > folder_size {
+                invalid_data!(
+                    "File {:?} extends past the end of folder {} \
+                     ({} bytes; folder has {} bytes)",
+                    name,
+                    index,
+                    end,
+                    folder_size
+                );
+            }
         }
     }
+    Ok(())
+}
+
+/// Moves the bytes in `start..end` forward by `shift` bytes, working
+/// backwards from the end so as not to overwrite anything before it's moved.
+fn shift_forward<W: Read + Write + Seek>(
+    writer: &mut W,
+    start: u64,
+    end: u64,
+    shift: u64,
+) -> io::Result<()> {
+    let mut buffer = vec![0u8; 0x10000];
+    let mut chunk_end = end;
+    while chunk_end > start {
+        let chunk_start =
+            chunk_end.saturating_sub(buffer.len() as u64).max(start);
+        let chunk = &mut buffer[..((chunk_end - chunk_start) as usize)];
+        writer.seek(SeekFrom::Start(chunk_start))?;
+        writer.read_exact(chunk)?;
+        writer.seek(SeekFrom::Start(chunk_start + shift))?;
+        writer.write_all(chunk)?;
+        chunk_end = chunk_start;
+    }
+    Ok(())
 }
 
 /// Allows writing data for a single file within a new cabinet.
 pub struct FileWriter<'a, W: 'a + Write + Seek> {
     folder_writer: &'a mut FolderWriter<W>,
     file_builder: &'a mut FileBuilder,
+    dedup_buffer: Option<&'a mut Vec<u8>>,
+    /// The `CabinetWriter`'s record of whether writing has failed.
+    failed: &'a mut bool,
 }
 
 impl<'a, W: Write + Seek> FileWriter<'a, W> {
     fn new(
         folder_writer: &'a mut FolderWriter<W>,
         file_builder: &'a mut FileBuilder,
+        dedup_buffer: Option<&'a mut Vec<u8>>,
+        failed: &'a mut bool,
     ) -> FileWriter<'a, W> {
-        FileWriter { folder_writer, file_builder }
+        FileWriter { folder_writer, file_builder, dedup_buffer, failed }
     }
 
     /// Returns the name of the file being written.
     pub fn file_name(&self) -> &str {
         &self.file_builder.name
     }
+
+    /// Returns the name that the file being written was originally added
+    /// with, before being renamed to avoid a duplicate name (see
+    /// [`DuplicateNames::Rename`]); for files that weren't renamed, this is
+    /// the same as [`file_name`](FileWriter::file_name).
+    pub fn original_name(&self) -> &str {
+        self.file_builder.original_name().unwrap_or(&self.file_builder.name)
+    }
 }
 
 impl<'a, W: Write + Seek> Write for FileWriter<'a, W> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let result = self.write_data(buf);
+        if result.is_err() {
+            *self.failed = true;
+        }
+        result
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.folder_writer.flush()
+    }
+}
+
+impl<'a, W: Write + Seek> FileWriter<'a, W> {
+    fn write_data(&mut self, buf: &[u8]) -> io::Result<usize> {
         if buf.is_empty() {
             return Ok(0);
         }
@@ -467,28 +2783,75 @@ impl<'a, W: Write + Seek> Write for FileWriter<'a, W> {
                 consts::MAX_FILE_SIZE
             );
         }
-        let remaining =
-            consts::MAX_FILE_SIZE - self.file_builder.uncompressed_size;
-        let max_bytes = (buf.len() as u64).min(remaining as u64) as usize;
-        let bytes_written = self.folder_writer.write(&buf[0..max_bytes])?;
+        let written = self.file_builder.uncompressed_size as u64;
+        if let Some(expected_size) = self.file_builder.expected_size {
+            if written >= expected_size {
+                invalid_input!(
+                    "File {:?} is longer than its expected size of {} bytes",
+                    self.file_builder.name,
+                    expected_size
+                );
+            }
+        }
+        let max_size = self
+            .file_builder
+            .expected_size
+            .unwrap_or(u64::MAX)
+            .min(consts::MAX_FILE_SIZE as u64);
+        let remaining = max_size - written;
+        let max_bytes = (buf.len() as u64).min(remaining) as usize;
+        let bytes_written = match self.dedup_buffer {
+            Some(ref mut buffer) => {
+                buffer.extend_from_slice(&buf[0..max_bytes]);
+                max_bytes
+            }
+            None => self.folder_writer.write(&buf[0..max_bytes])?,
+        };
         self.file_builder.uncompressed_size += bytes_written as u32;
         Ok(bytes_written)
     }
-
-    fn flush(&mut self) -> io::Result<()> {
-        self.folder_writer.flush()
-    }
 }
 
 /// A writer for writer data into a cabinet folder.
 struct FolderWriter<W: Write + Seek> {
     writer: W,
     compressor: FolderCompressor,
+    /// The compression type actually being used, which differs from the
+    /// folder's if it was switched to being stored uncompressed.
+    compression_type: CompressionType,
+    /// The value to write for the folder entry's compression type field.
+    compression_bits: u16,
+    /// While deciding whether to store an incompressible folder
+    /// uncompressed, the blocks compressed so far (which have not yet been
+    /// written).
+    probe: Option<Vec<ProbedBlock>>,
+    /// The number of MSZIP blocks written whose data didn't compress.
+    stored_blocks: u16,
+    /// For uncompressed folders holding a run of incompressible files, a
+    /// fast compressor used only to measure how compressible the data is.
+    monitor: Option<MsZipCompressor>,
+    /// The total uncompressed and compressed (or, with a monitor,
+    /// measured) sizes of the blocks compressed so far.
+    measured: (u64, u64),
+    /// The value of `measured` when the current file began.
+    measured_at_file_start: (u64, u64),
     folder_entry_offset: u32,
     first_data_block_offset: u32,
     next_data_block_offset: u64,
     num_data_blocks: u16,
     data_block_buffer: Vec<u8>,
+    folder_index: usize,
+    transform: Option<Arc<dyn BlockTransform>>,
+    verifier: Option<Decompressor>,
+    cancel: Option<CancelToken>,
+    throttle: Option<Arc<dyn Throttle>>,
+    /// The total uncompressed size of the data blocks written so far.
+    uncompressed_size: u64,
+}
+
+struct ProbedBlock {
+    data: Vec<u8>,
+    compressed: Vec<u8>,
 }
 
 enum FolderCompressor {
@@ -500,8 +2863,12 @@ enum FolderCompressor {
 impl<W: Write + Seek> FolderWriter<W> {
     fn new(
         mut writer: W,
-        compression_type: CompressionType,
-        folder_entry_offset: u32,
+        folder: &FolderBuilder,
+        folder_index: usize,
+        transform: Option<Arc<dyn BlockTransform>>,
+        verify: bool,
+        cancel: Option<CancelToken>,
+        throttle: Option<Arc<dyn Throttle>>,
     ) -> io::Result<FolderWriter<W>> {
         let current_offset = writer.stream_position()?;
         if current_offset > (consts::MAX_TOTAL_CAB_SIZE as u64) {
@@ -512,10 +2879,15 @@ impl<W: Write + Seek> FolderWriter<W> {
                 consts::MAX_TOTAL_CAB_SIZE
             );
         }
-        let compressor = match compression_type {
+        let compressor = match folder.compression_type {
+            // Data blocks for precompressed folders are written as-is.
+            _ if folder.precompressed => FolderCompressor::Uncompressed,
             CompressionType::None => FolderCompressor::Uncompressed,
             CompressionType::MsZip => {
-                FolderCompressor::MsZip(MsZipCompressor::new())
+                let level = folder.compression_effort.deflate_level();
+                let mut compressor = MsZipCompressor::with_level(level);
+                compressor.set_shared_history(folder.mszip_shared_history);
+                FolderCompressor::MsZip(compressor)
             }
             CompressionType::Quantum(_, _) => {
                 invalid_data!("Quantum compression is not yet supported.");
@@ -523,56 +2895,274 @@ impl<W: Write + Seek> FolderWriter<W> {
             CompressionType::Lzx(_) => {
                 invalid_data!("LZX compression is not yet supported.");
             }
+            CompressionType::Unknown(bits) => {
+                invalid_data!("Unknown compression type: 0x{:04x}", bits);
+            }
+        };
+        let verifier = if verify && !folder.precompressed {
+            Some(folder.compression_type.into_decompressor()?)
+        } else {
+            None
+        };
+        let probe = match compressor {
+            FolderCompressor::MsZip(_) if folder.store_if_incompressible => {
+                Some(Vec::with_capacity(INCOMPRESSIBLE_PROBE_BLOCKS))
+            }
+            _ => None,
+        };
+        let monitor = match folder.incompressible_run_of {
+            Some(_) if folder.split_incompressible => {
+                let mut monitor = MsZipCompressor::with_level(1);
+                monitor.set_shared_history(false);
+                Some(monitor)
+            }
+            _ => None,
         };
         Ok(FolderWriter {
             writer,
             compressor,
-            folder_entry_offset,
+            compression_type: folder.compression_type,
+            compression_bits: folder.compression_bits(),
+            probe,
+            stored_blocks: 0,
+            monitor,
+            measured: (0, 0),
+            measured_at_file_start: (0, 0),
+            folder_entry_offset: folder.entry_offset,
             first_data_block_offset: current_offset as u32,
             next_data_block_offset: current_offset,
             num_data_blocks: 0,
             data_block_buffer: Vec::with_capacity(MAX_UNCOMPRESSED_BLOCK_SIZE),
+            folder_index,
+            transform,
+            verifier,
+            cancel,
+            throttle,
+            uncompressed_size: 0,
         })
     }
 
-    fn finish(mut self, files: &[FileBuilder]) -> io::Result<W> {
-        if !self.data_block_buffer.is_empty() {
-            self.write_data_block(true)?;
-        }
+    /// Returns the number of bytes of compressed data (including data block
+    /// headers) written so far for this folder, not counting data that is
+    /// still buffered.
+    fn compressed_size(&self) -> u64 {
+        let probed = self.probe.as_ref().map_or(0, |probe| {
+            probe.iter().map(|block| 8 + block.compressed.len() as u64).sum()
+        });
+        self.next_data_block_offset - self.first_data_block_offset as u64
+            + probed
+    }
+
+    fn finish(
+        mut self,
+        files: &[FileBuilder],
+        folder_index: u16,
+    ) -> io::Result<W> {
+        self.finish_blocks()?;
         let mut writer = self.writer;
         let offset = writer.stream_position()?;
         writer.seek(SeekFrom::Start(self.folder_entry_offset as u64))?;
         writer.write_u32::<LittleEndian>(self.first_data_block_offset)?;
         writer.write_u16::<LittleEndian>(self.num_data_blocks)?;
+        writer.write_u16::<LittleEndian>(self.compression_bits)?;
         for file in files.iter() {
             writer.seek(SeekFrom::Start(file.entry_offset))?;
             writer.write_u32::<LittleEndian>(file.uncompressed_size)?;
             writer.write_u32::<LittleEndian>(file.offset_within_folder)?;
+            writer.write_u16::<LittleEndian>(folder_index)?;
         }
         writer.seek(SeekFrom::Start(offset))?;
         Ok(writer)
     }
 
+    /// Writes out any data still buffered as the folder's last data block.
+    fn finish_blocks(&mut self) -> io::Result<()> {
+        if !self.data_block_buffer.is_empty() {
+            self.write_data_block(true)?;
+        }
+        if self.probe.is_some() {
+            self.end_probe()?;
+        }
+        Ok(())
+    }
+
+    /// Records the start of a new file, for [`file_ratio`](Self::file_ratio).
+    fn mark_file_start(&mut self) {
+        self.measured_at_file_start = self.measured;
+    }
+
+    /// Returns the compression ratio of the blocks compressed (or measured)
+    /// since the current file began, or `None` if too little data has been
+    /// compressed to tell.
+    fn file_ratio(&self) -> Option<f64> {
+        let uncompressed = self.measured.0 - self.measured_at_file_start.0;
+        let compressed = self.measured.1 - self.measured_at_file_start.1;
+        if uncompressed < MIN_MEASURED_SIZE {
+            None
+        } else {
+            Some(compressed as f64 / uncompressed as f64)
+        }
+    }
+
+    /// Decides whether to store the folder uncompressed, based on the blocks
+    /// compressed so far, and writes out those blocks accordingly.
+    fn end_probe(&mut self) -> io::Result<()> {
+        let probe = self.probe.take().unwrap();
+        let data_size: usize =
+            probe.iter().map(|block| block.data.len()).sum();
+        let compressed_size: usize =
+            probe.iter().map(|block| block.compressed.len()).sum();
+        let store = compressed_size >= data_size;
+        if store {
+            self.compressor = FolderCompressor::Uncompressed;
+            self.compression_type = CompressionType::None;
+            self.compression_bits = CompressionType::None.to_bitfield();
+            if self.verifier.is_some() {
+                self.verifier =
+                    Some(CompressionType::None.into_decompressor()?);
+            }
+        }
+        for block in probe {
+            let uncompressed_size = block.data.len() as u16;
+            if store {
+                let original =
+                    self.verifier.as_ref().map(|_| block.data.clone());
+                self.write_block(
+                    block.data,
+                    uncompressed_size,
+                    original.as_deref(),
+                )?;
+            } else {
+                if block.compressed.len() >= block.data.len() {
+                    self.stored_blocks += 1;
+                }
+                let original = self.verifier.as_ref().map(|_| &block.data[..]);
+                self.write_block(
+                    block.compressed,
+                    uncompressed_size,
+                    original,
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the given data block payload decodes back to the original
+    /// uncompressed data.
+    fn verify_block(
+        &mut self,
+        original: &[u8],
+        payload: &[u8],
+    ) -> io::Result<()> {
+        let block_index = self.num_data_blocks as usize;
+        let payload = match self.transform {
+            Some(ref transform) => transform.decode(
+                self.folder_index,
+                block_index,
+                &[],
+                payload.to_vec(),
+            )?,
+            None => payload.to_vec(),
+        };
+        let verifier = self.verifier.as_mut().unwrap();
+        let decoded = verifier.decompress(payload, original.len())?;
+        if decoded != original {
+            invalid_data!(
+                "Data block {} of folder {} failed verification: it does \
+                 not decompress to the data that was written",
+                block_index,
+                self.folder_index
+            );
+        }
+        Ok(())
+    }
+
     fn write_data_block(&mut self, is_last_block: bool) -> io::Result<()> {
         debug_assert!(!self.data_block_buffer.is_empty());
         let uncompressed_size = self.data_block_buffer.len() as u16;
+        let original = match self.verifier {
+            Some(_) if self.probe.is_none() => {
+                Some(self.data_block_buffer.clone())
+            }
+            _ => None,
+        };
         let compressed = match self.compressor {
             FolderCompressor::Uncompressed => {
+                if let Some(ref mut monitor) = self.monitor {
+                    let data = &self.data_block_buffer;
+                    let measured = monitor.compress_block(data, true)?;
+                    self.measured.0 += data.len() as u64;
+                    self.measured.1 += measured.len() as u64;
+                }
                 let empty = Vec::with_capacity(MAX_UNCOMPRESSED_BLOCK_SIZE);
                 mem::replace(&mut self.data_block_buffer, empty)
             }
             FolderCompressor::MsZip(ref mut compressor) => {
                 let compressed = compressor
                     .compress_block(&self.data_block_buffer, is_last_block)?;
+                self.measured.0 += self.data_block_buffer.len() as u64;
+                self.measured.1 += compressed.len() as u64;
+                if let Some(ref mut probe) = self.probe {
+                    let empty =
+                        Vec::with_capacity(MAX_UNCOMPRESSED_BLOCK_SIZE);
+                    let data =
+                        mem::replace(&mut self.data_block_buffer, empty);
+                    probe.push(ProbedBlock { data, compressed });
+                    if probe.len() == INCOMPRESSIBLE_PROBE_BLOCKS {
+                        self.end_probe()?;
+                    }
+                    return Ok(());
+                }
+                if compressed.len() >= self.data_block_buffer.len() {
+                    self.stored_blocks += 1;
+                }
                 self.data_block_buffer.clear();
                 compressed
             }
         };
+        self.write_block(compressed, uncompressed_size, original.as_deref())
+    }
+
+    /// Writes out a data block with the given (compressed) payload.  If
+    /// `original` is given, checks that the payload decodes back to it.
+    fn write_block(
+        &mut self,
+        compressed: Vec<u8>,
+        uncompressed_size: u16,
+        original: Option<&[u8]>,
+    ) -> io::Result<()> {
+        if self.num_data_blocks == u16::MAX {
+            invalid_input!(
+                "Folder has too many data blocks (max is {})",
+                u16::MAX
+            );
+        }
+        if let Some(ref cancel) = self.cancel {
+            cancel.check()?;
+        }
+        let compressed = match self.transform {
+            Some(ref transform) => transform.encode(
+                self.folder_index,
+                self.num_data_blocks as usize,
+                compressed,
+            )?,
+            None => compressed,
+        };
+        if compressed.len() > (u16::MAX as usize) {
+            invalid_data!(
+                "Data block payload is too large ({} bytes; max is {} bytes)",
+                compressed.len(),
+                u16::MAX
+            );
+        }
+        if let Some(original) = original {
+            self.verify_block(original, &compressed)?;
+        }
         let compressed_size = compressed.len() as u16;
         let mut checksum = Checksum::new();
         checksum.update(&compressed);
-        let checksum_value = checksum.value()
-            ^ ((compressed_size as u32) | ((uncompressed_size as u32) << 16));
+        let checksum_value =
+            checksum.data_block_value(compressed_size, uncompressed_size);
         let total_data_block_size = 8 + compressed_size as u64;
         self.writer.seek(SeekFrom::Start(self.next_data_block_offset))?;
         self.writer.write_u32::<LittleEndian>(checksum_value)?;
@@ -581,6 +3171,13 @@ impl<W: Write + Seek> FolderWriter<W> {
         self.writer.write_all(&compressed)?;
         self.next_data_block_offset += total_data_block_size;
         self.num_data_blocks += 1;
+        self.uncompressed_size += uncompressed_size as u64;
+        if let Some(ref throttle) = self.throttle {
+            throttle.throttle(
+                compressed_size as usize,
+                uncompressed_size as usize,
+            );
+        }
         Ok(())
     }
 }
@@ -589,17 +3186,26 @@ impl<W: Write + Seek> Write for FolderWriter<W> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         let capacity = self.data_block_buffer.capacity();
         debug_assert_eq!(capacity, MAX_UNCOMPRESSED_BLOCK_SIZE);
-        if buf.is_empty() {
-            return Ok(0);
-        }
-        if self.data_block_buffer.len() == capacity {
-            self.write_data_block(false)?;
+        // Take in the whole buffer, writing out each block as it fills up
+        // (but leaving the last one buffered, since the next write may add
+        // to it).
+        let mut written = 0;
+        while written < buf.len() {
+            if self.data_block_buffer.len() == capacity {
+                // If this fails, the block has already been taken out of the
+                // buffer, so the error must be reported now (even if some of
+                // `buf` was taken in) rather than losing the block's data.
+                self.write_data_block(false)?;
+            }
+            let remaining = &buf[written..];
+            let max_bytes =
+                remaining.len().min(capacity - self.data_block_buffer.len());
+            debug_assert!(max_bytes > 0);
+            self.data_block_buffer.extend_from_slice(&remaining[..max_bytes]);
+            debug_assert_eq!(self.data_block_buffer.capacity(), capacity);
+            written += max_bytes;
         }
-        let max_bytes = buf.len().min(capacity - self.data_block_buffer.len());
-        debug_assert!(max_bytes > 0);
-        self.data_block_buffer.extend_from_slice(&buf[..max_bytes]);
-        debug_assert_eq!(self.data_block_buffer.capacity(), capacity);
-        Ok(max_bytes)
+        Ok(written)
     }
 
     fn flush(&mut self) -> io::Result<()> {
@@ -609,12 +3215,100 @@ impl<W: Write + Seek> Write for FolderWriter<W> {
 
 #[cfg(test)]
 mod tests {
-    use super::CabinetBuilder;
+    use super::{split_extension, CabinetBuilder, DedupState};
+    use crate::cabinet::Cabinet;
     use crate::ctype::CompressionType;
-    use std::io::{Cursor, Write};
+    use lzxd::WindowSize;
+    use std::io::{Cursor, Read, Write};
+    #[cfg(feature = "time")]
     use time::macros::datetime;
 
     #[test]
+    fn checkpoint_within_precompressed_folder_without_files() {
+        let mut builder = CabinetBuilder::new();
+        builder.add_folder(CompressionType::None).set_precompressed(true);
+        let mut cab_writer = builder.build(Cursor::new(Vec::new())).unwrap();
+        cab_writer.write_precompressed_block(b"hello", 5).unwrap();
+        let token = cab_writer.checkpoint().unwrap();
+        assert_eq!(token.num_completed_folders(), 0);
+        assert_eq!(token.data_offset(), 0x2c);
+    }
+
+    /// A writer that fails the first write made after it has been armed.
+    struct FailOnceWriter {
+        inner: Cursor<Vec<u8>>,
+        armed: bool,
+    }
+
+    impl Write for FailOnceWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.armed {
+                self.armed = false;
+                return Err(std::io::Error::other("disk full"));
+            }
+            self.inner.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.inner.flush()
+        }
+    }
+
+    impl std::io::Seek for FailOnceWriter {
+        fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    #[test]
+    fn report_data_block_write_errors() {
+        let mut builder = CabinetBuilder::new();
+        builder.add_folder(CompressionType::None).add_file("a.bin");
+        let writer =
+            FailOnceWriter { inner: Cursor::new(Vec::new()), armed: false };
+        let mut cab_writer = builder.build(writer).unwrap();
+        let mut file_writer = cab_writer.next_file().unwrap().unwrap();
+        file_writer.folder_writer.writer.armed = true;
+        // The first data block fills up (and is written out) partway through
+        // this write, so the write has already taken in some of the data
+        // when the error happens:
+        let error = file_writer.write(&[0x55; 100_000]).unwrap_err();
+        assert_eq!(error.to_string(), "disk full");
+    }
+
+    #[test]
+    fn append_rejects_data_offsets_that_would_overflow() {
+        // A cabinet with two folders and no files, whose second folder's
+        // data claims to start just short of 4 GiB:
+        let binary: &[u8] = b"MSCF\0\0\0\0\x34\0\0\0\0\0\0\0\
+            \x34\0\0\0\0\0\0\0\x03\x01\x02\0\0\0\0\0\0\0\0\0\
+            \x34\0\0\0\x01\0\0\0\
+            \xfc\xff\xff\xff\x01\0\0\0";
+        let cabinet = Cabinet::new(Cursor::new(binary.to_vec())).unwrap();
+        let mut appender = cabinet.into_appender();
+        appender.add_folder(CompressionType::None).add_file("a.txt");
+        let error = appender.build().err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(
+            error.to_string(),
+            "Data for folder 1 would be moved past the maximum cabinet size"
+        );
+    }
+
+    #[test]
+    fn dedup_compares_bytes_on_hash_collision() {
+        let mut dedup = DedupState::default();
+        dedup.contents.insert(7, vec![(0, b"Hello".to_vec())]);
+        dedup.buffer.extend_from_slice(b"Jello");
+        assert_eq!(dedup.find(7), None);
+        dedup.buffer.clear();
+        dedup.buffer.extend_from_slice(b"Hello");
+        assert_eq!(dedup.find(7), Some(0));
+        assert_eq!(dedup.find(8), None);
+    }
+
+    #[test]
+    #[cfg(feature = "time")]
     fn write_uncompressed_cabinet_with_one_file() {
         let mut builder = CabinetBuilder::new();
         let dt = datetime!(1997-03-12 11:13:52);
@@ -636,6 +3330,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(feature = "time")]
     fn write_uncompressed_cabinet_with_two_files() {
         let mut builder = CabinetBuilder::new();
         let dt = datetime!(2018-01-06 15:19:42);
@@ -664,6 +3359,64 @@ mod tests {
     }
 
     #[test]
+    fn add_folders_in_scopes() {
+        let mut builder = CabinetBuilder::new();
+        builder
+            .folder(CompressionType::None, |folder| {
+                folder.add_file("a.txt");
+            })
+            .folder(CompressionType::MsZip, |folder| {
+                folder.add_file("b.txt");
+                folder.add_file("c.txt").set_is_hidden(true);
+            });
+        let mut cab_writer = builder.build(Cursor::new(Vec::new())).unwrap();
+        while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+            file_writer.write_all(b"Hello, world!\n").unwrap();
+        }
+        let output = cab_writer.finish().unwrap().into_inner();
+        let cabinet = Cabinet::new(Cursor::new(output)).unwrap();
+        let folders: Vec<(CompressionType, Vec<&str>)> = cabinet
+            .folder_entries()
+            .map(|folder| {
+                let names = folder.file_entries().map(|file| file.name());
+                (folder.compression_type(), names.collect())
+            })
+            .collect();
+        assert_eq!(
+            folders,
+            vec![
+                (CompressionType::None, vec!["a.txt"]),
+                (CompressionType::MsZip, vec!["b.txt", "c.txt"]),
+            ]
+        );
+        assert!(cabinet.get_file_entry("c.txt").unwrap().is_hidden());
+    }
+
+    #[test]
+    fn write_many_blocks_in_one_call() {
+        let data: Vec<u8> = (0..100_000u32).map(|n| (n % 251) as u8).collect();
+        let mut builder = CabinetBuilder::new();
+        builder.add_folder(CompressionType::MsZip).add_file("big.bin");
+        let mut cab_writer = builder.build(Cursor::new(Vec::new())).unwrap();
+        let mut file_writer = cab_writer.next_file().unwrap().unwrap();
+        assert_eq!(file_writer.write(&data).unwrap(), data.len());
+        let output = cab_writer.finish().unwrap().into_inner();
+        let mut cabinet = Cabinet::new(Cursor::new(output)).unwrap();
+        assert_eq!(
+            cabinet.folder_entries().next().unwrap().num_data_blocks(),
+            4
+        );
+        let mut output = Vec::new();
+        cabinet
+            .read_file("big.bin")
+            .unwrap()
+            .read_to_end(&mut output)
+            .unwrap();
+        assert_eq!(output, data);
+    }
+
+    #[test]
+    #[cfg(feature = "time")]
     fn write_uncompressed_cabinet_with_non_ascii_filename() {
         let mut builder = CabinetBuilder::new();
         let dt = datetime!(1997-03-12 11:13:52);
@@ -683,4 +3436,230 @@ mod tests {
             \x3d\x0f\x08\x56\x09\0\x09\0Snowman!\n";
         assert_eq!(output.as_slice(), expected);
     }
+
+    #[test]
+    fn reject_out_of_range_quantum_parameters() {
+        let mut builder = CabinetBuilder::new();
+        builder.add_folder(CompressionType::Quantum(9, 20)).add_file("hi.txt");
+        let error = builder.build(Cursor::new(Vec::new())).err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn write_older_version() {
+        let mut builder = CabinetBuilder::new();
+        builder.set_version(1, 1);
+        builder.add_folder(CompressionType::MsZip).add_file("hi.txt");
+        assert!(builder.validate().is_ok());
+        let mut cab_writer = builder.build(Cursor::new(Vec::new())).unwrap();
+        while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+            file_writer.write_all(b"Hello, world!\n").unwrap();
+        }
+        let output = cab_writer.finish().unwrap().into_inner();
+        assert_eq!(&output[24..26], b"\x01\x01");
+        let cabinet = Cabinet::new(Cursor::new(output)).unwrap();
+        assert_eq!(cabinet.version(), (1, 1));
+
+        let mut builder = CabinetBuilder::new();
+        builder.set_version(1, 2);
+        builder.add_folder(CompressionType::Lzx(WindowSize::KB32));
+        assert!(builder.validate().is_err());
+        builder.folders[0].compression_type = CompressionType::MsZip;
+        builder.folders[0].add_file("\u{2603}.txt");
+        let error = builder.validate().unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(error.to_string().ends_with(": \"\u{2603}.txt\""));
+        builder.folders[0].files.clear();
+        builder.set_reserve_data(vec![1, 2, 3]);
+        assert!(builder.validate().is_err());
+        builder.set_version(1, 3);
+        assert!(builder.validate().is_ok());
+        builder.set_version(2, 0);
+        assert!(builder.build(Cursor::new(Vec::new())).is_err());
+    }
+
+    #[test]
+    fn validate_declared_file_sizes() {
+        let mut builder = CabinetBuilder::new();
+        let folder = builder.add_folder(CompressionType::MsZip);
+        folder.add_file("a.bin").set_uncompressed_size(0x7fff0000);
+        folder.add_file("b.bin").set_uncompressed_size(0x7fff0000);
+        folder.add_file("c.bin").set_uncompressed_size(0x1ffff);
+        folder.add_file("d.bin");
+        assert!(builder.validate().is_ok());
+        builder.folders[0].files[2].set_uncompressed_size(0x20000);
+        let error = builder.validate().unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(error.to_string().ends_with(": \"d.bin\""));
+        // A folder that can be split just continues in a new folder:
+        builder.folders[0].set_max_compressed_size(Some(0x7fff0000));
+        assert!(builder.validate().is_ok());
+        builder.folders[0].files[3].set_uncompressed_size(0x80000000);
+        let error = builder.validate().unwrap_err();
+        assert!(error.to_string().ends_with(": \"d.bin\""));
+    }
+
+    #[test]
+    fn enforce_expected_file_sizes() {
+        let mut builder = CabinetBuilder::new();
+        builder.add_folder(CompressionType::None).add_file("a.bin");
+        builder.folders[0].files[0].set_expected_size(4);
+        let mut cab_writer = builder.build(Cursor::new(Vec::new())).unwrap();
+        let mut file_writer = cab_writer.next_file().unwrap().unwrap();
+        assert_eq!(file_writer.write(b"abcdef").unwrap(), 4);
+        assert_eq!(file_writer.write(b"").unwrap(), 0);
+        let error = file_writer.write(b"ef").unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(cab_writer.next_file().unwrap().is_none());
+
+        let mut builder = CabinetBuilder::new();
+        let folder = builder.add_folder(CompressionType::MsZip);
+        folder.add_file("a.bin").set_expected_size(4);
+        folder.add_file("b.bin").set_expected_size(4);
+        let mut cab_writer = builder.build(Cursor::new(Vec::new())).unwrap();
+        cab_writer.next_file().unwrap().unwrap().write_all(b"abc").unwrap();
+        let error = cab_writer.next_file().err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+
+        let mut builder = CabinetBuilder::new();
+        let folder = builder.add_folder(CompressionType::MsZip);
+        folder.add_file("a.bin").set_expected_size(0x80000000);
+        assert!(builder.validate().is_err());
+        assert!(builder.build(Cursor::new(Vec::new())).is_err());
+    }
+
+    #[test]
+    fn finalize_or_abandon_cabinet() {
+        let mut builder = CabinetBuilder::new();
+        builder.add_folder(CompressionType::None).add_file("hi.txt");
+        let mut cab_writer = builder.build(Cursor::new(Vec::new())).unwrap();
+        while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+            file_writer.write_all(b"Hello, world!\n").unwrap();
+        }
+        let finished = cab_writer.finalize().unwrap();
+        assert_eq!(finished.total_size(), 0x59);
+        assert_eq!(finished.report().total_uncompressed_size(), 14);
+        assert_eq!(finished.get_ref().get_ref().len(), 0x59);
+
+        let mut builder = CabinetBuilder::new();
+        let folder = builder.add_folder(CompressionType::None);
+        folder.add_file("hi.txt");
+        folder.add_file("bye.txt");
+        let mut cab_writer = builder.build(Cursor::new(Vec::new())).unwrap();
+        let mut file_writer = cab_writer.next_file().unwrap().unwrap();
+        file_writer.write_all(b"Hello, world!\n").unwrap();
+        let output = cab_writer.into_inner_unfinished().into_inner();
+        // The file entries are there, but not the data:
+        assert_eq!(output.len(), 0x5b);
+        assert_eq!(&output[8..12], b"\0\0\0\0");
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "1 file(s) not yet written")]
+    fn dropping_writer_with_unwritten_files_panics_in_debug_builds() {
+        let mut builder = CabinetBuilder::new();
+        let folder = builder.add_folder(CompressionType::None);
+        folder.add_file("hi.txt");
+        folder.add_file("bye.txt");
+        let mut cab_writer = builder.build(Cursor::new(Vec::new())).unwrap();
+        cab_writer.set_require_finish(true);
+        let mut file_writer = cab_writer.next_file().unwrap().unwrap();
+        file_writer.write_all(b"Hello, world!\n").unwrap();
+        drop(cab_writer);
+    }
+
+    #[test]
+    fn dropping_writer_after_an_error_does_not_panic() {
+        let new_builder = || {
+            let mut builder = CabinetBuilder::new();
+            let folder = builder.add_folder(CompressionType::None);
+            folder.add_file("hi.txt");
+            folder.add_file("bye.txt");
+            builder
+        };
+        // By default, a caller may return early and drop the writer:
+        let mut cab_writer =
+            new_builder().build(Cursor::new(Vec::new())).unwrap();
+        let mut file_writer = cab_writer.next_file().unwrap().unwrap();
+        file_writer.write_all(b"Hello, world!\n").unwrap();
+        drop(cab_writer);
+        // And a failing provider's error is returned, even when finishing is
+        // required:
+        let mut cab_writer =
+            new_builder().build(Cursor::new(Vec::new())).unwrap();
+        cab_writer.set_require_finish(true);
+        let result = cab_writer.write_all_files(|name| match name {
+            "hi.txt" => Ok(&b"Hello, world!\n"[..]),
+            _ => Err(std::io::Error::from(std::io::ErrorKind::NotFound)),
+        });
+        assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn estimate_cabinet_size() {
+        let mut builder = CabinetBuilder::new();
+        builder
+            .add_folder(CompressionType::None)
+            .add_file("hi.txt")
+            .set_uncompressed_size(14);
+        assert_eq!(builder.estimated_header_size(), 0x43);
+        assert_eq!(builder.estimate_total_size(0.5), 0x59);
+        let folder = builder.add_folder(CompressionType::MsZip);
+        folder.add_file("big.bin").set_uncompressed_size(0x10000);
+        folder.add_duplicate_file("copy.bin", "big.bin");
+        assert_eq!(builder.estimated_header_size(), 0x43 + 8 + 24 + 25);
+        assert_eq!(
+            builder.estimate_total_size(0.5),
+            0x59 + 8 + 24 + 25 + 2 * 8 + 0x8000
+        );
+    }
+
+    #[test]
+    fn introspect_writer_progress() {
+        let mut builder = CabinetBuilder::new();
+        builder.add_folder(CompressionType::None).add_file("a.txt");
+        let folder = builder.add_folder(CompressionType::MsZip);
+        folder.add_file("b.txt");
+        folder.add_file("c.txt");
+        let mut writer = builder.build(Cursor::new(Vec::new())).unwrap();
+        let planned: Vec<(usize, &str)> = writer
+            .planned_files()
+            .map(|(index, file)| (index, file.name()))
+            .collect();
+        assert_eq!(planned, vec![(0, "a.txt"), (1, "b.txt"), (1, "c.txt")]);
+        assert!(writer.current_file().is_none());
+        assert_eq!(writer.files_remaining(), 3);
+        let mut names = Vec::new();
+        while let Some(mut file) = writer.next_file().unwrap() {
+            file.write_all(b"hello").unwrap();
+            names.push((
+                writer.current_folder_index(),
+                writer.current_file().unwrap().name().to_string(),
+                writer.current_file().unwrap().uncompressed_size(),
+                writer.files_started(),
+                writer.files_remaining(),
+            ));
+        }
+        assert_eq!(
+            names,
+            vec![
+                (0, "a.txt".to_string(), 5, 1, 2),
+                (1, "b.txt".to_string(), 5, 2, 1),
+                (1, "c.txt".to_string(), 5, 3, 0),
+            ]
+        );
+        assert!(writer.current_file().is_none());
+        assert_eq!(writer.files_remaining(), 0);
+    }
+
+    #[test]
+    fn split_file_extension() {
+        assert_eq!(split_extension("foo.txt"), ("foo", ".txt"));
+        assert_eq!(split_extension("foo.tar.gz"), ("foo.tar", ".gz"));
+        assert_eq!(split_extension("README"), ("README", ""));
+        assert_eq!(split_extension(".profile"), (".profile", ""));
+        assert_eq!(split_extension("v1.0\\notes"), ("v1.0\\notes", ""));
+        assert_eq!(split_extension("a.b/c.d"), ("a.b/c", ".d"));
+    }
 }
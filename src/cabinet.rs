@@ -1,36 +1,163 @@
+use std::borrow::Cow;
 use std::cell::RefCell;
-use std::io::{self, Read, Seek, SeekFrom};
+use std::cmp::Ordering;
+use std::io::{self, BufReader, Cursor, Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, OnceLock};
 
 use byteorder::{LittleEndian, ReadBytesExt};
+#[cfg(feature = "time")]
+use time::PrimitiveDateTime;
 
+use crate::builder::{AppendInfo, CabinetAppender};
+use crate::cache::BlockCache;
+use crate::checksum::Checksum;
 use crate::consts;
-use crate::file::{parse_file_entry, FileEntry, FileReader};
+#[cfg(feature = "time")]
+use crate::datetime::datetime_to_bits;
+use crate::file::{
+    parse_file_entry, read_file_name_into, FileEntries, FileEntry,
+    FileInfoRef, FileReader,
+};
 use crate::folder::{
-    parse_folder_entry, FolderEntries, FolderEntry, FolderReader,
+    checked_offset, parse_folder_entry, BlockSource, BlockSpan, Continuation,
+    DataBlockInfo, FolderEntries, FolderEntry, FolderInfoRef, FolderReader,
+    FolderResumeToken,
 };
-use crate::string::read_null_terminated_string;
+use crate::options::ReadOptions;
+use crate::readers::FileReaders;
+use crate::report::{self, GroupingSuggestion};
+use crate::string::{read_null_terminated_bytes, NameDecoding, NameLocation};
+use crate::throttle::Throttle;
+use crate::transform::BlockTransform;
+use crate::tree::TreeDirectory;
+use crate::warning::{self, Warning};
 
-pub(crate) trait ReadSeek: Read + Seek {}
+/// A reader that can also seek, implemented for every type that is both
+/// [`Read`] and [`Seek`].  This allows a cabinet whose source is only known
+/// at runtime (a file, a buffer in memory, a network adapter, etc.) to be
+/// held as a `Cabinet<Box<dyn ReadSeek>>` (or `Box<dyn ReadSeek + Send>`,
+/// for moving it to another thread):
+///
+/// ```no_run
+/// use std::io::{Cursor, Read};
+/// use cab::{Cabinet, ReadSeek};
+///
+/// fn open(path: &str) -> std::io::Result<Cabinet<Box<dyn ReadSeek>>> {
+///     let reader: Box<dyn ReadSeek> = if path == "-" {
+///         let mut data = Vec::new();
+///         std::io::stdin().read_to_end(&mut data)?;
+///         Box::new(Cursor::new(data))
+///     } else {
+///         Box::new(std::fs::File::open(path)?)
+///     };
+///     Cabinet::new(reader)
+/// }
+/// ```
+pub trait ReadSeek: Read + Seek {}
 impl<R: Read + Seek> ReadSeek for R {}
 
+/// An iterator over the data block headers in a cabinet folder, returned by
+/// [`Cabinet::data_blocks`].
+pub struct DataBlocks<'a> {
+    reader: &'a mut dyn ReadSeek,
+    next_offset: u64,
+    remaining: usize,
+    data_reserve_size: usize,
+}
+
+impl<'a> DataBlocks<'a> {
+    fn read_block(&mut self) -> io::Result<DataBlockInfo> {
+        let offset = self.next_offset;
+        self.reader.seek(SeekFrom::Start(offset))?;
+        let checksum = self.reader.read_u32::<LittleEndian>()?;
+        let compressed_size = self.reader.read_u16::<LittleEndian>()?;
+        let uncompressed_size = self.reader.read_u16::<LittleEndian>()?;
+        let mut reserve_data = vec![0u8; self.data_reserve_size];
+        self.reader.read_exact(&mut reserve_data)?;
+        self.next_offset = checked_offset(
+            offset,
+            8 + self.data_reserve_size as u64 + compressed_size as u64,
+        )?;
+        Ok(DataBlockInfo {
+            offset,
+            checksum,
+            compressed_size,
+            uncompressed_size,
+            reserve_data,
+        })
+    }
+}
+
+impl<'a> Iterator for DataBlocks<'a> {
+    type Item = io::Result<DataBlockInfo>;
+
+    fn next(&mut self) -> Option<io::Result<DataBlockInfo>> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let result = self.read_block();
+        // Stop after an error, since the position of any later blocks is
+        // unknown.
+        self.remaining = if result.is_ok() { self.remaining - 1 } else { 0 };
+        Some(result)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.remaining))
+    }
+}
+
 /// A structure for reading a cabinet file.
 pub struct Cabinet<R: ?Sized> {
     pub(crate) inner: CabinetInner<R>,
 }
 
 pub(crate) struct CabinetInner<R: ?Sized> {
+    total_size: u32,
+    version: (u8, u8),
+    pub(crate) first_folder_offset: u32,
+    first_file_offset: u32,
     cabinet_set_id: u16,
     cabinet_set_index: u16,
-    data_reserve_size: u8,
+    folder_reserve_size: u8,
+    pub(crate) data_reserve_size: u8,
+    declared_data_reserve_size: u8,
     reserve_data: Vec<u8>,
-    folders: Vec<FolderEntry>,
-    files: Vec<FileEntry>,
-    reader: RefCell<R>,
+    reserve_data_size: u16,
+    pub(crate) folders: Vec<FolderEntry>,
+    pub(crate) files: Vec<FileEntry>,
+    /// The positions within `files` of the file entries, sorted by name
+    /// (see [`Cabinet::sorted_file_entries`]).  Built on first use.
+    name_index: OnceLock<Vec<usize>>,
+    num_files: u16,
+    files_loaded: bool,
+    prev_cabinet: Option<(String, String)>,
+    next_cabinet: Option<(String, String)>,
+    has_continued_files: bool,
+    warnings: Vec<Warning>,
+    pub(crate) continue_on_error: bool,
+    pub(crate) block_transform: Option<Arc<dyn BlockTransform>>,
+    pub(crate) throttle: Option<Arc<dyn Throttle>>,
+    pub(crate) options: ReadOptions,
+    /// The headers of each folder's data blocks, if known in advance (from
+    /// an index; see [`Cabinet::open_with_index`]), or else empty.
+    pub(crate) block_maps: Vec<Vec<DataBlockInfo>>,
+    pub(crate) block_cache: RefCell<BlockCache>,
+    pub(crate) reader: RefCell<R>,
 }
 
 impl<R: Read + Seek> Cabinet<R> {
     /// Open an existing cabinet file.
-    pub fn new(mut reader: R) -> io::Result<Cabinet<R>> {
+    pub fn new(reader: R) -> io::Result<Cabinet<R>> {
+        Cabinet::with_options(reader, ReadOptions::new())
+    }
+
+    /// Open an existing cabinet file, using the given options.  See also
+    /// [`ReadOptions::open`].
+    pub fn with_options(
+        mut reader: R,
+        options: ReadOptions,
+    ) -> io::Result<Cabinet<R>> {
         let signature = reader.read_u32::<LittleEndian>()?;
         if signature != consts::FILE_SIGNATURE {
             invalid_data!("Not a cabinet file (invalid file signature)");
@@ -46,7 +173,7 @@ impl<R: Read + Seek> Cabinet<R> {
             );
         }
         let _reserved2 = reader.read_u32::<LittleEndian>()?;
-        let first_file_offset = reader.read_u32::<LittleEndian>()?;
+        let mut first_file_offset = reader.read_u32::<LittleEndian>()?;
         let _reserved3 = reader.read_u32::<LittleEndian>()?;
         let minor_version = reader.read_u8()?;
         let major_version = reader.read_u8()?;
@@ -73,57 +200,278 @@ impl<R: Read + Seek> Cabinet<R> {
             folder_reserve_size = reader.read_u8()?;
             data_reserve_size = reader.read_u8()?;
         }
-        let mut header_reserve_data = vec![0u8; header_reserve_size as usize];
-        if header_reserve_size > 0 {
-            reader.read_exact(&mut header_reserve_data)?;
+        let mut header_reserve_data = Vec::new();
+        if options.skip_reserve_data {
+            reader.seek(SeekFrom::Current(header_reserve_size as i64))?;
+        } else {
+            header_reserve_data =
+                read_reserve(&mut reader, header_reserve_size)?;
         }
-        let _prev_cabinet = if (flags & consts::FLAG_PREV_CABINET) != 0 {
-            let (cab_name, _) =
-                read_null_terminated_string(&mut reader, false)?;
-            let (disk_name, _) =
-                read_null_terminated_string(&mut reader, false)?;
+        let max_name_len = options.max_name_len;
+        let mut read_name = |location| {
+            read_null_terminated_bytes(&mut reader, max_name_len, location)
+                .map(|name| String::from_utf8_lossy(&name).into_owned())
+        };
+        let prev_cabinet = if (flags & consts::FLAG_PREV_CABINET) != 0 {
+            let cab_name = read_name(NameLocation::PrevCabinet)?;
+            let disk_name = read_name(NameLocation::PrevDisk)?;
             Some((cab_name, disk_name))
         } else {
             None
         };
-        let _next_cabinet = if (flags & consts::FLAG_NEXT_CABINET) != 0 {
-            let (cab_name, _) =
-                read_null_terminated_string(&mut reader, false)?;
-            let (disk_name, _) =
-                read_null_terminated_string(&mut reader, false)?;
+        let next_cabinet = if (flags & consts::FLAG_NEXT_CABINET) != 0 {
+            let cab_name = read_name(NameLocation::NextCabinet)?;
+            let disk_name = read_name(NameLocation::NextDisk)?;
             Some((cab_name, disk_name))
         } else {
             None
         };
+        let first_folder_offset = reader.stream_position()? as u32;
         let mut folders = Vec::with_capacity(num_folders);
-        for _ in 0..num_folders {
-            let entry =
+        let folder_entry_size = 8 + folder_reserve_size as u64;
+        for index in 0..num_folders {
+            let mut entry =
                 parse_folder_entry(&mut reader, folder_reserve_size as usize)?;
+            entry.entry_offset =
+                first_folder_offset as u64 + index as u64 * folder_entry_size;
             folders.push(entry);
         }
-        reader.seek(SeekFrom::Start(first_file_offset as u64))?;
-        let mut files = Vec::with_capacity(num_files as usize);
-        for _ in 0..num_files {
-            let entry = parse_file_entry(&mut reader)?;
-            let folder_index = entry.folder_index as usize;
-            if folder_index >= folders.len() {
-                invalid_data!("File entry folder index out of bounds");
+        let folder_entries_end = first_folder_offset as u64
+            + num_folders as u64 * folder_entry_size;
+        let mut warnings = Vec::new();
+        if (first_file_offset as u64) < folder_entries_end {
+            if !options.lenient_layout {
+                invalid_data!(
+                    "File entries start at offset {}, before the end of the \
+                     folder entries at offset {}",
+                    first_file_offset,
+                    folder_entries_end
+                );
             }
-            let folder = &mut folders[folder_index];
-            folder.files.push(entry.clone());
-            files.push(entry);
+            warnings.push(Warning::MisplacedFileEntries {
+                first_file_offset,
+                folder_entries_end,
+            });
+            // The folder entries are where the header layout says they are,
+            // so the file entries can only start once they end.
+            first_file_offset = folder_entries_end as u32;
         }
-        Ok(Cabinet {
+        let declared_data_reserve_size = data_reserve_size;
+        if let Some(size) = options.known_data_reserve {
+            data_reserve_size = size;
+        } else if options.detect_data_reserve {
+            data_reserve_size = detect_data_reserve_size(
+                &mut reader,
+                &folders,
+                declared_data_reserve_size,
+            )?;
+        }
+        warning::check_header(
+            flags,
+            (major_version, minor_version),
+            (declared_data_reserve_size, data_reserve_size),
+            &mut warnings,
+        );
+        let defer_file_entries = options.defer_file_entries;
+        let mut cabinet = Cabinet {
             inner: CabinetInner {
+                total_size,
+                version: (major_version, minor_version),
+                first_folder_offset,
+                first_file_offset,
                 cabinet_set_id,
                 cabinet_set_index,
+                folder_reserve_size,
                 data_reserve_size,
+                declared_data_reserve_size,
                 reserve_data: header_reserve_data,
+                reserve_data_size: header_reserve_size,
                 folders,
-                files,
+                files: Vec::new(),
+                name_index: OnceLock::new(),
+                num_files,
+                files_loaded: false,
+                prev_cabinet,
+                next_cabinet,
+                has_continued_files: false,
+                warnings,
+                continue_on_error: false,
+                block_transform: None,
+                throttle: None,
+                block_cache: RefCell::new(BlockCache::new(
+                    options.block_cache_size,
+                )),
+                options,
+                block_maps: Vec::new(),
                 reader: RefCell::new(reader),
             },
-        })
+        };
+        if !defer_file_entries {
+            cabinet.load_file_entries()?;
+        }
+        Ok(cabinet)
+    }
+
+    /// Parses the cabinet's file entries, if they haven't been already.
+    ///
+    /// This is only needed for cabinets opened with
+    /// [`ReadOptions::defer_file_entries`]; otherwise the file entries are
+    /// parsed when the cabinet is opened, and this does nothing.  Methods
+    /// that return an `io::Result` (such as
+    /// [`read_file`](Cabinet::read_file)) call this automatically, but the
+    /// other methods for listing files only see the file entries once they
+    /// have been loaded.
+    pub fn load_file_entries(&mut self) -> io::Result<()> {
+        if self.inner.files_loaded {
+            return Ok(());
+        }
+        let inner = &mut self.inner;
+        let reader = inner.reader.get_mut();
+        let folders = &mut inner.folders;
+        reader.seek(SeekFrom::Start(inner.first_file_offset as u64))?;
+        let num_files = inner.num_files as usize;
+        let mut files = Vec::with_capacity(num_files);
+        let mut first_from_prev = false;
+        let mut last_to_next = false;
+        let mut entry_offset = inner.first_file_offset as u64;
+        for index in 0..num_files {
+            let mut entry =
+                parse_file_entry(&mut *reader, index, &inner.options)?;
+            entry.entry_offset = entry_offset;
+            entry_offset += 16 + entry.stored_name_len() as u64;
+            let (folder_index, continuation) =
+                resolve_folder_index(entry.folder_index, folders.len())?;
+            first_from_prev |= continuation.is_from_prev();
+            last_to_next |= continuation.is_to_next();
+            entry.folder_index = folder_index;
+            entry.continuation = continuation;
+            files.push(entry);
+        }
+        if let Some(first) = folders.first_mut() {
+            first.continuation = Continuation::new(first_from_prev, false);
+        }
+        if let Some(last) = folders.last_mut() {
+            let from_prev = last.continuation.is_from_prev();
+            last.continuation = Continuation::new(from_prev, last_to_next);
+        }
+        #[cfg(feature = "chardet")]
+        detect_name_encoding(&mut files);
+        for entry in files.iter() {
+            folders[entry.folder_index as usize].files.push(entry.clone());
+        }
+        warning::check_file_order(&files, &mut inner.warnings);
+        warning::check_folders(folders, &mut inner.warnings);
+        inner.files = files;
+        inner.name_index = OnceLock::new();
+        inner.has_continued_files = first_from_prev || last_to_next;
+        inner.files_loaded = true;
+        Ok(())
+    }
+
+    /// Calls `visitor` with each file entry in the cabinet's file directory
+    /// (in directory order), along with the index and entry of the folder
+    /// holding the file.  Unlike loading the file entries, this decodes each
+    /// entry on the fly as it is read, without building a `FileEntry` for
+    /// it; the only memory used is a read buffer and a buffer for the
+    /// current name, both reused from one entry to the next.  This makes it
+    /// possible to list cabinets with tens of thousands of files that were
+    /// opened with [`ReadOptions::defer_file_entries`], without ever holding
+    /// all their entries in memory.
+    ///
+    /// The entries are always read afresh from the underlying reader, even
+    /// if they have already been loaded.  Names are read as when loading the
+    /// entries (subject to [`ReadOptions::max_name_len`] and
+    /// [`ReadOptions::lenient_names`]), except that charset detection is
+    /// never applied.
+    pub fn visit_entries<F>(&mut self, mut visitor: F) -> io::Result<()>
+    where
+        F: FnMut(usize, FolderInfoRef<'_>, FileInfoRef<'_>),
+    {
+        let inner = &mut self.inner;
+        let reader = inner.reader.get_mut();
+        reader.seek(SeekFrom::Start(inner.first_file_offset as u64))?;
+        let mut reader = BufReader::new(reader);
+        let options = &inner.options;
+        let mut name = Vec::new();
+        let mut entry_offset = inner.first_file_offset as u64;
+        for index in 0..inner.num_files as usize {
+            let uncompressed_size = reader.read_u32::<LittleEndian>()?;
+            let uncompressed_offset = reader.read_u32::<LittleEndian>()?;
+            let folder_index = reader.read_u16::<LittleEndian>()?;
+            let date = reader.read_u16::<LittleEndian>()?;
+            let time = reader.read_u16::<LittleEndian>()?;
+            let attributes = reader.read_u16::<LittleEndian>()?;
+            let name_decoding =
+                read_file_name_into(&mut reader, index, options, &mut name)?;
+            let (folder_index, continuation) =
+                resolve_folder_index(folder_index, inner.folders.len())?;
+            let folder =
+                FolderInfoRef { entry: &inner.folders[folder_index as usize] };
+            let file = FileInfoRef {
+                name_raw: &name,
+                name_decoding,
+                datetime_bits: (date, time),
+                #[cfg(feature = "time")]
+                default_datetime: options.default_datetime,
+                uncompressed_size,
+                uncompressed_offset,
+                attributes,
+                folder_index,
+                continuation,
+                entry_offset,
+                index,
+            };
+            visitor(folder_index as usize, folder, file);
+            let stored_name_len = match name_decoding {
+                NameDecoding::Utf16 => name.len() + 2,
+                _ => name.len() + 1,
+            };
+            entry_offset += 16 + stored_name_len as u64;
+        }
+        Ok(())
+    }
+
+    /// Returns true if this cabinet's file entries have been parsed (see
+    /// [`load_file_entries`](Cabinet::load_file_entries)).
+    pub fn file_entries_loaded(&self) -> bool {
+        self.inner.files_loaded
+    }
+
+    /// Returns this cabinet, but reading from `reader` instead (which must
+    /// hold the same cabinet file).
+    pub(crate) fn with_reader<S>(self, reader: S) -> Cabinet<S> {
+        let inner = self.inner;
+        Cabinet {
+            inner: CabinetInner {
+                total_size: inner.total_size,
+                version: inner.version,
+                first_folder_offset: inner.first_folder_offset,
+                first_file_offset: inner.first_file_offset,
+                cabinet_set_id: inner.cabinet_set_id,
+                cabinet_set_index: inner.cabinet_set_index,
+                folder_reserve_size: inner.folder_reserve_size,
+                data_reserve_size: inner.data_reserve_size,
+                declared_data_reserve_size: inner.declared_data_reserve_size,
+                reserve_data: inner.reserve_data,
+                reserve_data_size: inner.reserve_data_size,
+                folders: inner.folders,
+                files: inner.files,
+                name_index: inner.name_index,
+                num_files: inner.num_files,
+                files_loaded: inner.files_loaded,
+                prev_cabinet: inner.prev_cabinet,
+                next_cabinet: inner.next_cabinet,
+                has_continued_files: inner.has_continued_files,
+                warnings: inner.warnings,
+                continue_on_error: inner.continue_on_error,
+                block_transform: inner.block_transform,
+                throttle: inner.throttle,
+                options: inner.options,
+                block_maps: inner.block_maps,
+                block_cache: inner.block_cache,
+                reader: RefCell::new(reader),
+            },
+        }
     }
 
     /// Returns the cabinet set ID for this cabinet (an arbitrary number used
@@ -137,48 +485,467 @@ impl<R: Read + Seek> Cabinet<R> {
         self.inner.cabinet_set_index
     }
 
+    /// Returns true if this cabinet is part of a multi-cabinet set (that is,
+    /// if it refers to a previous and/or next cabinet in the set).
+    pub fn is_multi_part(&self) -> bool {
+        self.inner.prev_cabinet.is_some() || self.inner.next_cabinet.is_some()
+    }
+
+    /// Returns true if any files in this cabinet are continued from the
+    /// previous cabinet or into the next cabinet in the set, meaning that
+    /// the other cabinet(s) are needed in order to extract them.
+    pub fn has_continued_files(&self) -> bool {
+        self.inner.has_continued_files
+    }
+
+    /// Returns the non-fatal anomalies found while opening this cabinet (such
+    /// as unknown header flags, or files whose data ranges overlap), which
+    /// don't stop the cabinet from being read but may indicate a problem
+    /// with whatever wrote it.
+    pub fn warnings(&self) -> &[Warning] {
+        &self.inner.warnings
+    }
+
+    /// Returns the file name of the previous cabinet in the set, if any.
+    pub fn prev_cabinet_name(&self) -> Option<&str> {
+        self.inner.prev_cabinet.as_ref().map(|(name, _)| name.as_str())
+    }
+
+    /// Returns the name of the disk containing the previous cabinet in the
+    /// set, if any.
+    pub fn prev_disk_name(&self) -> Option<&str> {
+        self.inner.prev_cabinet.as_ref().map(|(_, disk)| disk.as_str())
+    }
+
+    /// Returns the file name of the next cabinet in the set, if any.
+    pub fn next_cabinet_name(&self) -> Option<&str> {
+        self.inner.next_cabinet.as_ref().map(|(name, _)| name.as_str())
+    }
+
+    /// Returns the name of the disk containing the next cabinet in the set,
+    /// if any.
+    pub fn next_disk_name(&self) -> Option<&str> {
+        self.inner.next_cabinet.as_ref().map(|(_, disk)| disk.as_str())
+    }
+
     /// Returns the application-defined reserve data stored in the cabinet
-    /// header.
+    /// header.  This is empty if the cabinet was opened with
+    /// [`ReadOptions::skip_reserve_data`]; use
+    /// [`reserve_data_reader`](Cabinet::reserve_data_reader) instead to read
+    /// it either way.
     pub fn reserve_data(&self) -> &[u8] {
         &self.inner.reserve_data
     }
 
+    /// Returns the size of the application-defined reserve data stored in
+    /// the cabinet header, in bytes (even if it was skipped when opening the
+    /// cabinet).
+    pub fn reserve_data_size(&self) -> u16 {
+        self.inner.reserve_data_size
+    }
+
+    /// Returns a reader for the application-defined reserve data stored in
+    /// the cabinet header.  If the cabinet was opened with
+    /// [`ReadOptions::skip_reserve_data`], the reserve is read from the
+    /// underlying reader.
+    pub fn reserve_data_reader(&self) -> io::Result<impl Read + '_> {
+        let data = if self.inner.options.skip_reserve_data {
+            let mut reader = self.inner.reader.borrow_mut();
+            reader.seek(SeekFrom::Start(consts::HEADER_RESERVE_OFFSET))?;
+            Cow::Owned(read_reserve(
+                &mut *reader,
+                self.inner.reserve_data_size,
+            )?)
+        } else {
+            Cow::Borrowed(self.inner.reserve_data.as_slice())
+        };
+        Ok(Cursor::new(data))
+    }
+
+    /// Returns the total size of the cabinet file, in bytes, as recorded in
+    /// its header.
+    pub fn total_size(&self) -> u32 {
+        self.inner.total_size
+    }
+
+    /// Returns the (major, minor) version of the CAB format recorded in the
+    /// cabinet header.
+    pub fn version(&self) -> (u8, u8) {
+        self.inner.version
+    }
+
+    /// Returns the size of the reserve data in each folder entry, in bytes.
+    pub fn folder_reserve_size(&self) -> u8 {
+        self.inner.folder_reserve_size
+    }
+
+    /// Returns the size of the reserve data in each data block header, in
+    /// bytes.  This is the size declared in the cabinet header, unless the
+    /// cabinet was opened with
+    /// [`ReadOptions::detect_data_reserve`](crate::ReadOptions::detect_data_reserve)
+    /// and the blocks turned out to be laid out differently.
+    pub fn data_reserve_size(&self) -> u8 {
+        self.inner.data_reserve_size
+    }
+
+    /// If the cabinet was opened with
+    /// [`ReadOptions::detect_data_reserve`](crate::ReadOptions::detect_data_reserve)
+    /// and its data blocks turned out not to match the data reserve size
+    /// declared in the cabinet header, returns the declared size and the
+    /// size actually being used, in that order.  Otherwise, returns `None`.
+    pub fn data_reserve_discrepancy(&self) -> Option<(u8, u8)> {
+        let declared = self.inner.declared_data_reserve_size;
+        let actual = self.inner.data_reserve_size;
+        if declared != actual {
+            Some((declared, actual))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the offset within the cabinet file of the first file entry.
+    pub fn first_file_offset(&self) -> u32 {
+        self.inner.first_file_offset
+    }
+
+    /// Sets whether readers returned by this cabinet should continue past
+    /// data blocks that fail to decompress, replacing them with zeros instead
+    /// of returning an error.  Disabled by default.  See
+    /// [`FolderReader::set_continue_on_error`](crate::FolderReader::set_continue_on_error)
+    /// for details, and [`FileReader::damaged_ranges`] for finding out which
+    /// parts of a file were affected.
+    pub fn set_continue_on_error(&mut self, continue_on_error: bool) {
+        self.inner.continue_on_error = continue_on_error;
+    }
+
+    /// Sets a transformation to apply to each data block's payload before
+    /// decompressing it, for cabinets written with a matching
+    /// [`CabinetBuilder::set_block_transform`](crate::CabinetBuilder::set_block_transform).
+    /// By default, no transformation is applied.
+    pub fn set_block_transform(
+        &mut self,
+        transform: Option<Arc<dyn BlockTransform>>,
+    ) {
+        self.inner.block_transform = transform;
+        // Blocks decoded with the old transform (if any) are no good now.
+        self.inner.block_cache.get_mut().clear();
+    }
+
+    /// Sets a hook to call for each data block read from the cabinet (see
+    /// [`Throttle`]), e.g. to rate-limit extraction.  By default, there is
+    /// no hook.
+    pub fn set_throttle(&mut self, throttle: Option<Arc<dyn Throttle>>) {
+        self.inner.throttle = throttle;
+    }
+
     /// Returns an iterator over the folder entries in this cabinet.
-    pub fn folder_entries(&self) -> FolderEntries {
+    pub fn folder_entries(&self) -> FolderEntries<'_> {
         FolderEntries { iter: self.inner.folders.iter() }
     }
 
-    /// Returns the entry for the file with the given name, if any..
+    /// Returns an iterator over all the file entries in this cabinet, in the
+    /// order they appear in the cabinet's file directory.
+    ///
+    /// The CAB spec requires file entries to be sorted by folder, in which
+    /// case this is the same order as iterating over the files of each of
+    /// the [`folder_entries`](Cabinet::folder_entries) in turn.  Some
+    /// cabinets in the wild interleave entries from different folders,
+    /// though (see [`Warning::UnsortedFileEntries`]); this method preserves
+    /// their original order, while each folder's
+    /// [`file_entries`](FolderEntry::file_entries) lists only that folder's
+    /// files (still in directory order).
+    pub fn file_entries_in_directory_order(&self) -> FileEntries<'_> {
+        FileEntries { iter: self.inner.files.iter() }
+    }
+
+    /// Returns an iterator over the file entries (in directory order) whose
+    /// stored datetimes aren't valid, e.g. with a month of zero or an hour
+    /// past 23.  Such files have no [`datetime`](FileEntry::datetime)
+    /// (unless the cabinet was opened with a
+    /// [default datetime](ReadOptions::default_datetime)), but their
+    /// [raw datetime](FileEntry::raw_datetime) can still be preserved when
+    /// rewriting the cabinet.
+    pub fn files_with_invalid_datetimes(
+        &self,
+    ) -> impl Iterator<Item = &FileEntry> {
+        self.inner.files.iter().filter(|file| !file.has_valid_datetime())
+    }
+
+    /// Returns a hierarchical view of the files in this cabinet, grouping
+    /// them into directories according to the backslash-separated components
+    /// of their names (see [`FileEntry::path_components`]).
+    pub fn tree(&self) -> TreeDirectory<'_> {
+        TreeDirectory::build(self.inner.files.iter())
+    }
+
+    /// Returns the entry for the file with the given name, if any.  If
+    /// several files have that name, returns the first one in directory
+    /// order.
     pub fn get_file_entry(&self, name: &str) -> Option<&FileEntry> {
-        self.inner.files.iter().find(|&file| file.name() == name)
+        self.file_position(name).map(|index| &self.inner.files[index])
+    }
+
+    /// Returns the entry for the file with the given name, ignoring case
+    /// (as Windows does when extracting), if any.  A file whose name matches
+    /// exactly is preferred; otherwise, of the files whose names match
+    /// ignoring case, the one that comes first in
+    /// [`sorted_file_entries`](Cabinet::sorted_file_entries) is returned.
+    ///
+    /// Like [`get_file_entry`](Cabinet::get_file_entry), this searches an
+    /// index of the file names that is built (in O(n log n) time) on first
+    /// use, so each lookup takes O(log n) time even for cabinets with many
+    /// thousands of files.
+    pub fn lookup(&self, name: &str) -> Option<&FileEntry> {
+        let matches = self.folded_matches(name);
+        let files = &self.inner.files;
+        let index = matches
+            .iter()
+            .copied()
+            .find(|&index| files[index].name() == name)
+            .or_else(|| matches.first().copied())?;
+        Some(&files[index])
+    }
+
+    /// Returns an iterator over the entries for all files in the cabinet,
+    /// sorted by name, ignoring case (with files whose names differ only in
+    /// case ordered by their exact names, and files with the same name in
+    /// directory order).
+    pub fn sorted_file_entries(
+        &self,
+    ) -> impl ExactSizeIterator<Item = &FileEntry> + DoubleEndedIterator + '_
+    {
+        let files = &self.inner.files;
+        self.name_index().iter().map(move |&index| &files[index])
+    }
+
+    /// Returns the positions within `files` of the file entries, sorted by
+    /// name, building the index if needed.
+    fn name_index(&self) -> &[usize] {
+        self.inner.name_index.get_or_init(|| {
+            let files = &self.inner.files;
+            let mut index: Vec<usize> = (0..files.len()).collect();
+            // This is a stable sort, so duplicate names stay in order.
+            index.sort_by(|&a, &b| {
+                compare_names(files[a].name(), files[b].name())
+            });
+            index
+        })
+    }
+
+    /// Returns the positions within `files` of the file entries whose names
+    /// match the given name ignoring case, in sorted order.
+    fn folded_matches(&self, name: &str) -> &[usize] {
+        let files = &self.inner.files;
+        let index = self.name_index();
+        let start = index.partition_point(|&i| {
+            fold_case(files[i].name()).lt(fold_case(name))
+        });
+        let len = index[start..].partition_point(|&i| {
+            fold_case(files[i].name()).eq(fold_case(name))
+        });
+        &index[start..(start + len)]
+    }
+
+    /// Returns the position within `files` of the first file entry with
+    /// exactly the given name, if any.
+    fn file_position(&self, name: &str) -> Option<usize> {
+        let files = &self.inner.files;
+        self.folded_matches(name)
+            .iter()
+            .copied()
+            .find(|&index| files[index].name() == name)
+    }
+
+    /// Returns true if the cabinet contains a file with the given name.
+    pub fn contains(&self, name: &str) -> bool {
+        self.get_file_entry(name).is_some()
+    }
+
+    /// Returns an iterator over the entries for files whose names start with
+    /// the given prefix (e.g. `"drivers\\x64\\"`), in cabinet order.
+    pub fn files_with_prefix<'a>(
+        &'a self,
+        prefix: &'a str,
+    ) -> impl Iterator<Item = &'a FileEntry> + 'a {
+        self.inner
+            .files
+            .iter()
+            .filter(move |file| file.name().starts_with(prefix))
+    }
+
+    /// Returns an iterator over the entries for files whose names match the
+    /// given glob pattern (e.g. `"drivers\\*\\*.inf"`), in cabinet order.
+    /// Either backslashes or forward slashes may be used as path separators
+    /// in the pattern, and `*` and `?` never match a separator (use `**` to
+    /// match across directories).  Returns an error if the pattern is
+    /// invalid.
+    #[cfg(feature = "globset")]
+    pub fn files_matching(
+        &self,
+        pattern: &str,
+    ) -> io::Result<impl Iterator<Item = &FileEntry> + '_> {
+        let pattern = pattern.replace('\\', "/");
+        let matcher = match globset::GlobBuilder::new(&pattern)
+            .literal_separator(true)
+            .backslash_escape(false)
+            .build()
+        {
+            Ok(glob) => glob.compile_matcher(),
+            Err(error) => {
+                invalid_input!("Invalid glob pattern {:?}: {}", pattern, error)
+            }
+        };
+        // Names are normalized into a single reused buffer, rather than
+        // allocating a new string for each file.
+        let mut normalized = String::new();
+        Ok(self.inner.files.iter().filter(move |file| {
+            normalized.clear();
+            normalized.extend(file.name().chars().map(|chr| {
+                if chr == '\\' {
+                    '/'
+                } else {
+                    chr
+                }
+            }));
+            matcher.is_match(normalized.as_str())
+        }))
     }
 
     /// Returns a reader over the decompressed data for the file in the cabinet
     /// with the given name.
-    pub fn read_file(&mut self, name: &str) -> io::Result<FileReader<R>> {
-        match self.get_file_entry(name) {
-            Some(file_entry) => {
-                let folder_index = file_entry.folder_index as usize;
-                let file_start_in_folder =
-                    file_entry.uncompressed_offset as u64;
-                let size = file_entry.uncompressed_size() as u64;
-                let mut folder_reader = self.read_folder(folder_index)?;
-                folder_reader
-                    .seek_to_uncompressed_offset(file_start_in_folder)?;
-                Ok(FileReader {
-                    reader: folder_reader,
-                    file_start_in_folder,
-                    offset: 0,
-                    size,
-                })
-            }
+    ///
+    /// Returns an error if the file is continued from or into another
+    /// cabinet in a set (see [`FileEntry::continuation`]), or if it is in a
+    /// folder that is continued from the previous cabinet (since decoding
+    /// such a folder requires the data from the previous cabinet).
+    pub fn read_file(&mut self, name: &str) -> io::Result<FileReader<'_, R>> {
+        self.load_file_entries()?;
+        let (folder_index, file_start_in_folder, size) =
+            self.locate_file(name)?;
+        let folder_reader = self.read_folder(folder_index)?;
+        FileReader::new(folder_reader, file_start_in_folder, size)
+    }
+
+    /// Consumes the cabinet and returns a reader for decompressing the
+    /// specified file.  Unlike [`read_file`](Cabinet::read_file), the
+    /// returned reader owns the cabinet, so it can be returned from a
+    /// function or stored without borrowing anything.
+    pub fn into_file_reader<'a>(
+        mut self,
+        name: &str,
+    ) -> io::Result<FileReader<'a, R>>
+    where
+        R: 'a,
+    {
+        self.load_file_entries()?;
+        let (folder_index, file_start_in_folder, size) =
+            self.locate_file(name)?;
+        let inner: Box<CabinetInner<dyn ReadSeek + 'a>> = Box::new(self.inner);
+        let folder_reader =
+            FolderReader::new(BlockSource::OwnedCabinet(inner), folder_index)?;
+        FileReader::new(folder_reader, file_start_in_folder, size)
+    }
 
+    /// Returns the folder index, starting offset within the folder, and
+    /// size of the named file, checking that it can be extracted from this
+    /// cabinet alone.
+    pub(crate) fn locate_file(
+        &self,
+        name: &str,
+    ) -> io::Result<(usize, u64, u64)> {
+        let file_entry = match self.get_file_entry(name) {
+            Some(file_entry) => file_entry,
             None => not_found!("No such file in cabinet: {:?}", name),
+        };
+        check_extractable(&self.inner, file_entry)?;
+        Ok((
+            file_entry.folder_index as usize,
+            file_entry.uncompressed_offset as u64,
+            file_entry.uncompressed_size() as u64,
+        ))
+    }
+
+    /// Returns an iterator over the headers of the data blocks in the
+    /// specified folder, without reading or decompressing their payloads.
+    pub fn data_blocks(&mut self, index: usize) -> io::Result<DataBlocks<'_>> {
+        if index >= self.inner.folders.len() {
+            invalid_input!(
+                "Folder index {} is out of range (cabinet has {} folders)",
+                index,
+                self.inner.folders.len()
+            );
+        }
+        let folder = &self.inner.folders[index];
+        Ok(DataBlocks {
+            reader: self.inner.reader.get_mut(),
+            next_offset: folder.first_data_block_offset() as u64,
+            remaining: folder.num_data_blocks() as usize,
+            data_reserve_size: self.inner.data_reserve_size as usize,
+        })
+    }
+
+    /// Returns which data blocks of its folder the named file's data lies
+    /// within, and where within those blocks it starts and ends.  This reads
+    /// the data block headers of the file's folder (but not their payloads).
+    pub fn file_block_span(&mut self, name: &str) -> io::Result<BlockSpan> {
+        self.load_file_entries()?;
+        let (folder_index, file_start, size) = self.locate_file(name)?;
+        let file_end = file_start + size;
+        let mut first = None;
+        let mut folder_offset = 0;
+        let mut last_block = None;
+        for (index, block) in self.data_blocks(folder_index)?.enumerate() {
+            let block = block?;
+            let block_start = folder_offset;
+            let block_end = block_start + block.uncompressed_size as u64;
+            let block_byte_end = checked_offset(
+                block.offset,
+                8 + block.reserve_data.len() as u64
+                    + block.compressed_size as u64,
+            )?;
+            if first.is_none()
+                && (file_start < block_end
+                    || file_start == block_end && size == 0)
+            {
+                first = Some((index, file_start - block_start, block.offset));
+            }
+            if let Some((first_block, start_within, first_byte)) = first {
+                if file_end <= block_end {
+                    return Ok(BlockSpan {
+                        folder_index,
+                        first_block,
+                        last_block: index,
+                        start_within_first_block: start_within as u16,
+                        end_within_last_block: (file_end - block_start) as u16,
+                        byte_range: first_byte..block_byte_end,
+                    });
+                }
+            }
+            folder_offset = block_end;
+            last_block = Some(index);
         }
+        invalid_data!(
+            "File {:?} extends past the end of folder {}'s data \
+             ({} bytes in {} blocks)",
+            name,
+            folder_index,
+            folder_offset,
+            last_block.map_or(0, |index| index + 1)
+        );
     }
 
-    /// Returns a reader over the decompressed data in the specified folder.
-    fn read_folder(&mut self, index: usize) -> io::Result<FolderReader<R>> {
+    /// Copies the raw data blocks for the specified folder (including each
+    /// block's CFDATA header and reserve data, followed by its compressed
+    /// payload) byte-for-byte into the given writer, without decompressing
+    /// anything.  Returns the number of bytes written.
+    ///
+    /// The exported bytes can later be decoded with
+    /// [`FolderReader::from_block_stream`](crate::FolderReader::from_block_stream).
+    pub fn export_folder_raw<W: Write>(
+        &mut self,
+        index: usize,
+        mut writer: W,
+    ) -> io::Result<u64> {
         if index >= self.inner.folders.len() {
             invalid_input!(
                 "Folder index {} is out of range (cabinet has {} folders)",
@@ -186,23 +953,411 @@ impl<R: Read + Seek> Cabinet<R> {
                 self.inner.folders.len()
             );
         }
+        let folder = &self.inner.folders[index];
+        let reader = self.inner.reader.get_mut();
+        reader.seek(SeekFrom::Start(folder.first_data_block_offset as u64))?;
+        let mut header = vec![0u8; 8 + self.inner.data_reserve_size as usize];
+        let mut total_size = 0;
+        for _ in 0..folder.num_data_blocks() {
+            reader.read_exact(&mut header)?;
+            writer.write_all(&header)?;
+            let compressed_size = u16::from_le_bytes([header[4], header[5]]);
+            let copied = io::copy(
+                &mut reader.take(compressed_size as u64),
+                &mut writer,
+            )?;
+            if copied < compressed_size as u64 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            total_size += header.len() as u64 + copied;
+        }
+        Ok(total_size)
+    }
 
-        let me: &Cabinet<dyn ReadSeek> = self;
-        FolderReader::new(
-            me,
-            &self.inner.folders[index],
-            self.inner.data_reserve_size,
-        )
+    /// Estimates, for each folder containing files with more than one
+    /// extension, whether splitting the folder into one folder per extension
+    /// would make the cabinet smaller, and returns a suggestion for each
+    /// folder where it would.  Sizes are estimated by recompressing each
+    /// folder's data with MSZIP, regardless of the folder's actual
+    /// compression type, so this reads and compresses the entire cabinet.
+    pub fn suggest_groupings(
+        &mut self,
+    ) -> io::Result<Vec<GroupingSuggestion>> {
+        self.load_file_entries()?;
+        report::suggest_groupings(self)
+    }
+
+    /// Returns an iterator over readers for every file in the cabinet,
+    /// folder by folder, which share one decompressor per folder, so that
+    /// reading each file in turn decompresses the cabinet only once (see
+    /// [`FileReaders`]).  Files that can't be extracted from this cabinet
+    /// alone (see [`read_file`](Cabinet::read_file)) are yielded as errors.
+    ///
+    /// ```no_run
+    /// # use std::io::Read;
+    /// let mut cabinet = cab::Cabinet::new(std::fs::File::open("foo.cab")?)?;
+    /// for reader in cabinet.file_reader_iter()? {
+    ///     let mut reader = reader?;
+    ///     let mut data = Vec::new();
+    ///     reader.read_to_end(&mut data)?;
+    ///     println!("{}: {} bytes", reader.entry().name(), data.len());
+    /// }
+    /// # Ok::<(), std::io::Error>(())
+    /// ```
+    pub fn file_reader_iter(&mut self) -> io::Result<FileReaders<'_, R>> {
+        self.load_file_entries()?;
+        let inner: &CabinetInner<dyn ReadSeek> = &self.inner;
+        Ok(FileReaders::new(inner))
+    }
+
+    /// Returns a reader over the decompressed data in the specified folder,
+    /// that is, the data of all the folder's files, concatenated in the
+    /// order they're stored.  This is useful for treating a folder as a
+    /// single solid blob (e.g. to compute a delta between two versions of a
+    /// cabinet).  The returned reader can also seek, though seeking
+    /// backwards within a compressed folder means decompressing it again
+    /// from the start, and seeking relative to the end means first
+    /// decompressing the whole folder (to find out how big it is).
+    ///
+    /// Note that a folder continued from the previous cabinet in a set (see
+    /// [`FolderEntry::continuation`]) generally can't be decompressed
+    /// without the previous cabinet's data.
+    pub fn read_folder(
+        &mut self,
+        index: usize,
+    ) -> io::Result<FolderReader<'_, R>> {
+        if index >= self.inner.folders.len() {
+            invalid_input!(
+                "Folder index {} is out of range (cabinet has {} folders)",
+                index,
+                self.inner.folders.len()
+            );
+        }
+
+        let inner: &CabinetInner<dyn ReadSeek> = &self.inner;
+        FolderReader::new(BlockSource::Cabinet(inner), index)
+    }
+
+    /// Returns a reader for the folder that the given token was taken from
+    /// (see [`FolderReader::resume_token`]), positioned to continue reading
+    /// where that token was taken.
+    pub fn resume_folder(
+        &mut self,
+        token: &FolderResumeToken,
+    ) -> io::Result<FolderReader<'_, R>> {
+        let mut reader = self.read_folder(token.folder_index())?;
+        reader.resume(token)?;
+        Ok(reader)
     }
 }
 
-impl<'a, R: ?Sized + Read> Read for &'a CabinetInner<R> {
+impl<R: Read + Write + Seek> Cabinet<R> {
+    /// Converts this cabinet into a `CabinetAppender`, which can be used to
+    /// add new folders (and files) to the end of the existing cabinet file in
+    /// place, without rewriting the existing data.
+    ///
+    /// The underlying stream must allow both reading and writing (e.g. a
+    /// file opened with both read and write access).
+    pub fn into_appender(self) -> CabinetAppender<R> {
+        let inner = self.inner;
+        // If the file entries were never loaded, the appender measures the
+        // file directory itself.
+        let file_entries_size = inner.files_loaded.then(|| {
+            inner
+                .files
+                .iter()
+                .map(|file| 16 + file.stored_name_len() as u64)
+                .sum()
+        });
+        let info = AppendInfo {
+            total_size: inner.total_size,
+            first_folder_offset: inner.first_folder_offset,
+            first_file_offset: inner.first_file_offset,
+            file_entries_size,
+            num_folders: inner.folders.len(),
+            num_files: inner.num_files as usize,
+            folder_reserve_size: inner.folder_reserve_size,
+            data_reserve_size: inner.data_reserve_size,
+            folder_data_offsets: inner
+                .folders
+                .iter()
+                .map(|folder| {
+                    (folder.first_data_block_offset, folder.num_data_blocks())
+                })
+                .collect(),
+        };
+        CabinetAppender::new(inner.reader.into_inner(), info)
+    }
+
+    /// Updates the datetime and/or attribute bits of the named file, in
+    /// place, by patching its entry in the underlying cabinet file (see
+    /// [`FileEntry::patch_datetime`] and [`FileEntry::patch_attributes`]),
+    /// and updates this cabinet's copy of the entry to match.  Passing
+    /// `None` leaves that field as it is.  This makes metadata-only fixes
+    /// (such as normalizing timestamps) far cheaper than rewriting the
+    /// cabinet.
+    ///
+    /// The "name is UTF" attribute bit cannot be changed this way; if the
+    /// new attributes try to, nothing is written.  Requires the `time`
+    /// feature (enabled by default).
+    #[cfg(feature = "time")]
+    pub fn update_entry_metadata(
+        &mut self,
+        name: &str,
+        datetime: Option<PrimitiveDateTime>,
+        attributes: Option<u16>,
+    ) -> io::Result<()> {
+        self.load_file_entries()?;
+        let index = match self.file_position(name) {
+            Some(index) => index,
+            None => not_found!("No such file in cabinet: {:?}", name),
+        };
+        let inner = &mut self.inner;
+        let mut entry = inner.files[index].clone();
+        let writer = inner.reader.get_mut();
+        if let Some(attributes) = attributes {
+            entry.patch_attributes(writer, attributes)?;
+        }
+        if let Some(datetime) = datetime {
+            entry.patch_datetime(writer, datetime)?;
+        }
+        entry.set_metadata(datetime.map(datetime_to_bits), attributes);
+        let folder = &mut inner.folders[entry.folder_index as usize];
+        for file in folder.files.iter_mut() {
+            if file.entry_offset == entry.entry_offset {
+                *file = entry.clone();
+            }
+        }
+        inner.files[index] = entry;
+        Ok(())
+    }
+}
+
+/// Reads the header reserve data, which may be up to 64K in size (well
+/// beyond the 60,000-byte limit set by the spec, in some cabinets).
+fn read_reserve<R: Read>(reader: &mut R, size: u16) -> io::Result<Vec<u8>> {
+    let mut data = Vec::new();
+    reader.take(size as u64).read_to_end(&mut data)?;
+    if data.len() < size as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!(
+                "Header reserve data is truncated ({} of {} bytes)",
+                data.len(),
+                size
+            ),
+        ));
+    }
+    Ok(data)
+}
+
+/// Decodes any file names that aren't valid UTF-8 using a character encoding
+/// guessed from all such names in the cabinet together, since each name on
+/// its own is usually too short for a reliable guess.
+#[cfg(feature = "chardet")]
+fn detect_name_encoding(files: &mut [FileEntry]) {
+    if !files.iter().any(FileEntry::needs_name_detection) {
+        return;
+    }
+    let (encoding, confident) = crate::string::detect_encoding(
+        files
+            .iter()
+            .filter(|file| file.needs_name_detection())
+            .map(FileEntry::name_raw),
+    );
+    for file in files.iter_mut() {
+        if file.needs_name_detection() {
+            file.decode_name_with(encoding, confident);
+        }
+    }
+}
+
+/// Works out the size of the reserve data actually present in each data
+/// block header, for cabinets whose header may declare the wrong size.  Each
+/// possible size is tried by walking the chain of data blocks in every
+/// folder: a size is ruled out if any block would have an invalid size, run
+/// into the next folder's data (or past the end of the file), or fail its
+/// checksum.  Otherwise, it scores a point for each checksum that matches and
+/// for each folder whose blocks end exactly where the next folder's begin (or
+/// at the end of the file).  The declared size wins any ties.
+fn detect_data_reserve_size<R: Read + Seek>(
+    reader: &mut R,
+    folders: &[FolderEntry],
+    declared: u8,
+) -> io::Result<u8> {
+    let file_end = reader.seek(SeekFrom::End(0))?;
+    let mut boundaries: Vec<u64> = folders
+        .iter()
+        .filter(|folder| folder.num_data_blocks() > 0)
+        .map(|folder| folder.first_data_block_offset as u64)
+        .collect();
+    boundaries.push(file_end);
+    boundaries.sort_unstable();
+    boundaries.dedup();
+    let candidates = std::iter::once(declared)
+        .chain((0..=u8::MAX).filter(|&size| size != declared));
+    let mut best: Option<(usize, u8)> = None;
+    for reserve_size in candidates {
+        let score = score_data_reserve_size(
+            reader,
+            folders,
+            &boundaries,
+            reserve_size as u64,
+        )?;
+        if let Some(score) = score {
+            if best.is_none_or(|(best_score, _)| score > best_score) {
+                best = Some((score, reserve_size));
+            }
+        }
+    }
+    Ok(best.map_or(declared, |(_, reserve_size)| reserve_size))
+}
+
+/// Scores how well the given data reserve size fits the cabinet's data
+/// blocks (see `detect_data_reserve_size`), or returns `None` if it can't be
+/// right.
+fn score_data_reserve_size<R: Read + Seek>(
+    reader: &mut R,
+    folders: &[FolderEntry],
+    boundaries: &[u64],
+    reserve_size: u64,
+) -> io::Result<Option<usize>> {
+    // Only verify checksums for the first few blocks of each folder, to keep
+    // this cheap for large cabinets:
+    const MAX_CHECKSUMS_PER_FOLDER: usize = 4;
+    let mut score = 0;
+    for folder in folders.iter() {
+        if folder.num_data_blocks() == 0 {
+            continue;
+        }
+        let mut offset = folder.first_data_block_offset as u64;
+        let limit = match boundaries.iter().find(|&&end| end > offset) {
+            Some(&limit) => limit,
+            None => return Ok(None),
+        };
+        for index in 0..(folder.num_data_blocks() as usize) {
+            let data_offset = offset + 8 + reserve_size;
+            if data_offset > limit {
+                return Ok(None);
+            }
+            reader.seek(SeekFrom::Start(offset))?;
+            let checksum = reader.read_u32::<LittleEndian>()?;
+            let compressed_size = reader.read_u16::<LittleEndian>()?;
+            let uncompressed_size = reader.read_u16::<LittleEndian>()?;
+            let next_offset = data_offset + compressed_size as u64;
+            if compressed_size == 0
+                || uncompressed_size > 0x8000
+                || next_offset > limit
+            {
+                return Ok(None);
+            }
+            if checksum != 0 && index < MAX_CHECKSUMS_PER_FOLDER {
+                let size = reserve_size as usize + compressed_size as usize;
+                let mut data = vec![0u8; size];
+                reader.read_exact(&mut data)?;
+                let mut actual = Checksum::new();
+                actual.update(&data);
+                let actual = actual
+                    .data_block_value(compressed_size, uncompressed_size);
+                if actual != checksum {
+                    return Ok(None);
+                }
+                score += 1;
+            }
+            offset = next_offset;
+        }
+        if boundaries.contains(&offset) {
+            score += 1;
+        }
+    }
+    Ok(Some(score))
+}
+
+/// Checks that the given file can be extracted from this cabinet alone:
+/// that it isn't split across cabinets, and that its folder doesn't start in
+/// the previous cabinet.
+/// Maps the folder index field of a file entry to the index of the folder
+/// in this cabinet that holds the file's data, along with whether the file
+/// is continued from/to other cabinets in the set.  Such files refer to the
+/// first/last folder in this cabinet via special marker values.
+fn resolve_folder_index(
+    folder_index: u16,
+    num_folders: usize,
+) -> io::Result<(u16, Continuation)> {
+    let last_folder_index = num_folders.saturating_sub(1) as u16;
+    let (folder_index, continuation) = match folder_index {
+        consts::IFOLD_CONTINUED_FROM_PREV => (0, Continuation::FromPrev),
+        consts::IFOLD_CONTINUED_TO_NEXT => {
+            (last_folder_index, Continuation::ToNext)
+        }
+        consts::IFOLD_CONTINUED_PREV_AND_NEXT => {
+            if num_folders > 1 {
+                invalid_data!(
+                    "File continued from previous and to next cabinet, but \
+                     cabinet has multiple folders"
+                );
+            }
+            (0, Continuation::Both)
+        }
+        index => (index, Continuation::NotContinued),
+    };
+    if folder_index as usize >= num_folders {
+        invalid_data!("File entry folder index out of bounds");
+    }
+    Ok((folder_index, continuation))
+}
+
+/// Returns the characters of a file name, folded to lower case.
+fn fold_case(name: &str) -> impl Iterator<Item = char> + '_ {
+    name.chars().flat_map(char::to_lowercase)
+}
+
+/// Compares two file names ignoring case, breaking ties by comparing them
+/// exactly.
+fn compare_names(a: &str, b: &str) -> Ordering {
+    fold_case(a).cmp(fold_case(b)).then_with(|| a.cmp(b))
+}
+
+pub(crate) fn check_extractable<R: ?Sized>(
+    inner: &CabinetInner<R>,
+    file_entry: &FileEntry,
+) -> io::Result<()> {
+    let name = file_entry.name();
+    if file_entry.is_continued() {
+        invalid_input!(
+            "File {:?} is split across cabinets ({:?}); the other \
+             cabinet(s) in the set are needed to extract it",
+            name,
+            file_entry.continuation()
+        );
+    }
+    let folder_index = file_entry.folder_index as usize;
+    if inner.folders[folder_index].continuation().is_from_prev() {
+        invalid_input!(
+            "File {:?} is in a folder continued from the previous \
+             cabinet, which is needed to extract it",
+            name
+        );
+    }
+    let size = file_entry.uncompressed_size() as u64;
+    if size > inner.options.max_file_size {
+        invalid_data!(
+            "File {:?} is {} bytes, more than the maximum of {}",
+            name,
+            size,
+            inner.options.max_file_size
+        );
+    }
+    Ok(())
+}
+
+impl<R: ?Sized + Read> Read for &CabinetInner<R> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         self.reader.borrow_mut().read(buf)
     }
 }
 
-impl<'a, R: ?Sized + Seek> Seek for &'a CabinetInner<R> {
+impl<R: ?Sized + Seek> Seek for &CabinetInner<R> {
     fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
         self.reader.borrow_mut().seek(pos)
     }
@@ -210,11 +1365,21 @@ impl<'a, R: ?Sized + Seek> Seek for &'a CabinetInner<R> {
 
 #[cfg(test)]
 mod tests {
-    use std::io::{Cursor, Read};
+    use std::io::{self, Cursor, Read};
+
+    #[cfg(feature = "time")]
+    use time::macros::datetime;
 
     use super::Cabinet;
+    use crate::ctype::{CompressionType, InvalidWindowSize};
+    use crate::extract::FileStatus;
+    use crate::folder::{BlockError, FolderReader};
+    use crate::options::{ReadOptions, SizeMismatch};
+    use crate::string::NameDecoding;
+    use crate::warning::Warning;
 
     #[test]
+    #[cfg(feature = "time")]
     fn read_uncompressed_cabinet_with_one_file() {
         let binary: &[u8] = b"MSCF\0\0\0\0\x59\0\0\0\0\0\0\0\
             \x2c\0\0\0\0\0\0\0\x03\x01\x01\0\x01\0\0\0\x34\x12\0\0\
@@ -274,6 +1439,51 @@ mod tests {
         assert_eq!(data, b"See you later!\n");
     }
 
+    #[test]
+    fn visit_entries_without_loading_them() {
+        let binary: &[u8] = b"MSCF\0\0\0\0\x80\0\0\0\0\0\0\0\
+            \x2c\0\0\0\0\0\0\0\x03\x01\x01\0\x02\0\0\0\x34\x12\0\0\
+            \x5b\0\0\0\x01\0\0\0\
+            \x0e\0\0\0\0\0\0\0\0\0\x6c\x22\xe7\x59\x01\0hi.txt\0\
+            \x0f\0\0\0\x0e\0\0\0\0\0\x6c\x22\xe7\x59\x01\0bye.txt\0\
+            \0\0\0\0\x1d\0\x1d\0Hello, world!\nSee you later!\n";
+        let mut options = ReadOptions::new();
+        options.defer_file_entries(true);
+        let mut cabinet = options.open(Cursor::new(binary)).unwrap();
+        let mut visited = Vec::new();
+        cabinet
+            .visit_entries(|folder_index, folder, file| {
+                assert_eq!(folder.compression_type(), CompressionType::None);
+                assert_eq!(folder.num_data_blocks(), 1);
+                assert_eq!(file.folder_index(), folder_index);
+                #[cfg(feature = "time")]
+                assert!(file.datetime().is_some());
+                assert_eq!(file.index(), visited.len());
+                visited.push((
+                    file.name().into_owned(),
+                    file.uncompressed_offset(),
+                    file.uncompressed_size(),
+                    file.entry_offset(),
+                ));
+            })
+            .unwrap();
+        assert!(!cabinet.file_entries_loaded());
+        assert_eq!(
+            visited,
+            vec![
+                ("hi.txt".to_string(), 0, 14, 0x2c),
+                ("bye.txt".to_string(), 14, 15, 0x43),
+            ]
+        );
+
+        cabinet.load_file_entries().unwrap();
+        let entries: Vec<(usize, u64)> = cabinet
+            .file_entries_in_directory_order()
+            .map(|file| (file.index(), file.entry_offset()))
+            .collect();
+        assert_eq!(entries, vec![(0, 0x2c), (1, 0x43)]);
+    }
+
     #[test]
     fn read_uncompressed_cabinet_with_two_data_blocks() {
         let binary: &[u8] = b"MSCF\0\0\0\0\x61\0\0\0\0\0\0\0\
@@ -286,7 +1496,7 @@ mod tests {
         let mut cabinet = Cabinet::new(Cursor::new(binary)).unwrap();
         assert_eq!(cabinet.folder_entries().len(), 1);
         assert_eq!(
-            cabinet.folder_entries().nth(0).unwrap().num_data_blocks(),
+            cabinet.folder_entries().next().unwrap().num_data_blocks(),
             2
         );
 
@@ -348,6 +1558,129 @@ mod tests {
         assert_eq!(data, b"See you later!\n");
     }
 
+    #[test]
+    fn export_raw_folder_data() {
+        let binary: &[u8] = b"MSCF\0\0\0\0\x61\0\0\0\0\0\0\0\
+            \x2c\0\0\0\0\0\0\0\x03\x01\x01\0\x01\0\0\0\x34\x12\0\0\
+            \x43\0\0\0\x02\0\0\0\
+            \x0e\0\0\0\0\0\0\0\0\0\x6c\x22\xba\x59\x01\0hi.txt\0\
+            \0\0\0\0\x06\0\x06\0Hello,\
+            \0\0\0\0\x08\0\x08\0 world!\n";
+        let mut cabinet = Cabinet::new(Cursor::new(binary)).unwrap();
+        let mut raw = Vec::new();
+        assert_eq!(cabinet.export_folder_raw(0, &mut raw).unwrap(), 0x1e);
+        assert_eq!(raw.as_slice(), &binary[0x43..]);
+        assert!(cabinet.export_folder_raw(1, &mut raw).is_err());
+
+        let mut reader = FolderReader::<&[u8]>::from_block_stream(
+            raw.as_slice(),
+            CompressionType::None,
+            2,
+            0,
+        )
+        .unwrap();
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"Hello, world!\n");
+    }
+
+    #[test]
+    fn continue_past_damaged_block() {
+        use rand::{RngCore, SeedableRng};
+        use std::io::Write;
+
+        let mut original = vec![0u8; 40000];
+        rand::rngs::SmallRng::seed_from_u64(2116).fill_bytes(&mut original);
+        let mut builder = crate::CabinetBuilder::new();
+        builder.add_folder(CompressionType::MsZip).add_file("data.bin");
+        let mut cab_writer = builder.build(Cursor::new(Vec::new())).unwrap();
+        while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+            file_writer.write_all(&original).unwrap();
+        }
+        let mut binary = cab_writer.finish().unwrap().into_inner();
+        // Corrupt the first data block's payload (but not its checksum):
+        let offset = u32::from_le_bytes(binary[36..40].try_into().unwrap());
+        binary[offset as usize + 1000] ^= 0xff;
+
+        let mut cabinet = Cabinet::new(Cursor::new(&binary)).unwrap();
+        assert!(cabinet
+            .read_file("data.bin")
+            .and_then(|mut reader| reader.read_to_end(&mut Vec::new()))
+            .is_err());
+
+        cabinet.set_continue_on_error(true);
+        let mut reader = cabinet.read_file("data.bin").unwrap();
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).unwrap();
+        assert_eq!(data.len(), original.len());
+        assert!(data[..0x8000].iter().all(|&byte| byte == 0));
+        assert_eq!(&data[0x8000..], &original[0x8000..]);
+        assert_eq!(reader.damaged_ranges(), vec![0..0x8000]);
+    }
+
+    #[test]
+    fn continue_past_damaged_lzx_block() {
+        use rand::{RngCore, SeedableRng};
+
+        // An LZX stream holding a single uncompressed LZX block, which spans
+        // three data blocks, so each data block depends on the decoder's
+        // state from the one before.
+        let mut original = vec![0u8; 70000];
+        rand::rngs::SmallRng::seed_from_u64(2116).fill_bytes(&mut original);
+        let size = original.len() as u32;
+        let mut stream = Vec::new();
+        // No E8 translation; block type 3 (uncompressed); 24-bit size; then
+        // padding to a 16-bit boundary, and R0-R2:
+        stream
+            .extend_from_slice(&(0x3000 | (size >> 12) as u16).to_le_bytes());
+        stream
+            .extend_from_slice(&(((size & 0xfff) << 4) as u16).to_le_bytes());
+        for _ in 0..3 {
+            stream.extend_from_slice(&1u32.to_le_bytes());
+        }
+        let header_len = stream.len();
+        stream.extend_from_slice(&original);
+
+        let mut builder = crate::CabinetBuilder::new();
+        let folder =
+            builder.add_folder(CompressionType::Lzx(lzxd::WindowSize::KB64));
+        folder.set_precompressed(true);
+        folder.add_file("data.bin").set_uncompressed_size(size);
+        let mut cab_writer = builder.build(Cursor::new(Vec::new())).unwrap();
+        let mut start = 0;
+        for chunk in original.chunks(0x8000) {
+            let end = header_len + start + chunk.len();
+            let payload =
+                &stream[if start == 0 { 0 } else { header_len + start }..end];
+            cab_writer
+                .write_precompressed_block(payload, chunk.len() as u16)
+                .unwrap();
+            start += chunk.len();
+        }
+        let mut binary = cab_writer.finish().unwrap().into_inner();
+
+        let mut cabinet = Cabinet::new(Cursor::new(&binary)).unwrap();
+        let mut data = Vec::new();
+        cabinet.read_file("data.bin").unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, original);
+
+        // Corrupt the first data block's payload (but not its checksum).
+        // Since the damaged block is still fed to the decoder, the decoder
+        // stays in sync with the LZX stream, and the blocks after it decode
+        // correctly:
+        let offset = u32::from_le_bytes(binary[36..40].try_into().unwrap());
+        binary[offset as usize + 1000] ^= 0xff;
+        let mut cabinet = Cabinet::new(Cursor::new(&binary)).unwrap();
+        cabinet.set_continue_on_error(true);
+        let mut reader = cabinet.read_file("data.bin").unwrap();
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).unwrap();
+        assert_eq!(data.len(), original.len());
+        assert!(data[..0x8000].iter().all(|&byte| byte == 0));
+        assert_eq!(&data[0x8000..], &original[0x8000..]);
+        assert_eq!(reader.damaged_ranges(), vec![0..0x8000]);
+    }
+
     #[test]
     fn read_lzx_cabinet_with_two_files() {
         let binary: &[u8] =
@@ -376,6 +1709,28 @@ mod tests {
         let mut data = Vec::new();
         cabinet.read_file("bye.txt").unwrap().read_to_end(&mut data).unwrap();
         assert_eq!(data, b"See you later!\r\n");
+
+        // The folder's only block is an uncompressed LZX block, which reads
+        // the same with any window size, including ones beyond the 2^21
+        // bytes allowed by the spec:
+        let mut binary = binary.to_vec();
+        assert_eq!(binary[43], 19);
+        binary[43] = 25;
+        let mut cabinet = Cabinet::new(Cursor::new(&binary)).unwrap();
+        assert_eq!(
+            cabinet.folder_entries().next().unwrap().compression_type(),
+            CompressionType::Lzx(lzxd::WindowSize::MB32)
+        );
+        let mut data = Vec::new();
+        cabinet.read_file("hi.txt").unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"Hello, world!\r\n");
+
+        binary[43] = 26;
+        let error = Cabinet::new(Cursor::new(&binary)).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        let error = error.into_inner().unwrap();
+        let error = error.downcast::<InvalidWindowSize>().unwrap();
+        assert_eq!(error.exponent(), 26);
     }
 
     #[test]
@@ -391,6 +1746,13 @@ mod tests {
             let file_entry = cabinet.get_file_entry("\u{2603}.txt").unwrap();
             assert_eq!(file_entry.name(), "\u{2603}.txt");
             assert!(file_entry.is_name_utf());
+            assert_eq!(file_entry.name_decoding(), NameDecoding::Utf8);
+            // UTF-8 names are borrowed from the raw bytes, not copied.
+            assert_eq!(file_entry.name_bytes(), "\u{2603}.txt".as_bytes());
+            assert_eq!(
+                file_entry.name().as_ptr(),
+                file_entry.name_bytes().as_ptr()
+            );
         }
         {
             let mut file_reader = cabinet.read_file("\u{2603}.txt").unwrap();
@@ -399,4 +1761,703 @@ mod tests {
             assert_eq!(data, b"Snowman!\n");
         }
     }
+
+    #[cfg(feature = "chardet")]
+    #[test]
+    fn detect_shift_jis_filenames() {
+        use crate::builder::CabinetBuilder;
+        use std::io::Write;
+
+        let names = ["ドライバー.inf", "説明書.txt", "設定ファイル.ini"];
+        let encoded: Vec<Vec<u8>> = names
+            .iter()
+            .map(|name| encoding_rs::SHIFT_JIS.encode(name).0.into_owned())
+            .collect();
+        let placeholders: Vec<String> = encoded
+            .iter()
+            .enumerate()
+            .map(|(index, raw)| format!("{}", index).repeat(raw.len()))
+            .collect();
+        let mut builder = CabinetBuilder::new();
+        let folder = builder.add_folder(CompressionType::None);
+        for placeholder in placeholders.iter() {
+            folder.add_file(placeholder.as_str());
+        }
+        let mut writer = builder.build(Cursor::new(Vec::new())).unwrap();
+        while let Some(mut file) = writer.next_file().unwrap() {
+            file.write_all(b"data").unwrap();
+        }
+        let mut binary = writer.finish().unwrap().into_inner();
+        for (placeholder, raw) in placeholders.iter().zip(encoded.iter()) {
+            let start = binary
+                .windows(raw.len())
+                .position(|window| window == placeholder.as_bytes())
+                .unwrap();
+            binary[start..(start + raw.len())].copy_from_slice(raw);
+        }
+
+        let cabinet = Cabinet::new(Cursor::new(binary)).unwrap();
+        for ((file, name), raw) in cabinet
+            .folder_entries()
+            .next()
+            .unwrap()
+            .file_entries()
+            .zip(names)
+            .zip(encoded.iter())
+        {
+            assert!(!file.is_name_utf());
+            assert_eq!(file.name_raw(), raw.as_slice());
+            assert_eq!(file.name(), name);
+            assert!(matches!(
+                file.name_decoding(),
+                NameDecoding::Detected { encoding: "Shift_JIS", .. }
+            ));
+        }
+        let file = cabinet.get_file_entry("説明書.txt").unwrap();
+        assert_eq!(file.name_raw(), encoded[1].as_slice());
+    }
+
+    fn build_cabinet_with_paths(names: &[&str]) -> Vec<u8> {
+        use crate::builder::CabinetBuilder;
+        use std::io::Write;
+
+        let mut builder = CabinetBuilder::new();
+        let folder = builder.add_folder(CompressionType::None);
+        for &name in names {
+            folder.add_file(name);
+        }
+        let mut writer = builder.build(Cursor::new(Vec::new())).unwrap();
+        while let Some(mut file) = writer.next_file().unwrap() {
+            file.write_all(b"data").unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn query_files_by_name_and_prefix() {
+        let binary = build_cabinet_with_paths(&[
+            "setup.inf",
+            "drivers\\x64\\foo.sys",
+            "drivers\\x86\\foo.sys",
+            "drivers\\x64\\foo.inf",
+        ]);
+        let cabinet = Cabinet::new(Cursor::new(binary)).unwrap();
+        assert!(cabinet.contains("setup.inf"));
+        assert!(!cabinet.contains("SETUP.INF"));
+        assert!(!cabinet.contains("drivers"));
+        let names: Vec<&str> = cabinet
+            .files_with_prefix("drivers\\x64\\")
+            .map(|file| file.name())
+            .collect();
+        assert_eq!(
+            names,
+            vec!["drivers\\x64\\foo.sys", "drivers\\x64\\foo.inf"]
+        );
+    }
+
+    #[cfg(feature = "globset")]
+    #[test]
+    fn query_files_by_glob() {
+        let binary = build_cabinet_with_paths(&[
+            "setup.inf",
+            "drivers\\x64\\foo.sys",
+            "drivers\\x86\\foo.sys",
+            "drivers\\x64\\foo.inf",
+        ]);
+        let cabinet = Cabinet::new(Cursor::new(binary)).unwrap();
+        let names = |pattern: &str| -> Vec<String> {
+            cabinet
+                .files_matching(pattern)
+                .unwrap()
+                .map(|file| file.name().to_string())
+                .collect()
+        };
+        assert_eq!(names("*.inf"), vec!["setup.inf"]);
+        assert_eq!(
+            names("**/*.inf"),
+            vec!["setup.inf", "drivers\\x64\\foo.inf"]
+        );
+        assert_eq!(
+            names("drivers\\*\\foo.sys"),
+            vec!["drivers\\x64\\foo.sys", "drivers\\x86\\foo.sys"]
+        );
+        assert!(cabinet.files_matching("[").is_err());
+    }
+
+    /// Builds a cabinet with two uncompressed folders, then patches it to
+    /// look like the middle cabinet of a set, with the given special folder
+    /// indices for its first and last files.
+    fn build_middle_cabinet(first_ifold: u16, last_ifold: u16) -> Vec<u8> {
+        let mut builder = crate::builder::CabinetBuilder::new();
+        builder.add_folder(CompressionType::None).add_file("prev.txt");
+        let folder = builder.add_folder(CompressionType::None);
+        folder.add_file("mid.txt");
+        folder.add_file("next.txt");
+        let mut writer = builder.build(Cursor::new(Vec::new())).unwrap();
+        while let Some(mut file) = writer.next_file().unwrap() {
+            std::io::Write::write_all(&mut file, b"data").unwrap();
+        }
+        let mut binary = writer.finish().unwrap().into_inner();
+
+        let names = b"one.cab\0disk 1\0three.cab\0disk 3\0";
+        let shift = names.len() as u32;
+        let patch_u32 = |binary: &mut Vec<u8>, offset: usize| {
+            let bytes: [u8; 4] = binary[offset..][..4].try_into().unwrap();
+            let value = u32::from_le_bytes(bytes) + shift;
+            binary[offset..][..4].copy_from_slice(&value.to_le_bytes());
+        };
+        patch_u32(&mut binary, 8); // total size
+        patch_u32(&mut binary, 16); // first file offset
+        patch_u32(&mut binary, 36); // folder 0 first data block
+        patch_u32(&mut binary, 44); // folder 1 first data block
+        binary[30] |= 0x3; // prev and next cabinet flags
+        binary.splice(36..36, names.iter().copied());
+        let first_file = 36 + names.len() + 16;
+        binary[(first_file + 8)..][..2]
+            .copy_from_slice(&first_ifold.to_le_bytes());
+        let last_file = first_file + (17 + 8) + (17 + 7);
+        binary[(last_file + 8)..][..2]
+            .copy_from_slice(&last_ifold.to_le_bytes());
+        binary
+    }
+
+    #[test]
+    fn detect_cabinet_set_membership() {
+        use crate::folder::Continuation;
+
+        let binary = build_cabinet_with_paths(&["a.txt"]);
+        let cabinet = Cabinet::new(Cursor::new(binary)).unwrap();
+        assert!(!cabinet.is_multi_part());
+        assert!(!cabinet.has_continued_files());
+        assert_eq!(cabinet.prev_cabinet_name(), None);
+        assert_eq!(
+            cabinet.folder_entries().next().unwrap().continuation(),
+            Continuation::NotContinued
+        );
+
+        let binary = build_middle_cabinet(0xfffd, 0xfffe);
+        let cabinet = Cabinet::new(Cursor::new(binary)).unwrap();
+        assert!(cabinet.is_multi_part());
+        assert!(cabinet.has_continued_files());
+        assert_eq!(cabinet.prev_cabinet_name(), Some("one.cab"));
+        assert_eq!(cabinet.prev_disk_name(), Some("disk 1"));
+        assert_eq!(cabinet.next_cabinet_name(), Some("three.cab"));
+        assert_eq!(cabinet.next_disk_name(), Some("disk 3"));
+        let continuations: Vec<Continuation> = cabinet
+            .folder_entries()
+            .map(|folder| folder.continuation())
+            .collect();
+        assert_eq!(
+            continuations,
+            vec![Continuation::FromPrev, Continuation::ToNext]
+        );
+        let folder_files: Vec<usize> = cabinet
+            .folder_entries()
+            .map(|folder| folder.file_entries().len())
+            .collect();
+        assert_eq!(folder_files, vec![1, 2]);
+
+        // A set can also be split at a folder boundary, without any files
+        // being continued:
+        let binary = build_middle_cabinet(0, 1);
+        let cabinet = Cabinet::new(Cursor::new(binary)).unwrap();
+        assert!(cabinet.is_multi_part());
+        assert!(!cabinet.has_continued_files());
+        assert!(
+            cabinet
+                .folder_entries()
+                .all(|folder| folder.continuation()
+                    == Continuation::NotContinued)
+        );
+    }
+
+    #[test]
+    fn read_non_continued_files_in_cabinet_set() {
+        use crate::folder::Continuation;
+
+        let binary = build_middle_cabinet(0xfffd, 0xfffe);
+        let mut cabinet = Cabinet::new(Cursor::new(binary)).unwrap();
+        let continuations: Vec<(&str, Continuation)> = cabinet
+            .folder_entries()
+            .flat_map(|folder| folder.file_entries())
+            .map(|file| (file.name(), file.continuation()))
+            .collect();
+        assert_eq!(
+            continuations,
+            vec![
+                ("prev.txt", Continuation::FromPrev),
+                ("mid.txt", Continuation::NotContinued),
+                ("next.txt", Continuation::ToNext),
+            ]
+        );
+        let mut data = Vec::new();
+        cabinet.read_file("mid.txt").unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"data");
+        assert!(cabinet.read_file("prev.txt").is_err());
+        assert!(cabinet.read_file("next.txt").is_err());
+
+        let binary = build_middle_cabinet(0xffff, 1);
+        assert!(Cabinet::new(Cursor::new(binary)).is_err());
+    }
+
+    fn open_owned_file_reader(
+        binary: Vec<u8>,
+        name: &str,
+    ) -> std::io::Result<crate::FileReader<'static, Cursor<Vec<u8>>>> {
+        Cabinet::new(Cursor::new(binary))?.into_file_reader(name)
+    }
+
+    #[test]
+    fn read_file_with_owned_reader() {
+        let binary = build_cabinet_with_paths(&["a.txt", "b.txt"]);
+        let mut reader =
+            open_owned_file_reader(binary.clone(), "b.txt").unwrap();
+        assert_eq!(reader.len(), 4);
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"data");
+
+        let error = open_owned_file_reader(binary, "c.txt").err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn list_data_block_headers() {
+        let binary: &[u8] = b"MSCF\0\0\0\0\x61\0\0\0\0\0\0\0\
+            \x2c\0\0\0\0\0\0\0\x03\x01\x01\0\x01\0\0\0\x34\x12\0\0\
+            \x43\0\0\0\x02\0\0\0\
+            \x0e\0\0\0\0\0\0\0\0\0\x6c\x22\xba\x59\x01\0hi.txt\0\
+            \x4c\x1a\x2e\x7f\x06\0\x06\0Hello,\
+            \0\0\0\0\x08\0\x08\0 world!\n";
+        assert_eq!(binary.len(), 0x61);
+        let mut cabinet = Cabinet::new(Cursor::new(binary)).unwrap();
+        assert_eq!(cabinet.version(), (1, 3));
+        assert_eq!(cabinet.total_size(), 0x61);
+        assert_eq!(cabinet.first_file_offset(), 0x2c);
+        let blocks: Vec<(u64, u32, u16, u16)> = cabinet
+            .data_blocks(0)
+            .unwrap()
+            .map(|block| {
+                let block = block.unwrap();
+                (
+                    block.offset(),
+                    block.checksum(),
+                    block.compressed_size(),
+                    block.uncompressed_size(),
+                )
+            })
+            .collect();
+        assert_eq!(blocks, vec![(0x43, 0x7f2e1a4c, 6, 6), (0x51, 0, 8, 8)]);
+        assert!(cabinet.data_blocks(1).is_err());
+    }
+
+    #[test]
+    fn detect_undeclared_data_reserve() {
+        // The cabinet header declares no reserve data, but each data block
+        // header has two bytes of it anyway:
+        let binary: &[u8] = b"MSCF\0\0\0\0\x65\0\0\0\0\0\0\0\
+            \x2c\0\0\0\0\0\0\0\x03\x01\x01\0\x01\0\0\0\x34\x12\0\0\
+            \x43\0\0\0\x02\0\0\0\
+            \x0e\0\0\0\0\0\0\0\0\0\x6c\x22\xba\x59\x01\0hi.txt\0\
+            \0\0\0\0\x06\0\x06\0\xaa\xbbHello,\
+            \0\0\0\0\x08\0\x08\0\xcc\xdd world!\n";
+        assert_eq!(binary.len(), 0x65);
+        let mut cabinet = Cabinet::new(Cursor::new(binary)).unwrap();
+        assert_eq!(cabinet.data_reserve_discrepancy(), None);
+        let mut data = Vec::new();
+        assert!(cabinet
+            .read_folder(0)
+            .unwrap()
+            .read_to_end(&mut data)
+            .is_err());
+
+        let mut cabinet = ReadOptions::new()
+            .detect_data_reserve(true)
+            .open(Cursor::new(binary))
+            .unwrap();
+        assert_eq!(cabinet.data_reserve_size(), 2);
+        assert_eq!(cabinet.data_reserve_discrepancy(), Some((0, 2)));
+        let mut data = Vec::new();
+        cabinet.read_file("hi.txt").unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"Hello, world!\n");
+        let reserves: Vec<Vec<u8>> = cabinet
+            .data_blocks(0)
+            .unwrap()
+            .map(|block| block.unwrap().reserve_data().to_vec())
+            .collect();
+        assert_eq!(reserves, vec![vec![0xaa, 0xbb], vec![0xcc, 0xdd]]);
+    }
+
+    #[test]
+    fn read_utf16_filename_with_lenient_names() {
+        use std::io::Write;
+
+        let binary: &[u8] = b"MSCF\0\0\0\0\x60\0\0\0\0\0\0\0\
+            \x2c\0\0\0\0\0\0\0\x03\x01\x01\0\x01\0\0\0\x34\x12\0\0\
+            \x4a\0\0\0\x01\0\0\0\
+            \x0e\0\0\0\0\0\0\0\0\0\x6c\x22\xba\x59\x01\0\
+            h\0i\0.\0t\0x\0t\0\0\0\
+            \x4c\x1a\x2e\x7f\x0e\0\x0e\0Hello, world!\n";
+        assert_eq!(binary.len(), 0x60);
+        let cabinet = Cabinet::new(Cursor::new(binary)).unwrap();
+        assert!(cabinet.get_file_entry("h").is_some());
+
+        let mut cabinet = ReadOptions::new()
+            .lenient_names(true)
+            .open(Cursor::new(binary.to_vec()))
+            .unwrap();
+        {
+            let file = cabinet.get_file_entry("hi.txt").unwrap();
+            assert_eq!(file.name_decoding(), NameDecoding::Utf16);
+            assert_eq!(file.name_raw(), b"h\0i\0.\0t\0x\0t\0");
+        }
+        let mut data = Vec::new();
+        cabinet.read_file("hi.txt").unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"Hello, world!\n");
+
+        // Appending to the cabinet keeps the UTF-16 entry intact:
+        let mut appender = cabinet.into_appender();
+        appender.add_folder(CompressionType::None).add_file("bye.txt");
+        let mut cab_writer = appender.build().unwrap();
+        while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+            file_writer.write_all(b"See you later!\n").unwrap();
+        }
+        let binary = cab_writer.finish().unwrap().into_inner();
+        let mut cabinet = ReadOptions::new()
+            .lenient_names(true)
+            .open(Cursor::new(binary))
+            .unwrap();
+        for (name, expected) in
+            [("hi.txt", "Hello, world!\n"), ("bye.txt", "See you later!\n")]
+        {
+            let mut data = String::new();
+            cabinet
+                .read_file(name)
+                .unwrap()
+                .read_to_string(&mut data)
+                .unwrap();
+            assert_eq!(data, expected);
+        }
+
+        // Visiting the entries decodes the UTF-16 name the same way, and
+        // steps over its full stored length to reach the next entry:
+        let expected: Vec<(String, u64)> = cabinet
+            .folder_entries()
+            .flat_map(|folder| folder.file_entries())
+            .map(|file| (file.name().to_string(), file.entry_offset()))
+            .collect();
+        let mut visited = Vec::new();
+        cabinet
+            .visit_entries(|_, _, file| {
+                visited.push((file.name().into_owned(), file.entry_offset()));
+            })
+            .unwrap();
+        assert_eq!(visited[0].0, "hi.txt");
+        assert_eq!(visited, expected);
+    }
+
+    #[test]
+    fn collect_warnings() {
+        let binary: &[u8] = b"MSCF\0\0\0\0\x80\0\0\0\0\0\0\0\
+            \x2c\0\0\0\0\0\0\0\x03\x01\x01\0\x02\0\0\0\x34\x12\0\0\
+            \x5b\0\0\0\x01\0\0\0\
+            \x0e\0\0\0\0\0\0\0\0\0\x6c\x22\xe7\x59\x01\0hi.txt\0\
+            \x0f\0\0\0\x0e\0\0\0\0\0\x6c\x22\xe7\x59\x01\0bye.txt\0\
+            \0\0\0\0\x1d\0\x1d\0Hello, world!\nSee you later!\n";
+        let cabinet = Cabinet::new(Cursor::new(binary)).unwrap();
+        assert_eq!(cabinet.warnings(), &[]);
+
+        let mut binary = binary.to_vec();
+        binary[24] = 0x02; // version 1.2
+        binary[30] = 0x10; // unknown flag
+        binary[0x47] = 0x0a; // bye.txt starts within hi.txt
+        let cabinet = Cabinet::new(Cursor::new(binary)).unwrap();
+        assert_eq!(
+            cabinet.warnings(),
+            &[
+                Warning::UnknownFlags(0x10),
+                Warning::OldVersion { major: 1, minor: 2 },
+                Warning::OverlappingFiles {
+                    folder_index: 0,
+                    first: "hi.txt".to_string(),
+                    second: "bye.txt".to_string(),
+                },
+            ]
+        );
+        assert_eq!(
+            cabinet.warnings()[2].to_string(),
+            "Files \"hi.txt\" and \"bye.txt\" in folder 0 overlap"
+        );
+    }
+
+    #[test]
+    fn block_transform_gets_data_block_reserve() {
+        struct XorWithReserve;
+
+        impl crate::transform::BlockTransform for XorWithReserve {
+            fn encode(
+                &self,
+                _folder_index: usize,
+                _block_index: usize,
+                payload: Vec<u8>,
+            ) -> io::Result<Vec<u8>> {
+                Ok(payload)
+            }
+
+            fn decode(
+                &self,
+                _folder_index: usize,
+                _block_index: usize,
+                reserve: &[u8],
+                payload: Vec<u8>,
+            ) -> io::Result<Vec<u8>> {
+                Ok(payload.into_iter().map(|byte| byte ^ reserve[0]).collect())
+            }
+        }
+
+        let binary: &[u8] = b"MSCF\0\0\0\0\x55\0\0\0\0\0\0\0\
+            \x30\0\0\0\0\0\0\0\x03\x01\x01\0\x01\0\x04\0\0\0\0\0\
+            \0\0\0\x01\
+            \x47\0\0\0\x01\0\0\0\
+            \x05\0\0\0\0\0\0\0\0\0\x6c\x22\xba\x59\x20\0hi.txt\0\
+            \0\0\0\0\x05\0\x05\0\x20HELLO";
+        assert_eq!(binary.len(), 0x55);
+        let mut cabinet = Cabinet::new(Cursor::new(binary)).unwrap();
+        cabinet.set_block_transform(Some(std::sync::Arc::new(XorWithReserve)));
+        let mut data = Vec::new();
+        cabinet.read_file("hi.txt").unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"hello");
+    }
+
+    #[test]
+    fn file_entries_overlapping_folder_entries() {
+        let mut binary = b"MSCF\0\0\0\0\x59\0\0\0\0\0\0\0\
+            \x2c\0\0\0\0\0\0\0\x03\x01\x01\0\x01\0\0\0\x34\x12\0\0\
+            \x43\0\0\0\x01\0\0\0\
+            \x0e\0\0\0\0\0\0\0\0\0\x6c\x22\xba\x59\x01\0hi.txt\0\
+            \x4c\x1a\x2e\x7f\x0e\0\x0e\0Hello, world!\n"
+            .to_vec();
+        binary[16] = 0x28; // file entries start within the folder entry
+
+        let error = Cabinet::new(Cursor::new(&binary)).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            error.to_string(),
+            "File entries start at offset 40, before the end of the folder \
+             entries at offset 44"
+        );
+
+        let mut options = ReadOptions::new();
+        options.lenient_layout(true);
+        let cabinet = options.open(Cursor::new(&binary)).unwrap();
+        assert_eq!(
+            cabinet.warnings(),
+            &[Warning::MisplacedFileEntries {
+                first_file_offset: 0x28,
+                folder_entries_end: 0x2c,
+            }]
+        );
+        assert_eq!(cabinet.first_file_offset(), 0x2c);
+        let file = cabinet.file_entries_in_directory_order().next().unwrap();
+        assert_eq!(file.entry_offset(), 0x2c);
+        assert_eq!(file.name(), "hi.txt");
+        assert_eq!(file.uncompressed_size(), 14);
+    }
+
+    #[test]
+    fn warn_about_files_in_folder_without_data_blocks() {
+        let binary: &[u8] = b"MSCF\0\0\0\0\x59\0\0\0\0\0\0\0\
+            \x2c\0\0\0\0\0\0\0\x03\x01\x01\0\x01\0\0\0\x34\x12\0\0\
+            \x43\0\0\0\0\0\0\0\
+            \x0e\0\0\0\0\0\0\0\0\0\x6c\x22\xba\x59\x01\0hi.txt\0\
+            \x4c\x1a\x2e\x7f\x0e\0\x0e\0Hello, world!\n";
+        let cabinet = Cabinet::new(Cursor::new(binary)).unwrap();
+        assert_eq!(
+            cabinet.warnings(),
+            &[Warning::EmptyFolderWithFiles { folder_index: 0, num_files: 1 }]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "time")]
+    fn audit_invalid_datetimes() {
+        let mut binary = b"MSCF\0\0\0\0\x59\0\0\0\0\0\0\0\
+            \x2c\0\0\0\0\0\0\0\x03\x01\x01\0\x01\0\0\0\0\0\0\0\
+            \x43\0\0\0\x01\0\0\0\
+            \x0e\0\0\0\0\0\0\0\0\0\x6c\x22\xba\x59\x20\0hi.txt\0\
+            \x4c\x1a\x2e\x7f\x0e\0\x0e\0Hello, world!\n"
+            .to_vec();
+        let cabinet = Cabinet::new(Cursor::new(&binary)).unwrap();
+        assert_eq!(cabinet.files_with_invalid_datetimes().count(), 0);
+        binary[0x36] = 0x0c; // month 0
+        let cabinet = Cabinet::new(Cursor::new(&binary)).unwrap();
+        let file = cabinet.files_with_invalid_datetimes().next().unwrap();
+        assert_eq!(file.name(), "hi.txt");
+        assert_eq!(file.datetime(), None);
+        assert_eq!(file.raw_datetime(), (0x220c, 0x59ba));
+        let default = datetime!(1980-01-01 0:00:00);
+        let cabinet = ReadOptions::new()
+            .default_datetime(default)
+            .open(Cursor::new(&binary))
+            .unwrap();
+        let file = cabinet.get_file_entry("hi.txt").unwrap();
+        assert!(!file.has_valid_datetime());
+        assert_eq!(file.datetime(), Some(default));
+    }
+
+    #[test]
+    fn read_file_in_folder_without_data_blocks() {
+        let mut binary = b"MSCF\0\0\0\0\x59\0\0\0\0\0\0\0\
+            \x2c\0\0\0\0\0\0\0\x03\x01\x01\0\x01\0\0\0\x34\x12\0\0\
+            \x43\0\0\0\0\0\0\0\
+            \x0e\0\0\0\0\0\0\0\0\0\x6c\x22\xba\x59\x01\0hi.txt\0\
+            \x4c\x1a\x2e\x7f\x0e\0\x0e\0Hello, world!\n"
+            .to_vec();
+        for offset in [0, 5] {
+            binary[0x47] = offset; // where hi.txt starts in the folder
+            let mut cabinet = Cabinet::new(Cursor::new(&binary)).unwrap();
+            let error = cabinet
+                .read_file("hi.txt")
+                .unwrap()
+                .read_to_end(&mut Vec::new())
+                .unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+            let error = error.get_ref().unwrap();
+            let error = error.downcast_ref::<BlockError>().unwrap();
+            assert_eq!(error.folder_index(), 0);
+            assert_eq!(error.files(), &["hi.txt".to_string()]);
+            let report = cabinet.verify_all();
+            match *report.files()[0].status() {
+                FileStatus::Failed { ref kind, .. } => {
+                    assert_eq!(kind, "UnexpectedEof")
+                }
+                ref status => panic!("Unexpected status: {:?}", status),
+            }
+        }
+    }
+
+    #[test]
+    fn read_file_longer_than_folder_data() {
+        let mut binary = b"MSCF\0\0\0\0\x59\0\0\0\0\0\0\0\
+            \x2c\0\0\0\0\0\0\0\x03\x01\x01\0\x01\0\0\0\x34\x12\0\0\
+            \x43\0\0\0\x01\0\0\0\
+            \x0e\0\0\0\0\0\0\0\0\0\x6c\x22\xba\x59\x01\0hi.txt\0\
+            \x4c\x1a\x2e\x7f\x0e\0\x0e\0Hello, world!\n"
+            .to_vec();
+        binary[0x2c] = 0x12; // declare hi.txt to be four bytes too long
+
+        let mut cabinet = Cabinet::new(Cursor::new(&binary)).unwrap();
+        let error = cabinet
+            .read_file("hi.txt")
+            .unwrap()
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+
+        let mut options = ReadOptions::new();
+        options.size_mismatch(SizeMismatch::Truncate);
+        let mut cabinet =
+            Cabinet::with_options(Cursor::new(&binary), options.clone())
+                .unwrap();
+        let mut reader = cabinet.read_file("hi.txt").unwrap();
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"Hello, world!\n");
+        assert_eq!(reader.damaged_ranges(), vec![14..18]);
+
+        options.size_mismatch(SizeMismatch::ZeroFill);
+        let mut cabinet =
+            Cabinet::with_options(Cursor::new(&binary), options).unwrap();
+        let mut data = Vec::new();
+        cabinet.read_file("hi.txt").unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"Hello, world!\n\0\0\0\0");
+        let report = cabinet.verify_all();
+        match *report.files()[0].status() {
+            FileStatus::Recovered { ref damaged_ranges } => {
+                assert_eq!(damaged_ranges.len(), 1);
+                assert_eq!(damaged_ranges[0], 14..18);
+            }
+            ref status => panic!("Unexpected status: {:?}", status),
+        }
+    }
+
+    #[test]
+    fn refuse_files_over_max_file_size() {
+        let binary: &[u8] = b"MSCF\0\0\0\0\x59\0\0\0\0\0\0\0\
+            \x2c\0\0\0\0\0\0\0\x03\x01\x01\0\x01\0\0\0\x34\x12\0\0\
+            \x43\0\0\0\x01\0\0\0\
+            \x0e\0\0\0\0\0\0\0\0\0\x6c\x22\xba\x59\x01\0hi.txt\0\
+            \x4c\x1a\x2e\x7f\x0e\0\x0e\0Hello, world!\n";
+        let mut options = ReadOptions::new();
+        options.max_file_size(13);
+        let mut cabinet = options.open(Cursor::new(binary)).unwrap();
+        let error = cabinet.read_file("hi.txt").err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        let mut readers = cabinet.file_reader_iter().unwrap();
+        assert!(readers.next().unwrap().is_err());
+
+        options.max_file_size(14);
+        let mut cabinet = options.open(Cursor::new(binary)).unwrap();
+        let mut data = Vec::new();
+        cabinet.read_file("hi.txt").unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"Hello, world!\n");
+    }
+
+    #[test]
+    fn block_larger_than_its_header_says() {
+        let mut binary = b"MSCF\0\0\0\0\x59\0\0\0\0\0\0\0\
+            \x2c\0\0\0\0\0\0\0\x03\x01\x01\0\x01\0\0\0\x34\x12\0\0\
+            \x43\0\0\0\x01\0\0\0\
+            \x0e\0\0\0\0\0\0\0\0\0\x6c\x22\xba\x59\x01\0hi.txt\0\
+            \x4c\x1a\x2e\x7f\x0e\0\x0e\0Hello, world!\n"
+            .to_vec();
+        // Clear the data block's checksum, and have its header (and the
+        // file) claim one byte less than the block's payload holds:
+        binary[0x43..0x47].copy_from_slice(&[0, 0, 0, 0]);
+        binary[0x49] = 0x0d;
+        binary[0x2c] = 0x0d;
+
+        let mut cabinet = Cabinet::new(Cursor::new(&binary)).unwrap();
+        let mut data = Vec::new();
+        cabinet.read_folder(0).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"Hello, world!");
+
+        let mut options = ReadOptions::new();
+        options.check_block_sizes(true);
+        let mut cabinet = options.open(Cursor::new(&binary)).unwrap();
+        let error = cabinet
+            .read_file("hi.txt")
+            .unwrap()
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        cabinet.set_continue_on_error(true);
+        let mut reader = cabinet.read_file("hi.txt").unwrap();
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).unwrap();
+        assert_eq!(data, vec![0u8; 13]);
+        assert_eq!(reader.damaged_ranges(), vec![0..13]);
+    }
+
+    #[test]
+    fn list_folder_with_unknown_compression_type() {
+        let mut binary = b"MSCF\0\0\0\0\x59\0\0\0\0\0\0\0\
+            \x2c\0\0\0\0\0\0\0\x03\x01\x01\0\x01\0\0\0\x34\x12\0\0\
+            \x43\0\0\0\x01\0\0\0\
+            \x0e\0\0\0\0\0\0\0\0\0\x6c\x22\xba\x59\x01\0hi.txt\0\
+            \x4c\x1a\x2e\x7f\x0e\0\x0e\0Hello, world!\n"
+            .to_vec();
+        binary[0x2a] = 0x05; // compression type
+
+        let mut cabinet = Cabinet::new(Cursor::new(&binary)).unwrap();
+        let folder = cabinet.folder_entries().next().unwrap();
+        assert_eq!(folder.compression_type(), CompressionType::Unknown(5));
+        assert_eq!(folder.file_entries().next().unwrap().name(), "hi.txt");
+        let mut raw = Vec::new();
+        cabinet.export_folder_raw(0, &mut raw).unwrap();
+        assert_eq!(raw, &binary[0x43..]);
+        let error = cabinet.read_file("hi.txt").err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
 }
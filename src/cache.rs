@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+
+use crate::folder::DataBlockEntry;
+
+/// A cache of decompressed data blocks (see
+/// [`ReadOptions::block_cache_size`](crate::ReadOptions::block_cache_size)),
+/// shared by all the readers created from one cabinet.  When the cache is
+/// full, the least recently used blocks are evicted first.
+pub(crate) struct BlockCache {
+    max_bytes: usize,
+    used_bytes: usize,
+    tick: u64,
+    /// The cached data for each (folder index, block index), along with the
+    /// tick when it was last used.
+    blocks: HashMap<(usize, usize), (Vec<u8>, u64)>,
+    /// The longest known run of block headers for each folder, so that a
+    /// reader given cached data for a block also knows where it ends.
+    headers: HashMap<usize, Vec<DataBlockEntry>>,
+}
+
+impl BlockCache {
+    pub(crate) fn new(max_bytes: usize) -> BlockCache {
+        BlockCache {
+            max_bytes,
+            used_bytes: 0,
+            tick: 0,
+            blocks: HashMap::new(),
+            headers: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.max_bytes > 0
+    }
+
+    /// Returns the cached data for the given block, if any.
+    pub(crate) fn get(
+        &mut self,
+        folder: usize,
+        block: usize,
+    ) -> Option<&[u8]> {
+        self.tick += 1;
+        let tick = self.tick;
+        let (data, last_used) = self.blocks.get_mut(&(folder, block))?;
+        *last_used = tick;
+        Some(data)
+    }
+
+    /// Adds a block's data to the cache, evicting other blocks as needed to
+    /// make room for it.
+    pub(crate) fn insert(&mut self, folder: usize, block: usize, data: &[u8]) {
+        if data.len() > self.max_bytes
+            || self.blocks.contains_key(&(folder, block))
+        {
+            return;
+        }
+        while self.used_bytes + data.len() > self.max_bytes {
+            let oldest = self
+                .blocks
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(&key, _)| key);
+            match oldest.and_then(|key| self.blocks.remove(&key)) {
+                Some((evicted, _)) => self.used_bytes -= evicted.len(),
+                None => break,
+            }
+        }
+        self.tick += 1;
+        self.used_bytes += data.len();
+        self.blocks.insert((folder, block), (data.to_vec(), self.tick));
+    }
+
+    /// Discards all cached data.
+    pub(crate) fn clear(&mut self) {
+        self.used_bytes = 0;
+        self.blocks.clear();
+        self.headers.clear();
+    }
+
+    /// Returns the known block headers for the given folder.
+    pub(crate) fn headers(&self, folder: usize) -> &[DataBlockEntry] {
+        self.headers.get(&folder).map_or(&[], Vec::as_slice)
+    }
+
+    /// Records the block headers known to a reader of the given folder, if
+    /// there are more of them than the cache knows about already.
+    pub(crate) fn record_headers(
+        &mut self,
+        folder: usize,
+        headers: &[DataBlockEntry],
+    ) {
+        let known = self.headers.entry(folder).or_default();
+        if headers.len() > known.len() {
+            known.extend_from_slice(&headers[known.len()..]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BlockCache;
+
+    #[test]
+    fn evict_least_recently_used_blocks() {
+        let mut cache = BlockCache::new(10);
+        assert!(cache.is_enabled());
+        cache.insert(0, 0, &[0; 4]);
+        cache.insert(0, 1, &[1; 4]);
+        assert_eq!(cache.get(0, 0), Some(&[0u8; 4][..]));
+        cache.insert(1, 0, &[2; 4]);
+        assert_eq!(cache.get(0, 1), None);
+        assert_eq!(cache.get(0, 0), Some(&[0u8; 4][..]));
+        assert_eq!(cache.get(1, 0), Some(&[2u8; 4][..]));
+        cache.insert(1, 1, &[3; 11]);
+        assert_eq!(cache.get(1, 1), None);
+        assert!(!BlockCache::new(0).is_enabled());
+    }
+}
@@ -0,0 +1,107 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A token for cooperatively cancelling a long-running read or write, e.g.
+/// from a GUI's "Cancel" button or a service's shutdown handler.
+///
+/// Clones of a token share the same state, so one clone can be handed to
+/// [`ReadOptions::cancel`](crate::ReadOptions::cancel) or
+/// [`CabinetBuilder::set_cancel_token`](crate::CabinetBuilder::set_cancel_token)
+/// while another is kept (possibly on another thread) to call
+/// [`cancel`](CancelToken::cancel).  The token is checked before each data
+/// block is read or written, so cancellation takes effect within one block
+/// (at most 32 KiB of uncompressed data) of work.
+///
+/// ```
+/// let token = cab::CancelToken::new();
+/// let mut builder = cab::CabinetBuilder::new();
+/// builder.add_folder(cab::CompressionType::MsZip).add_file("big.bin");
+/// builder.set_cancel_token(Some(token.clone()));
+/// let mut writer = builder.build(std::io::Cursor::new(Vec::new()))?;
+/// let mut file_writer = writer.next_file()?.unwrap();
+/// token.cancel();
+/// let error = std::io::copy(&mut std::io::repeat(0), &mut file_writer)
+///     .unwrap_err();
+/// assert!(cab::Cancelled::is_cancelled(&error));
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    /// Creates a new token that has not been cancelled.
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    /// Requests cancellation of any operations using this token (or a clone
+    /// of it).
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns true if [`cancel`](CancelToken::cancel) has been called on
+    /// this token or a clone of it.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Returns a [`Cancelled`] error if this token has been cancelled.
+    pub(crate) fn check(&self) -> io::Result<()> {
+        if self.is_cancelled() {
+            Err(io::Error::other(Cancelled))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// The error returned by operations that were stopped by a
+/// [`CancelToken`].  Use [`Cancelled::is_cancelled`] to check whether an
+/// `io::Error` is one of these.
+///
+/// The wrapping `io::Error` has kind `Other` rather than `Interrupted`,
+/// since helpers such as [`io::copy`] and [`Read::read_to_end`](io::Read)
+/// retry on `Interrupted` errors, and would never give up.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Cancelled;
+
+impl Cancelled {
+    /// Returns true if the given error was caused by cancellation.
+    pub fn is_cancelled(error: &io::Error) -> bool {
+        error.get_ref().is_some_and(|inner| inner.is::<Cancelled>())
+    }
+}
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str("Operation was cancelled")
+    }
+}
+
+impl Error for Cancelled {}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use super::{CancelToken, Cancelled};
+
+    #[test]
+    fn cancel_shared_token() {
+        let token = CancelToken::new();
+        let clone = token.clone();
+        assert!(!clone.is_cancelled());
+        assert!(clone.check().is_ok());
+        token.cancel();
+        assert!(clone.is_cancelled());
+        let error = clone.check().unwrap_err();
+        assert!(Cancelled::is_cancelled(&error));
+        assert!(!Cancelled::is_cancelled(&io::Error::other("foo")));
+    }
+}
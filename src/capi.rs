@@ -0,0 +1,469 @@
+//! A C API for reading and writing cabinet files, enabled by the `capi`
+//! feature.
+//!
+//! The C header for this API is in `include/cab.h`, generated with
+//! [cbindgen](https://github.com/mozilla/cbindgen) by running
+//! `cbindgen --config cbindgen.toml --output include/cab.h` from the root of
+//! this crate.  To build a C-linkable library, run e.g.
+//! `cargo rustc --release --features capi --crate-type cdylib` (or
+//! `--crate-type staticlib`).
+//!
+//! Functions that can fail return a [`CabStatus`] code (or a null pointer),
+//! and [`cab_last_error`] then returns a description of the error.  All
+//! strings passed in or out are NUL-terminated UTF-8.
+
+#![allow(unsafe_code)]
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::ptr;
+
+use crate::builder::CabinetBuilder;
+use crate::cabinet::Cabinet;
+use crate::ctype::CompressionType;
+
+/// The status codes returned by the C API.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CabStatus {
+    /// The call succeeded.
+    CabOk = 0,
+    /// An argument was invalid (e.g. a null pointer, or a string that isn't
+    /// UTF-8).
+    CabErrorInvalidArgument = -1,
+    /// The named file (or file within the cabinet) was not found.
+    CabErrorNotFound = -2,
+    /// The cabinet file is malformed, or uses an unsupported feature.
+    CabErrorInvalidData = -3,
+    /// Some other I/O error occurred.
+    CabErrorIo = -4,
+}
+
+/// The compression types supported by [`cab_builder_add_folder`].
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CabCompression {
+    /// No compression.
+    CabCompressionNone = 0,
+    /// MSZIP compression.
+    CabCompressionMsZip = 1,
+}
+
+/// An open cabinet file.  Create with [`cab_open`], and free with
+/// [`cab_close`].
+pub struct CabCabinet {
+    cabinet: Cabinet<File>,
+}
+
+/// A cabinet file being built.  Create with [`cab_builder_new`], and free
+/// with [`cab_builder_free`].
+pub struct CabBuilder {
+    /// The compression type of each folder, and the name and source path of
+    /// each file in it.
+    folders: Vec<(CompressionType, Vec<(String, String)>)>,
+}
+
+/// A callback for [`cab_list`], called with the name and uncompressed size
+/// of each file, and the `user_data` passed to `cab_list`.  Returning
+/// nonzero stops the listing.  Passing a null callback to `cab_list` is an
+/// error.
+pub type CabListCallback = Option<
+    extern "C" fn(
+        name: *const c_char,
+        size: u32,
+        user_data: *mut c_void,
+    ) -> c_int,
+>;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', "\\0")).unwrap();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+fn fail(error: io::Error) -> CabStatus {
+    let status = match error.kind() {
+        io::ErrorKind::InvalidInput => CabStatus::CabErrorInvalidArgument,
+        io::ErrorKind::NotFound => CabStatus::CabErrorNotFound,
+        io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => {
+            CabStatus::CabErrorInvalidData
+        }
+        _ => CabStatus::CabErrorIo,
+    };
+    set_last_error(error.to_string());
+    status
+}
+
+fn status_of(result: io::Result<()>) -> CabStatus {
+    match result {
+        Ok(()) => CabStatus::CabOk,
+        Err(error) => fail(error),
+    }
+}
+
+/// Converts a C string argument into a `&str`.
+///
+/// # Safety
+///
+/// `string` must be null or point to a NUL-terminated string that outlives
+/// the returned reference.
+unsafe fn str_arg<'a>(
+    string: *const c_char,
+    what: &str,
+) -> io::Result<&'a str> {
+    if string.is_null() {
+        invalid_input!("{} must not be null", what);
+    }
+    match CStr::from_ptr(string).to_str() {
+        Ok(string) => Ok(string),
+        Err(_) => invalid_input!("{} is not valid UTF-8", what),
+    }
+}
+
+/// Returns a description of the last error that occurred on this thread, or
+/// null if there hasn't been one.  The returned string remains valid until
+/// the next failing call on this thread.
+#[no_mangle]
+pub extern "C" fn cab_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| match *last.borrow() {
+        Some(ref message) => message.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+/// Opens the cabinet file at the given path.  Returns null on failure.
+///
+/// # Safety
+///
+/// `path` must be a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn cab_open(path: *const c_char) -> *mut CabCabinet {
+    let result =
+        str_arg(path, "path").and_then(File::open).and_then(Cabinet::new);
+    match result {
+        Ok(cabinet) => Box::into_raw(Box::new(CabCabinet { cabinet })),
+        Err(error) => {
+            fail(error);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Closes a cabinet opened with [`cab_open`].  Does nothing if `cabinet` is
+/// null.
+///
+/// # Safety
+///
+/// `cabinet` must be null or a pointer returned by [`cab_open`] that hasn't
+/// already been closed.
+#[no_mangle]
+pub unsafe extern "C" fn cab_close(cabinet: *mut CabCabinet) {
+    if !cabinet.is_null() {
+        drop(Box::from_raw(cabinet));
+    }
+}
+
+/// Calls `callback` for each file in the cabinet, in order, until it
+/// returns nonzero.
+///
+/// # Safety
+///
+/// `cabinet` must be a valid pointer returned by [`cab_open`], and
+/// `callback` must be null or a valid function pointer.  The name passed to
+/// `callback` is only valid for the duration of that call.
+#[no_mangle]
+pub unsafe extern "C" fn cab_list(
+    cabinet: *const CabCabinet,
+    callback: CabListCallback,
+    user_data: *mut c_void,
+) -> CabStatus {
+    let cabinet = match cabinet.as_ref() {
+        Some(cabinet) => &cabinet.cabinet,
+        None => {
+            return fail(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "cabinet must not be null",
+            ))
+        }
+    };
+    let callback = match callback {
+        Some(callback) => callback,
+        None => {
+            return fail(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "callback must not be null",
+            ))
+        }
+    };
+    for folder in cabinet.folder_entries() {
+        for file in folder.file_entries() {
+            let name = CString::new(file.name().replace('\0', "\\0")).unwrap();
+            let size = file.uncompressed_size();
+            if callback(name.as_ptr(), size, user_data) != 0 {
+                return CabStatus::CabOk;
+            }
+        }
+    }
+    CabStatus::CabOk
+}
+
+/// Extracts the named file from the cabinet, writing it to `out_path`
+/// (which is created or truncated).
+///
+/// # Safety
+///
+/// `cabinet` must be a valid pointer returned by [`cab_open`], and `name`
+/// and `out_path` must be NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn cab_extract_file(
+    cabinet: *mut CabCabinet,
+    name: *const c_char,
+    out_path: *const c_char,
+) -> CabStatus {
+    status_of((|| {
+        let cabinet = match cabinet.as_mut() {
+            Some(cabinet) => &mut cabinet.cabinet,
+            None => invalid_input!("cabinet must not be null"),
+        };
+        let name = str_arg(name, "name")?;
+        let out_path = str_arg(out_path, "out_path")?;
+        let mut reader = cabinet.read_file(name)?;
+        let mut writer = BufWriter::new(File::create(out_path)?);
+        io::copy(&mut reader, &mut writer)?;
+        writer.into_inner().map_err(|error| error.into_error())?;
+        Ok(())
+    })())
+}
+
+/// Creates a new, empty cabinet builder.
+#[no_mangle]
+pub extern "C" fn cab_builder_new() -> *mut CabBuilder {
+    Box::into_raw(Box::new(CabBuilder { folders: Vec::new() }))
+}
+
+/// Frees a builder created with [`cab_builder_new`].  Does nothing if
+/// `builder` is null.
+///
+/// # Safety
+///
+/// `builder` must be null or a pointer returned by [`cab_builder_new`] that
+/// hasn't already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn cab_builder_free(builder: *mut CabBuilder) {
+    if !builder.is_null() {
+        drop(Box::from_raw(builder));
+    }
+}
+
+/// Adds a new folder to the cabinet being built.  Files added afterwards
+/// with [`cab_builder_add_file`] go in this folder.
+///
+/// # Safety
+///
+/// `builder` must be a valid pointer returned by [`cab_builder_new`].
+#[no_mangle]
+pub unsafe extern "C" fn cab_builder_add_folder(
+    builder: *mut CabBuilder,
+    compression: CabCompression,
+) -> CabStatus {
+    status_of((|| {
+        let builder = match builder.as_mut() {
+            Some(builder) => builder,
+            None => invalid_input!("builder must not be null"),
+        };
+        let ctype = match compression {
+            CabCompression::CabCompressionNone => CompressionType::None,
+            CabCompression::CabCompressionMsZip => CompressionType::MsZip,
+        };
+        builder.folders.push((ctype, Vec::new()));
+        Ok(())
+    })())
+}
+
+/// Adds a file to the most recently added folder, to be stored in the
+/// cabinet as `name` and read from `source_path` when the cabinet is
+/// written.
+///
+/// # Safety
+///
+/// `builder` must be a valid pointer returned by [`cab_builder_new`], and
+/// `name` and `source_path` must be NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn cab_builder_add_file(
+    builder: *mut CabBuilder,
+    name: *const c_char,
+    source_path: *const c_char,
+) -> CabStatus {
+    status_of((|| {
+        let builder = match builder.as_mut() {
+            Some(builder) => builder,
+            None => invalid_input!("builder must not be null"),
+        };
+        let name = str_arg(name, "name")?;
+        let source_path = str_arg(source_path, "source_path")?;
+        let files = match builder.folders.last_mut() {
+            Some((_, files)) => files,
+            None => invalid_input!("No folder has been added yet"),
+        };
+        files.push((name.to_string(), source_path.to_string()));
+        Ok(())
+    })())
+}
+
+/// Writes the cabinet to `out_path` (which is created or truncated), reading
+/// each file's data from its source path.  The builder is left unchanged,
+/// and must still be freed with [`cab_builder_free`].
+///
+/// # Safety
+///
+/// `builder` must be a valid pointer returned by [`cab_builder_new`], and
+/// `out_path` must be a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn cab_builder_write(
+    builder: *const CabBuilder,
+    out_path: *const c_char,
+) -> CabStatus {
+    status_of((|| {
+        let builder = match builder.as_ref() {
+            Some(builder) => builder,
+            None => invalid_input!("builder must not be null"),
+        };
+        let out_path = str_arg(out_path, "out_path")?;
+        let mut cab_builder = CabinetBuilder::new();
+        for (ctype, files) in builder.folders.iter() {
+            let folder = cab_builder.add_folder(*ctype);
+            for (name, _) in files.iter() {
+                folder.add_file(name.as_str());
+            }
+        }
+        let sources: HashMap<&str, &str> = builder
+            .folders
+            .iter()
+            .flat_map(|(_, files)| files.iter())
+            .map(|(name, source)| (name.as_str(), source.as_str()))
+            .collect();
+        let mut cab_writer = cab_builder.build(File::create(out_path)?)?;
+        while let Some(mut writer) = cab_writer.next_file()? {
+            let source = match sources.get(writer.file_name()) {
+                Some(&source) => source,
+                None => invalid_input!(
+                    "No source path for file {:?}",
+                    writer.file_name()
+                ),
+            };
+            io::copy(&mut File::open(source)?, &mut writer)?;
+        }
+        cab_writer.finish()?;
+        Ok(())
+    })())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{c_char, c_int, c_void, CStr, CString};
+    use std::fs;
+
+    use super::*;
+
+    extern "C" fn collect_name(
+        name: *const c_char,
+        size: u32,
+        user_data: *mut c_void,
+    ) -> c_int {
+        let names = unsafe { &mut *(user_data as *mut Vec<(String, u32)>) };
+        let name = unsafe { CStr::from_ptr(name) };
+        names.push((name.to_str().unwrap().to_string(), size));
+        0
+    }
+
+    fn c_path(path: &std::path::Path) -> CString {
+        CString::new(path.to_str().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn build_list_and_extract_through_c_api() {
+        let dir = std::env::temp_dir()
+            .join(format!("cab-capi-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("source.txt");
+        fs::write(&source, b"Hello, world!\n").unwrap();
+        let cab_path = dir.join("test.cab");
+        let out_path = dir.join("out.txt");
+
+        unsafe {
+            let builder = cab_builder_new();
+            let name = CString::new("docs\\hi.txt").unwrap();
+            assert_eq!(
+                cab_builder_add_file(
+                    builder,
+                    name.as_ptr(),
+                    c_path(&source).as_ptr()
+                ),
+                CabStatus::CabErrorInvalidArgument
+            );
+            assert!(!cab_last_error().is_null());
+            assert_eq!(
+                cab_builder_add_folder(
+                    builder,
+                    CabCompression::CabCompressionMsZip
+                ),
+                CabStatus::CabOk
+            );
+            assert_eq!(
+                cab_builder_add_file(
+                    builder,
+                    name.as_ptr(),
+                    c_path(&source).as_ptr()
+                ),
+                CabStatus::CabOk
+            );
+            assert_eq!(
+                cab_builder_write(builder, c_path(&cab_path).as_ptr()),
+                CabStatus::CabOk
+            );
+            cab_builder_free(builder);
+
+            let cabinet = cab_open(c_path(&cab_path).as_ptr());
+            assert!(!cabinet.is_null());
+            let mut names: Vec<(String, u32)> = Vec::new();
+            let user_data = &mut names as *mut _ as *mut c_void;
+            assert_eq!(
+                cab_list(cabinet, Some(collect_name), user_data),
+                CabStatus::CabOk
+            );
+            assert_eq!(
+                cab_list(cabinet, None, user_data),
+                CabStatus::CabErrorInvalidArgument
+            );
+            assert_eq!(names, vec![("docs\\hi.txt".to_string(), 14)]);
+            assert_eq!(
+                cab_extract_file(
+                    cabinet,
+                    name.as_ptr(),
+                    c_path(&out_path).as_ptr()
+                ),
+                CabStatus::CabOk
+            );
+            let missing = CString::new("missing.txt").unwrap();
+            assert_eq!(
+                cab_extract_file(
+                    cabinet,
+                    missing.as_ptr(),
+                    c_path(&out_path).as_ptr()
+                ),
+                CabStatus::CabErrorNotFound
+            );
+            cab_close(cabinet);
+
+            assert!(cab_open(c_path(&dir.join("nope.cab")).as_ptr()).is_null());
+        }
+        assert_eq!(fs::read(&out_path).unwrap(), b"Hello, world!\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -28,6 +28,17 @@ impl Checksum {
         }
     }
 
+    /// Returns the value to store in a CFDATA header for a data block whose
+    /// reserve and payload bytes have been fed into this checksum.
+    pub fn data_block_value(
+        &self,
+        compressed_size: u16,
+        uncompressed_size: u16,
+    ) -> u32 {
+        self.value()
+            ^ ((compressed_size as u32) | ((uncompressed_size as u32) << 16))
+    }
+
     pub fn update(&mut self, buf: &[u8]) {
         for &byte in buf {
             self.remainder |= (byte as u32) << self.remainder_shift;
@@ -0,0 +1,34 @@
+//! Low-level block codecs used by cabinet folders, for use by other formats
+//! that reuse them.
+
+pub mod mszip {
+    //! The MSZIP block format: a `CK` signature followed by deflate data,
+    //! with each block of up to 32 KiB of uncompressed data able to refer
+    //! back to the previous blocks' data.  Besides cabinets, MSZIP blocks
+    //! appear in a few other Microsoft formats.
+    //!
+    //! ```
+    //! use cab::codec::mszip::{MsZipCompressor, MsZipDecompressor};
+    //!
+    //! let data = b"Hello, world! Hello, world! Hello, world!\n".repeat(1000);
+    //! let mut compressor = MsZipCompressor::new();
+    //! let chunks: Vec<&[u8]> = data.chunks(0x8000).collect();
+    //! let mut blocks = Vec::new();
+    //! for (index, chunk) in chunks.iter().enumerate() {
+    //!     let is_last_block = index + 1 == chunks.len();
+    //!     blocks.push(compressor.compress_block(chunk, is_last_block)?);
+    //! }
+    //!
+    //! let mut decompressor = MsZipDecompressor::new();
+    //! let mut output = Vec::new();
+    //! for (block, chunk) in blocks.iter().zip(chunks.iter()) {
+    //!     output.extend(decompressor.decompress_block(block, chunk.len())?);
+    //! }
+    //! assert_eq!(output, data);
+    //! # Ok::<(), std::io::Error>(())
+    //! ```
+
+    pub use crate::mszip::{
+        MsZipCompressor, MsZipDecompressor, MAX_BLOCK_SIZE,
+    };
+}
@@ -4,6 +4,8 @@ pub const VERSION_MAJOR: u8 = 1;
 pub const VERSION_MINOR: u8 = 3;
 
 pub const MAX_TOTAL_CAB_SIZE: u32 = 0x7fffffff;
+/// The offset of the header reserve data, when present.
+pub const HEADER_RESERVE_OFFSET: u64 = 40;
 pub const MAX_HEADER_RESERVE_SIZE: usize = 60_000;
 pub const MAX_FOLDER_RESERVE_SIZE: usize = 255;
 pub const MAX_STRING_SIZE: usize = 255;
@@ -16,6 +18,11 @@ pub const FLAG_PREV_CABINET: u16 = 0x1;
 pub const FLAG_NEXT_CABINET: u16 = 0x2;
 pub const FLAG_RESERVE_PRESENT: u16 = 0x4;
 
+// Special file folder indices:
+pub const IFOLD_CONTINUED_FROM_PREV: u16 = 0xfffd;
+pub const IFOLD_CONTINUED_TO_NEXT: u16 = 0xfffe;
+pub const IFOLD_CONTINUED_PREV_AND_NEXT: u16 = 0xffff;
+
 // File attributes:
 pub const ATTR_READ_ONLY: u16 = 0x01;
 pub const ATTR_HIDDEN: u16 = 0x02;
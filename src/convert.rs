@@ -0,0 +1,209 @@
+//! Conversion between cabinets and zip archives, enabled by the `zip`
+//! feature.
+//!
+//! Entries are streamed one at a time, so neither archive is ever held in
+//! memory.  File names, modification times, and the read-only attribute are
+//! preserved; other cabinet attributes (hidden, system, etc.) have no zip
+//! equivalent and are dropped by [`to_zip`].  Directory entries in a zip
+//! archive are skipped by [`from_zip`], since cabinets only store files.
+
+use std::io::{self, Read, Seek, Write};
+
+use time::PrimitiveDateTime;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::builder::CabinetBuilder;
+use crate::cabinet::Cabinet;
+use crate::ctype::CompressionType;
+
+/// Options for converting a zip archive into a cabinet with [`from_zip`], in
+/// the style of [`std::fs::OpenOptions`].
+#[derive(Clone, Debug)]
+pub struct ConvertOptions {
+    compression_type: CompressionType,
+    backslash_separators: bool,
+}
+
+impl ConvertOptions {
+    /// Creates a new set of options, with all options set to their defaults.
+    pub fn new() -> ConvertOptions {
+        ConvertOptions {
+            compression_type: CompressionType::MsZip,
+            backslash_separators: false,
+        }
+    }
+
+    /// Sets the compression type for the cabinet's folder.  Defaults to
+    /// [`CompressionType::MsZip`].
+    pub fn compression_type(
+        &mut self,
+        ctype: CompressionType,
+    ) -> &mut ConvertOptions {
+        self.compression_type = ctype;
+        self
+    }
+
+    /// If true, `/` path separators in zip entry names are replaced with
+    /// `\`, the separator conventionally used in cabinets.  Defaults to
+    /// false, which keeps names exactly as they are in the zip archive.
+    pub fn backslash_separators(
+        &mut self,
+        enable: bool,
+    ) -> &mut ConvertOptions {
+        self.backslash_separators = enable;
+        self
+    }
+}
+
+impl Default for ConvertOptions {
+    fn default() -> Self {
+        ConvertOptions::new()
+    }
+}
+
+/// Converts the zip archive read from `zip_reader` into a cabinet written
+/// to `writer`, with all files compressed together in a single folder;
+/// returns the underlying writer once the cabinet is finished.
+pub fn from_zip<R: Read + Seek, W: Write + Seek>(
+    zip_reader: R,
+    writer: W,
+    options: &ConvertOptions,
+) -> io::Result<W> {
+    let mut archive = ZipArchive::new(zip_reader)?;
+    let mut indices = Vec::new();
+    let mut builder = CabinetBuilder::new();
+    let folder = builder.add_folder(options.compression_type);
+    for index in 0..archive.len() {
+        let entry = archive.by_index_raw(index)?;
+        if entry.is_dir() {
+            continue;
+        }
+        let name = if options.backslash_separators {
+            entry.name().replace('/', "\\")
+        } else {
+            entry.name().to_string()
+        };
+        let file = folder.add_file(name);
+        if let Some(datetime) = entry.last_modified() {
+            if let Ok(datetime) = time::OffsetDateTime::try_from(datetime) {
+                file.set_datetime(PrimitiveDateTime::new(
+                    datetime.date(),
+                    datetime.time(),
+                ));
+            }
+        }
+        if let Some(mode) = entry.unix_mode() {
+            file.set_is_read_only(mode & 0o222 == 0);
+        }
+        indices.push(index);
+    }
+    let mut cabinet = builder.build(writer)?;
+    let mut indices = indices.into_iter();
+    while let Some(mut file_writer) = cabinet.next_file()? {
+        let mut entry = archive.by_index(indices.next().unwrap())?;
+        io::copy(&mut entry, &mut file_writer)?;
+    }
+    cabinet.finish()
+}
+
+/// Converts the given cabinet into a zip archive written to `zip_writer`,
+/// with each file deflated; returns the underlying writer once the archive
+/// is finished.  `\` path separators in the cabinet's file names are
+/// replaced with `/`, as the zip format requires.
+pub fn to_zip<R: Read + Seek, W: Write + Seek>(
+    cabinet: &mut Cabinet<R>,
+    zip_writer: W,
+) -> io::Result<W> {
+    let files: Vec<(String, SimpleFileOptions)> = cabinet
+        .folder_entries()
+        .flat_map(|folder| folder.file_entries())
+        .map(|file| {
+            let mut options = SimpleFileOptions::default()
+                .compression_method(CompressionMethod::Deflated)
+                .unix_permissions(if file.is_read_only() {
+                    0o444
+                } else {
+                    0o644
+                });
+            let datetime = file
+                .datetime()
+                .and_then(|dt| zip::DateTime::try_from(dt.assume_utc()).ok());
+            if let Some(datetime) = datetime {
+                options = options.last_modified_time(datetime);
+            }
+            (file.name().to_string(), options)
+        })
+        .collect();
+    let mut zip = ZipWriter::new(zip_writer);
+    for (name, options) in files {
+        let mut reader = cabinet.read_file(&name)?;
+        zip.start_file(name.replace('\\', "/"), options)?;
+        io::copy(&mut reader, &mut zip)?;
+    }
+    Ok(zip.finish()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read, Write};
+
+    use time::macros::datetime;
+    use zip::write::SimpleFileOptions;
+    use zip::{ZipArchive, ZipWriter};
+
+    use super::{from_zip, to_zip, ConvertOptions};
+    use crate::cabinet::Cabinet;
+    use crate::ctype::CompressionType;
+
+    #[test]
+    fn zip_to_cab_to_zip() {
+        let lorem = lipsum::lipsum(2000);
+        let modified =
+            zip::DateTime::from_date_and_time(2019, 7, 4, 8, 30, 0).unwrap();
+        let options =
+            SimpleFileOptions::default().last_modified_time(modified);
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        zip.add_directory("docs/", options).unwrap();
+        zip.start_file("docs/lorem.txt", options).unwrap();
+        zip.write_all(lorem.as_bytes()).unwrap();
+        zip.start_file("hi.txt", options.unix_permissions(0o444)).unwrap();
+        zip.write_all(b"Hello, world!\n").unwrap();
+        let zip_file = zip.finish().unwrap();
+
+        let mut options = ConvertOptions::new();
+        options
+            .compression_type(CompressionType::None)
+            .backslash_separators(true);
+        let cab_file =
+            from_zip(zip_file, Cursor::new(Vec::new()), &options).unwrap();
+        let mut cabinet =
+            Cabinet::new(Cursor::new(cab_file.into_inner())).unwrap();
+        let names: Vec<&str> = cabinet
+            .folder_entries()
+            .flat_map(|folder| folder.file_entries())
+            .map(|file| file.name())
+            .collect();
+        assert_eq!(names, vec!["docs\\lorem.txt", "hi.txt"]);
+        let hi = cabinet.get_file_entry("hi.txt").unwrap();
+        assert!(hi.is_read_only());
+        assert_eq!(hi.datetime(), Some(datetime!(2019-07-04 08:30:00)));
+        assert!(!cabinet
+            .get_file_entry("docs\\lorem.txt")
+            .unwrap()
+            .is_read_only());
+
+        let zip_file = to_zip(&mut cabinet, Cursor::new(Vec::new())).unwrap();
+        let mut archive = ZipArchive::new(zip_file).unwrap();
+        assert_eq!(archive.len(), 2);
+        let mut entry = archive.by_name("docs/lorem.txt").unwrap();
+        let mut output = String::new();
+        entry.read_to_string(&mut output).unwrap();
+        assert_eq!(output, lorem);
+        drop(entry);
+        let entry = archive.by_name("hi.txt").unwrap();
+        assert_eq!(entry.unix_mode().map(|mode| mode & 0o777), Some(0o444));
+        let modified = entry.last_modified().unwrap();
+        assert_eq!((modified.year(), modified.hour()), (2019, 8));
+    }
+}
@@ -1,4 +1,7 @@
+use std::error::Error;
+use std::fmt;
 use std::io;
+use std::str::FromStr;
 
 use lzxd::Lzxd;
 
@@ -13,24 +16,77 @@ const QUANTUM_LEVEL_MIN: u16 = 1;
 const QUANTUM_LEVEL_MAX: u16 = 7;
 const QUANTUM_MEMORY_MIN: u16 = 10;
 const QUANTUM_MEMORY_MAX: u16 = 21;
+const LZX_WINDOW_MIN: u16 = 15;
+const LZX_WINDOW_MAX: u16 = 25;
 
 /// A scheme for compressing data within the cabinet.
 #[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+#[non_exhaustive]
 pub enum CompressionType {
     /// No compression.
     None,
     /// MSZIP compression.  MSZIP is described further in
     /// [MS-MCI](https://msdn.microsoft.com/en-us/library/cc483131.aspx).
     MsZip,
-    /// Quantum compression with the given level and memory.
+    /// Quantum compression with the given level and memory.  The level must
+    /// be from 1 to 7 and the memory from 10 to 21; use
+    /// [`CompressionType::quantum`] to construct this with validation.
+    /// Out-of-range values are rejected when building a cabinet.
     Quantum(u16, u16),
     /// LZX compression with the given window size.  The LZX compression scheme
     /// is described further in
     /// [MS-PATCH](https://msdn.microsoft.com/en-us/library/cc483133.aspx).
     Lzx(lzxd::WindowSize),
+    /// A compression type not known to this crate, with the given raw
+    /// compression type bits (as stored in the folder entry).  Cabinets
+    /// with such folders can still be listed, and their raw data blocks
+    /// read, but their files can't be decompressed, and such folders can
+    /// only be written with already-compressed data (see
+    /// [`FolderBuilder::set_precompressed`](crate::FolderBuilder::set_precompressed)).
+    Unknown(u16),
 }
 
 impl CompressionType {
+    /// Returns a Quantum compression type with the given level (1 to 7) and
+    /// memory (10 to 21), or an error if either is out of range.
+    pub fn quantum(level: u16, memory: u16) -> io::Result<CompressionType> {
+        let ctype = CompressionType::Quantum(level, memory);
+        ctype.validate()?;
+        Ok(ctype)
+    }
+
+    /// Returns an LZX compression type with a window size of
+    /// 2<sup>`window_bits`</sup> bytes, or an error if `window_bits` is not
+    /// from 15 to 25.
+    pub fn lzx(window_bits: u16) -> io::Result<CompressionType> {
+        let window_size = lzxd::WindowSize::from_exponent(window_bits)?;
+        Ok(CompressionType::Lzx(window_size))
+    }
+
+    /// Returns an error if this compression type's parameters are out of
+    /// range.
+    pub(crate) fn validate(self) -> io::Result<()> {
+        if let CompressionType::Quantum(level, memory) = self {
+            if !(QUANTUM_LEVEL_MIN..=QUANTUM_LEVEL_MAX).contains(&level) {
+                invalid_input!(
+                    "Invalid Quantum level: {} (must be {} to {})",
+                    level,
+                    QUANTUM_LEVEL_MIN,
+                    QUANTUM_LEVEL_MAX
+                );
+            }
+            if !(QUANTUM_MEMORY_MIN..=QUANTUM_MEMORY_MAX).contains(&memory) {
+                invalid_input!(
+                    "Invalid Quantum memory: {} (must be {} to {})",
+                    memory,
+                    QUANTUM_MEMORY_MIN,
+                    QUANTUM_MEMORY_MAX
+                );
+            }
+        }
+        Ok(())
+    }
+
     pub(crate) fn from_bitfield(bits: u16) -> io::Result<CompressionType> {
         let ctype = bits & 0x000f;
         if ctype == CTYPE_NONE {
@@ -49,26 +105,19 @@ impl CompressionType {
             Ok(CompressionType::Quantum(level, memory))
         } else if ctype == CTYPE_LZX {
             let window = (bits & 0x1f00) >> 8;
-            let window = match window {
-                15 => lzxd::WindowSize::KB32,
-                16 => lzxd::WindowSize::KB64,
-                17 => lzxd::WindowSize::KB128,
-                18 => lzxd::WindowSize::KB256,
-                19 => lzxd::WindowSize::KB512,
-                20 => lzxd::WindowSize::MB1,
-                21 => lzxd::WindowSize::MB2,
-                22 => lzxd::WindowSize::MB4,
-                23 => lzxd::WindowSize::MB8,
-                24 => lzxd::WindowSize::MB16,
-                25 => lzxd::WindowSize::MB32,
-                _ => invalid_data!("Invalid LZX window: 0x{:02x}", window),
-            };
-            Ok(CompressionType::Lzx(window))
+            match lzxd::WindowSize::from_exponent(window) {
+                Ok(window) => Ok(CompressionType::Lzx(window)),
+                Err(error) => {
+                    Err(io::Error::new(io::ErrorKind::InvalidData, error))
+                }
+            }
         } else {
-            invalid_data!("Invalid compression type: 0x{:04x}", bits);
+            Ok(CompressionType::Unknown(bits))
         }
     }
 
+    /// Note that out-of-range Quantum parameters are clamped here; callers
+    /// that write cabinets should call `validate` first.
     pub(crate) fn to_bitfield(self) -> u16 {
         match self {
             CompressionType::None => CTYPE_NONE,
@@ -80,40 +129,251 @@ impl CompressionType {
                         << 8)
             }
             CompressionType::Lzx(window_size) => {
-                let window = match window_size {
-                    lzxd::WindowSize::KB32 => 15,
-                    lzxd::WindowSize::KB64 => 16,
-                    lzxd::WindowSize::KB128 => 17,
-                    lzxd::WindowSize::KB256 => 18,
-                    lzxd::WindowSize::KB512 => 19,
-                    lzxd::WindowSize::MB1 => 20,
-                    lzxd::WindowSize::MB2 => 21,
-                    lzxd::WindowSize::MB4 => 22,
-                    lzxd::WindowSize::MB8 => 23,
-                    lzxd::WindowSize::MB16 => 24,
-                    lzxd::WindowSize::MB32 => 25,
-                };
-                CTYPE_LZX | (window << 8)
+                CTYPE_LZX | (window_size.to_exponent() << 8)
             }
+            CompressionType::Unknown(bits) => bits,
         }
     }
 
     pub(crate) fn into_decompressor(self) -> io::Result<Decompressor> {
         match self {
             CompressionType::None => Ok(Decompressor::Uncompressed),
-            CompressionType::MsZip => {
-                Ok(Decompressor::MsZip(Box::new(MsZipDecompressor::new())))
-            }
+            CompressionType::MsZip => Ok(Decompressor::MsZip(Box::default())),
             CompressionType::Quantum(_, _) => {
                 invalid_data!("Quantum decompression is not yet supported.")
             }
             CompressionType::Lzx(window_size) => {
                 Ok(Decompressor::Lzx(Box::new(Lzxd::new(window_size))))
             }
+            CompressionType::Unknown(bits) => {
+                invalid_data!("Unknown compression type: 0x{:04x}", bits)
+            }
+        }
+    }
+}
+
+impl FromStr for CompressionType {
+    type Err = io::Error;
+
+    /// Parses a compression type from a string such as `"none"`, `"mszip"`,
+    /// `"lzx:21"` (LZX with a 2<sup>21</sup>-byte window), or
+    /// `"quantum:7,20"` (Quantum with level 7 and memory 20).  A bare `"lzx"`
+    /// uses a 2<sup>21</sup>-byte window.  Parsing is case-insensitive.
+    fn from_str(string: &str) -> io::Result<CompressionType> {
+        let lower = string.trim().to_ascii_lowercase();
+        let (name, params) = match lower.split_once(':') {
+            Some((name, params)) => (name, Some(params)),
+            None => (lower.as_str(), None),
+        };
+        let parse_number = |number: &str| match number.trim().parse::<u16>() {
+            Ok(number) => Ok(number),
+            Err(_) => invalid_input!(
+                "Invalid compression type parameter {:?} in {:?}",
+                number,
+                string
+            ),
+        };
+        match (name, params) {
+            ("none", None) => Ok(CompressionType::None),
+            ("mszip", None) => Ok(CompressionType::MsZip),
+            ("lzx", None) => Ok(CompressionType::Lzx(lzxd::WindowSize::MB2)),
+            ("lzx", Some(window)) => {
+                CompressionType::lzx(parse_number(window)?)
+            }
+            ("quantum", Some(params)) => match params.split_once(',') {
+                Some((level, memory)) => CompressionType::quantum(
+                    parse_number(level)?,
+                    parse_number(memory)?,
+                ),
+                None => invalid_input!(
+                    "Quantum compression type {:?} must specify level and \
+                     memory (e.g. \"quantum:7,20\")",
+                    string
+                ),
+            },
+            _ => invalid_input!("Invalid compression type: {:?}", string),
+        }
+    }
+}
+
+impl fmt::Display for CompressionType {
+    /// Formats the compression type in the same form accepted by
+    /// [`FromStr`](CompressionType::from_str), e.g. `"lzx:21"`.  Unknown
+    /// compression types (which can't be parsed) are formatted with their
+    /// raw bits, e.g. `"unknown:0x0005"`.
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            CompressionType::None => formatter.write_str("none"),
+            CompressionType::MsZip => formatter.write_str("mszip"),
+            CompressionType::Quantum(level, memory) => {
+                write!(formatter, "quantum:{},{}", level, memory)
+            }
+            CompressionType::Lzx(window_size) => {
+                write!(formatter, "lzx:{}", window_size.to_exponent())
+            }
+            CompressionType::Unknown(bits) => {
+                write!(formatter, "unknown:0x{:04x}", bits)
+            }
+        }
+    }
+}
+
+/// Conversions between LZX window sizes and the exponents used to store
+/// them in a folder's compression type (a window of 2<sup>n</sup> bytes is
+/// stored as n).
+///
+/// ```
+/// use cab::{WindowSize, WindowSizeExt};
+/// assert_eq!(WindowSize::from_exponent(21).unwrap(), WindowSize::MB2);
+/// assert_eq!(WindowSize::KB64.to_exponent(), 16);
+/// assert_eq!(WindowSize::from_exponent(14).unwrap_err().exponent(), 14);
+/// ```
+pub trait WindowSizeExt: Sized {
+    /// Returns the window size of 2<sup>`exponent`</sup> bytes, or an error
+    /// if `exponent` is not from 15 to 25.
+    fn from_exponent(exponent: u16) -> Result<Self, InvalidWindowSize>;
+
+    /// Returns the base-2 logarithm of the window size, in bytes.
+    fn to_exponent(self) -> u16;
+}
+
+impl WindowSizeExt for lzxd::WindowSize {
+    fn from_exponent(exponent: u16) -> Result<Self, InvalidWindowSize> {
+        Ok(match exponent {
+            15 => lzxd::WindowSize::KB32,
+            16 => lzxd::WindowSize::KB64,
+            17 => lzxd::WindowSize::KB128,
+            18 => lzxd::WindowSize::KB256,
+            19 => lzxd::WindowSize::KB512,
+            20 => lzxd::WindowSize::MB1,
+            21 => lzxd::WindowSize::MB2,
+            22 => lzxd::WindowSize::MB4,
+            23 => lzxd::WindowSize::MB8,
+            24 => lzxd::WindowSize::MB16,
+            25 => lzxd::WindowSize::MB32,
+            _ => return Err(InvalidWindowSize { exponent }),
+        })
+    }
+
+    fn to_exponent(self) -> u16 {
+        match self {
+            lzxd::WindowSize::KB32 => 15,
+            lzxd::WindowSize::KB64 => 16,
+            lzxd::WindowSize::KB128 => 17,
+            lzxd::WindowSize::KB256 => 18,
+            lzxd::WindowSize::KB512 => 19,
+            lzxd::WindowSize::MB1 => 20,
+            lzxd::WindowSize::MB2 => 21,
+            lzxd::WindowSize::MB4 => 22,
+            lzxd::WindowSize::MB8 => 23,
+            lzxd::WindowSize::MB16 => 24,
+            lzxd::WindowSize::MB32 => 25,
+        }
+    }
+}
+
+/// The error returned by [`WindowSizeExt::from_exponent`] for an exponent
+/// that isn't a valid LZX window size.  It converts into an [`io::Error`] of
+/// kind [`InvalidInput`](io::ErrorKind::InvalidInput).
+///
+/// Opening a cabinet with an LZX folder whose window size is out of range
+/// (that is, one that the decoder can't handle) also fails with this error,
+/// wrapped in an [`io::Error`] of kind
+/// [`InvalidData`](io::ErrorKind::InvalidData).  Windows larger than the
+/// 2<sup>21</sup> bytes allowed by the CAB spec, as used by some cabinets
+/// in the wild, are accepted.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct InvalidWindowSize {
+    exponent: u16,
+}
+
+impl InvalidWindowSize {
+    /// Returns the invalid exponent.
+    pub fn exponent(&self) -> u16 {
+        self.exponent
+    }
+}
+
+impl fmt::Display for InvalidWindowSize {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "Invalid LZX window bits: {} (must be {} to {})",
+            self.exponent, LZX_WINDOW_MIN, LZX_WINDOW_MAX
+        )
+    }
+}
+
+impl Error for InvalidWindowSize {}
+
+impl From<InvalidWindowSize> for io::Error {
+    fn from(error: InvalidWindowSize) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidInput, error)
+    }
+}
+
+/// How hard a compressor should work to shrink the data, trading speed for
+/// compression ratio.
+///
+/// Each effort level maps to a deflate compression level for MSZIP folders:
+///
+/// | Effort   | MSZIP (deflate level) |
+/// |----------|-----------------------|
+/// | `Fast`   | 1                     |
+/// | `Normal` | 6                     |
+/// | `Max`    | 9                     |
+///
+/// LZX encoding is not yet supported, so for now this only affects MSZIP
+/// folders; uncompressed folders ignore it entirely.
+#[derive(Clone, Copy, Debug, Default, Hash, Eq, PartialEq)]
+pub enum CompressionEffort {
+    /// Favor speed over compression ratio (e.g. for quick test builds).
+    Fast,
+    /// A balance between speed and compression ratio.
+    Normal,
+    /// Favor compression ratio over speed (e.g. for release artifacts).  This
+    /// is the default.
+    #[default]
+    Max,
+}
+
+impl CompressionEffort {
+    /// Returns the deflate compression level (from 1 to 9) used for MSZIP
+    /// compression at this effort.
+    pub fn deflate_level(self) -> u32 {
+        match self {
+            CompressionEffort::Fast => 1,
+            CompressionEffort::Normal => 6,
+            CompressionEffort::Max => 9,
         }
     }
 }
 
+impl FromStr for CompressionEffort {
+    type Err = io::Error;
+
+    /// Parses a compression effort from `"fast"`, `"normal"`, or `"max"`
+    /// (case-insensitive).
+    fn from_str(string: &str) -> io::Result<CompressionEffort> {
+        match string.trim().to_ascii_lowercase().as_str() {
+            "fast" => Ok(CompressionEffort::Fast),
+            "normal" => Ok(CompressionEffort::Normal),
+            "max" => Ok(CompressionEffort::Max),
+            _ => invalid_input!("Invalid compression effort: {:?}", string),
+        }
+    }
+}
+
+impl fmt::Display for CompressionEffort {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str(match *self {
+            CompressionEffort::Fast => "fast",
+            CompressionEffort::Normal => "normal",
+            CompressionEffort::Max => "max",
+        })
+    }
+}
+
 pub enum Decompressor {
     Uncompressed,
     MsZip(Box<MsZipDecompressor>),
@@ -129,6 +389,21 @@ impl Decompressor {
         }
     }
 
+    /// Puts the decompressor in the state it would be in after
+    /// decompressing blocks whose data ended with `window` (the last 32 KiB
+    /// of it, or all of it if there was less).  Returns false (leaving the
+    /// decompressor as it was) if its state depends on more than that.
+    pub(crate) fn restore_window(&mut self, window: &[u8]) -> bool {
+        match self {
+            Self::Uncompressed => true,
+            Self::MsZip(d) => {
+                d.set_window(window);
+                true
+            }
+            Self::Lzx(_) => false,
+        }
+    }
+
     pub(crate) fn decompress(
         &mut self,
         data: Vec<u8>,
@@ -138,20 +413,45 @@ impl Decompressor {
             Decompressor::Uncompressed => data,
             Decompressor::MsZip(decompressor) => decompressor
                 .decompress_block(&data, uncompressed_size)
-                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
+                .map_err(io::Error::other)?
                 .to_vec(),
             Decompressor::Lzx(decompressor) => decompressor
                 .decompress_next(&data, uncompressed_size)
-                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
+                .map_err(io::Error::other)?
                 .to_vec(),
         };
         Ok(data)
     }
+
+    /// Like `decompress`, but only updates the decompressor's state, without
+    /// copying out the decompressed data.
+    pub(crate) fn skip(
+        &mut self,
+        data: &[u8],
+        uncompressed_size: usize,
+    ) -> io::Result<()> {
+        match self {
+            Decompressor::Uncompressed => {}
+            Decompressor::MsZip(decompressor) => {
+                decompressor
+                    .decompress_block(data, uncompressed_size)
+                    .map_err(io::Error::other)?;
+            }
+            Decompressor::Lzx(decompressor) => {
+                decompressor
+                    .decompress_next(data, uncompressed_size)
+                    .map_err(io::Error::other)?;
+            }
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::CompressionType;
+    use super::{
+        CompressionEffort, CompressionType, InvalidWindowSize, WindowSizeExt,
+    };
 
     #[test]
     fn compression_type_to_bitfield() {
@@ -182,5 +482,127 @@ mod tests {
             CompressionType::from_bitfield(0x1503).unwrap(),
             CompressionType::Lzx(lzxd::WindowSize::MB2)
         );
+        assert_eq!(
+            CompressionType::from_bitfield(0x1903).unwrap(),
+            CompressionType::Lzx(lzxd::WindowSize::MB32)
+        );
+        assert_eq!(
+            CompressionType::from_bitfield(0x1205).unwrap(),
+            CompressionType::Unknown(0x1205)
+        );
+        for bits in [0x0e03, 0x1a03] {
+            let error = CompressionType::from_bitfield(bits).unwrap_err();
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+            let error = error.into_inner().unwrap();
+            let error = error.downcast::<InvalidWindowSize>().unwrap();
+            assert_eq!(error.exponent(), bits >> 8);
+        }
+        assert_eq!(CompressionType::Unknown(0x1205).to_bitfield(), 0x1205);
+        assert_eq!(
+            CompressionType::Unknown(0x000f).to_string(),
+            "unknown:0x000f"
+        );
+    }
+
+    #[test]
+    fn validated_constructors() {
+        assert_eq!(
+            CompressionType::quantum(7, 20).unwrap(),
+            CompressionType::Quantum(7, 20)
+        );
+        assert!(CompressionType::quantum(0, 20).is_err());
+        assert!(CompressionType::quantum(8, 20).is_err());
+        assert!(CompressionType::quantum(7, 9).is_err());
+        assert!(CompressionType::quantum(7, 22).is_err());
+        assert_eq!(
+            CompressionType::lzx(21).unwrap(),
+            CompressionType::Lzx(lzxd::WindowSize::MB2)
+        );
+        assert!(CompressionType::lzx(14).is_err());
+        assert!(CompressionType::lzx(26).is_err());
+        assert!(CompressionType::lzx(0x115).is_err());
+    }
+
+    #[test]
+    fn parse_compression_type_from_string() {
+        let parse = |string: &str| string.parse::<CompressionType>().ok();
+        assert_eq!(parse("none"), Some(CompressionType::None));
+        assert_eq!(parse("MSZIP"), Some(CompressionType::MsZip));
+        assert_eq!(
+            parse("lzx"),
+            Some(CompressionType::Lzx(lzxd::WindowSize::MB2))
+        );
+        assert_eq!(
+            parse("lzx:16"),
+            Some(CompressionType::Lzx(lzxd::WindowSize::KB64))
+        );
+        assert_eq!(
+            parse("quantum:7,20"),
+            Some(CompressionType::Quantum(7, 20))
+        );
+        assert_eq!(parse("quantum"), None);
+        assert_eq!(parse("quantum:9,20"), None);
+        assert_eq!(parse("lzx:99"), None);
+        assert_eq!(parse("lzx:big"), None);
+        assert_eq!(parse("mszip:1"), None);
+        assert_eq!(parse("zstd"), None);
+    }
+
+    #[test]
+    fn display_round_trips_through_from_str() {
+        for ctype in [
+            CompressionType::None,
+            CompressionType::MsZip,
+            CompressionType::Quantum(3, 15),
+            CompressionType::Lzx(lzxd::WindowSize::KB32),
+            CompressionType::Lzx(lzxd::WindowSize::MB32),
+        ] {
+            let string = ctype.to_string();
+            assert_eq!(string.parse::<CompressionType>().unwrap(), ctype);
+        }
+        assert_eq!(
+            CompressionType::Quantum(7, 20).to_string(),
+            "quantum:7,20"
+        );
+        assert_eq!(
+            CompressionType::Lzx(lzxd::WindowSize::MB2).to_string(),
+            "lzx:21"
+        );
+    }
+
+    #[test]
+    fn window_size_exponents() {
+        for exponent in 15..=25 {
+            let window_size = lzxd::WindowSize::from_exponent(exponent);
+            assert_eq!(window_size.unwrap().to_exponent(), exponent);
+        }
+        for exponent in [0, 14, 26, 0x115] {
+            let error = lzxd::WindowSize::from_exponent(exponent).unwrap_err();
+            assert_eq!(error.exponent(), exponent);
+        }
+        let error = CompressionType::lzx(14).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        assert_eq!(
+            error.to_string(),
+            "Invalid LZX window bits: 14 (must be 15 to 25)"
+        );
+    }
+
+    #[test]
+    fn parse_compression_effort() {
+        for effort in [
+            CompressionEffort::Fast,
+            CompressionEffort::Normal,
+            CompressionEffort::Max,
+        ] {
+            let string = effort.to_string();
+            assert_eq!(string.parse::<CompressionEffort>().unwrap(), effort);
+        }
+        assert_eq!(
+            " FAST ".parse::<CompressionEffort>().unwrap(),
+            CompressionEffort::Fast
+        );
+        assert!("slow".parse::<CompressionEffort>().is_err());
+        assert_eq!(CompressionEffort::default(), CompressionEffort::Max);
     }
 }
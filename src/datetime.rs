@@ -1,7 +1,18 @@
+#[cfg(feature = "time")]
 use std::convert::TryInto;
+use std::time::{SystemTime, UNIX_EPOCH};
 
+#[cfg(feature = "time")]
 use time::PrimitiveDateTime;
 
+/// The date and time bits for 1980-01-01 00:00:00, the earliest datetime a
+/// cabinet can store.
+const MIN_DATETIME_BITS: (u16, u16) = (0x21, 0);
+/// The date and time bits for 2107-12-31 23:59:58, the latest datetime a
+/// cabinet can store.
+const MAX_DATETIME_BITS: (u16, u16) = (0xff9f, 0xbf7d);
+
+#[cfg(feature = "time")]
 pub fn datetime_from_bits(date: u16, time: u16) -> Option<PrimitiveDateTime> {
     let year = (date >> 9) as i32 + 1980;
     let month = (((date >> 5) & 0xf) as u8).try_into().ok()?;
@@ -16,17 +27,21 @@ pub fn datetime_from_bits(date: u16, time: u16) -> Option<PrimitiveDateTime> {
     Some(PrimitiveDateTime::new(date, time))
 }
 
+#[cfg(feature = "time")]
 pub fn datetime_to_bits(mut datetime: PrimitiveDateTime) -> (u16, u16) {
     // Clamp to legal range:
     if datetime.year() < 1980 {
-        return (0x21, 0); // 1980-01-01 00:00:00
+        return MIN_DATETIME_BITS;
     } else if datetime.year() > 2107 {
-        return (0xff9f, 0xbf7d); // 2107-12-31 23:59:59
+        return MAX_DATETIME_BITS;
     }
 
     // Round to nearest two seconds:
-    if datetime.second() % 2 != 0 {
+    if !datetime.second().is_multiple_of(2) {
         datetime += time::Duration::seconds(1);
+        if datetime.year() > 2107 {
+            return MAX_DATETIME_BITS;
+        }
     }
 
     let year = datetime.year() as u16;
@@ -40,11 +55,91 @@ pub fn datetime_to_bits(mut datetime: PrimitiveDateTime) -> (u16, u16) {
     (date, time)
 }
 
-#[cfg(test)]
+/// Returns true if the given date and time bits form a valid datetime (the
+/// same ones for which `datetime_from_bits` returns `Some`).
+pub fn is_valid_datetime_bits(date: u16, time: u16) -> bool {
+    let year = (date >> 9) as u32 + 1980;
+    let month = ((date >> 5) & 0xf) as u32;
+    let day = (date & 0x1f) as u32;
+    let hour = time >> 11;
+    let minute = (time >> 5) & 0x3f;
+    let second = 2 * (time & 0x1f);
+    (1..=12).contains(&month)
+        && day >= 1
+        && day <= days_in_month(year, month)
+        && hour < 24
+        && minute < 60
+        && second < 60
+}
+
+/// Returns the date and time bits for the given system time (in UTC), with
+/// the same clamping and rounding as `datetime_to_bits`.  Unlike that
+/// function, this doesn't need the `time` crate.
+pub fn datetime_bits_from_system_time(time: SystemTime) -> (u16, u16) {
+    let mut secs = match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs(),
+        Err(_) => return MIN_DATETIME_BITS,
+    };
+    // Round to nearest two seconds:
+    if !secs.is_multiple_of(2) {
+        secs += 1;
+    }
+    let (year, month, day) = civil_from_days(secs / 86400);
+    if year < 1980 {
+        return MIN_DATETIME_BITS;
+    } else if year > 2107 {
+        return MAX_DATETIME_BITS;
+    }
+    let secs_of_day = secs % 86400;
+    let date = (((year - 1980) << 9) | (month << 5) | day) as u16;
+    let hour = secs_of_day / 3600;
+    let minute = (secs_of_day / 60) % 60;
+    let second = secs_of_day % 60;
+    let time = ((hour << 11) | (minute << 5) | (second / 2)) as u16;
+    (date, time)
+}
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+    let is_leap_year = year.is_multiple_of(4)
+        && (!year.is_multiple_of(100) || year.is_multiple_of(400));
+    match month {
+        2 if is_leap_year => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Converts a number of days since 1970-01-01 into a (year, month, day)
+/// triple in the proleptic Gregorian calendar.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    // See http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524
+        - day_of_era / 146_096)
+        / 365;
+    let day_of_year =
+        day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month =
+        if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(all(test, feature = "time"))]
 mod tests {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
     use time::macros::datetime;
 
-    use super::{datetime_from_bits, datetime_to_bits};
+    use super::{
+        datetime_bits_from_system_time, datetime_from_bits, datetime_to_bits,
+        is_valid_datetime_bits,
+    };
 
     #[test]
     fn valid_datetime_bits() {
@@ -84,4 +179,49 @@ mod tests {
         assert_eq!(datetime_from_bits(bits.0, bits.1), Some(dt));
         assert_eq!(bits, (0x4064, 0x28e0));
     }
+
+    #[test]
+    fn validity_of_datetime_bits() {
+        for date in 0..=u16::MAX {
+            for time in [0, 0x7a75, 0xbf7d, 0xbf7e, 0xc000, 0x0780] {
+                assert_eq!(
+                    is_valid_datetime_bits(date, time),
+                    datetime_from_bits(date, time).is_some(),
+                    "date={:#x} time={:#x}",
+                    date,
+                    time
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn datetime_bits_from_system_times() {
+        let time = |secs: u64| UNIX_EPOCH + Duration::from_secs(secs);
+        for secs in [
+            315_532_800,   // 1980-01-01 00:00:00
+            951_782_399,   // 2000-02-28 23:59:59
+            951_868_800,   // 2000-03-01 00:00:00
+            1_515_251_982, // 2018-01-06 15:19:42
+            1_709_251_199, // 2024-02-29 23:59:59
+            4_354_819_199, // 2107-12-31 23:59:59
+            4_354_819_200, // 2108-01-01 00:00:00
+            315_532_799,   // 1979-12-31 23:59:59
+            0,
+        ] {
+            let datetime =
+                time::OffsetDateTime::from_unix_timestamp(secs as i64)
+                    .unwrap();
+            let datetime =
+                time::PrimitiveDateTime::new(datetime.date(), datetime.time());
+            assert_eq!(
+                datetime_bits_from_system_time(time(secs)),
+                datetime_to_bits(datetime)
+            );
+        }
+        let before_epoch = UNIX_EPOCH - Duration::from_secs(1);
+        assert_eq!(datetime_bits_from_system_time(before_epoch), (0x21, 0));
+        let now = datetime_bits_from_system_time(SystemTime::now());
+        assert!(is_valid_datetime_bits(now.0, now.1));
+    }
 }
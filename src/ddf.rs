@@ -0,0 +1,462 @@
+//! Support for makecab's Diamond Directive File (DDF) format.
+//!
+//! Only the commonly-used subset of DDF is understood:
+//!
+//! * `.Set` directives for `CabinetNameTemplate`, `DiskDirectoryTemplate`,
+//!   `DestinationDir`, `Compress`, `CompressionType`, `CompressionMemory`, and
+//!   `MaxDiskSize` (other variables are accepted and ignored, but can still be
+//!   referenced with `%name%` substitution).
+//! * `.Define` directives and `%name%` variable substitution.
+//! * `.New Folder` and `.New Cabinet` directives.
+//! * File lines of the form `source [destination] [/parameter=value ...]`
+//!   (parameters are ignored).
+//!
+//! Comments start with `;`.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::ctype::CompressionType;
+use crate::plan::{
+    link_cabinets, tokenize_line, CabinetPlan, FILE_ENTRY_OVERHEAD,
+};
+
+/// Parses the given DDF text into a `CabinetPlan`.
+///
+/// If the DDF sets `MaxDiskSize`, files are split across multiple cabinets so
+/// that the total uncompressed size of each cabinet stays under that limit,
+/// which requires reading the size of each source file from disk.  Use
+/// [`parse_with_sizes`] to supply the sizes some other way.  As with
+/// makecab, all the cabinets in the plan form one cabinet set, each
+/// recording the names of the previous and next ones.
+pub fn parse(text: &str) -> io::Result<CabinetPlan> {
+    parse_with_sizes(text, |path| Ok(fs::metadata(path)?.len()))
+}
+
+/// Parses the given DDF text into a `CabinetPlan`, using the given function
+/// to determine the size of each source file when `MaxDiskSize` is in effect.
+pub fn parse_with_sizes<F>(
+    text: &str,
+    mut size_of: F,
+) -> io::Result<CabinetPlan>
+where
+    F: FnMut(&Path) -> io::Result<u64>,
+{
+    let mut parser = DdfParser::new();
+    for (index, line) in text.lines().enumerate() {
+        parser.parse_line(index + 1, line, &mut size_of)?;
+    }
+    link_cabinets(&mut parser.plan.cabinets);
+    Ok(parser.plan)
+}
+
+struct DdfParser {
+    plan: CabinetPlan,
+    variables: HashMap<String, String>,
+    compression_type: CompressionType,
+    need_new_cabinet: bool,
+    need_new_folder: bool,
+    num_cabinets: usize,
+    cabinet_size: u64,
+}
+
+impl DdfParser {
+    fn new() -> DdfParser {
+        let mut variables = HashMap::new();
+        for (name, value) in [
+            ("cabinetnametemplate", "*.cab"),
+            ("diskdirectorytemplate", ""),
+            ("destinationdir", ""),
+            ("compress", "on"),
+            ("compressiontype", "mszip"),
+            ("compressionmemory", "21"),
+            ("maxdisksize", "0"),
+        ] {
+            variables.insert(name.to_string(), value.to_string());
+        }
+        DdfParser {
+            plan: CabinetPlan::new(),
+            variables,
+            compression_type: CompressionType::MsZip,
+            need_new_cabinet: true,
+            need_new_folder: true,
+            num_cabinets: 0,
+            cabinet_size: 0,
+        }
+    }
+
+    fn parse_line<F>(
+        &mut self,
+        line_number: usize,
+        line: &str,
+        size_of: &mut F,
+    ) -> io::Result<()>
+    where
+        F: FnMut(&Path) -> io::Result<u64>,
+    {
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            return Ok(());
+        }
+        let line = self.substitute(line_number, line)?;
+        if let Some(directive) = line.strip_prefix('.') {
+            return self.parse_directive(line_number, directive);
+        }
+        let tokens = match tokenize_line(&line) {
+            Some(tokens) => tokens,
+            None => {
+                invalid_data!("Unterminated quote on DDF line {}", line_number)
+            }
+        };
+        let mut positional =
+            tokens.iter().filter(|token| !token.starts_with('/'));
+        let source = match positional.next() {
+            Some(source) => PathBuf::from(source),
+            None => invalid_data!(
+                "Missing source file name on DDF line {}",
+                line_number
+            ),
+        };
+        let name = match positional.next() {
+            Some(name) => name.clone(),
+            None => match source.file_name() {
+                Some(name) => name.to_string_lossy().into_owned(),
+                None => invalid_data!(
+                    "Cannot determine file name for {:?} on DDF line {}",
+                    source,
+                    line_number
+                ),
+            },
+        };
+        if positional.next().is_some() {
+            invalid_data!("Too many fields on DDF line {}", line_number);
+        }
+        let destination_dir = self.variable("destinationdir");
+        let name = if destination_dir.is_empty() {
+            name
+        } else {
+            format!("{}\\{}", destination_dir.trim_end_matches('\\'), name)
+        };
+        let max_size = self.max_disk_size(line_number)?;
+        if max_size > 0 {
+            let size =
+                size_of(&source)? + FILE_ENTRY_OVERHEAD + name.len() as u64;
+            if self.cabinet_size > 0 && self.cabinet_size + size > max_size {
+                self.need_new_cabinet = true;
+            }
+            if self.need_new_cabinet {
+                self.cabinet_size = 0;
+            }
+            self.cabinet_size += size;
+        }
+        self.start_cabinet_and_folder_if_needed();
+        let cabinet = self.plan.cabinets.last_mut().unwrap();
+        cabinet.folders.last_mut().unwrap().add_file(source, name);
+        Ok(())
+    }
+
+    fn parse_directive(
+        &mut self,
+        line_number: usize,
+        directive: &str,
+    ) -> io::Result<()> {
+        let (command, rest) = match directive.split_once(char::is_whitespace) {
+            Some((command, rest)) => (command, rest.trim()),
+            None => (directive, ""),
+        };
+        match command.to_ascii_lowercase().as_str() {
+            "set" | "define" => {
+                let (name, value) = match rest.split_once('=') {
+                    Some((name, value)) => (name.trim(), value.trim()),
+                    None => invalid_data!(
+                        "Expected `.{} NAME=VALUE` on DDF line {}",
+                        command,
+                        line_number
+                    ),
+                };
+                let value = unquote(value);
+                let name = name.to_ascii_lowercase();
+                self.variables.insert(name.clone(), value.to_string());
+                match name.as_str() {
+                    "compress" | "compressiontype" | "compressionmemory" => {
+                        let ctype = self.compression_type(line_number)?;
+                        if ctype != self.compression_type {
+                            self.compression_type = ctype;
+                            self.need_new_folder = true;
+                        }
+                    }
+                    "maxdisksize" => {
+                        self.max_disk_size(line_number)?;
+                    }
+                    _ => {}
+                }
+            }
+            "new" => match rest.to_ascii_lowercase().as_str() {
+                "folder" => self.need_new_folder = true,
+                "cabinet" => self.need_new_cabinet = true,
+                "disk" => self.need_new_cabinet = true,
+                _ => invalid_data!(
+                    "Expected `.New Folder` or `.New Cabinet` on DDF line {}",
+                    line_number
+                ),
+            },
+            "option" => {}
+            _ => invalid_data!(
+                "Unsupported directive {:?} on DDF line {}",
+                command,
+                line_number
+            ),
+        }
+        Ok(())
+    }
+
+    fn start_cabinet_and_folder_if_needed(&mut self) {
+        if self.need_new_cabinet {
+            self.num_cabinets += 1;
+            let template = self.variable("cabinetnametemplate");
+            let mut name =
+                template.replace('*', &self.num_cabinets.to_string());
+            let directory = self.variable("diskdirectorytemplate");
+            if !directory.is_empty() {
+                let directory =
+                    directory.replace('*', &self.num_cabinets.to_string());
+                name = Path::new(&directory)
+                    .join(name)
+                    .to_string_lossy()
+                    .into_owned();
+            }
+            self.plan.add_cabinet(name);
+            self.need_new_cabinet = false;
+            self.need_new_folder = true;
+        }
+        if self.need_new_folder {
+            let cabinet = self.plan.cabinets.last_mut().unwrap();
+            cabinet.add_folder(self.compression_type);
+            self.need_new_folder = false;
+        }
+    }
+
+    fn compression_type(
+        &self,
+        line_number: usize,
+    ) -> io::Result<CompressionType> {
+        if is_off(self.variable("compress")) {
+            return Ok(CompressionType::None);
+        }
+        let ctype = self.variable("compressiontype");
+        match ctype.to_ascii_lowercase().as_str() {
+            "mszip" => Ok(CompressionType::MsZip),
+            "lzx" => {
+                let memory = self.variable("compressionmemory");
+                match memory.parse::<u16>() {
+                    Ok(window) if (15..=21).contains(&window) => {
+                        CompressionType::lzx(window)
+                    }
+                    _ => invalid_data!(
+                        "Invalid CompressionMemory {:?} on DDF line {}",
+                        memory,
+                        line_number
+                    ),
+                }
+            }
+            "none" => Ok(CompressionType::None),
+            _ => invalid_data!(
+                "Unsupported CompressionType {:?} on DDF line {}",
+                ctype,
+                line_number
+            ),
+        }
+    }
+
+    fn max_disk_size(&self, line_number: usize) -> io::Result<u64> {
+        let value = self.variable("maxdisksize");
+        let size = match value.to_ascii_lowercase().as_str() {
+            "cdrom" => 681_574_400,
+            "1.44m" => 1_457_664,
+            "1.2m" => 1_213_952,
+            "720k" => 730_112,
+            "360k" => 362_496,
+            value => match value.parse::<u64>() {
+                Ok(size) => size,
+                Err(_) => invalid_data!(
+                    "Invalid MaxDiskSize {:?} on DDF line {}",
+                    value,
+                    line_number
+                ),
+            },
+        };
+        Ok(size)
+    }
+
+    fn variable(&self, name: &str) -> &str {
+        self.variables.get(name).map(String::as_str).unwrap_or("")
+    }
+
+    /// Replaces `%name%` references with the values of DDF variables, and
+    /// `%%` with a literal percent sign.
+    fn substitute(
+        &self,
+        line_number: usize,
+        line: &str,
+    ) -> io::Result<String> {
+        let mut output = String::with_capacity(line.len());
+        let mut rest = line;
+        while let Some(start) = rest.find('%') {
+            output.push_str(&rest[..start]);
+            rest = &rest[(start + 1)..];
+            let end = match rest.find('%') {
+                Some(end) => end,
+                None => invalid_data!(
+                    "Unterminated variable reference on DDF line {}",
+                    line_number
+                ),
+            };
+            let name = &rest[..end];
+            if name.is_empty() {
+                output.push('%');
+            } else {
+                match self.variables.get(&name.to_ascii_lowercase()) {
+                    Some(value) => output.push_str(value),
+                    None => invalid_data!(
+                        "Undefined variable {:?} on DDF line {}",
+                        name,
+                        line_number
+                    ),
+                }
+            }
+            rest = &rest[(end + 1)..];
+        }
+        output.push_str(rest);
+        Ok(output)
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_quotes = false;
+    for (index, chr) in line.char_indices() {
+        match chr {
+            '"' => in_quotes = !in_quotes,
+            ';' if !in_quotes => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+fn is_off(value: &str) -> bool {
+    matches!(value.to_ascii_lowercase().as_str(), "off" | "no" | "false" | "0")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::path::Path;
+
+    use super::{parse, parse_with_sizes};
+    use crate::ctype::CompressionType;
+
+    #[test]
+    fn parse_simple_ddf() {
+        let ddf = "; Example DDF\n\
+                   .OPTION EXPLICIT\n\
+                   .Set CabinetNameTemplate=setup*.cab\n\
+                   .Set DiskDirectoryTemplate=\n\
+                   .Set CompressionType=MSZIP\n\
+                   readme.txt\n\
+                   .Set DestinationDir=bin\n\
+                   \"tool v2.exe\" tool.exe /inf=no\n\
+                   .Set Compress=off\n\
+                   data.bin ; stored uncompressed\n";
+        let plan = parse(ddf).unwrap();
+        let cabinets: Vec<_> = plan.cabinets().collect();
+        assert_eq!(cabinets.len(), 1);
+        assert_eq!(cabinets[0].name(), Some("setup1.cab"));
+        let folders = cabinets[0].folders();
+        assert_eq!(folders.len(), 2);
+        assert_eq!(folders[0].compression_type(), CompressionType::MsZip);
+        let names: Vec<_> =
+            folders[0].files().iter().map(|file| file.name()).collect();
+        assert_eq!(names, vec!["readme.txt", "bin\\tool.exe"]);
+        assert_eq!(folders[0].files()[1].source(), Path::new("tool v2.exe"));
+        assert_eq!(folders[1].compression_type(), CompressionType::None);
+        assert_eq!(folders[1].files()[0].name(), "bin\\data.bin");
+    }
+
+    #[test]
+    fn parse_ddf_with_variables_and_lzx() {
+        let ddf = ".Define SRC=build\\out\n\
+                   .Set CompressionType=LZX\n\
+                   .Set CompressionMemory=16\n\
+                   %SRC%\\app.exe app.exe\n\
+                   .New Folder\n\
+                   %SRC%\\100%%.txt\n";
+        let plan = parse(ddf).unwrap();
+        let folders = plan.cabinets().next().unwrap().folders();
+        assert_eq!(folders.len(), 2);
+        assert_eq!(
+            folders[0].compression_type(),
+            CompressionType::Lzx(lzxd::WindowSize::KB64)
+        );
+        assert_eq!(
+            folders[0].files()[0].source(),
+            Path::new("build\\out\\app.exe")
+        );
+        assert_eq!(
+            folders[1].files()[0].source(),
+            Path::new("build\\out\\100%.txt")
+        );
+    }
+
+    #[test]
+    fn split_cabinets_by_max_disk_size() {
+        let ddf = ".Set MaxDiskSize=1000\n\
+                   .Set CabinetNameTemplate=disk*.cab\n\
+                   a\nb\nc\n.New Cabinet\nd\n";
+        let plan = parse_with_sizes(ddf, |_| Ok(400)).unwrap();
+        let names: Vec<_> =
+            plan.cabinets().map(|cabinet| cabinet.name().unwrap()).collect();
+        assert_eq!(names, vec!["disk1.cab", "disk2.cab", "disk3.cab"]);
+        let counts: Vec<_> = plan
+            .cabinets()
+            .map(|cabinet| cabinet.folders()[0].files().len())
+            .collect();
+        assert_eq!(counts, vec![2, 1, 1]);
+        let links: Vec<_> = plan
+            .cabinets()
+            .map(|cabinet| {
+                (cabinet.prev_cabinet_name(), cabinet.next_cabinet_name())
+            })
+            .collect();
+        assert_eq!(
+            links,
+            vec![
+                (None, Some("disk2.cab")),
+                (Some("disk1.cab"), Some("disk3.cab")),
+                (Some("disk2.cab"), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_ddfs() {
+        let sizes = |_: &Path| -> io::Result<u64> { Ok(0) };
+        assert!(parse_with_sizes(".Set CompressionType=ZSTD", sizes).is_err());
+        assert!(parse_with_sizes(".Bogus", sizes).is_err());
+        assert!(parse_with_sizes("%UNDEFINED%\\a.txt", sizes).is_err());
+        assert!(parse_with_sizes(".Set MaxDiskSize=big", sizes).is_err());
+        let error = parse_with_sizes("a.txt\n/inf=no", sizes).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(error.to_string().ends_with("DDF line 2"));
+        let error = parse_with_sizes(".Define X=\n%X%", sizes).unwrap_err();
+        assert!(error.to_string().ends_with("DDF line 2"));
+    }
+}
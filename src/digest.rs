@@ -0,0 +1,181 @@
+use std::io::{self, Read, Seek, SeekFrom};
+use std::ops::Range;
+
+use byteorder::{ByteOrder, LittleEndian};
+use flate2::Crc;
+
+use crate::cabinet::Cabinet;
+use crate::consts;
+
+/// The offset of the header's total size field, which signing tools update
+/// when they append a signature.
+const TOTAL_SIZE_FIELD: Range<u64> = 8..12;
+/// The first field of a header reserve laid out the way signing tools lay
+/// it out, pointing to a signature appended to the cabinet.
+const SIGNED_RESERVE_MARKER: u32 = 0x0010_0000;
+
+impl<R: Read + Seek> Cabinet<R> {
+    /// Returns the range of the cabinet file holding its header and
+    /// directory: the cabinet header (with its reserve data and the names of
+    /// the other cabinets in its set), the folder entries, and the file
+    /// entries.  The data blocks come after this range.
+    ///
+    /// Every in-place edit (such as
+    /// [`FileEntry::patch_datetime`](crate::FileEntry::patch_datetime))
+    /// falls within this range.
+    pub fn directory_range(&mut self) -> io::Result<Range<u64>> {
+        self.load_file_entries()?;
+        let start = self.first_file_offset() as u64;
+        let end = self
+            .file_entries_in_directory_order()
+            .map(|file| {
+                file.entry_offset() + 16 + file.stored_name_len() as u64
+            })
+            .max()
+            .unwrap_or(start);
+        Ok(0..end)
+    }
+
+    /// Feeds the bytes of the cabinet's header and directory (see
+    /// [`directory_range`](Cabinet::directory_range)) to `update`, in
+    /// order, so that they can be hashed with any hash function.  The total
+    /// size field and the header's reserve data are left out, since signing
+    /// tools change both when they append a signature to the cabinet; so
+    /// the result is the same before and after signing, and changes exactly
+    /// when an edit touches the signed parts of the directory.
+    pub fn hash_directory<F: FnMut(&[u8])>(
+        &mut self,
+        mut update: F,
+    ) -> io::Result<()> {
+        let range = self.directory_range()?;
+        let reserve_start = consts::HEADER_RESERVE_OFFSET;
+        let reserve_end = reserve_start + self.reserve_data_size() as u64;
+        let mut reader = self.inner.reader.borrow_mut();
+        let mut buffer = vec![0u8; 0x1000];
+        let mut offset = range.start;
+        reader.seek(SeekFrom::Start(offset))?;
+        while offset < range.end {
+            let skip_end = if TOTAL_SIZE_FIELD.contains(&offset) {
+                TOTAL_SIZE_FIELD.end
+            } else if (reserve_start..reserve_end).contains(&offset) {
+                reserve_end
+            } else {
+                offset
+            };
+            if skip_end > offset {
+                offset = skip_end.min(range.end);
+                reader.seek(SeekFrom::Start(offset))?;
+                continue;
+            }
+            // Stop each chunk at the next skipped range:
+            let mut chunk_end = range.end;
+            for next_skip in [TOTAL_SIZE_FIELD.start, reserve_start] {
+                if next_skip > offset {
+                    chunk_end = chunk_end.min(next_skip);
+                }
+            }
+            let len = ((chunk_end - offset) as usize).min(buffer.len());
+            reader.read_exact(&mut buffer[..len])?;
+            update(&buffer[..len]);
+            offset += len as u64;
+        }
+        Ok(())
+    }
+
+    /// Returns the CRC-32 of the cabinet's header and directory, as hashed
+    /// by [`hash_directory`](Cabinet::hash_directory).  Comparing this
+    /// before and after an edit shows whether the edit touched the parts of
+    /// the cabinet covered by a signature; tooling that needs to resist
+    /// deliberate tampering should use `hash_directory` with a
+    /// cryptographic hash instead.
+    pub fn directory_crc32(&mut self) -> io::Result<u32> {
+        let mut crc = Crc::new();
+        self.hash_directory(|bytes| crc.update(bytes))?;
+        Ok(crc.sum())
+    }
+
+    /// Returns the range of the cabinet file holding an appended signature
+    /// (such as an Authenticode signature), if the header's reserve data
+    /// points to one the way signing tools lay it out, or `None` if the
+    /// cabinet doesn't appear to be signed.  Any in-place edit of a signed
+    /// cabinet (outside of the signature itself) invalidates the signature.
+    pub fn signature_range(&self) -> io::Result<Option<Range<u64>>> {
+        let mut reserve = Vec::new();
+        self.reserve_data_reader()?.read_to_end(&mut reserve)?;
+        if reserve.len() < 12
+            || LittleEndian::read_u32(&reserve[0..4]) != SIGNED_RESERVE_MARKER
+        {
+            return Ok(None);
+        }
+        let offset = LittleEndian::read_u32(&reserve[4..8]) as u64;
+        let size = LittleEndian::read_u32(&reserve[8..12]) as u64;
+        if size == 0 {
+            return Ok(None);
+        }
+        Ok(Some(offset..(offset + size)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::builder::CabinetBuilder;
+    use crate::cabinet::Cabinet;
+    use crate::ctype::CompressionType;
+
+    const BINARY: &[u8] = b"MSCF\0\0\0\0\x59\0\0\0\0\0\0\0\
+        \x2c\0\0\0\0\0\0\0\x03\x01\x01\0\x01\0\0\0\x34\x12\0\0\
+        \x43\0\0\0\x01\0\0\0\
+        \x0e\0\0\0\0\0\0\0\0\0\x6c\x22\xba\x59\x01\0hi.txt\0\
+        \x4c\x1a\x2e\x7f\x0e\0\x0e\0Hello, world!\n";
+
+    #[test]
+    fn hash_header_and_directory() {
+        let mut cabinet = Cabinet::new(Cursor::new(BINARY)).unwrap();
+        assert_eq!(cabinet.directory_range().unwrap(), 0..0x43);
+        let mut hashed = Vec::new();
+        cabinet
+            .hash_directory(|bytes| hashed.extend_from_slice(bytes))
+            .unwrap();
+        let expected = [&BINARY[..8], &BINARY[12..0x43]].concat();
+        assert_eq!(hashed, expected);
+        let crc = cabinet.directory_crc32().unwrap();
+
+        // Changing the total size or a data block doesn't change the hash,
+        // but changing a file entry does:
+        let mut binary = BINARY.to_vec();
+        binary[8] = 0x60;
+        binary[0x50] = b'J';
+        let mut cabinet = Cabinet::new(Cursor::new(&binary)).unwrap();
+        assert_eq!(cabinet.directory_crc32().unwrap(), crc);
+        binary[0x36] = 0x21;
+        let mut cabinet = Cabinet::new(Cursor::new(&binary)).unwrap();
+        assert_ne!(cabinet.directory_crc32().unwrap(), crc);
+    }
+
+    #[test]
+    fn find_signature_in_header_reserve() {
+        let cabinet = Cabinet::new(Cursor::new(BINARY)).unwrap();
+        assert_eq!(cabinet.signature_range().unwrap(), None);
+
+        let mut builder = CabinetBuilder::new();
+        let mut reserve = vec![0u8; 20];
+        reserve[0..4].copy_from_slice(&0x0010_0000u32.to_le_bytes());
+        reserve[4..8].copy_from_slice(&0x1234u32.to_le_bytes());
+        reserve[8..12].copy_from_slice(&0x100u32.to_le_bytes());
+        builder.set_reserve_data(reserve);
+        builder.add_folder(CompressionType::None).add_file("hi.txt");
+        let cab_writer = builder.build(Cursor::new(Vec::new())).unwrap();
+        let binary = cab_writer.finish().unwrap().into_inner();
+        let mut cabinet = Cabinet::new(Cursor::new(&binary)).unwrap();
+        assert_eq!(cabinet.signature_range().unwrap(), Some(0x1234..0x1334));
+        // The reserve data isn't hashed:
+        let mut hashed = Vec::new();
+        cabinet
+            .hash_directory(|bytes| hashed.extend_from_slice(bytes))
+            .unwrap();
+        let range = cabinet.directory_range().unwrap();
+        assert_eq!(hashed.len() as u64, range.end - 4 - 20);
+    }
+}
@@ -0,0 +1,360 @@
+use std::fmt::Write as _;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use crate::cabinet::Cabinet;
+#[cfg(not(feature = "time"))]
+use crate::datetime::is_valid_datetime_bits;
+use crate::file::FileEntry;
+
+/// The output format for [`Cabinet::dump_structure`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum DumpFormat {
+    /// An indented, human-readable listing.
+    Text,
+    /// A single JSON object, for consumption by other tools.
+    Json,
+}
+
+/// A node in the structure dump, which is built up first and then rendered
+/// in the requested format.
+enum Node {
+    Num(u64),
+    Hex(u64),
+    Str(String),
+    Bytes(Vec<u8>),
+    Null,
+    List(Vec<Node>),
+    Map(Vec<(&'static str, Node)>),
+}
+
+impl Node {
+    fn opt_str(string: Option<&str>) -> Node {
+        string.map_or(Node::Null, |string| Node::Str(string.to_string()))
+    }
+}
+
+impl<R: Read + Seek> Cabinet<R> {
+    /// Writes the full low-level structure of the cabinet to `writer`: the
+    /// raw header fields, each folder entry along with the headers of its
+    /// data blocks (with their offsets and checksums), and each file entry.
+    /// This is meant for debugging interoperability problems and triaging
+    /// fuzzer findings, much like the `cabinfo` tool.
+    ///
+    /// The header fields are re-read from the underlying reader, so they
+    /// show what is actually in the file, even where the parser tolerated
+    /// or corrected a bad value.  If a folder's data block headers can't be
+    /// read (e.g. because the cabinet is truncated), the blocks read so far
+    /// are dumped along with the error, rather than failing the whole dump.
+    pub fn dump_structure<W: Write>(
+        &mut self,
+        mut writer: W,
+        format: DumpFormat,
+    ) -> io::Result<()> {
+        let root = self.structure_node()?;
+        let mut output = String::new();
+        match format {
+            DumpFormat::Text => render_text(&mut output, &root, 0),
+            DumpFormat::Json => {
+                render_json(&mut output, &root);
+                output.push('\n');
+            }
+        }
+        writer.write_all(output.as_bytes())?;
+        writer.flush()
+    }
+
+    fn structure_node(&mut self) -> io::Result<Node> {
+        self.load_file_entries()?;
+        let header = self.header_node()?;
+        let mut folders = Vec::new();
+        for index in 0..self.inner.folders.len() {
+            let folder = &self.inner.folders[index];
+            let mut fields = vec![
+                ("index", Node::Num(index as u64)),
+                ("entry_offset", Node::Hex(folder.entry_offset())),
+                (
+                    "first_data_block_offset",
+                    Node::Hex(folder.first_data_block_offset() as u64),
+                ),
+                (
+                    "num_data_blocks",
+                    Node::Num(folder.num_data_blocks() as u64),
+                ),
+                (
+                    "compression_type",
+                    Node::Str(folder.compression_type().to_string()),
+                ),
+                (
+                    "compression_bits",
+                    Node::Hex(folder.raw_compression_bits() as u64),
+                ),
+                (
+                    "continuation",
+                    Node::Str(format!("{:?}", folder.continuation)),
+                ),
+                ("reserve_data", Node::Bytes(folder.reserve_data().to_vec())),
+            ];
+            let mut blocks = Vec::new();
+            let mut error = Node::Null;
+            for (block_index, block) in self.data_blocks(index)?.enumerate() {
+                match block {
+                    Ok(block) => blocks.push(Node::Map(vec![
+                        ("index", Node::Num(block_index as u64)),
+                        ("offset", Node::Hex(block.offset())),
+                        ("checksum", Node::Hex(block.checksum() as u64)),
+                        (
+                            "compressed_size",
+                            Node::Num(block.compressed_size() as u64),
+                        ),
+                        (
+                            "uncompressed_size",
+                            Node::Num(block.uncompressed_size() as u64),
+                        ),
+                        (
+                            "reserve_data",
+                            Node::Bytes(block.reserve_data().to_vec()),
+                        ),
+                    ])),
+                    Err(err) => error = Node::Str(err.to_string()),
+                }
+            }
+            fields.push(("blocks", Node::List(blocks)));
+            fields.push(("blocks_error", error));
+            folders.push(Node::Map(fields));
+        }
+        let files = self
+            .file_entries_in_directory_order()
+            .enumerate()
+            .map(|(index, file)| {
+                Node::Map(vec![
+                    ("index", Node::Num(index as u64)),
+                    ("entry_offset", Node::Hex(file.entry_offset())),
+                    ("name", Node::Str(file.name().to_string())),
+                    ("name_raw", Node::Bytes(file.name_raw().to_vec())),
+                    (
+                        "uncompressed_size",
+                        Node::Num(file.uncompressed_size() as u64),
+                    ),
+                    (
+                        "uncompressed_offset",
+                        Node::Num(file.uncompressed_offset() as u64),
+                    ),
+                    ("folder_index", Node::Num(file.folder_index() as u64)),
+                    (
+                        "continuation",
+                        Node::Str(format!("{:?}", file.continuation())),
+                    ),
+                    ("datetime", datetime_node(file)),
+                    ("attributes", Node::Hex(file.attributes() as u64)),
+                ])
+            })
+            .collect();
+        let warnings = self
+            .warnings()
+            .iter()
+            .map(|warning| Node::Str(warning.to_string()))
+            .collect();
+        Ok(Node::Map(vec![
+            ("header", header),
+            ("folders", Node::List(folders)),
+            ("files", Node::List(files)),
+            ("warnings", Node::List(warnings)),
+        ]))
+    }
+
+    fn header_node(&mut self) -> io::Result<Node> {
+        let reader = self.inner.reader.get_mut();
+        reader.seek(SeekFrom::Start(0))?;
+        let mut signature = [0u8; 4];
+        reader.read_exact(&mut signature)?;
+        let reserved1 = reader.read_u32::<LittleEndian>()?;
+        let total_size = reader.read_u32::<LittleEndian>()?;
+        let reserved2 = reader.read_u32::<LittleEndian>()?;
+        let first_file_offset = reader.read_u32::<LittleEndian>()?;
+        let reserved3 = reader.read_u32::<LittleEndian>()?;
+        let minor_version = reader.read_u8()?;
+        let major_version = reader.read_u8()?;
+        let num_folders = reader.read_u16::<LittleEndian>()?;
+        let num_files = reader.read_u16::<LittleEndian>()?;
+        let flags = reader.read_u16::<LittleEndian>()?;
+        let cabinet_set_id = reader.read_u16::<LittleEndian>()?;
+        let cabinet_set_index = reader.read_u16::<LittleEndian>()?;
+        Ok(Node::Map(vec![
+            ("signature", Node::Bytes(signature.to_vec())),
+            ("reserved1", Node::Hex(reserved1 as u64)),
+            ("total_size", Node::Num(total_size as u64)),
+            ("reserved2", Node::Hex(reserved2 as u64)),
+            ("first_file_offset", Node::Hex(first_file_offset as u64)),
+            ("reserved3", Node::Hex(reserved3 as u64)),
+            (
+                "version",
+                Node::Str(format!("{}.{}", major_version, minor_version)),
+            ),
+            ("num_folders", Node::Num(num_folders as u64)),
+            ("num_files", Node::Num(num_files as u64)),
+            ("flags", Node::Hex(flags as u64)),
+            ("cabinet_set_id", Node::Hex(cabinet_set_id as u64)),
+            ("cabinet_set_index", Node::Num(cabinet_set_index as u64)),
+            ("header_reserve_data", Node::Bytes(self.reserve_data().to_vec())),
+            (
+                "folder_reserve_size",
+                Node::Num(self.folder_reserve_size() as u64),
+            ),
+            ("data_reserve_size", Node::Num(self.data_reserve_size() as u64)),
+            ("prev_cabinet", Node::opt_str(self.prev_cabinet_name())),
+            ("prev_disk", Node::opt_str(self.prev_disk_name())),
+            ("next_cabinet", Node::opt_str(self.next_cabinet_name())),
+            ("next_disk", Node::opt_str(self.next_disk_name())),
+        ]))
+    }
+}
+
+fn is_compound(node: &Node) -> bool {
+    matches!(node, Node::List(items) if !items.is_empty())
+        || matches!(node, Node::Map(fields) if !fields.is_empty())
+}
+
+/// Renders a scalar node (or an empty list or map) on a single line.
+fn render_scalar(output: &mut String, node: &Node) {
+    match node {
+        Node::Num(number) => write!(output, "{}", number).unwrap(),
+        Node::Hex(number) => write!(output, "0x{:x}", number).unwrap(),
+        Node::Str(string) => write!(output, "{:?}", string).unwrap(),
+        Node::Bytes(bytes) if bytes.is_empty() => output.push_str("(none)"),
+        Node::Bytes(bytes) => output.push_str(&hex(bytes)),
+        Node::Null => output.push_str("(none)"),
+        Node::List(_) => output.push_str("[]"),
+        Node::Map(_) => output.push_str("{}"),
+    }
+}
+
+fn render_text(output: &mut String, node: &Node, depth: usize) {
+    let indent = "  ".repeat(depth);
+    match node {
+        Node::Map(fields) if !fields.is_empty() => {
+            for (key, value) in fields {
+                write!(output, "{}{}:", indent, key).unwrap();
+                if is_compound(value) {
+                    output.push('\n');
+                    render_text(output, value, depth + 1);
+                } else {
+                    output.push(' ');
+                    render_scalar(output, value);
+                    output.push('\n');
+                }
+            }
+        }
+        Node::List(items) if !items.is_empty() => {
+            for (index, item) in items.iter().enumerate() {
+                write!(output, "{}[{}]", indent, index).unwrap();
+                if is_compound(item) {
+                    output.push('\n');
+                    render_text(output, item, depth + 1);
+                } else {
+                    output.push(' ');
+                    render_scalar(output, item);
+                    output.push('\n');
+                }
+            }
+        }
+        _ => {
+            output.push_str(&indent);
+            render_scalar(output, node);
+            output.push('\n');
+        }
+    }
+}
+
+fn render_json(output: &mut String, node: &Node) {
+    match node {
+        Node::Num(number) | Node::Hex(number) => {
+            write!(output, "{}", number).unwrap()
+        }
+        Node::Str(string) => json_string(output, string),
+        Node::Bytes(bytes) => json_string(output, &hex(bytes)),
+        Node::Null => output.push_str("null"),
+        Node::List(items) => {
+            output.push('[');
+            for (index, item) in items.iter().enumerate() {
+                if index > 0 {
+                    output.push(',');
+                }
+                render_json(output, item);
+            }
+            output.push(']');
+        }
+        Node::Map(fields) => {
+            output.push('{');
+            for (index, (key, value)) in fields.iter().enumerate() {
+                if index > 0 {
+                    output.push(',');
+                }
+                json_string(output, key);
+                output.push(':');
+                render_json(output, value);
+            }
+            output.push('}');
+        }
+    }
+}
+
+fn json_string(output: &mut String, string: &str) {
+    output.push('"');
+    for chr in string.chars() {
+        match chr {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            _ if (chr as u32) < 0x20 => {
+                write!(output, "\\u{:04x}", chr as u32).unwrap()
+            }
+            _ => output.push(chr),
+        }
+    }
+    output.push('"');
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(feature = "time")]
+fn datetime_node(file: &FileEntry) -> Node {
+    file.datetime()
+        .map_or(Node::Null, |datetime| Node::Str(datetime.to_string()))
+}
+
+/// Formats a file's datetime the same way as `time::PrimitiveDateTime` does,
+/// for when the `time` feature is disabled.
+#[cfg(not(feature = "time"))]
+fn datetime_node(file: &FileEntry) -> Node {
+    let (date, time) = file.raw_datetime();
+    if !is_valid_datetime_bits(date, time) {
+        return Node::Null;
+    }
+    Node::Str(format!(
+        "{}-{:02}-{:02} {}:{:02}:{:02}.0",
+        (date >> 9) + 1980,
+        (date >> 5) & 0xf,
+        date & 0x1f,
+        time >> 11,
+        (time >> 5) & 0x3f,
+        2 * (time & 0x1f)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::json_string;
+
+    #[test]
+    fn escape_json_strings() {
+        let mut output = String::new();
+        json_string(&mut output, "a\"b\\c\nd\u{1}é");
+        assert_eq!(output, "\"a\\\"b\\\\c\\nd\\u0001é\"");
+    }
+}
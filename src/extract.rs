@@ -0,0 +1,445 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Seek, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use flate2::Crc;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::cabinet::{check_extractable, Cabinet, CabinetInner, ReadSeek};
+use crate::file::FileReader;
+use crate::folder::{BlockSource, FolderReader};
+
+/// The outcome of a batch operation over every file in a cabinet, as
+/// returned by [`Cabinet::extract_all`] and [`Cabinet::verify_all`].
+///
+/// With the `serde` feature enabled, reports can be serialized (e.g. to
+/// JSON) so that orchestration tools can persist and render them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ExtractReport {
+    files: Vec<FileReport>,
+    duration: Duration,
+}
+
+impl ExtractReport {
+    /// Returns the report for each file, in directory order.
+    pub fn files(&self) -> &[FileReport] {
+        &self.files
+    }
+
+    /// Returns the total time taken by the operation.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Returns the total number of bytes extracted (or verified) across all
+    /// files.
+    pub fn total_bytes(&self) -> u64 {
+        self.files.iter().map(|file| file.bytes).sum()
+    }
+
+    /// Returns true if every file was processed without errors, skips, or
+    /// damage.
+    pub fn is_success(&self) -> bool {
+        self.files.iter().all(|file| file.status == FileStatus::Ok)
+    }
+
+    /// Returns an iterator over the reports for files that failed.
+    pub fn failures(&self) -> impl Iterator<Item = &FileReport> {
+        self.files.iter().filter(|file| file.status.is_failed())
+    }
+}
+
+/// The outcome of extracting (or verifying) a single file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FileReport {
+    name: String,
+    folder_index: usize,
+    size: u32,
+    path: Option<PathBuf>,
+    bytes: u64,
+    crc32: Option<u32>,
+    duration: Duration,
+    status: FileStatus,
+}
+
+impl FileReport {
+    /// Returns the file's name within the cabinet.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the index of the folder containing the file.
+    pub fn folder_index(&self) -> usize {
+        self.folder_index
+    }
+
+    /// Returns the file's uncompressed size, as recorded in the cabinet.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Returns the path that the file was extracted to, if any.  This is
+    /// always `None` for [`Cabinet::verify_all`].
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Returns the number of bytes that were decompressed for the file
+    /// (which may be less than [`size`](FileReport::size) if it failed).
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    /// Returns the CRC-32 (as used by zip and gzip) of the file's
+    /// decompressed data, if the cabinet was opened with
+    /// [`ReadOptions::file_crc32`](crate::ReadOptions::file_crc32) enabled
+    /// and the file was read in full.  This lets the data be checked against
+    /// an external manifest without reading the extracted file back in.
+    pub fn crc32(&self) -> Option<u32> {
+        self.crc32
+    }
+
+    /// Returns the time spent on this file.
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Returns what happened to the file.
+    pub fn status(&self) -> &FileStatus {
+        &self.status
+    }
+}
+
+/// What happened to a single file in an [`ExtractReport`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum FileStatus {
+    /// The file was processed successfully.
+    Ok,
+    /// The file was processed, but some of its data blocks could not be
+    /// decoded and were replaced with zeros (only possible with
+    /// [`Cabinet::set_continue_on_error`]), or some of its data lay past the
+    /// end of its folder's data (only possible with a
+    /// [`SizeMismatch`](crate::SizeMismatch) policy other than `Error`).
+    Recovered {
+        /// The damaged byte ranges, relative to the start of the file.
+        damaged_ranges: Vec<Range<u64>>,
+    },
+    /// The file was not processed.
+    Skipped(SkipReason),
+    /// Processing the file failed.
+    Failed {
+        /// The kind of I/O error (as formatted by `Debug`, e.g.
+        /// `"InvalidData"`).
+        kind: String,
+        /// The error message.
+        message: String,
+    },
+}
+
+impl FileStatus {
+    /// Returns true if this is a [`Failed`](FileStatus::Failed) status.
+    pub fn is_failed(&self) -> bool {
+        matches!(self, FileStatus::Failed { .. })
+    }
+
+    fn failed(error: &io::Error) -> FileStatus {
+        FileStatus::Failed {
+            kind: format!("{:?}", error.kind()),
+            message: error.to_string(),
+        }
+    }
+}
+
+/// Why a file in an [`ExtractReport`] was skipped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum SkipReason {
+    /// The file's data is (at least partly) in another cabinet in the set.
+    NeedsOtherCabinet,
+    /// The file's name can't safely be used as a path within the output
+    /// directory (e.g. it contains a `..` component).
+    UnsafePath,
+    /// The operation was cancelled (see
+    /// [`ReadOptions::cancel`](crate::ReadOptions::cancel)) before this file
+    /// was reached.
+    Cancelled,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        formatter.write_str(match *self {
+            SkipReason::NeedsOtherCabinet => {
+                "needs another cabinet in the set"
+            }
+            SkipReason::UnsafePath => "unsafe path",
+            SkipReason::Cancelled => "cancelled",
+        })
+    }
+}
+
+/// Returns the relative path to extract a file with the given name to, or
+/// `None` if the name contains components that could escape the output
+/// directory.
+fn relative_path(name: &str) -> Option<PathBuf> {
+    let mut path = PathBuf::new();
+    for component in name.split(['\\', '/']) {
+        match component {
+            "" | "." => {}
+            ".." => return None,
+            _ if component.contains(':') => return None,
+            _ => path.push(component),
+        }
+    }
+    if path.as_os_str().is_empty() {
+        None
+    } else {
+        Some(path)
+    }
+}
+
+impl<R: Read + Seek> Cabinet<R> {
+    /// Extracts every file in the cabinet into `out_dir` (creating it and
+    /// any subdirectories as needed), using the backslash-separated
+    /// components of each file's name as a relative path.
+    ///
+    /// Unlike reading files one at a time, a failure on one file doesn't
+    /// stop the others from being extracted; instead, the outcome for each
+    /// file is recorded in the returned report.  Files whose names would
+    /// escape `out_dir`, and files that need another cabinet in the set, are
+    /// skipped.  To recover as much data as possible from a damaged cabinet,
+    /// call [`set_continue_on_error`](Cabinet::set_continue_on_error) first.
+    ///
+    /// Returns an error only if `out_dir` can't be created (or if the file
+    /// entries haven't been [loaded](Cabinet::load_file_entries) yet and
+    /// can't be).
+    pub fn extract_all(
+        &mut self,
+        out_dir: &Path,
+    ) -> io::Result<ExtractReport> {
+        self.load_file_entries()?;
+        fs::create_dir_all(out_dir)?;
+        Ok(self.process_all(Some(out_dir)))
+    }
+
+    /// Decompresses every file in the cabinet without writing it anywhere,
+    /// checking that it can be read in full, and reports the outcome for
+    /// each file.  If the cabinet was opened with
+    /// [`ReadOptions::defer_file_entries`](crate::ReadOptions::defer_file_entries),
+    /// call [`load_file_entries`](Cabinet::load_file_entries) first, since
+    /// otherwise there are no files to verify.
+    pub fn verify_all(&mut self) -> ExtractReport {
+        self.process_all(None)
+    }
+
+    /// Processes each file, extracting it into `out_dir` if given, or else
+    /// just decompressing it.  The files are read folder by folder in the
+    /// order their data is stored, sharing one folder reader per folder, so
+    /// that each folder is decompressed only once; the report still lists
+    /// them in directory order.
+    fn process_all(&mut self, out_dir: Option<&Path>) -> ExtractReport {
+        let start = Instant::now();
+        let inner: &CabinetInner<dyn ReadSeek> = &self.inner;
+        let mut reports: Vec<(FileReport, Option<PathBuf>)> = inner
+            .files
+            .iter()
+            .map(|file| {
+                let report = FileReport {
+                    name: file.name().to_string(),
+                    folder_index: file.folder_index(),
+                    size: file.uncompressed_size(),
+                    path: None,
+                    bytes: 0,
+                    crc32: None,
+                    duration: Duration::ZERO,
+                    status: FileStatus::Ok,
+                };
+                let path = out_dir.and_then(|out_dir| {
+                    Some(out_dir.join(relative_path(file.name())?))
+                });
+                (report, path)
+            })
+            .collect();
+        let mut order: Vec<usize> = (0..inner.files.len()).collect();
+        order.sort_by_key(|&index| {
+            let file = &inner.files[index];
+            (file.folder_index(), file.uncompressed_offset)
+        });
+        let crc32 = inner.options.file_crc32;
+        let cancel = inner.options.cancel.as_ref();
+        // The reader for the folder of the most recently processed file, if
+        // it can be reused for the next one.
+        let mut folder: Option<(usize, FolderReader<'_, R>)> = None;
+        for index in order {
+            let file = &inner.files[index];
+            let (file_report, path) = &mut reports[index];
+            let file_start = Instant::now();
+            if cancel.is_some_and(|token| token.is_cancelled()) {
+                file_report.status =
+                    FileStatus::Skipped(SkipReason::Cancelled);
+            } else if file.is_continued() {
+                file_report.status =
+                    FileStatus::Skipped(SkipReason::NeedsOtherCabinet);
+            } else if out_dir.is_some() && path.is_none() {
+                file_report.status =
+                    FileStatus::Skipped(SkipReason::UnsafePath);
+            } else {
+                let folder_index = file.folder_index();
+                let file_reader = check_extractable(inner, file)
+                    .and_then(|()| match folder.take() {
+                        Some((index, reader)) if index == folder_index => {
+                            Ok(reader)
+                        }
+                        _ => {
+                            let source = BlockSource::Cabinet(inner);
+                            FolderReader::new(source, folder_index)
+                        }
+                    })
+                    .and_then(|reader| {
+                        let offset = file.uncompressed_offset as u64;
+                        let size = file.uncompressed_size() as u64;
+                        FileReader::new(reader, offset, size)
+                    });
+                let (result, reader) = match (file_reader, path.as_ref()) {
+                    (Err(error), _) => ((0, Err(error)), None),
+                    (Ok(reader), None) => {
+                        copy_file_to(reader, io::sink(), crc32)
+                    }
+                    (Ok(reader), Some(path)) => {
+                        file_report.path = Some(path.clone());
+                        extract_one(reader, path, crc32)
+                    }
+                };
+                // Only keep the folder reader for the next file if this one
+                // was read without errors, since otherwise the reader may
+                // have been left in an unknown state.
+                if result.1.is_ok() {
+                    folder = reader.map(|reader| (folder_index, reader));
+                }
+                record(file_report, result);
+            }
+            file_report.duration = file_start.elapsed();
+        }
+        ExtractReport {
+            files: reports.into_iter().map(|(report, _)| report).collect(),
+            duration: start.elapsed(),
+        }
+    }
+}
+
+/// The outcome of reading one file, along with the folder reader it was read
+/// from, if that is still usable.
+type Outcome<'a, R> = ((u64, io::Result<Copied>), Option<FolderReader<'a, R>>);
+
+fn extract_one<'a, R: Read + Seek>(
+    reader: FileReader<'a, R>,
+    path: &Path,
+    crc32: bool,
+) -> Outcome<'a, R> {
+    if let Some(parent) = path.parent() {
+        if let Err(error) = fs::create_dir_all(parent) {
+            return ((0, Err(error)), Some(reader.into_folder_reader()));
+        }
+    }
+    let writer = match File::create(path) {
+        Ok(file) => BufWriter::new(file),
+        Err(error) => {
+            return ((0, Err(error)), Some(reader.into_folder_reader()))
+        }
+    };
+    copy_file_to(reader, writer, crc32)
+}
+
+/// Copies a file's data into `writer`, returning the number of bytes copied
+/// along with the file's damaged ranges and CRC-32 (or the error that stopped
+/// the copy).
+fn copy_file_to<'a, R: Read + Seek, W: Write>(
+    mut reader: FileReader<'a, R>,
+    mut writer: W,
+    crc32: bool,
+) -> Outcome<'a, R> {
+    let mut crc = crc32.then(Crc::new);
+    let mut buffer = vec![0u8; 0x8000];
+    let mut bytes = 0u64;
+    loop {
+        let count = match reader.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(count) => count,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {
+                continue
+            }
+            Err(error) => return ((bytes, Err(error)), None),
+        };
+        if let Err(error) = writer.write_all(&buffer[..count]) {
+            return ((bytes, Err(error)), Some(reader.into_folder_reader()));
+        }
+        if let Some(ref mut crc) = crc {
+            crc.update(&buffer[..count]);
+        }
+        bytes += count as u64;
+    }
+    if let Err(error) = writer.flush() {
+        return ((bytes, Err(error)), Some(reader.into_folder_reader()));
+    }
+    let crc32 = crc.map(|crc| crc.sum());
+    let damaged_ranges = reader.damaged_ranges();
+    ((bytes, Ok((damaged_ranges, crc32))), Some(reader.into_folder_reader()))
+}
+
+/// The damaged ranges and CRC-32 (if requested) of a successfully copied
+/// file.
+type Copied = (Vec<Range<u64>>, Option<u32>);
+
+fn record(
+    file_report: &mut FileReport,
+    (bytes, result): (u64, io::Result<Copied>),
+) {
+    file_report.bytes = bytes;
+    file_report.status = match result {
+        Ok((damaged_ranges, crc32)) => {
+            file_report.crc32 = crc32;
+            if damaged_ranges.is_empty() {
+                FileStatus::Ok
+            } else {
+                FileStatus::Recovered { damaged_ranges }
+            }
+        }
+        Err(error) => FileStatus::failed(&error),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::relative_path;
+
+    #[test]
+    fn relative_paths_for_file_names() {
+        assert_eq!(relative_path("foo.txt"), Some(PathBuf::from("foo.txt")));
+        assert_eq!(
+            relative_path("a\\.\\b/c.txt"),
+            Some(["a", "b", "c.txt"].iter().collect())
+        );
+        assert_eq!(
+            relative_path("\\root.txt"),
+            Some(PathBuf::from("root.txt"))
+        );
+        assert_eq!(relative_path("a\\..\\b.txt"), None);
+        assert_eq!(relative_path("C:\\Windows\\win.ini"), None);
+        assert_eq!(relative_path("\\\\"), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn reports_are_serializable() {
+        fn assert_serde<T: serde::Serialize + serde::de::DeserializeOwned>() {}
+        assert_serde::<super::ExtractReport>();
+    }
+}
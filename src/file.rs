@@ -1,13 +1,23 @@
-use std::io::{self, Read, Seek, SeekFrom};
+use std::borrow::Cow;
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
 use std::slice;
+use std::sync::OnceLock;
 
-use byteorder::{LittleEndian, ReadBytesExt};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+#[cfg(feature = "time")]
 use time::PrimitiveDateTime;
 
 use crate::consts;
-use crate::datetime::datetime_from_bits;
-use crate::folder::FolderReader;
-use crate::string::read_null_terminated_string;
+use crate::datetime::is_valid_datetime_bits;
+#[cfg(feature = "time")]
+use crate::datetime::{datetime_from_bits, datetime_to_bits};
+use crate::folder::{Continuation, FolderReader};
+use crate::options::{ReadOptions, SizeMismatch};
+use crate::string::{
+    decode_utf16, looks_like_utf16, read_null_terminated_bytes_into,
+    read_null_terminated_utf16_into, NameDecoding, NameLocation,
+};
 
 /// An iterator over the file entries in a folder.
 #[derive(Clone)]
@@ -15,16 +25,51 @@ pub struct FileEntries<'a> {
     pub(crate) iter: slice::Iter<'a, FileEntry>,
 }
 
+/// An iterator over the backslash-separated components of a file's path
+/// within a cabinet.
+#[derive(Clone)]
+pub struct PathComponents<'a> {
+    iter: std::str::Split<'a, char>,
+}
+
 /// Metadata about one file stored in a cabinet.
 #[derive(Debug, Clone)]
 pub struct FileEntry {
-    name: String,
     name_raw: Vec<u8>,
+    name_decoding: NameDecoding,
+    /// The decoded name, for names that aren't valid UTF-8 (and so can't be
+    /// borrowed directly from `name_raw`).  Filled in on first use.
+    decoded_name: OnceLock<String>,
+    #[cfg(feature = "time")]
     datetime: Option<PrimitiveDateTime>,
+    datetime_bits: (u16, u16),
     uncompressed_size: u32,
     attributes: u16,
     pub(crate) folder_index: u16,
     pub(crate) uncompressed_offset: u32,
+    pub(crate) continuation: Continuation,
+    pub(crate) entry_offset: u64,
+    index: usize,
+}
+
+/// A borrowed view of one file's entry, decoded on the fly while visiting a
+/// cabinet's file directory with
+/// [`Cabinet::visit_entries`](crate::Cabinet::visit_entries).  The name is
+/// borrowed from a buffer that is reused for the next entry.
+#[derive(Clone, Copy)]
+pub struct FileInfoRef<'a> {
+    pub(crate) name_raw: &'a [u8],
+    pub(crate) name_decoding: NameDecoding,
+    pub(crate) datetime_bits: (u16, u16),
+    #[cfg(feature = "time")]
+    pub(crate) default_datetime: Option<PrimitiveDateTime>,
+    pub(crate) uncompressed_size: u32,
+    pub(crate) uncompressed_offset: u32,
+    pub(crate) attributes: u16,
+    pub(crate) folder_index: u16,
+    pub(crate) continuation: Continuation,
+    pub(crate) entry_offset: u64,
+    pub(crate) index: usize,
 }
 
 /// A reader for reading decompressed data from a cabinet file.
@@ -33,8 +78,20 @@ pub struct FileReader<'a, R: 'a> {
     pub(crate) file_start_in_folder: u64,
     pub(crate) offset: u64,
     pub(crate) size: u64,
+    /// False if `reader` hasn't yet been moved to `offset` (which is put off
+    /// until the next read, so that creating or seeking a `FileReader` is
+    /// cheap).
+    pub(crate) positioned: bool,
+    pub(crate) size_mismatch: SizeMismatch,
+    /// The offset within the file at which the folder's data was found to
+    /// run out, if it has been reached.
+    pub(crate) data_end: Option<u64>,
 }
 
+/// Zeros to serve from `FileReader::fill_buf` for the missing part of a file
+/// under `SizeMismatch::ZeroFill`.
+static ZEROS: [u8; 0x1000] = [0; 0x1000];
+
 impl<'a> Iterator for FileEntries<'a> {
     type Item = &'a FileEntry;
 
@@ -49,32 +106,151 @@ impl<'a> Iterator for FileEntries<'a> {
 
 impl<'a> ExactSizeIterator for FileEntries<'a> {}
 
+impl<'a> Iterator for PathComponents<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        self.iter.by_ref().find(|component| !component.is_empty())
+    }
+}
+
 impl FileEntry {
     /// Returns the name of file.
+    ///
+    /// Names that are valid UTF-8 (the common case) are borrowed directly
+    /// from the [raw bytes](FileEntry::name_bytes), so this never allocates
+    /// for them.  Other names are decoded (according to
+    /// [`name_decoding`](FileEntry::name_decoding)) the first time this is
+    /// called, and the result is cached, so opening a cabinet with a huge
+    /// directory doesn't pay for decoding names that are never looked at.
     pub fn name(&self) -> &str {
-        &self.name
+        if self.name_decoding == NameDecoding::Utf8 {
+            if let Ok(name) = std::str::from_utf8(&self.name_raw) {
+                return name;
+            }
+        }
+        self.decoded_name.get_or_init(|| match self.name_decoding {
+            NameDecoding::Utf16 => decode_utf16(&self.name_raw),
+            _ => String::from_utf8_lossy(&self.name_raw).into_owned(),
+        })
+    }
+
+    /// Returns the name of the file as the raw bytes stored in the cabinet
+    /// (without the NUL terminator).  This never allocates or decodes.
+    pub fn name_bytes(&self) -> &[u8] {
+        &self.name_raw
+    }
+
+    /// Returns an iterator over the components of this file's path, as split
+    /// on the backslash (`\\`) separators used in cabinet files.  Empty
+    /// components (e.g. from a leading or doubled separator) are skipped.
+    pub fn path_components(&self) -> PathComponents<'_> {
+        PathComponents { iter: self.name().split('\\') }
     }
 
-    /// Returns the name of file as a byte slice, useful if the string is not utf-8.
+    /// Returns the name of file as a byte slice, useful if the string is not
+    /// utf-8.  This is the same as [`name_bytes`](FileEntry::name_bytes).
     pub fn name_raw(&self) -> &[u8] {
         &self.name_raw
     }
 
+    /// Returns how the string returned by [`name`](FileEntry::name) was
+    /// decoded from the [raw bytes](FileEntry::name_raw).  Callers that need
+    /// exact names can use this to tell whether `name()` is trustworthy.
+    pub fn name_decoding(&self) -> NameDecoding {
+        self.name_decoding
+    }
+
+    /// Returns true if this file's name is not flagged as UTF-8 and is not
+    /// valid UTF-8, and so is a candidate for charset detection.
+    #[cfg(feature = "chardet")]
+    pub(crate) fn needs_name_detection(&self) -> bool {
+        !self.is_name_utf() && self.name_decoding == NameDecoding::Lossy
+    }
+
+    /// Re-decodes this file's name using the given encoding.
+    #[cfg(feature = "chardet")]
+    pub(crate) fn decode_name_with(
+        &mut self,
+        encoding: &'static encoding_rs::Encoding,
+        confident: bool,
+    ) {
+        let (name, _, _) = encoding.decode(&self.name_raw);
+        self.decoded_name = OnceLock::from(name.into_owned());
+        self.name_decoding =
+            NameDecoding::Detected { encoding: encoding.name(), confident };
+    }
+
     /// Returns the datetime for this file.  According to the CAB spec, this
     /// "is typically considered the 'last modified' time in local time, but
     /// the actual definition is application-defined."
     ///
     /// Note that this will return [`None`] if the datetime in the cabinet file
-    /// was not a valid date/time.
+    /// was not a valid date/time, unless the cabinet was opened with a
+    /// [default datetime](crate::ReadOptions::default_datetime), which is
+    /// returned instead.
+    ///
+    /// Requires the `time` feature (enabled by default); see also
+    /// [`raw_datetime`](FileEntry::raw_datetime).
+    #[cfg(feature = "time")]
     pub fn datetime(&self) -> Option<PrimitiveDateTime> {
         self.datetime
     }
 
+    /// Returns true if the date and time stored in this file's entry form a
+    /// valid datetime.  Use
+    /// [`Cabinet::files_with_invalid_datetimes`](crate::Cabinet::files_with_invalid_datetimes)
+    /// to find all the files that don't.
+    pub fn has_valid_datetime(&self) -> bool {
+        let (date, time) = self.datetime_bits;
+        is_valid_datetime_bits(date, time)
+    }
+
+    /// Returns the raw date and time bits stored in this file's entry, as a
+    /// `(date, time)` pair in MS-DOS format, whether or not they are valid.
+    /// These can be written back out unchanged with
+    /// [`FileBuilder::set_raw_datetime`](crate::FileBuilder::set_raw_datetime).
+    pub fn raw_datetime(&self) -> (u16, u16) {
+        self.datetime_bits
+    }
+
     /// Returns the total size of the file when decompressed, in bytes.
     pub fn uncompressed_size(&self) -> u32 {
         self.uncompressed_size
     }
 
+    /// Returns the index of the folder that this file's data is stored in.
+    /// (For files continued from or to another cabinet, this is the first or
+    /// last folder in this cabinet, respectively.)
+    pub fn folder_index(&self) -> usize {
+        self.folder_index as usize
+    }
+
+    /// Returns the offset of the start of this file's data within its
+    /// folder's uncompressed data stream.
+    ///
+    /// Files normally occupy disjoint extents of the folder, but some
+    /// cabinets deliberately give several files the same offset (or
+    /// overlapping extents) so that they share their data; each such file
+    /// can still be read normally.  Use
+    /// [`shares_data_with`](FileEntry::shares_data_with) to detect this.
+    pub fn uncompressed_offset(&self) -> u32 {
+        self.uncompressed_offset
+    }
+
+    /// Returns true if this file and `other` are in the same folder and
+    /// their extents within the folder's uncompressed data overlap, so that
+    /// at least some of their data is shared.  Empty files never share data.
+    pub fn shares_data_with(&self, other: &FileEntry) -> bool {
+        let start = self.uncompressed_offset as u64;
+        let end = start + self.uncompressed_size as u64;
+        let other_start = other.uncompressed_offset as u64;
+        let other_end = other_start + other.uncompressed_size as u64;
+        self.folder_index == other.folder_index
+            && start < other_end
+            && other_start < end
+    }
+
     /// Returns true if this file has the "read-only" attribute set.
     pub fn is_read_only(&self) -> bool {
         (self.attributes & consts::ATTR_READ_ONLY) != 0
@@ -102,10 +278,348 @@ impl FileEntry {
         (self.attributes & consts::ATTR_EXEC) != 0
     }
 
+    /// Returns whether this file's data is split across cabinets in a set
+    /// (as indicated by the special folder index values in its file entry).
+    /// Continued files cannot be extracted from this cabinet alone.
+    pub fn continuation(&self) -> Continuation {
+        self.continuation
+    }
+
+    /// Returns true if this file's data is split across cabinets in a set.
+    pub fn is_continued(&self) -> bool {
+        self.continuation != Continuation::NotContinued
+    }
+
     /// Returns true if this file has the "name is UTF" attribute set.
     pub fn is_name_utf(&self) -> bool {
         (self.attributes & consts::ATTR_NAME_IS_UTF) != 0
     }
+
+    /// Returns the raw attribute bits stored in this file's entry.
+    pub fn attributes(&self) -> u16 {
+        self.attributes
+    }
+
+    /// Returns the offset within the cabinet file of this file's entry.
+    /// Together with the layout of file entries given in the CAB spec, this
+    /// allows tools to edit the entry in place; see also
+    /// [`patch_datetime`](FileEntry::patch_datetime) and
+    /// [`patch_attributes`](FileEntry::patch_attributes).
+    pub fn entry_offset(&self) -> u64 {
+        self.entry_offset
+    }
+
+    /// Returns the position of this file's entry in the cabinet's file
+    /// directory, counting from zero (i.e. its position in
+    /// [`Cabinet::file_entries_in_directory_order`](crate::Cabinet::file_entries_in_directory_order)).
+    /// Tools that rewrite a cabinet can use this to keep the files in their
+    /// original order, or to match files up with other tables that refer to
+    /// them by position (such as the sequence numbers of an MSI package).
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Overwrites the datetime stored in this file's entry, in place, within
+    /// the given cabinet file (which must be the file this entry was read
+    /// from).  Nothing else in the cabinet needs to change, so this is much
+    /// cheaper than rewriting the cabinet.  Returns an error without writing
+    /// anything if the entry at this offset doesn't match this one.
+    ///
+    /// This entry (and the `Cabinet` it came from) are not updated; reopen
+    /// the cabinet to see the change.  Like any edit to the directory, this
+    /// invalidates the cabinet's signature, if it has one (see
+    /// [`Cabinet::signature_range`](crate::Cabinet::signature_range)).
+    #[cfg(feature = "time")]
+    pub fn patch_datetime<W: Read + Write + Seek>(
+        &self,
+        writer: &mut W,
+        datetime: PrimitiveDateTime,
+    ) -> io::Result<()> {
+        let (date, time) = datetime_to_bits(datetime);
+        self.patch_raw_datetime(writer, date, time)
+    }
+
+    /// Overwrites the date and time bits stored in this file's entry with
+    /// the given `(date, time)` pair in MS-DOS format, in place, as for
+    /// [`patch_datetime`](FileEntry::patch_datetime).  The bits are written
+    /// as given, even if they don't form a valid datetime.
+    pub fn patch_raw_datetime<W: Read + Write + Seek>(
+        &self,
+        writer: &mut W,
+        date: u16,
+        time: u16,
+    ) -> io::Result<()> {
+        self.check_entry_matches(writer)?;
+        writer.seek(SeekFrom::Start(self.entry_offset + 10))?;
+        writer.write_u16::<LittleEndian>(date)?;
+        writer.write_u16::<LittleEndian>(time)?;
+        Ok(())
+    }
+
+    /// Overwrites the attribute bits stored in this file's entry, in place,
+    /// within the given cabinet file (which must be the file this entry was
+    /// read from).  The "name is UTF" bit cannot be changed this way, since
+    /// that would change how the file's name is read.  Returns an error
+    /// without writing anything if the entry at this offset doesn't match
+    /// this one.
+    ///
+    /// This entry (and the `Cabinet` it came from) are not updated; reopen
+    /// the cabinet to see the change.  This invalidates any signature, as
+    /// for [`patch_datetime`](FileEntry::patch_datetime).
+    pub fn patch_attributes<W: Read + Write + Seek>(
+        &self,
+        writer: &mut W,
+        attributes: u16,
+    ) -> io::Result<()> {
+        if (attributes ^ self.attributes) & consts::ATTR_NAME_IS_UTF != 0 {
+            invalid_input!(
+                "Cannot change the name-is-UTF attribute of {:?} in place",
+                self.name()
+            );
+        }
+        self.check_entry_matches(writer)?;
+        writer.seek(SeekFrom::Start(self.entry_offset + 14))?;
+        writer.write_u16::<LittleEndian>(attributes)?;
+        Ok(())
+    }
+
+    /// Returns the number of bytes taken up by this file's name in its
+    /// entry, including the terminator.
+    pub(crate) fn stored_name_len(&self) -> usize {
+        match self.name_decoding {
+            NameDecoding::Utf16 => self.name_raw.len() + 2,
+            _ => self.name_raw.len() + 1,
+        }
+    }
+
+    /// Updates this entry to match date and time bits and/or attributes
+    /// that have been patched into its cabinet file.
+    #[cfg(feature = "time")]
+    pub(crate) fn set_metadata(
+        &mut self,
+        datetime_bits: Option<(u16, u16)>,
+        attributes: Option<u16>,
+    ) {
+        if let Some((date, time)) = datetime_bits {
+            self.datetime_bits = (date, time);
+            self.datetime = datetime_from_bits(date, time);
+        }
+        if let Some(attributes) = attributes {
+            self.attributes = attributes;
+        }
+    }
+
+    /// Checks that the file entry stored at this entry's offset has the same
+    /// size, folder offset, and name as this entry.
+    fn check_entry_matches<R: Read + Seek>(
+        &self,
+        reader: &mut R,
+    ) -> io::Result<()> {
+        reader.seek(SeekFrom::Start(self.entry_offset))?;
+        let uncompressed_size = reader.read_u32::<LittleEndian>()?;
+        let uncompressed_offset = reader.read_u32::<LittleEndian>()?;
+        reader.seek(SeekFrom::Current(8))?;
+        let mut name = vec![0u8; self.stored_name_len()];
+        reader.read_exact(&mut name)?;
+        let (name, terminator) = name.split_at(self.name_raw.len());
+        if uncompressed_size != self.uncompressed_size
+            || uncompressed_offset != self.uncompressed_offset
+            || name != self.name_raw
+            || terminator.iter().any(|&byte| byte != 0)
+        {
+            invalid_input!(
+                "File entry at offset {} does not match {:?}",
+                self.entry_offset,
+                self.name()
+            );
+        }
+        Ok(())
+    }
+}
+
+impl<'a> FileInfoRef<'a> {
+    /// Returns the name of the file.  This only allocates if the name isn't
+    /// valid UTF-8, in which case it is decoded according to
+    /// [`name_decoding`](FileInfoRef::name_decoding) (unlike
+    /// [`FileEntry::name`], charset detection is never tried).
+    pub fn name(&self) -> Cow<'a, str> {
+        match self.name_decoding {
+            NameDecoding::Utf16 => Cow::Owned(decode_utf16(self.name_raw)),
+            _ => String::from_utf8_lossy(self.name_raw),
+        }
+    }
+
+    /// Returns how the string returned by [`name`](FileInfoRef::name) is
+    /// decoded from the [raw bytes](FileInfoRef::name_bytes).
+    pub fn name_decoding(&self) -> NameDecoding {
+        self.name_decoding
+    }
+
+    /// Returns the name of the file as the raw bytes stored in the cabinet
+    /// (without the NUL terminator).
+    pub fn name_bytes(&self) -> &'a [u8] {
+        self.name_raw
+    }
+
+    /// Returns the datetime for this file, as for [`FileEntry::datetime`].
+    #[cfg(feature = "time")]
+    pub fn datetime(&self) -> Option<PrimitiveDateTime> {
+        let (date, time) = self.datetime_bits;
+        datetime_from_bits(date, time).or(self.default_datetime)
+    }
+
+    /// Returns the raw date and time bits stored in this file's entry, as a
+    /// `(date, time)` pair in MS-DOS format.
+    pub fn raw_datetime(&self) -> (u16, u16) {
+        self.datetime_bits
+    }
+
+    /// Returns the total size of the file when decompressed, in bytes.
+    pub fn uncompressed_size(&self) -> u32 {
+        self.uncompressed_size
+    }
+
+    /// Returns the offset of the start of this file's data within its
+    /// folder's uncompressed data stream.
+    pub fn uncompressed_offset(&self) -> u32 {
+        self.uncompressed_offset
+    }
+
+    /// Returns the index of the folder that this file's data is stored in,
+    /// as for [`FileEntry::folder_index`].
+    pub fn folder_index(&self) -> usize {
+        self.folder_index as usize
+    }
+
+    /// Returns whether this file's data is split across cabinets in a set.
+    pub fn continuation(&self) -> Continuation {
+        self.continuation
+    }
+
+    /// Returns the raw attribute bits stored in this file's entry.
+    pub fn attributes(&self) -> u16 {
+        self.attributes
+    }
+
+    /// Returns the offset within the cabinet file of this file's entry.
+    pub fn entry_offset(&self) -> u64 {
+        self.entry_offset
+    }
+
+    /// Returns the position of this file's entry in the cabinet's file
+    /// directory, as for [`FileEntry::index`].
+    pub fn index(&self) -> usize {
+        self.index
+    }
+}
+
+impl<'a, R> FileReader<'a, R> {
+    /// Creates a reader for the given range of a folder's data.  This
+    /// doesn't read or decompress anything until the first read.
+    pub(crate) fn new(
+        reader: FolderReader<'a, R>,
+        file_start_in_folder: u64,
+        size: u64,
+    ) -> io::Result<FileReader<'a, R>> {
+        let size_mismatch = reader.size_mismatch();
+        Ok(FileReader {
+            reader,
+            file_start_in_folder,
+            offset: 0,
+            size,
+            positioned: false,
+            size_mismatch,
+            data_end: None,
+        })
+    }
+
+    /// Moves the underlying folder reader to the current offset, if it isn't
+    /// there already, decompressing data as needed.
+    fn position_reader(&mut self) -> io::Result<()> {
+        if !self.positioned {
+            // If the offset is past the end of the folder's data, the reader
+            // is left at the end, so the next read finds no data.
+            self.reader.try_seek_to_uncompressed_offset(
+                self.file_start_in_folder + self.offset,
+            )?;
+            self.positioned = true;
+        }
+        Ok(())
+    }
+
+    /// Called when the folder's data has run out at the current offset,
+    /// before the end of the file; records where, and returns an error
+    /// unless the size mismatch policy says to carry on.
+    fn reached_data_end(&mut self) -> io::Result<()> {
+        if self.data_end.is_none_or(|end| self.offset < end) {
+            self.data_end = Some(self.offset);
+        }
+        match self.size_mismatch {
+            SizeMismatch::Error => Err(self.reader.past_end_error()),
+            SizeMismatch::Truncate | SizeMismatch::ZeroFill => Ok(()),
+        }
+    }
+
+    /// Returns the total (uncompressed) size of the file, in bytes.
+    pub fn len(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns true if the file is empty.
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Returns the current position within the file.  This is the same value
+    /// as [`Seek::stream_position`], but without needing `&mut self`.
+    pub fn position(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the number of bytes left to read from the current position
+    /// until the end of the file.
+    pub fn remaining(&self) -> u64 {
+        self.size - self.offset
+    }
+
+    /// Returns the total size of the file, in bytes.  This mirrors the
+    /// (currently unstable) `Seek::stream_len` method, but never needs to
+    /// seek.
+    pub fn stream_len(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns the ranges of this file's data (as offsets from the start of
+    /// the file) that have so far failed to decode and been replaced with
+    /// zeros, or that turned out to lie past the end of the folder's data.
+    /// This can only be non-empty if
+    /// [`Cabinet::set_continue_on_error`](crate::Cabinet::set_continue_on_error)
+    /// was enabled, or the cabinet was opened with a
+    /// [`SizeMismatch`](crate::SizeMismatch) policy other than `Error`.
+    pub fn damaged_ranges(&self) -> Vec<Range<u64>> {
+        let file_end = self.file_start_in_folder + self.size;
+        let missing = self.data_end.map(|end| end..self.size);
+        self.reader
+            .damaged_ranges()
+            .iter()
+            .filter(|range| {
+                range.start < file_end && range.end > self.file_start_in_folder
+            })
+            .map(|range| {
+                (range.start.max(self.file_start_in_folder)
+                    - self.file_start_in_folder)
+                    ..(range.end.min(file_end) - self.file_start_in_folder)
+            })
+            .chain(missing)
+            .collect()
+    }
+
+    /// Consumes this reader and returns the underlying folder reader, so
+    /// that it can be reused to read a later file in the same folder without
+    /// decompressing the folder from the start again.
+    pub(crate) fn into_folder_reader(self) -> FolderReader<'a, R> {
+        self.reader
+    }
 }
 
 impl<'a, R: Read + Seek> Read for FileReader<'a, R> {
@@ -116,12 +630,52 @@ impl<'a, R: Read + Seek> Read for FileReader<'a, R> {
         if max_bytes == 0 {
             return Ok(0);
         }
-        let bytes_read = self.reader.read(&mut buf[..max_bytes])?;
+        self.position_reader()?;
+        let mut bytes_read = self.reader.read(&mut buf[..max_bytes])?;
+        if bytes_read == 0 {
+            self.reached_data_end()?;
+            if self.size_mismatch == SizeMismatch::ZeroFill {
+                buf[..max_bytes].fill(0);
+                bytes_read = max_bytes;
+            }
+        }
         self.offset += bytes_read as u64;
         Ok(bytes_read)
     }
 }
 
+impl<'a, R: Read + Seek> BufRead for FileReader<'a, R> {
+    /// Returns data directly out of the current decompressed data block
+    /// (limited to the end of this file), without any extra buffering.
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let bytes_remaining = self.size - self.offset;
+        if bytes_remaining == 0 {
+            return Ok(&[]);
+        }
+        self.position_reader()?;
+        if self.reader.fill_buf()?.is_empty() {
+            self.reached_data_end()?;
+            return Ok(match self.size_mismatch {
+                SizeMismatch::ZeroFill => {
+                    &ZEROS[..bytes_remaining.min(ZEROS.len() as u64) as usize]
+                }
+                _ => &[],
+            });
+        }
+        let data = self.reader.fill_buf()?;
+        let max_bytes = bytes_remaining.min(data.len() as u64) as usize;
+        Ok(&data[..max_bytes])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let amt = (self.size - self.offset).min(amt as u64);
+        if self.positioned {
+            self.reader.consume(amt as usize);
+        }
+        self.offset += amt;
+    }
+}
+
 impl<'a, R: Read + Seek> Seek for FileReader<'a, R> {
     fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
         let new_offset = match pos {
@@ -137,34 +691,71 @@ impl<'a, R: Read + Seek> Seek for FileReader<'a, R> {
             );
         }
         let new_offset = new_offset as u64;
-        self.reader.seek_to_uncompressed_offset(
-            self.file_start_in_folder + new_offset,
-        )?;
-        self.offset = new_offset;
+        if new_offset != self.offset {
+            self.offset = new_offset;
+            self.positioned = false;
+        }
         Ok(new_offset)
     }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.offset)
+    }
+}
+
+/// Reads the name at the end of a file entry (which the reader must be
+/// positioned at) into `name_raw` (replacing its previous contents),
+/// returning how the name should be decoded.
+pub(crate) fn read_file_name_into<R: Read + Seek>(
+    reader: &mut R,
+    index: usize,
+    options: &ReadOptions,
+    name_raw: &mut Vec<u8>,
+) -> io::Result<NameDecoding> {
+    let location = NameLocation::FileEntry(index);
+    let max_len = options.max_name_len;
+    if options.lenient_names && looks_like_utf16(reader)? {
+        read_null_terminated_utf16_into(reader, max_len, location, name_raw)?;
+        return Ok(NameDecoding::Utf16);
+    }
+    read_null_terminated_bytes_into(reader, max_len, location, name_raw)?;
+    if std::str::from_utf8(name_raw).is_ok() {
+        Ok(NameDecoding::Utf8)
+    } else {
+        Ok(NameDecoding::Lossy)
+    }
 }
 
-pub(crate) fn parse_file_entry<R: Read>(
+pub(crate) fn parse_file_entry<R: Read + Seek>(
     mut reader: R,
+    index: usize,
+    options: &ReadOptions,
 ) -> io::Result<FileEntry> {
     let uncompressed_size = reader.read_u32::<LittleEndian>()?;
     let uncompressed_offset = reader.read_u32::<LittleEndian>()?;
     let folder_index = reader.read_u16::<LittleEndian>()?;
     let date = reader.read_u16::<LittleEndian>()?;
     let time = reader.read_u16::<LittleEndian>()?;
-    let datetime = datetime_from_bits(date, time);
+    #[cfg(feature = "time")]
+    let datetime = datetime_from_bits(date, time).or(options.default_datetime);
     let attributes = reader.read_u16::<LittleEndian>()?;
-    let is_utf8 = (attributes & consts::ATTR_NAME_IS_UTF) != 0;
-    let (name, name_raw) = read_null_terminated_string(&mut reader, is_utf8)?;
+    let mut name_raw = Vec::new();
+    let name_decoding =
+        read_file_name_into(&mut reader, index, options, &mut name_raw)?;
     let entry = FileEntry {
-        name,
         name_raw,
+        name_decoding,
+        decoded_name: OnceLock::new(),
         folder_index,
+        #[cfg(feature = "time")]
         datetime,
+        datetime_bits: (date, time),
         uncompressed_size,
         uncompressed_offset,
         attributes,
+        continuation: Continuation::NotContinued, // filled in by Cabinet
+        entry_offset: 0,                          // filled in by Cabinet
+        index,
     };
     Ok(entry)
 }
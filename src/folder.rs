@@ -1,13 +1,28 @@
-use std::io::{self, Read, Seek, SeekFrom};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
 use std::marker::PhantomData;
+use std::mem;
+use std::ops::Range;
 use std::slice;
+use std::sync::Arc;
 
 use byteorder::{LittleEndian, ReadBytesExt};
 
-use crate::cabinet::{Cabinet, ReadSeek};
+use crate::cabinet::{CabinetInner, ReadSeek};
+use crate::cancel::{CancelToken, Cancelled};
 use crate::checksum::Checksum;
+use crate::consts;
 use crate::ctype::{CompressionType, Decompressor};
 use crate::file::{FileEntries, FileEntry};
+use crate::mszip::DEFLATE_MAX_DICT_LEN;
+use crate::options::SizeMismatch;
+#[cfg(feature = "pipeline")]
+use crate::pipeline::{Pipeline, Prefetched};
+use crate::throttle::Throttle;
+use crate::transform::BlockTransform;
+
+const RESUME_SIGNATURE: &[u8; 8] = b"CABRSUM1";
 
 /// An iterator over the folder entries in a cabinet.
 #[derive(Clone)]
@@ -17,15 +32,60 @@ pub struct FolderEntries<'a> {
 
 /// Metadata about one folder in a cabinet.
 pub struct FolderEntry {
-    first_data_block_offset: u32,
+    pub(crate) first_data_block_offset: u32,
     num_data_blocks: u16,
     compression_type: CompressionType,
+    compression_bits: u16,
     reserve_data: Vec<u8>,
     pub(crate) files: Vec<FileEntry>,
+    pub(crate) continuation: Continuation,
+    pub(crate) entry_offset: u64,
+}
+
+/// A borrowed view of a folder's entry, as passed to the visitor given to
+/// [`Cabinet::visit_entries`](crate::Cabinet::visit_entries).
+#[derive(Clone, Copy)]
+pub struct FolderInfoRef<'a> {
+    pub(crate) entry: &'a FolderEntry,
+}
+
+/// Whether a folder's data is split across multiple cabinets in a set.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Continuation {
+    /// The folder is entirely contained within this cabinet.
+    NotContinued,
+    /// The folder started in the previous cabinet in the set.
+    FromPrev,
+    /// The folder continues into the next cabinet in the set.
+    ToNext,
+    /// The folder started in the previous cabinet and continues into the
+    /// next one.
+    Both,
+}
+
+impl Continuation {
+    pub(crate) fn new(from_prev: bool, to_next: bool) -> Continuation {
+        match (from_prev, to_next) {
+            (false, false) => Continuation::NotContinued,
+            (true, false) => Continuation::FromPrev,
+            (false, true) => Continuation::ToNext,
+            (true, true) => Continuation::Both,
+        }
+    }
+
+    /// Returns true if the folder started in the previous cabinet.
+    pub fn is_from_prev(self) -> bool {
+        matches!(self, Continuation::FromPrev | Continuation::Both)
+    }
+
+    /// Returns true if the folder continues into the next cabinet.
+    pub fn is_to_next(self) -> bool {
+        matches!(self, Continuation::ToNext | Continuation::Both)
+    }
 }
 
 #[derive(Debug, Clone)]
-struct DataBlockEntry {
+pub(crate) struct DataBlockEntry {
     checksum: u32,
     compressed_size: u16,
     uncompressed_size: u16,
@@ -34,23 +94,360 @@ struct DataBlockEntry {
     cumulative_size: u64,
 }
 
+/// The header of a single data block within a cabinet folder, as returned by
+/// [`Cabinet::data_blocks`](crate::Cabinet::data_blocks).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DataBlockInfo {
+    pub(crate) offset: u64,
+    pub(crate) checksum: u32,
+    pub(crate) compressed_size: u16,
+    pub(crate) uncompressed_size: u16,
+    pub(crate) reserve_data: Vec<u8>,
+}
+
+impl DataBlockInfo {
+    /// Returns the offset within the cabinet file of the block's header.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the checksum stored in the block's header (zero if the block
+    /// has no checksum).
+    pub fn checksum(&self) -> u32 {
+        self.checksum
+    }
+
+    /// Returns the size of the block's compressed payload, in bytes.
+    pub fn compressed_size(&self) -> u16 {
+        self.compressed_size
+    }
+
+    /// Returns the number of bytes of uncompressed data in the block.
+    pub fn uncompressed_size(&self) -> u16 {
+        self.uncompressed_size
+    }
+
+    /// Returns the application-defined reserve data for this block.
+    pub fn reserve_data(&self) -> &[u8] {
+        &self.reserve_data
+    }
+}
+
+/// The error returned (wrapped in an [`io::Error`] of the same kind as the
+/// underlying error) when a data block can't be read or decoded, identifying
+/// which part of the cabinet is damaged.  Use [`io::Error::get_ref`] and
+/// `downcast_ref` to get at it, and [`Error::source`] to get the underlying
+/// error.
+#[derive(Debug)]
+pub struct BlockError {
+    folder_index: usize,
+    block_index: usize,
+    files: Vec<String>,
+    error: io::Error,
+}
+
+impl BlockError {
+    /// Returns the index of the folder containing the bad block.
+    pub fn folder_index(&self) -> usize {
+        self.folder_index
+    }
+
+    /// Returns the index of the bad block within its folder.
+    pub fn block_index(&self) -> usize {
+        self.block_index
+    }
+
+    /// Returns the names of the files whose data lies (at least partly)
+    /// within the bad block, if known.
+    pub fn files(&self) -> &[String] {
+        &self.files
+    }
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "Error in data block {} of folder {}",
+            self.block_index, self.folder_index
+        )?;
+        if !self.files.is_empty() {
+            write!(formatter, " (affecting {:?})", self.files)?;
+        }
+        write!(formatter, ": {}", self.error)
+    }
+}
+
+impl Error for BlockError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Wraps an error from reading or decoding a data block in a [`BlockError`]
+/// (unless it is a cancellation, which isn't the block's fault).
+fn block_error(
+    error: io::Error,
+    folder_index: usize,
+    block_index: usize,
+    files: Vec<String>,
+) -> io::Error {
+    if Cancelled::is_cancelled(&error) {
+        return error;
+    }
+    let kind = error.kind();
+    io::Error::new(
+        kind,
+        BlockError { folder_index, block_index, files, error },
+    )
+}
+
+/// The data blocks that a single file's data lies within, as returned by
+/// [`Cabinet::file_block_span`](crate::Cabinet::file_block_span).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BlockSpan {
+    pub(crate) folder_index: usize,
+    pub(crate) first_block: usize,
+    pub(crate) last_block: usize,
+    pub(crate) start_within_first_block: u16,
+    pub(crate) end_within_last_block: u16,
+    pub(crate) byte_range: Range<u64>,
+}
+
+impl BlockSpan {
+    /// Returns the index of the folder containing the file.
+    pub fn folder_index(&self) -> usize {
+        self.folder_index
+    }
+
+    /// Returns the index (within the folder) of the first data block
+    /// containing the file's data.
+    pub fn first_block(&self) -> usize {
+        self.first_block
+    }
+
+    /// Returns the index (within the folder) of the last data block
+    /// containing the file's data.  For an empty file, this is the same as
+    /// the first block.
+    pub fn last_block(&self) -> usize {
+        self.last_block
+    }
+
+    /// Returns the offset within the first block's uncompressed data at
+    /// which the file starts.
+    pub fn start_within_first_block(&self) -> u16 {
+        self.start_within_first_block
+    }
+
+    /// Returns the offset within the last block's uncompressed data at which
+    /// the file ends (exclusive).
+    pub fn end_within_last_block(&self) -> u16 {
+        self.end_within_last_block
+    }
+
+    /// Returns the range of bytes within the cabinet file holding the
+    /// blocks in this span (including their headers).
+    ///
+    /// Note that for compressed folders, decompressing a block generally
+    /// requires having decompressed all the earlier blocks in the folder
+    /// first (since compression history carries over from block to block),
+    /// so the blocks in this range alone are only sufficient to recover the
+    /// file for uncompressed folders.
+    pub fn byte_range(&self) -> Range<u64> {
+        self.byte_range.clone()
+    }
+}
+
+/// A record of how far a `FolderReader` has gotten through a folder's data,
+/// which can be used to continue reading from there later (e.g. after
+/// reopening the cabinet).  See [`FolderReader::resume_token`] and
+/// [`Cabinet::resume_folder`](crate::Cabinet::resume_folder).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FolderResumeToken {
+    folder_index: u32,
+    num_data_blocks: u32,
+    block_index: u32,
+    offset_within_block: u32,
+    uncompressed_offset: u64,
+    reset_offset: u64,
+    /// The data (at most 32 KiB of it) that the decompressor's history
+    /// consists of at the start of the block, for compression types whose
+    /// state can be restored from that.
+    window: Option<Vec<u8>>,
+}
+
+impl FolderResumeToken {
+    /// Returns the index of the folder within its cabinet.
+    pub fn folder_index(&self) -> usize {
+        self.folder_index as usize
+    }
+
+    /// Returns the offset within the folder's uncompressed data at which
+    /// reading will continue.
+    pub fn uncompressed_offset(&self) -> u64 {
+        self.uncompressed_offset
+    }
+
+    /// Returns the offset within the folder's uncompressed data from which
+    /// decoding will start over when resuming: the start of the block being
+    /// read, if the decompressor's state could be recorded there (which is
+    /// always the case for uncompressed folders, and usually for MSZIP
+    /// folders), or else the start of the folder (as for LZX folders, whose
+    /// decompressor state spans the whole folder).
+    pub fn reset_offset(&self) -> u64 {
+        self.reset_offset
+    }
+
+    /// Serializes this token so that it can be saved (e.g. to disk) and later
+    /// restored with [`FolderResumeToken::from_bytes`].
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = RESUME_SIGNATURE.to_vec();
+        bytes.extend_from_slice(&self.folder_index.to_le_bytes());
+        bytes.extend_from_slice(&self.num_data_blocks.to_le_bytes());
+        bytes.extend_from_slice(&self.block_index.to_le_bytes());
+        bytes.extend_from_slice(&self.offset_within_block.to_le_bytes());
+        bytes.extend_from_slice(&self.uncompressed_offset.to_le_bytes());
+        bytes.extend_from_slice(&self.reset_offset.to_le_bytes());
+        match self.window {
+            Some(ref window) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&(window.len() as u32).to_le_bytes());
+                bytes.extend_from_slice(window);
+            }
+            None => bytes.push(0),
+        }
+        bytes
+    }
+
+    /// Deserializes a token previously serialized with
+    /// [`FolderResumeToken::to_bytes`].
+    pub fn from_bytes(mut bytes: &[u8]) -> io::Result<FolderResumeToken> {
+        let mut signature = [0u8; 8];
+        bytes.read_exact(&mut signature)?;
+        if &signature != RESUME_SIGNATURE {
+            invalid_data!("Not a folder resume token");
+        }
+        let folder_index = bytes.read_u32::<LittleEndian>()?;
+        let num_data_blocks = bytes.read_u32::<LittleEndian>()?;
+        let block_index = bytes.read_u32::<LittleEndian>()?;
+        let offset_within_block = bytes.read_u32::<LittleEndian>()?;
+        let uncompressed_offset = bytes.read_u64::<LittleEndian>()?;
+        let reset_offset = bytes.read_u64::<LittleEndian>()?;
+        if reset_offset > uncompressed_offset {
+            invalid_data!("Resume token's reset offset is past its offset");
+        }
+        // A block holds at most 64 KiB of uncompressed data, and if the
+        // token resumes from the start of its block, that's where the reset
+        // offset is.
+        if offset_within_block > u16::MAX as u32
+            || reset_offset != 0
+                && uncompressed_offset - reset_offset
+                    != offset_within_block as u64
+        {
+            invalid_data!("Resume token has an invalid offset within block");
+        }
+        let window = match bytes.read_u8()? {
+            0 => None,
+            1 => {
+                let len = bytes.read_u32::<LittleEndian>()? as usize;
+                if len > DEFLATE_MAX_DICT_LEN || len > bytes.len() {
+                    invalid_data!("Resume token has an invalid window");
+                }
+                let (window, rest) = bytes.split_at(len);
+                bytes = rest;
+                Some(window.to_vec())
+            }
+            _ => invalid_data!("Resume token has an invalid window"),
+        };
+        if !bytes.is_empty() {
+            invalid_data!("Resume token has trailing data");
+        }
+        Ok(FolderResumeToken {
+            folder_index,
+            num_data_blocks,
+            block_index,
+            offset_within_block,
+            uncompressed_offset,
+            reset_offset,
+            window,
+        })
+    }
+}
+
 /// A reader for reading decompressed data from a cabinet folder.
-pub(crate) struct FolderReader<'a, R> {
-    reader: &'a Cabinet<dyn ReadSeek + 'a>,
+pub struct FolderReader<'a, R> {
+    source: BlockSource<'a>,
     num_data_blocks: usize,
+    /// The offset of the folder's first data block header, which isn't read
+    /// until the first block is needed.
+    first_block_offset: u64,
     data_reserve_size: u8,
+    compression_type: CompressionType,
+    /// The folder's decompressor (which, when pipelining, is out on the
+    /// pipeline's worker thread while a block is decompressed ahead).
     decompressor: Decompressor,
     /// The data blocks we've read so far.
     /// This always has len() <= num_data_blocks and grows once we encounter
     /// a new block in load_block().
     data_blocks: Vec<DataBlockEntry>,
     current_block_index: usize,
+    /// The index of the block that the decompressor expects to be given
+    /// next (which can differ from `current_block_index` after a seek, or
+    /// after blocks were served from the cabinet's block cache).
+    decoder_next_block: usize,
     current_block_data: Vec<u8>,
+    /// The index and data of the last block read through to its end before
+    /// moving on to the next one (kept for MSZIP folders only, since it's
+    /// what the decompressor's state at the start of the next block consists
+    /// of; see `resume_token`).
+    previous_block: Option<(usize, Vec<u8>)>,
+    /// False if `current_block_data` has not been loaded yet for the current
+    /// block (in which case it will be loaded on the next read).
+    current_block_loaded: bool,
     current_offset_within_block: usize,
     current_offset_within_folder: u64,
+    /// The checksum so far of the block being read directly from the source
+    /// (see `reads_directly`), if it has a checksum and has been read from
+    /// its start.
+    direct_checksum: Option<Checksum>,
+    continue_on_error: bool,
+    /// Whether to report blocks that decompress to a different size than
+    /// their headers say (see `ReadOptions::check_block_sizes`).
+    check_block_sizes: bool,
+    /// Ranges of the folder's uncompressed data that could not be decoded
+    /// and were replaced with zeros (only when `continue_on_error` is set).
+    damaged_ranges: Vec<Range<u64>>,
+    folder_index: usize,
+    transform: Option<Arc<dyn BlockTransform>>,
+    throttle: Option<Arc<dyn Throttle>>,
+    io_batch_size: usize,
+    cancel: Option<CancelToken>,
+    /// Bytes read ahead from the source (see `ReadOptions::io_batch_size`),
+    /// along with the offset they were read from.
+    read_ahead: Vec<u8>,
+    read_ahead_offset: u64,
+    /// Where the source's reader is positioned, if known (since nothing else
+    /// can use the reader while this `FolderReader` exists), to save seeking
+    /// before each read.
+    source_position: Option<u64>,
+    /// Decompresses the block after the current one in the background, if
+    /// enabled (see `ReadOptions::pipeline`).
+    #[cfg(feature = "pipeline")]
+    pipeline: Option<Pipeline>,
     _p: PhantomData<R>,
 }
 
+/// Where a `FolderReader` gets its data blocks from.
+pub(crate) enum BlockSource<'a> {
+    /// A cabinet file, which can be seeked to revisit earlier blocks.
+    Cabinet(&'a CabinetInner<dyn ReadSeek + 'a>),
+    /// A cabinet file that the reader has taken ownership of.
+    OwnedCabinet(Box<CabinetInner<dyn ReadSeek + 'a>>),
+    /// A forward-only stream of data blocks, along with the number of bytes
+    /// consumed from it so far.
+    Stream(Box<dyn Read + 'a>, u64),
+}
+
 impl<'a> Iterator for FolderEntries<'a> {
     type Item = &'a FolderEntry;
 
@@ -65,86 +462,798 @@ impl<'a> Iterator for FolderEntries<'a> {
 
 impl<'a> ExactSizeIterator for FolderEntries<'a> {}
 
+impl<'a> FolderInfoRef<'a> {
+    /// Returns the folder's full entry.  While visiting entries, its list of
+    /// [`file_entries`](FolderEntry::file_entries) is only filled in if the
+    /// cabinet's file entries have been loaded.
+    pub fn entry(&self) -> &'a FolderEntry {
+        self.entry
+    }
+
+    /// Returns the scheme used to compress this folder's data.
+    pub fn compression_type(&self) -> CompressionType {
+        self.entry.compression_type
+    }
+
+    /// Returns the number of data blocks used to store this folder's data.
+    pub fn num_data_blocks(&self) -> u16 {
+        self.entry.num_data_blocks
+    }
+}
+
 impl FolderEntry {
     /// Returns the scheme used to compress this folder's data.
     pub fn compression_type(&self) -> CompressionType {
         self.compression_type
     }
 
+    /// Returns the compression type field exactly as stored in the folder's
+    /// entry.  Besides the information in
+    /// [`compression_type`](FolderEntry::compression_type), this includes
+    /// any reserved bits that the CAB spec leaves unused, which some tools
+    /// set for their own purposes; use
+    /// [`FolderBuilder::set_raw_compression_bits`](crate::FolderBuilder::set_raw_compression_bits)
+    /// to write them back out when rewriting a cabinet.
+    pub fn raw_compression_bits(&self) -> u16 {
+        self.compression_bits
+    }
+
     /// Returns the number of data blocks used to store this folder's data.
     pub fn num_data_blocks(&self) -> u16 {
         self.num_data_blocks
     }
 
+    /// Returns the offset within the cabinet file of this folder's first data
+    /// block.
+    pub fn first_data_block_offset(&self) -> u32 {
+        self.first_data_block_offset
+    }
+
     /// Returns the application-defined reserve data for this folder.
     pub fn reserve_data(&self) -> &[u8] {
         &self.reserve_data
     }
 
+    /// Returns the offset within the cabinet file of this folder's entry.
+    /// Together with the layout of folder entries given in the CAB spec,
+    /// this allows tools to edit the entry in place; see also
+    /// [`patch_reserve_data`](FolderEntry::patch_reserve_data).
+    pub fn entry_offset(&self) -> u64 {
+        self.entry_offset
+    }
+
+    /// Overwrites this folder's reserve data, in place, within the given
+    /// cabinet file (which must be the file this entry was read from).  The
+    /// new data must be the same length as the existing reserve data.
+    /// Returns an error without writing anything if the entry at this offset
+    /// doesn't match this one.
+    ///
+    /// This entry (and the `Cabinet` it came from) are not updated; reopen
+    /// the cabinet to see the change.  Folder entries are covered by the
+    /// cabinet's signature, if it has one (see
+    /// [`Cabinet::signature_range`](crate::Cabinet::signature_range)), so
+    /// this invalidates it.
+    pub fn patch_reserve_data<W: Read + Write + Seek>(
+        &self,
+        writer: &mut W,
+        data: &[u8],
+    ) -> io::Result<()> {
+        if data.len() != self.reserve_data.len() {
+            invalid_input!(
+                "Folder reserve data must stay {} bytes long (was given {})",
+                self.reserve_data.len(),
+                data.len()
+            );
+        }
+        writer.seek(SeekFrom::Start(self.entry_offset))?;
+        let first_data_block_offset = writer.read_u32::<LittleEndian>()?;
+        let num_data_blocks = writer.read_u16::<LittleEndian>()?;
+        let compression_bits = writer.read_u16::<LittleEndian>()?;
+        let mut reserve_data = vec![0u8; self.reserve_data.len()];
+        writer.read_exact(&mut reserve_data)?;
+        if first_data_block_offset != self.first_data_block_offset
+            || num_data_blocks != self.num_data_blocks
+            || compression_bits != self.compression_bits
+            || reserve_data != self.reserve_data
+        {
+            invalid_input!(
+                "Folder entry at offset {} does not match this folder",
+                self.entry_offset
+            );
+        }
+        writer.seek(SeekFrom::Start(self.entry_offset + 8))?;
+        writer.write_all(data)?;
+        Ok(())
+    }
+
     /// Returns an iterator over the file entries in this folder.
-    pub fn file_entries(&self) -> FileEntries {
+    pub fn file_entries(&self) -> FileEntries<'_> {
         FileEntries { iter: self.files.iter() }
     }
+
+    /// Returns whether this folder's data is split across cabinets in a set.
+    /// If so, this cabinet alone only holds part of the folder's data, and
+    /// the other cabinet(s) are needed to extract the continued files.
+    pub fn continuation(&self) -> Continuation {
+        self.continuation
+    }
 }
 
 impl<'a, R: Read + Seek> FolderReader<'a, R> {
+    /// Creates a reader for the folder with the given index within the
+    /// cabinet that `source` reads from.  Nothing is read from the cabinet
+    /// until the reader is first used.
     pub(crate) fn new(
-        reader: &'a Cabinet<dyn ReadSeek + 'a>,
-        entry: &FolderEntry,
-        data_reserve_size: u8,
+        source: BlockSource<'a>,
+        folder_index: usize,
     ) -> io::Result<FolderReader<'a, R>> {
+        let inner = source.inner().unwrap();
+        let entry = &inner.folders[folder_index];
         let num_data_blocks = entry.num_data_blocks as usize;
+        let first_block_offset = entry.first_data_block_offset as u64;
+        let data_reserve_size = inner.data_reserve_size;
+        let compression_type = entry.compression_type;
+        let decompressor = compression_type.into_decompressor()?;
+        let continue_on_error = inner.continue_on_error;
+        let check_block_sizes = inner.options.check_block_sizes;
+        let transform = inner.block_transform.clone();
+        let throttle = inner.throttle.clone();
+        let io_batch_size = inner.options.io_batch_size;
+        let cancel = inner.options.cancel.clone();
+        #[cfg(feature = "pipeline")]
+        let pipeline = (inner.options.pipeline
+            && compression_type != CompressionType::None)
+            .then(Pipeline::new);
+
         let mut data_blocks = Vec::with_capacity(num_data_blocks);
+        let block_map = inner.block_maps.get(folder_index);
+        if let Some(blocks) = block_map.filter(|b| b.len() == num_data_blocks)
+        {
+            // The block headers are already known (from an index), so they
+            // don't need to be read as we go.
+            let mut cumulative_size = 0;
+            for block in blocks {
+                cumulative_size += block.uncompressed_size as u64;
+                data_blocks.push(DataBlockEntry {
+                    checksum: block.checksum,
+                    compressed_size: block.compressed_size,
+                    uncompressed_size: block.uncompressed_size,
+                    reserve_data: block.reserve_data.clone(),
+                    data_offset: checked_offset(
+                        block.offset,
+                        8 + data_reserve_size as u64,
+                    )?,
+                    cumulative_size,
+                });
+            }
+        }
+
+        Ok(FolderReader {
+            source,
+            num_data_blocks,
+            first_block_offset,
+            data_reserve_size,
+            compression_type,
+            decompressor,
+            data_blocks,
+            current_block_index: 0,
+            decoder_next_block: 0,
+            current_block_data: Vec::new(),
+            previous_block: None,
+            current_block_loaded: false,
+            current_offset_within_block: 0,
+            current_offset_within_folder: 0,
+            direct_checksum: None,
+            continue_on_error,
+            check_block_sizes,
+            damaged_ranges: Vec::new(),
+            folder_index,
+            transform,
+            throttle,
+            io_batch_size,
+            cancel,
+            read_ahead: Vec::new(),
+            read_ahead_offset: 0,
+            source_position: None,
+            #[cfg(feature = "pipeline")]
+            pipeline,
+            _p: PhantomData,
+        })
+    }
+}
 
-        let r = &mut &reader.inner;
-        r.seek(SeekFrom::Start(entry.first_data_block_offset as u64))?;
+impl<'a, R: Read + 'a> FolderReader<'a, R> {
+    /// Creates a reader that decodes a folder's data from a stream positioned
+    /// at the start of the folder's first data (CFDATA) block, reading
+    /// strictly forward.  This is useful when the folder's blocks have been
+    /// carved out of some other container, without the rest of the cabinet
+    /// file around them.
+    ///
+    /// The `num_data_blocks` and `data_reserve_size` parameters must match
+    /// the values from the original cabinet's folder entry and header,
+    /// respectively.  Since the stream cannot be rewound, seeking backwards
+    /// within the returned reader is not supported.
+    pub fn from_block_stream(
+        mut reader: R,
+        compression_type: CompressionType,
+        num_data_blocks: u16,
+        data_reserve_size: u8,
+    ) -> io::Result<FolderReader<'a, R>> {
+        let num_data_blocks = num_data_blocks as usize;
+        let mut data_blocks = Vec::with_capacity(num_data_blocks);
         if num_data_blocks != 0 {
-            let first_block =
-                parse_block_entry(*r, 0, data_reserve_size as usize)?;
+            let first_block = parse_block_entry(
+                &mut reader,
+                0,
+                0,
+                data_reserve_size as usize,
+            )?;
             data_blocks.push(first_block);
         }
-
-        let decompressor = entry.compression_type.into_decompressor()?;
-        let mut folder_reader = FolderReader {
-            reader,
+        let position =
+            data_blocks.first().map_or(0, |block| block.data_offset);
+        let decompressor = compression_type.into_decompressor()?;
+        let folder_reader = FolderReader {
+            source: BlockSource::Stream(Box::new(reader), position),
             num_data_blocks,
+            first_block_offset: 0,
             data_reserve_size,
+            compression_type,
             decompressor,
             data_blocks,
             current_block_index: 0,
+            decoder_next_block: 0,
             current_block_data: Vec::new(),
+            previous_block: None,
+            current_block_loaded: false,
             current_offset_within_block: 0,
             current_offset_within_folder: 0,
+            direct_checksum: None,
+            continue_on_error: false,
+            check_block_sizes: false,
+            damaged_ranges: Vec::new(),
+            folder_index: 0,
+            transform: None,
+            throttle: None,
+            io_batch_size: 0,
+            cancel: None,
+            read_ahead: Vec::new(),
+            read_ahead_offset: 0,
+            source_position: None,
+            #[cfg(feature = "pipeline")]
+            pipeline: None,
             _p: PhantomData,
         };
-        folder_reader.load_block()?;
         Ok(folder_reader)
     }
+}
+
+impl<'a, R> FolderReader<'a, R> {
+    /// Sets whether decoding should continue past data blocks that fail to
+    /// decompress.  If enabled, each such block is replaced with zeros in the
+    /// output and its range is recorded (see
+    /// [`damaged_ranges`](FolderReader::damaged_ranges)), rather than
+    /// returning an error.  Disabled by default.
+    ///
+    /// For LZX folders, the decoder state is not reset after a damaged block,
+    /// so that its window keeps as much history as the decoder was able to
+    /// recover; later blocks that only refer back to intact data will still
+    /// decode correctly.
+    pub fn set_continue_on_error(&mut self, continue_on_error: bool) {
+        self.continue_on_error = continue_on_error;
+    }
 
-    pub fn seek_to_uncompressed_offset(
+    /// Sets a transformation to apply to each data block's payload before
+    /// decompressing it (see [`BlockTransform`]), along with the index of
+    /// this folder within its original cabinet (which is passed along to
+    /// the transform).  This is only needed for readers created with
+    /// [`from_block_stream`](FolderReader::from_block_stream); readers
+    /// obtained from a `Cabinet` use the cabinet's transform, if any.
+    pub fn set_block_transform(
+        &mut self,
+        transform: Option<Arc<dyn BlockTransform>>,
+        folder_index: usize,
+    ) {
+        self.transform = transform;
+        self.folder_index = folder_index;
+    }
+
+    /// Sets a token that can be used to cancel reading (see
+    /// [`CancelToken`]).  Readers obtained from a `Cabinet` use the token
+    /// from its [`ReadOptions`](crate::ReadOptions), if any.
+    pub fn set_cancel_token(&mut self, token: Option<CancelToken>) {
+        self.cancel = token;
+    }
+
+    /// Sets a hook to call for each data block read (see [`Throttle`]).
+    /// Readers obtained from a `Cabinet` use the cabinet's hook, if any.
+    pub fn set_throttle(&mut self, throttle: Option<Arc<dyn Throttle>>) {
+        self.throttle = throttle;
+    }
+
+    /// Returns the total size of the folder's uncompressed data, which (in a
+    /// compressed folder) means decompressing every block but the last, to
+    /// find the last block's header.
+    fn uncompressed_size(&mut self) -> io::Result<u64> {
+        let last = match self.num_data_blocks.checked_sub(1) {
+            Some(last) => last,
+            None => return Ok(0),
+        };
+        if last >= self.data_blocks.len() {
+            self.learn_header(last).map_err(|error| {
+                block_error(error, self.folder_index, last, Vec::new())
+            })?;
+        }
+        Ok(self.data_blocks[last].cumulative_size)
+    }
+
+    /// Returns the reader's current offset within the folder's uncompressed
+    /// data.
+    pub(crate) fn uncompressed_offset(&self) -> u64 {
+        self.current_offset_within_folder
+    }
+
+    /// Returns the ranges of the folder's uncompressed data (as offsets from
+    /// the start of the folder) that have so far failed to decode and been
+    /// replaced with zeros.
+    pub fn damaged_ranges(&self) -> &[Range<u64>] {
+        &self.damaged_ranges
+    }
+
+    pub(crate) fn seek_to_uncompressed_offset(
         &mut self,
         new_offset: u64,
     ) -> io::Result<()> {
+        if !self.try_seek_to_uncompressed_offset(new_offset)? {
+            return Err(self.past_end_error());
+        }
+        Ok(())
+    }
+
+    /// Like `seek_to_uncompressed_offset`, but returns false (leaving the
+    /// reader at the end of the folder's data) rather than an error if the
+    /// offset is past the end of the folder's data.
+    pub(crate) fn try_seek_to_uncompressed_offset(
+        &mut self,
+        new_offset: u64,
+    ) -> io::Result<bool> {
+        if self.reads_directly() {
+            return self.seek_directly(new_offset);
+        }
         if new_offset < self.current_block_start() {
-            self.rewind()?;
+            self.current_block_index = 0;
+            self.current_block_loaded = false;
+            self.current_block_data = Vec::new();
+            self.current_offset_within_block = 0;
+            self.current_offset_within_folder = 0;
         }
-        if new_offset > 0 {
-            // TODO: If folder is uncompressed, we should just jump straight to
-            // the correct block without "decompressing" those in between.
-            while self.data_blocks[self.current_block_index].cumulative_size
-                < new_offset
+        self.skip_to(new_offset)
+    }
+
+    /// Skips over the next `n` bytes of the folder's decompressed data (or
+    /// as many of them as remain), returning how many were skipped.  This is
+    /// much cheaper than reading the data and throwing it away: the blocks
+    /// in between are still decompressed (since the blocks after them
+    /// depend on them), but their data isn't kept, and in an uncompressed
+    /// folder they are passed over by reading only their headers.  Unlike
+    /// seeking, this also works for readers created by
+    /// [`from_block_stream`](Self::from_block_stream).
+    pub fn skip(&mut self, n: u64) -> io::Result<u64> {
+        let start = self.current_offset_within_folder;
+        self.try_seek_to_uncompressed_offset(start.saturating_add(n))?;
+        Ok(self.current_offset_within_folder - start)
+    }
+
+    /// Returns a token recording the reader's current position, from which
+    /// reading can later be continued with [`resume`](Self::resume) (even
+    /// by a reader for the same folder in a newly-opened copy of the
+    /// cabinet), so that e.g. an extraction over an unreliable network
+    /// connection can be paused without having to start the folder over.
+    ///
+    /// Where possible, the token also records the decompressor's state at
+    /// the start of the current block, so that resuming only means decoding
+    /// that one block again (see [`FolderResumeToken::reset_offset`]).  For
+    /// MSZIP folders, this requires that the previous block was read
+    /// through (rather than seeked over) by this reader.
+    pub fn resume_token(&self) -> FolderResumeToken {
+        let mut block_index = self.current_block_index;
+        let mut offset_within_block = self.current_offset_within_block;
+        let mut window = None;
+        if self.compression_type == CompressionType::MsZip {
+            window = self.window_before_block(block_index);
+            if self.current_block_loaded
+                && offset_within_block == self.current_block_data.len()
+                && block_index + 1 < self.num_data_blocks
             {
-                self.current_block_index += 1;
-                self.load_block()?;
+                // At the end of the current block, the decompressor's state
+                // for the next one may be known even if it isn't for this
+                // one.
+                let next_window = self.window_after(
+                    block_index,
+                    &self.current_block_data,
+                    self.current_block_start(),
+                );
+                if next_window.is_some() {
+                    block_index += 1;
+                    offset_within_block = 0;
+                    window = next_window;
+                }
             }
         }
+        let uncompressed_offset = self.current_offset_within_folder;
+        let reset_offset = if self.compression_type == CompressionType::None
+            || window.is_some()
+        {
+            uncompressed_offset - offset_within_block as u64
+        } else {
+            0
+        };
+        FolderResumeToken {
+            folder_index: self.folder_index as u32,
+            num_data_blocks: self.num_data_blocks as u32,
+            block_index: block_index as u32,
+            offset_within_block: offset_within_block as u32,
+            uncompressed_offset,
+            reset_offset,
+            window,
+        }
+    }
+
+    /// Continues reading from the position recorded in the given token
+    /// (see [`resume_token`](Self::resume_token)), which must have come
+    /// from a reader for this same folder.  If the token holds the
+    /// decompressor's state, decoding picks up from the start of the
+    /// token's block; otherwise, this is the same as seeking to the token's
+    /// offset.
+    pub fn resume(&mut self, token: &FolderResumeToken) -> io::Result<()> {
+        if token.folder_index() != self.folder_index
+            || token.num_data_blocks as usize != self.num_data_blocks
+        {
+            invalid_input!(
+                "Resume token is for folder {} (with {} data blocks), not \
+                 folder {} (with {} data blocks)",
+                token.folder_index,
+                token.num_data_blocks,
+                self.folder_index,
+                self.num_data_blocks
+            );
+        }
+        let index = token.block_index as usize;
+        let resumable = match token.window {
+            Some(_) => self.compression_type == CompressionType::MsZip,
+            None => self.compression_type == CompressionType::None,
+        };
+        if !resumable
+            || token.reset_offset == 0
+            || index >= self.num_data_blocks
+        {
+            return self
+                .seek_to_uncompressed_offset(token.uncompressed_offset);
+        }
+        self.ensure_header(index).map_err(|error| {
+            block_error(error, self.folder_index, index, Vec::new())
+        })?;
+        let block = &self.data_blocks[index];
+        let block_start =
+            block.cumulative_size - block.uncompressed_size as u64;
+        let offset_within_block = token.offset_within_block as u64;
+        if block_start != token.reset_offset
+            || token.uncompressed_offset - token.reset_offset
+                != offset_within_block
+            || offset_within_block > block.uncompressed_size as u64
+        {
+            invalid_data!(
+                "Resume token doesn't match the data blocks of folder {}",
+                self.folder_index
+            );
+        }
+        self.discard_prefetched();
+        let window = token.window.as_deref().unwrap_or(&[]);
+        let restored = self.decompressor.restore_window(window);
+        debug_assert!(restored);
+        self.decoder_next_block = index;
+        self.current_block_index = index;
+        self.current_block_loaded = false;
+        self.current_block_data = Vec::new();
+        self.current_offset_within_block = token.offset_within_block as usize;
+        self.current_offset_within_folder = token.uncompressed_offset;
+        self.direct_checksum = None;
+        Ok(())
+    }
+
+    /// Returns the data that the decompressor's history would consist of at
+    /// the start of the given block, if it's known.
+    fn window_before_block(&self, index: usize) -> Option<Vec<u8>> {
+        if index == 0 {
+            return Some(Vec::new());
+        }
+        match self.previous_block {
+            Some((previous, ref data)) if previous + 1 == index => {
+                let end = self.data_blocks.get(previous)?.cumulative_size;
+                self.window_after(previous, data, end - data.len() as u64)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the data that the decompressor's history would consist of
+    /// after decoding the given block (with the given data, starting at the
+    /// given offset), if that's determined by the block alone.
+    fn window_after(
+        &self,
+        index: usize,
+        data: &[u8],
+        start: u64,
+    ) -> Option<Vec<u8>> {
+        let end = start + data.len() as u64;
+        if self.damaged_ranges.iter().any(|r| r.start < end && start < r.end) {
+            return None;
+        }
+        if data.len() >= DEFLATE_MAX_DICT_LEN {
+            Some(data[(data.len() - DEFLATE_MAX_DICT_LEN)..].to_vec())
+        } else if index == 0 {
+            Some(data.to_vec())
+        } else {
+            None
+        }
+    }
+
+    /// Moves forward to the given offset (which must be at or after the
+    /// start of the current block), passing over whole blocks without
+    /// loading their data.  Returns false (leaving the reader at the end of
+    /// the folder's data) if the offset is past the end of the folder's
+    /// data.
+    fn skip_to(&mut self, new_offset: u64) -> io::Result<bool> {
+        if self.current_block_index >= self.num_data_blocks {
+            return Ok(new_offset == self.current_offset_within_folder);
+        }
+        loop {
+            let index = self.current_block_index;
+            if index >= self.data_blocks.len() {
+                self.learn_header(index)
+                    .map_err(|error| self.current_block_error(error))?;
+            }
+            let block = &self.data_blocks[index];
+            if block.cumulative_size >= new_offset {
+                break;
+            }
+            if index + 1 >= self.num_data_blocks {
+                // Leave the reader at the end of the last block.
+                self.current_offset_within_block =
+                    block.uncompressed_size as usize;
+                self.current_offset_within_folder = block.cumulative_size;
+                return Ok(false);
+            }
+            if let Some(ref cancel) = self.cancel {
+                cancel.check()?;
+            }
+            if index + 1 >= self.data_blocks.len() {
+                self.learn_header(index + 1)
+                    .map_err(|error| self.current_block_error(error))?;
+            }
+            self.current_block_index += 1;
+            self.current_block_loaded = false;
+            self.current_block_data = Vec::new();
+        }
         debug_assert!(new_offset >= self.current_block_start());
         self.current_offset_within_block =
             (new_offset - self.current_block_start()) as usize;
         self.current_offset_within_folder = new_offset;
+        Ok(true)
+    }
+
+    /// Makes sure that the header of the given block is known.  In a
+    /// compressed folder, that means decompressing the blocks before it
+    /// (without keeping their data), since each block's header is read
+    /// along with the previous block's payload.
+    fn learn_header(&mut self, index: usize) -> io::Result<()> {
+        if let Some(inner) = self.source.inner() {
+            let cache = inner.block_cache.borrow();
+            let headers = cache.headers(self.folder_index);
+            if headers.len() > self.data_blocks.len() {
+                let known = self.data_blocks.len();
+                self.data_blocks.extend_from_slice(&headers[known..]);
+            }
+        }
+        if index < self.data_blocks.len() {
+            return Ok(());
+        }
+        if index == 0 || self.compression_type == CompressionType::None {
+            return self.ensure_header(index);
+        }
+        self.catch_up_decoder(index)
+    }
+
+    /// Seeks for a reader that `reads_directly`, by reading just the headers
+    /// of the blocks up to the new offset.
+    fn seek_directly(&mut self, new_offset: u64) -> io::Result<bool> {
+        if new_offset == self.current_offset_within_folder {
+            return Ok(true);
+        }
+        if self.current_block_loaded {
+            let start = self.current_block_start();
+            let end = start + self.current_block_data.len() as u64;
+            if (start..=end).contains(&new_offset) {
+                self.current_offset_within_block =
+                    (new_offset - start) as usize;
+                self.current_offset_within_folder = new_offset;
+                return Ok(true);
+            }
+            self.current_block_loaded = false;
+            self.current_block_data = Vec::new();
+        }
+        self.direct_checksum = None;
+        let mut index = self
+            .data_blocks
+            .partition_point(|block| block.cumulative_size < new_offset);
+        while index == self.data_blocks.len() && index < self.num_data_blocks {
+            self.ensure_header(index).map_err(|error| {
+                block_error(error, self.folder_index, index, Vec::new())
+            })?;
+            if self.data_blocks[index].cumulative_size < new_offset {
+                index += 1;
+            }
+        }
+        if index >= self.num_data_blocks {
+            // Leave the reader at the end of the last block.
+            let last = self.data_blocks.last();
+            self.current_block_index = self.num_data_blocks.saturating_sub(1);
+            self.current_offset_within_block =
+                last.map_or(0, |block| block.uncompressed_size as usize);
+            self.current_offset_within_folder =
+                last.map_or(0, |block| block.cumulative_size);
+            return Ok(false);
+        }
+        self.current_block_index = index;
+        let start = self.current_block_start();
+        self.current_offset_within_block = (new_offset - start) as usize;
+        self.current_offset_within_folder = new_offset;
+        Ok(true)
+    }
+
+    /// Returns true if reads can be served straight from the source, without
+    /// buffering each data block: that is, if the folder is uncompressed and
+    /// its blocks' payloads aren't transformed.  (With `continue_on_error`,
+    /// a block must be checked before any of it is returned, so that it can
+    /// be replaced with zeros if it turns out to be damaged.)
+    fn reads_directly(&self) -> bool {
+        self.compression_type == CompressionType::None
+            && self.transform.is_none()
+            && !self.continue_on_error
+    }
+
+    /// Reads data from the current block straight from the source, checking
+    /// the block's checksum (if any) once its last byte has been read.
+    /// Returns `None` if the block must be loaded into the buffer instead.
+    fn read_directly(&mut self, buf: &mut [u8]) -> io::Result<Option<usize>> {
+        loop {
+            let index = self.current_block_index;
+            if index >= self.num_data_blocks {
+                return Ok(Some(0));
+            }
+            self.ensure_header(index)?;
+            let block_size = self.data_blocks[index].uncompressed_size;
+            if self.current_offset_within_block < block_size as usize {
+                break;
+            }
+            self.current_block_index += 1;
+            self.current_offset_within_block = 0;
+            self.direct_checksum = None;
+        }
+        let index = self.current_block_index;
+        let block = &self.data_blocks[index];
+        let size = block.uncompressed_size;
+        let within_block = self.current_offset_within_block;
+        // If the block is malformed, let decoding it report how.  And if we
+        // seeked into the middle of the block, checking its checksum means
+        // reading all of it.
+        if block.compressed_size != size
+            || block.checksum != 0
+                && within_block > 0
+                && self.direct_checksum.is_none()
+        {
+            return Ok(None);
+        }
+        if within_block == 0 {
+            if let Some(ref cancel) = self.cancel {
+                cancel.check()?;
+            }
+            if let Some(ref throttle) = self.throttle {
+                throttle.throttle(size as usize, size as usize);
+            }
+            if block.checksum != 0 {
+                let mut checksum = Checksum::new();
+                checksum.update(&block.reserve_data);
+                self.direct_checksum = Some(checksum);
+            }
+        }
+        let len = buf.len().min(size as usize - within_block);
+        let offset = block.data_offset + within_block as u64;
+        let expected_checksum = block.checksum;
+        self.fetch_into(offset, &mut buf[..len])?;
+        if let Some(ref mut checksum) = self.direct_checksum {
+            checksum.update(&buf[..len]);
+        }
+        self.current_offset_within_block += len;
+        self.current_offset_within_folder += len as u64;
+        if self.current_offset_within_block == size as usize {
+            if let Some(checksum) = self.direct_checksum.take() {
+                let actual_checksum = checksum.data_block_value(size, size);
+                if actual_checksum != expected_checksum {
+                    invalid_data!(
+                        "Checksum error in data block {} \
+                         (expected {:08x}, actual {:08x})",
+                        index,
+                        expected_checksum,
+                        actual_checksum
+                    );
+                }
+            }
+        }
+        Ok(Some(len))
+    }
+
+    /// Makes sure that the header of the given block is known, reading the
+    /// headers of any blocks before it (but not their payloads) as needed.
+    fn ensure_header(&mut self, index: usize) -> io::Result<()> {
+        let header_size = 8 + self.data_reserve_size as usize;
+        while self.data_blocks.len() <= index {
+            let (offset, cumulative_size) = match self.data_blocks.last() {
+                Some(block) => (
+                    block.data_offset + block.compressed_size as u64,
+                    block.cumulative_size,
+                ),
+                None => (self.first_block_offset, 0),
+            };
+            let mut header = vec![0u8; header_size];
+            self.fetch_into(offset, &mut header)?;
+            let block = parse_block_entry(
+                header.as_slice(),
+                offset,
+                cumulative_size,
+                self.data_reserve_size as usize,
+            )?;
+            self.data_blocks.push(block);
+        }
         Ok(())
     }
 
+    /// Returns the cabinet's policy for files that run past the end of the
+    /// folder's data.
+    pub(crate) fn size_mismatch(&self) -> SizeMismatch {
+        self.source
+            .inner()
+            .map_or(SizeMismatch::Error, |inner| inner.options.size_mismatch)
+    }
+
+    /// Returns an error for a read that needs data beyond the end of the
+    /// folder's data blocks (e.g. for a file in a folder with no data blocks
+    /// at all), naming the files that extend past the end.
+    pub(crate) fn past_end_error(&self) -> io::Error {
+        let data_size =
+            self.data_blocks.last().map_or(0, |block| block.cumulative_size);
+        let files = match self.source.inner() {
+            Some(inner) => inner.folders[self.folder_index]
+                .files
+                .iter()
+                .filter(|file| {
+                    let start = file.uncompressed_offset as u64;
+                    start + file.uncompressed_size() as u64 > data_size
+                })
+                .map(|file| file.name().to_string())
+                .collect(),
+            None => Vec::new(),
+        };
+        let error = io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!(
+                "Folder data ends after {} byte(s) in {} data block(s)",
+                data_size, self.num_data_blocks
+            ),
+        );
+        block_error(error, self.folder_index, self.num_data_blocks, files)
+    }
+
     fn current_block_start(&self) -> u64 {
         if self.current_block_index == 0 {
             0
@@ -153,91 +1262,549 @@ impl<'a, R: Read + Seek> FolderReader<'a, R> {
         }
     }
 
-    fn rewind(&mut self) -> io::Result<()> {
-        self.current_offset_within_block = 0;
-        self.current_offset_within_folder = 0;
-        if self.current_block_index != 0 {
-            self.current_block_index = 0;
-            self.decompressor.reset();
-            self.load_block()?;
-        }
-        Ok(())
-    }
-
     fn load_block(&mut self) -> io::Result<()> {
+        self.current_block_loaded = false;
+        self.direct_checksum = None;
         if self.current_block_index >= self.num_data_blocks {
             self.current_block_data = Vec::new();
+            self.current_block_loaded = true;
             return Ok(());
         }
-        debug_assert!(self.current_block_index <= self.data_blocks.len());
-        let block = if self.current_block_index == self.data_blocks.len() {
-            let previous_block = self.data_blocks.last().unwrap();
-            let reader = &mut &self.reader.inner;
-            reader.seek(SeekFrom::Start(
-                previous_block.data_offset
-                    + previous_block.compressed_size as u64,
-            ))?;
-            let block = parse_block_entry(
-                reader,
-                previous_block.cumulative_size,
-                self.data_reserve_size as usize,
-            )?;
-            self.data_blocks.push(block);
-            &self.data_blocks[self.current_block_index]
+        if let Some(ref cancel) = self.cancel {
+            cancel.check()?;
+        }
+        self.load_current_block()
+            .map_err(|error| self.current_block_error(error))
+    }
+
+    /// Wraps an error from reading or decoding the current block in a
+    /// [`BlockError`].
+    fn current_block_error(&self, error: io::Error) -> io::Error {
+        let files = self.files_in_current_block();
+        block_error(error, self.folder_index, self.current_block_index, files)
+    }
+
+    /// Returns the names of the files (if known) whose data overlaps the
+    /// current block.
+    fn files_in_current_block(&self) -> Vec<String> {
+        let inner = match self.source.inner() {
+            Some(inner) => inner,
+            None => return Vec::new(),
+        };
+        let block_end = match self.data_blocks.get(self.current_block_index) {
+            Some(block) => block.cumulative_size,
+            None => return Vec::new(),
+        };
+        let block_start = self.current_block_start();
+        inner.folders[self.folder_index]
+            .files
+            .iter()
+            .filter(|file| {
+                let start = file.uncompressed_offset as u64;
+                let end = start + file.uncompressed_size() as u64;
+                start < block_end && end > block_start
+            })
+            .map(|file| file.name().to_string())
+            .collect()
+    }
+
+    fn load_current_block(&mut self) -> io::Result<()> {
+        let index = self.current_block_index;
+        if let Some(data) = self.cached_block(index) {
+            self.current_block_data = data;
+            self.current_block_loaded = true;
+            return Ok(());
+        }
+        if self.compression_type == CompressionType::None {
+            // Uncompressed blocks don't depend on the blocks before them, so
+            // those only need their headers read, to find this one.
+            self.ensure_header(index)?;
+            self.decoder_next_block = index;
         } else {
-            let block = &self.data_blocks[self.current_block_index];
-            let reader = &mut &self.reader.inner;
-            reader.seek(SeekFrom::Start(block.data_offset))?;
-            block
+            self.catch_up_decoder(index)?;
+        }
+        self.current_block_data = self.decode_block(index, true)?;
+        self.current_block_loaded = true;
+        Ok(())
+    }
+
+    /// Brings the decompressor's state up to date with the blocks before the
+    /// given one (starting over if it's already past them), since it depends
+    /// on them.
+    fn catch_up_decoder(&mut self, index: usize) -> io::Result<()> {
+        self.ensure_header(0)?;
+        if self.decoder_next_block > index {
+            self.discard_prefetched();
+            self.decompressor.reset();
+            self.decoder_next_block = 0;
+        }
+        while self.decoder_next_block < index {
+            self.decode_block(self.decoder_next_block, false)?;
+        }
+        Ok(())
+    }
+
+    /// Returns true if the cabinet has a block cache for decoded blocks to
+    /// go in.
+    fn cache_enabled(&self) -> bool {
+        self.source
+            .inner()
+            .is_some_and(|inner| inner.block_cache.borrow().is_enabled())
+    }
+
+    /// Returns the given block's data from the cabinet's block cache, if
+    /// it's there, making sure we know the block's header.
+    fn cached_block(&mut self, index: usize) -> Option<Vec<u8>> {
+        let inner = self.source.inner()?;
+        let mut cache = inner.block_cache.borrow_mut();
+        if !cache.is_enabled() {
+            return None;
+        }
+        let data = cache.get(self.folder_index, index)?.to_vec();
+        let headers = cache.headers(self.folder_index);
+        if headers.len() <= index {
+            return None;
+        }
+        if headers.len() > self.data_blocks.len() {
+            let known = self.data_blocks.len();
+            self.data_blocks.extend_from_slice(&headers[known..]);
+        }
+        Some(data)
+    }
+
+    /// Reads, checks, and decompresses the given block (whose header must
+    /// already be known), which must be the next block the decompressor
+    /// expects.  If `keep` is false, the block's data is only needed for the
+    /// cache (if any), and otherwise an empty vector is returned.
+    fn decode_block(
+        &mut self,
+        index: usize,
+        keep: bool,
+    ) -> io::Result<Vec<u8>> {
+        debug_assert_eq!(index, self.decoder_next_block);
+        let keep = keep || self.cache_enabled();
+        // A block whose payload can't be read is fatal, but one whose
+        // payload is invalid can be skipped over (see continue_on_error).
+        let prepared = match self.take_prefetched(index) {
+            Some(prefetched) => prefetched.map(Ok),
+            None => self.prepare_block(index).map(Err),
+        };
+        let mut result = match prepared {
+            Ok(Ok(result)) => result,
+            Ok(Err(compressed_data)) => {
+                let size = self.data_blocks[index].uncompressed_size as usize;
+                if keep {
+                    self.decompressor.decompress(compressed_data, size)
+                } else {
+                    let result =
+                        self.decompressor.skip(&compressed_data, size);
+                    result.map(|()| Vec::new())
+                }
+            }
+            Err(error)
+                if self.continue_on_error
+                    && error.kind() == io::ErrorKind::InvalidData =>
+            {
+                Err(error)
+            }
+            Err(error) => return Err(error),
         };
+        let next_index = index + 1;
+        let block = &self.data_blocks[index];
+        let uncompressed_size = block.uncompressed_size as usize;
+        let cumulative_size = block.cumulative_size;
+        let range =
+            (cumulative_size - uncompressed_size as u64)..cumulative_size;
+        if result.is_ok() && self.damaged_ranges.contains(&range) {
+            // The block failed its checksum, but was decompressed anyway (see
+            // prepare_block).
+            result = Err(io::ErrorKind::InvalidData.into());
+        }
+        let result = result.and_then(|mut data| {
+            // Never let a block yield more data than its header claims.
+            if keep && data.len() != uncompressed_size {
+                if self.check_block_sizes {
+                    invalid_data!(
+                        "Data block {} decompressed to {} bytes, but its \
+                         header says {}",
+                        index,
+                        data.len(),
+                        uncompressed_size
+                    );
+                }
+                data.truncate(uncompressed_size);
+            }
+            Ok(data)
+        });
+        let data = match result {
+            Ok(data) => data,
+            Err(_) if self.continue_on_error => {
+                if !self.damaged_ranges.contains(&range) {
+                    self.damaged_ranges.push(range);
+                }
+                self.decoder_next_block = next_index;
+                // Don't cache the zeros, so that other readers record the
+                // damage too.
+                if !keep {
+                    return Ok(Vec::new());
+                }
+                return Ok(vec![0u8; uncompressed_size]);
+            }
+            Err(error) => return Err(error),
+        };
+        self.decoder_next_block = next_index;
+        if let Some(inner) = self.source.inner() {
+            let mut cache = inner.block_cache.borrow_mut();
+            if cache.is_enabled() {
+                cache.record_headers(self.folder_index, &self.data_blocks);
+                cache.insert(self.folder_index, index, &data);
+            }
+        }
+        self.start_prefetch(next_index);
+        Ok(data)
+    }
 
-        let mut compressed_data = vec![0u8; block.compressed_size as usize];
-        let reader = &mut &self.reader.inner;
-        reader.read_exact(&mut compressed_data)?;
+    /// Reads and checks the given block's payload (whose header must already
+    /// be known), returning it ready to be decompressed.
+    fn prepare_block(&mut self, index: usize) -> io::Result<Vec<u8>> {
+        // Each block's header is read along with the previous block's
+        // payload (or, for the first block, in load_current_block).
+        debug_assert!(index < self.data_blocks.len());
+        // Read the block's payload, along with the next block's header (if
+        // we haven't already parsed it) in the same read.
+        let header_size = 8 + self.data_reserve_size as usize;
+        let next_index = index + 1;
+        let fetch_next_header = next_index < self.num_data_blocks
+            && next_index == self.data_blocks.len();
+        let block = &self.data_blocks[index];
+        let payload_size = block.compressed_size as usize;
+        let payload_offset = block.data_offset;
+        let cumulative_size = block.cumulative_size;
+        let fetch_size =
+            payload_size + if fetch_next_header { header_size } else { 0 };
+        let mut compressed_data = self.fetch(payload_offset, fetch_size)?;
+        if fetch_next_header {
+            let next_block = parse_block_entry(
+                &compressed_data[payload_size..],
+                payload_offset + payload_size as u64,
+                cumulative_size,
+                self.data_reserve_size as usize,
+            )?;
+            compressed_data.truncate(payload_size);
+            self.data_blocks.push(next_block);
+        }
+        let block = &self.data_blocks[index];
         if block.checksum != 0 {
             let mut checksum = Checksum::new();
             checksum.update(&block.reserve_data);
             checksum.update(&compressed_data);
-            let actual_checksum = checksum.value()
-                ^ ((block.compressed_size as u32)
-                    | ((block.uncompressed_size as u32) << 16));
+            let actual_checksum = checksum.data_block_value(
+                block.compressed_size,
+                block.uncompressed_size,
+            );
             if actual_checksum != block.checksum {
-                invalid_data!(
-                    "Checksum error in data block {} \
-                     (expected {:08x}, actual {:08x})",
-                    self.current_block_index,
-                    block.checksum,
-                    actual_checksum
-                );
+                if !self.continue_on_error {
+                    invalid_data!(
+                        "Checksum error in data block {} \
+                         (expected {:08x}, actual {:08x})",
+                        index,
+                        block.checksum,
+                        actual_checksum
+                    );
+                }
+                // Still decompress the damaged block, so that as much of the
+                // decompressor's history survives as possible, but record
+                // it as damaged, so that its data is replaced with zeros.
+                let end = block.cumulative_size;
+                let range = (end - block.uncompressed_size as u64)..end;
+                if !self.damaged_ranges.contains(&range) {
+                    self.damaged_ranges.push(range);
+                }
             }
         }
-        self.current_block_data = self
-            .decompressor
-            .decompress(compressed_data, block.uncompressed_size as usize)?;
+        let block = &self.data_blocks[index];
+        let uncompressed_size = block.uncompressed_size as usize;
+        if let Some(ref throttle) = self.throttle {
+            throttle.throttle(payload_size, uncompressed_size);
+        }
+        match self.transform {
+            Some(ref transform) => transform.decode(
+                self.folder_index,
+                index,
+                &block.reserve_data,
+                compressed_data,
+            ),
+            None => Ok(compressed_data),
+        }
+    }
+
+    /// If pipelining, reads the given block (which the decompressor expects
+    /// next) and starts decompressing it in the background.
+    #[cfg(feature = "pipeline")]
+    fn start_prefetch(&mut self, index: usize) {
+        if self.pipeline.is_none() || index >= self.data_blocks.len() {
+            return;
+        }
+        let prepared = self.prepare_block(index);
+        let size = self.data_blocks[index].uncompressed_size as usize;
+        let pipeline = self.pipeline.as_mut().unwrap();
+        match prepared {
+            Ok(data) => {
+                pipeline.start(index, &mut self.decompressor, data, size)
+            }
+            Err(error) => pipeline.fail(index, error),
+        }
+    }
+
+    #[cfg(not(feature = "pipeline"))]
+    fn start_prefetch(&mut self, _index: usize) {}
+
+    /// Takes the given block as prepared in the background, if it was.
+    #[cfg(feature = "pipeline")]
+    fn take_prefetched(&mut self, index: usize) -> Option<Prefetched> {
+        self.pipeline.as_mut()?.take(index, &mut self.decompressor)
+    }
+
+    #[cfg(not(feature = "pipeline"))]
+    fn take_prefetched(
+        &mut self,
+        _index: usize,
+    ) -> Option<io::Result<io::Result<Vec<u8>>>> {
+        None
+    }
+
+    /// Throws away any block prepared in the background.
+    fn discard_prefetched(&mut self) {
+        #[cfg(feature = "pipeline")]
+        if let Some(ref mut pipeline) = self.pipeline {
+            pipeline.discard(&mut self.decompressor);
+        }
+    }
+}
+
+impl<'a, R> FolderReader<'a, R> {
+    /// Reads `len` bytes starting at the given offset in the source, using
+    /// (and refilling) the read-ahead buffer if batching is enabled.
+    fn fetch(&mut self, offset: u64, len: usize) -> io::Result<Vec<u8>> {
+        let buffered_end =
+            self.read_ahead_offset + self.read_ahead.len() as u64;
+        if offset >= self.read_ahead_offset
+            && offset + len as u64 <= buffered_end
+        {
+            let start = (offset - self.read_ahead_offset) as usize;
+            return Ok(self.read_ahead[start..start + len].to_vec());
+        }
+        // A forward-only stream may hold other data after the folder's
+        // blocks, so never read past what is needed from one.
+        let batch_size = match self.source {
+            BlockSource::Stream(_, _) => len,
+            _ => len.max(self.io_batch_size),
+        };
+        let mut data = Vec::with_capacity(batch_size);
+        self.source_position = None;
+        self.source
+            .reader_at(offset)?
+            .take(batch_size as u64)
+            .read_to_end(&mut data)?;
+        self.source_position = Some(offset + data.len() as u64);
+        if data.len() < len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        self.source.advance_to(offset + data.len() as u64);
+        if data.len() > len {
+            let result = data[..len].to_vec();
+            self.read_ahead = data;
+            self.read_ahead_offset = offset;
+            Ok(result)
+        } else {
+            Ok(data)
+        }
+    }
+
+    /// Fills `buf` with the bytes starting at the given offset in the source,
+    /// reading directly into it unless batching is enabled (or the bytes are
+    /// already in the read-ahead buffer).
+    fn fetch_into(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        let buffered_end =
+            self.read_ahead_offset + self.read_ahead.len() as u64;
+        let in_read_ahead = offset >= self.read_ahead_offset
+            && offset + buf.len() as u64 <= buffered_end;
+        if in_read_ahead || buf.len() < self.io_batch_size {
+            let data = self.fetch(offset, buf.len())?;
+            buf.copy_from_slice(&data);
+            return Ok(());
+        }
+        self.reader_at(offset)?.read_exact(buf)?;
+        self.source_position = Some(offset + buf.len() as u64);
+        self.source.advance_to(offset + buf.len() as u64);
         Ok(())
     }
+
+    /// Returns the source's reader, positioned at the given offset (without
+    /// seeking, if it's already there).
+    fn reader_at(&mut self, offset: u64) -> io::Result<&mut dyn Read> {
+        if self.source_position.take() == Some(offset) {
+            return Ok(self.source.reader());
+        }
+        self.source.reader_at(offset)
+    }
+}
+
+impl<'a> BlockSource<'a> {
+    /// Returns the cabinet that this source reads from, if any.
+    fn inner(&self) -> Option<&CabinetInner<dyn ReadSeek + 'a>> {
+        match self {
+            BlockSource::Cabinet(inner) => Some(inner),
+            BlockSource::OwnedCabinet(inner) => Some(inner),
+            BlockSource::Stream(_, _) => None,
+        }
+    }
+
+    /// Returns a reader positioned at the given offset.  For a forward-only
+    /// stream, this will fail if the offset has already been passed.
+    fn reader_at(&mut self, offset: u64) -> io::Result<&mut dyn Read> {
+        match self {
+            BlockSource::Cabinet(reader) => {
+                reader.seek(SeekFrom::Start(offset))?;
+                Ok(reader)
+            }
+            BlockSource::OwnedCabinet(inner) => {
+                let reader = inner.reader.get_mut();
+                reader.seek(SeekFrom::Start(offset))?;
+                Ok(reader)
+            }
+            BlockSource::Stream(reader, position) => {
+                if offset < *position {
+                    invalid_input!(
+                        "Cannot seek backwards in a forward-only block stream"
+                    );
+                }
+                let skip = offset - *position;
+                if skip > 0 {
+                    let skipped =
+                        io::copy(&mut reader.take(skip), &mut io::sink())?;
+                    if skipped < skip {
+                        return Err(io::ErrorKind::UnexpectedEof.into());
+                    }
+                    *position = offset;
+                }
+                Ok(reader)
+            }
+        }
+    }
+
+    /// Returns the underlying reader, wherever it's positioned.
+    fn reader(&mut self) -> &mut dyn Read {
+        match self {
+            BlockSource::Cabinet(reader) => reader,
+            BlockSource::OwnedCabinet(inner) => inner.reader.get_mut(),
+            BlockSource::Stream(reader, _) => reader,
+        }
+    }
+
+    /// Records that the underlying reader is now positioned at the given
+    /// offset.
+    fn advance_to(&mut self, offset: u64) {
+        if let BlockSource::Stream(_, position) = self {
+            *position = offset;
+        }
+    }
 }
 
-impl<'a, R: Read + Seek + 'a> Read for FolderReader<'a, R> {
+impl<'a, R> Read for FolderReader<'a, R> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        if buf.is_empty() || self.current_block_index >= self.num_data_blocks {
+        if buf.is_empty() {
             return Ok(0);
         }
+        if self.reads_directly() {
+            if self.current_block_loaded
+                && self.current_offset_within_block
+                    == self.current_block_data.len()
+                && self.current_block_index < self.num_data_blocks
+            {
+                // Go back to reading directly after a buffered block.
+                self.current_block_index += 1;
+                self.current_offset_within_block = 0;
+                self.current_block_loaded = false;
+                self.current_block_data = Vec::new();
+            }
+            if !self.current_block_loaded {
+                let result = self.read_directly(buf).map_err(|error| {
+                    let files = self.files_in_current_block();
+                    let index = self.current_block_index;
+                    block_error(error, self.folder_index, index, files)
+                });
+                if let Some(bytes_read) = result? {
+                    return Ok(bytes_read);
+                }
+            }
+        }
+        let data = self.fill_buf()?;
+        let max_bytes = buf.len().min(data.len());
+        buf[..max_bytes].copy_from_slice(&data[..max_bytes]);
+        self.consume(max_bytes);
+        Ok(max_bytes)
+    }
+}
+
+impl<'a, R> Seek for FolderReader<'a, R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_offset = match pos {
+            SeekFrom::Start(offset) => offset as i128,
+            SeekFrom::Current(delta) => {
+                self.current_offset_within_folder as i128 + delta as i128
+            }
+            SeekFrom::End(delta) => {
+                self.uncompressed_size()? as i128 + delta as i128
+            }
+        };
+        if new_offset < 0 {
+            invalid_input!("Cannot seek to {}", new_offset);
+        }
+        let new_offset = new_offset as u64;
+        if !self.try_seek_to_uncompressed_offset(new_offset)? {
+            invalid_input!(
+                "Cannot seek to {}, folder data length is {}",
+                new_offset,
+                self.current_offset_within_folder
+            );
+        }
+        Ok(new_offset)
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.current_offset_within_folder)
+    }
+}
+
+impl<'a, R> BufRead for FolderReader<'a, R> {
+    /// Returns the rest of the current decompressed data block, loading the
+    /// next block first if the current one has been used up.
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.current_block_index >= self.num_data_blocks {
+            return Ok(&[]);
+        }
+        if !self.current_block_loaded {
+            self.load_block()?;
+        }
         if self.current_offset_within_block == self.current_block_data.len() {
+            if self.compression_type == CompressionType::MsZip {
+                let data = mem::take(&mut self.current_block_data);
+                self.previous_block = Some((self.current_block_index, data));
+            }
             self.current_block_index += 1;
             self.current_offset_within_block = 0;
             self.load_block()?;
         }
-        let max_bytes = buf.len().min(
-            self.current_block_data.len() - self.current_offset_within_block,
-        );
-        buf[..max_bytes].copy_from_slice(
-            &self.current_block_data[self.current_offset_within_block..]
-                [..max_bytes],
-        );
-        self.current_offset_within_block += max_bytes;
-        self.current_offset_within_folder += max_bytes as u64;
-        Ok(max_bytes)
+        Ok(&self.current_block_data[self.current_offset_within_block..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let available =
+            self.current_block_data.len() - self.current_offset_within_block;
+        let amt = amt.min(available);
+        self.current_offset_within_block += amt;
+        self.current_offset_within_folder += amt as u64;
     }
 }
 
@@ -257,23 +1824,44 @@ pub(crate) fn parse_folder_entry<R: Read>(
         first_data_block_offset: first_data_offset,
         num_data_blocks,
         compression_type,
+        compression_bits,
         reserve_data: folder_reserve_data,
         files: vec![],
+        continuation: Continuation::NotContinued, // filled in later by Cabinet
+        entry_offset: 0,                          // filled in later by Cabinet
     };
     Ok(entry)
 }
 
+/// Returns `offset + len`, or an error if that would be past the end of the
+/// largest possible cabinet file.  Offsets computed from the (untrusted) size
+/// fields of a corrupt cabinet can otherwise run far past the end of the
+/// file, and overflow once cast to `usize` on 32-bit targets.
+pub(crate) fn checked_offset(offset: u64, len: u64) -> io::Result<u64> {
+    match offset.checked_add(len) {
+        Some(end) if end <= consts::MAX_TOTAL_CAB_SIZE as u64 => Ok(end),
+        _ => invalid_data!(
+            "Offset {} + {} is past the end of the largest possible cabinet \
+             ({} bytes)",
+            offset,
+            len,
+            consts::MAX_TOTAL_CAB_SIZE
+        ),
+    }
+}
+
 /// Parse a data block entry from a reader.
 ///
-/// The reader must be positioned at the start of the data block,
-/// which is either at the start first data block or immediately after
-/// the previous data block, i.e. at position
+/// The reader must be positioned at the start of the data block (given by
+/// `block_offset`), which is either at the start first data block or
+/// immediately after the previous data block, i.e. at position
 /// `previous_data_block.data_offset + previous_data_block.compressed_size`.
 ///
 /// Once this function returns, the reader will be positioned at the current
 /// block's `data_offset`.
-fn parse_block_entry<R: ReadSeek>(
+fn parse_block_entry<R: Read>(
     mut reader: R,
+    block_offset: u64,
     cumulative_size: u64,
     data_reserve_size: usize,
 ) -> io::Result<DataBlockEntry> {
@@ -282,7 +1870,9 @@ fn parse_block_entry<R: ReadSeek>(
     let uncompressed_size = reader.read_u16::<LittleEndian>()?;
     let mut reserve_data = vec![0u8; data_reserve_size];
     reader.read_exact(&mut reserve_data)?;
-    let data_offset = reader.stream_position()?;
+    let data_offset =
+        checked_offset(block_offset, 8 + data_reserve_size as u64)?;
+    checked_offset(data_offset, compressed_size as u64)?;
     let cumulative_size = cumulative_size + uncompressed_size as u64;
 
     Ok(DataBlockEntry {
@@ -294,3 +1884,100 @@ fn parse_block_entry<R: ReadSeek>(
         data_offset,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Read};
+
+    use super::{checked_offset, FolderReader};
+    use crate::ctype::CompressionType;
+
+    #[test]
+    fn offsets_past_max_cabinet_size() {
+        assert_eq!(checked_offset(0x7fff_0000, 0xffff).unwrap(), 0x7fff_ffff);
+        let error = checked_offset(0x7fff_0000, 0x1_0000).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(checked_offset(u64::MAX, 8).is_err());
+    }
+
+    #[test]
+    fn read_uncompressed_block_stream() {
+        let blocks: &[u8] = b"\0\0\0\0\x06\0\x06\0Hello,\
+            \0\0\0\0\x08\0\x08\0 world!\n";
+        let mut reader = FolderReader::from_block_stream(
+            blocks,
+            CompressionType::None,
+            2,
+            0,
+        )
+        .unwrap();
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"Hello, world!\n");
+    }
+
+    #[test]
+    fn read_mszip_block_stream_with_reserve_data() {
+        let blocks: &[u8] = b"\0\0\0\0\x25\0\x1d\0\xab\xcd\
+            CK\xf3H\xcd\xc9\xc9\xd7Q(\xcf/\xcaIQ\xe4\
+            \nNMU\xa8\xcc/U\xc8I,I-R\xe4\x02\x00\x93\xfc\t\x91";
+        let mut reader = FolderReader::from_block_stream(
+            blocks,
+            CompressionType::MsZip,
+            1,
+            2,
+        )
+        .unwrap();
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"Hello, world!\nSee you later!\n");
+    }
+
+    #[test]
+    fn skip_within_block_stream() {
+        let blocks: &[u8] = b"\0\0\0\0\x06\0\x06\0Hello,\
+            \0\0\0\0\x08\0\x08\0 world!\n";
+        let mut reader = FolderReader::from_block_stream(
+            blocks,
+            CompressionType::None,
+            2,
+            0,
+        )
+        .unwrap();
+        assert_eq!(reader.skip(7).unwrap(), 7);
+        let mut data = [0u8; 5];
+        reader.read_exact(&mut data).unwrap();
+        assert_eq!(&data, b"world");
+        assert_eq!(reader.skip(100).unwrap(), 2);
+        assert_eq!(reader.skip(100).unwrap(), 0);
+
+        let blocks: &[u8] = b"\0\0\0\0\x25\0\x1d\0\xab\xcd\
+            CK\xf3H\xcd\xc9\xc9\xd7Q(\xcf/\xcaIQ\xe4\
+            \nNMU\xa8\xcc/U\xc8I,I-R\xe4\x02\x00\x93\xfc\t\x91";
+        let mut reader = FolderReader::from_block_stream(
+            blocks,
+            CompressionType::MsZip,
+            1,
+            2,
+        )
+        .unwrap();
+        assert_eq!(reader.skip(14).unwrap(), 14);
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"See you later!\n");
+    }
+
+    #[test]
+    fn truncated_block_stream() {
+        let blocks: &[u8] = b"\0\0\0\0\x06\0\x06\0Hello,\0\0\0\0\x08\0";
+        let mut reader = FolderReader::from_block_stream(
+            blocks,
+            CompressionType::None,
+            2,
+            0,
+        )
+        .unwrap();
+        let mut data = Vec::new();
+        assert!(reader.read_to_end(&mut data).is_err());
+    }
+}
@@ -0,0 +1,231 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::cabinet::Cabinet;
+use crate::folder::DataBlockInfo;
+use crate::options::ReadOptions;
+
+const INDEX_SIGNATURE: &[u8; 8] = b"CABINDEX";
+const INDEX_VERSION: u16 = 1;
+const INDEX_FLAG_LENIENT_NAMES: u8 = 0x1;
+
+impl<R: Read + Seek> Cabinet<R> {
+    /// Writes an index of this cabinet to `writer`, for later use with
+    /// [`open_with_index`](Cabinet::open_with_index).
+    ///
+    /// The index holds a copy of the cabinet's header, folder entries, and
+    /// file entries, along with the headers of every data block in every
+    /// folder, so it is typically only a small fraction of the size of the
+    /// cabinet.  Writing it requires reading all of the data block headers
+    /// (but not their payloads).
+    pub fn write_index<W: Write>(&mut self, mut writer: W) -> io::Result<()> {
+        self.load_file_entries()?;
+        let num_folders = self.inner.folders.len();
+        let folder_entry_size = 8 + self.folder_reserve_size() as u64;
+        let header_len = self.inner.first_folder_offset as u64
+            + num_folders as u64 * folder_entry_size;
+        let file_table_offset = self.first_file_offset() as u64;
+        let file_table_len: u64 = self
+            .file_entries_in_directory_order()
+            .map(|file| 16 + file.stored_name_len() as u64)
+            .sum();
+        let reader = self.inner.reader.get_mut();
+        let header = read_range(reader, 0, header_len)?;
+        let file_table =
+            read_range(reader, file_table_offset, file_table_len)?;
+        let mut block_maps = Vec::with_capacity(num_folders);
+        for index in 0..num_folders {
+            block_maps.push(
+                self.data_blocks(index)?
+                    .collect::<Result<Vec<DataBlockInfo>, io::Error>>()?,
+            );
+        }
+
+        writer.write_all(INDEX_SIGNATURE)?;
+        writer.write_u16::<LittleEndian>(INDEX_VERSION)?;
+        let options = &self.inner.options;
+        let flags =
+            if options.lenient_names { INDEX_FLAG_LENIENT_NAMES } else { 0 };
+        writer.write_u8(flags)?;
+        writer.write_u32::<LittleEndian>(options.max_name_len as u32)?;
+        writer.write_u8(self.data_reserve_size())?;
+        for (offset, bytes) in [(0, &header), (file_table_offset, &file_table)]
+        {
+            writer.write_u32::<LittleEndian>(offset as u32)?;
+            writer.write_u32::<LittleEndian>(bytes.len() as u32)?;
+            writer.write_all(bytes)?;
+        }
+        writer.write_u16::<LittleEndian>(num_folders as u16)?;
+        for blocks in block_maps.iter() {
+            writer.write_u16::<LittleEndian>(blocks.len() as u16)?;
+            for block in blocks.iter() {
+                writer.write_u32::<LittleEndian>(block.offset as u32)?;
+                writer.write_u32::<LittleEndian>(block.checksum)?;
+                writer.write_u16::<LittleEndian>(block.compressed_size)?;
+                writer.write_u16::<LittleEndian>(block.uncompressed_size)?;
+                writer.write_all(&block.reserve_data)?;
+            }
+        }
+        writer.flush()
+    }
+
+    /// Opens a cabinet file using an index previously written by
+    /// [`write_index`](Cabinet::write_index), which makes opening (and then
+    /// seeking within) a large cabinet much cheaper: the file entries and
+    /// data block headers are taken from the index instead of being read
+    /// from the cabinet.  The [`ReadOptions`] that affect how the directory
+    /// is parsed are carried over from when the index was written; all
+    /// others have their default values.
+    ///
+    /// The header and folder entries stored in the index are compared with
+    /// those at the start of `reader` (a single small read), and an error is
+    /// returned if they differ, e.g. because the index is stale.
+    pub fn open_with_index<I: Read>(
+        mut reader: R,
+        mut index: I,
+    ) -> io::Result<Cabinet<R>> {
+        let mut signature = [0u8; 8];
+        index.read_exact(&mut signature)?;
+        if &signature != INDEX_SIGNATURE {
+            invalid_data!("Not a cabinet index (invalid signature)");
+        }
+        let version = index.read_u16::<LittleEndian>()?;
+        if version != INDEX_VERSION {
+            invalid_data!(
+                "Cabinet index version {} is not supported",
+                version
+            );
+        }
+        let flags = index.read_u8()?;
+        let mut options = ReadOptions::new();
+        options.lenient_names(flags & INDEX_FLAG_LENIENT_NAMES != 0);
+        options.max_name_len(index.read_u32::<LittleEndian>()? as usize);
+        let data_reserve_size = index.read_u8()?;
+        options.known_data_reserve = Some(data_reserve_size);
+        let mut segments = Vec::with_capacity(2);
+        for _ in 0..2 {
+            let offset = index.read_u32::<LittleEndian>()? as u64;
+            let len = index.read_u32::<LittleEndian>()? as u64;
+            segments.push((offset, read_bytes(&mut index, len)?));
+        }
+        let num_folders = index.read_u16::<LittleEndian>()? as usize;
+        let mut block_maps = Vec::with_capacity(num_folders);
+        for _ in 0..num_folders {
+            let num_blocks = index.read_u16::<LittleEndian>()? as usize;
+            let mut blocks = Vec::with_capacity(num_blocks);
+            for _ in 0..num_blocks {
+                let offset = index.read_u32::<LittleEndian>()? as u64;
+                let checksum = index.read_u32::<LittleEndian>()?;
+                let compressed_size = index.read_u16::<LittleEndian>()?;
+                let uncompressed_size = index.read_u16::<LittleEndian>()?;
+                let mut reserve_data = vec![0u8; data_reserve_size as usize];
+                index.read_exact(&mut reserve_data)?;
+                blocks.push(DataBlockInfo {
+                    offset,
+                    checksum,
+                    compressed_size,
+                    uncompressed_size,
+                    reserve_data,
+                });
+            }
+            block_maps.push(blocks);
+        }
+
+        let header = &segments[0].1;
+        let actual_header = read_range(&mut reader, 0, header.len() as u64)?;
+        if &actual_header != header {
+            invalid_data!("Cabinet index does not match the cabinet file");
+        }
+        let directory = Segments { segments, position: 0 };
+        let mut cabinet = Cabinet::with_options(directory, options)?;
+        let folders_match = block_maps.len() == cabinet.inner.folders.len()
+            && block_maps.iter().zip(cabinet.folder_entries()).all(
+                |(blocks, folder)| {
+                    blocks.len() == folder.num_data_blocks() as usize
+                },
+            );
+        if !folders_match {
+            invalid_data!("Cabinet index has inconsistent data block maps");
+        }
+        cabinet.inner.block_maps = block_maps;
+        Ok(cabinet.with_reader(reader))
+    }
+}
+
+/// Reads `len` bytes starting at the given offset.
+fn read_range<R: Read + Seek + ?Sized>(
+    reader: &mut R,
+    offset: u64,
+    len: u64,
+) -> io::Result<Vec<u8>> {
+    reader.seek(SeekFrom::Start(offset))?;
+    read_bytes(reader, len)
+}
+
+/// Reads exactly `len` bytes.
+fn read_bytes<R: Read + ?Sized>(
+    reader: &mut R,
+    len: u64,
+) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader.take(len).read_to_end(&mut bytes)?;
+    if (bytes.len() as u64) < len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(bytes)
+}
+
+/// An in-memory copy of parts of a cabinet file (at their original offsets),
+/// from which the directory can be parsed as if from the file itself.
+struct Segments {
+    segments: Vec<(u64, Vec<u8>)>,
+    position: u64,
+}
+
+impl Read for Segments {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let position = self.position;
+        let segment = self.segments.iter().find(|(offset, bytes)| {
+            position >= *offset && position < offset + bytes.len() as u64
+        });
+        let (offset, bytes) = match segment {
+            Some(segment) => segment,
+            None if buf.is_empty() => return Ok(0),
+            None => invalid_data!(
+                "Cabinet index has no data for offset {}",
+                position
+            ),
+        };
+        let start = (position - offset) as usize;
+        let count = buf.len().min(bytes.len() - start);
+        buf[..count].copy_from_slice(&bytes[start..start + count]);
+        self.position += count as u64;
+        Ok(count)
+    }
+}
+
+impl Seek for Segments {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let end = self
+            .segments
+            .iter()
+            .map(|(offset, bytes)| offset + bytes.len() as u64)
+            .max()
+            .unwrap_or(0);
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => {
+                self.position.checked_add_signed(delta)
+            }
+            SeekFrom::End(delta) => end.checked_add_signed(delta),
+        };
+        match position {
+            Some(position) => {
+                self.position = position;
+                Ok(position)
+            }
+            None => invalid_input!("Cannot seek to a negative position"),
+        }
+    }
+}
@@ -0,0 +1,229 @@
+//! Helpers for checking cabinet files against other CAB implementations.
+//!
+//! This module is only available when the `interop` feature is enabled.  It
+//! is intended for use in tests (both this crate's and downstream crates'),
+//! to verify that generated cabinet files can be read by reference
+//! extractors such as [cabextract](https://www.cabextract.org.uk/) and
+//! [gcab](https://wiki.gnome.org/msitools), when those are installed.
+//!
+//! ```no_run
+//! use cab::interop::Extractor;
+//!
+//! # let cab_bytes: Vec<u8> = Vec::new();
+//! for extractor in Extractor::available() {
+//!     extractor
+//!         .verify(&cab_bytes, &[("hi.txt", b"Hello, world!\n")])
+//!         .unwrap();
+//! }
+//! ```
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// An external program capable of extracting cabinet files.
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub enum Extractor {
+    /// The `cabextract` command-line tool.
+    Cabextract,
+    /// The `gcab` command-line tool from GNOME's msitools.
+    Gcab,
+    /// The `expand.exe` tool that ships with Windows.
+    WindowsExpand,
+}
+
+impl Extractor {
+    /// Returns all extractors that are known to this module.
+    pub fn all() -> &'static [Extractor] {
+        &[Extractor::Cabextract, Extractor::Gcab, Extractor::WindowsExpand]
+    }
+
+    /// Returns the extractors that are installed on this system.
+    pub fn available() -> Vec<Extractor> {
+        Extractor::all()
+            .iter()
+            .copied()
+            .filter(|extractor| extractor.is_available())
+            .collect()
+    }
+
+    /// Returns the name of the program this extractor runs.
+    pub fn program(self) -> &'static str {
+        match self {
+            Extractor::Cabextract => "cabextract",
+            Extractor::Gcab => "gcab",
+            Extractor::WindowsExpand => "expand.exe",
+        }
+    }
+
+    /// Returns true if this extractor is installed on this system.
+    pub fn is_available(self) -> bool {
+        if self == Extractor::WindowsExpand && !cfg!(windows) {
+            return false;
+        }
+        let arg = match self {
+            Extractor::Cabextract | Extractor::Gcab => "--version",
+            Extractor::WindowsExpand => "/?",
+        };
+        Command::new(self.program())
+            .arg(arg)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok()
+    }
+
+    /// Extracts all files from the cabinet file at `cab_path` into the
+    /// directory `out_dir`, which must already exist.
+    pub fn extract(self, cab_path: &Path, out_dir: &Path) -> io::Result<()> {
+        let mut command = Command::new(self.program());
+        match self {
+            Extractor::Cabextract => {
+                command.arg("-q").arg("-d").arg(out_dir).arg(cab_path);
+            }
+            Extractor::Gcab => {
+                command.arg("-x").arg("-C").arg(out_dir).arg(cab_path);
+            }
+            Extractor::WindowsExpand => {
+                command.arg(cab_path).arg("-F:*").arg(out_dir);
+            }
+        }
+        let output = command.stdin(Stdio::null()).output()?;
+        if !output.status.success() {
+            invalid_data!(
+                "{} failed ({}): {}",
+                self.program(),
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(())
+    }
+
+    /// Extracts the given cabinet file data with this extractor, and checks
+    /// that the extracted files exactly match the expected `(name, data)`
+    /// pairs, with no other files extracted.  Backslashes in names are
+    /// treated as path separators.
+    pub fn verify<N, D>(
+        self,
+        cab_data: &[u8],
+        expected: &[(N, D)],
+    ) -> io::Result<()>
+    where
+        N: AsRef<str>,
+        D: AsRef<[u8]>,
+    {
+        let dir = TempDir::new()?;
+        let cab_path = dir.path().join("input.cab");
+        fs::write(&cab_path, cab_data)?;
+        let out_dir = dir.path().join("out");
+        fs::create_dir(&out_dir)?;
+        self.extract(&cab_path, &out_dir)?;
+        let mut extracted = Vec::new();
+        list_files(&out_dir, &mut extracted)?;
+        let mut expected_paths = Vec::with_capacity(expected.len());
+        for (name, data) in expected.iter() {
+            let name = name.as_ref();
+            let path = name
+                .split(['\\', '/'])
+                .fold(out_dir.clone(), |path, part| path.join(part));
+            expected_paths.push(path.clone());
+            let actual = match fs::read(&path) {
+                Ok(actual) => actual,
+                Err(error) => invalid_data!(
+                    "{} did not extract {:?}: {}",
+                    self.program(),
+                    name,
+                    error
+                ),
+            };
+            if actual != data.as_ref() {
+                invalid_data!(
+                    "{} extracted wrong data for {:?} \
+                     ({} bytes, expected {} bytes)",
+                    self.program(),
+                    name,
+                    actual.len(),
+                    data.as_ref().len()
+                );
+            }
+        }
+        if let Some(path) =
+            extracted.iter().find(|path| !expected_paths.contains(path))
+        {
+            invalid_data!(
+                "{} extracted unexpected file {:?}",
+                self.program(),
+                path.strip_prefix(&out_dir).unwrap_or(path)
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Appends the paths of all files within `dir` (recursively) to `paths`.
+fn list_files(dir: &Path, paths: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            list_files(&entry.path(), paths)?;
+        } else {
+            paths.push(entry.path());
+        }
+    }
+    Ok(())
+}
+
+/// Checks the given cabinet file data against every available extractor (see
+/// [`Extractor::verify`]), and returns the extractors that were used.
+pub fn verify_with_available<N, D>(
+    cab_data: &[u8],
+    expected: &[(N, D)],
+) -> io::Result<Vec<Extractor>>
+where
+    N: AsRef<str>,
+    D: AsRef<[u8]>,
+{
+    let extractors = Extractor::available();
+    for extractor in extractors.iter() {
+        extractor.verify(cab_data, expected)?;
+    }
+    Ok(extractors)
+}
+
+/// A scratch directory that is deleted when dropped.
+struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    fn new() -> io::Result<TempDir> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.subsec_nanos())
+            .unwrap_or(0);
+        let path = std::env::temp_dir().join(format!(
+            "cab-interop-{}-{}-{}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed),
+            nanos
+        ));
+        fs::create_dir_all(&path)?;
+        Ok(TempDir { path })
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
@@ -64,20 +64,20 @@
 //! use std::io;
 //!
 //! let mut cab_builder = cab::CabinetBuilder::new();
-//! // Add a single file in its own folder:
-//! cab_builder.add_folder(cab::CompressionType::None).add_file("img/foo.jpg");
-//! // Add several more files, compressed together in a second folder:
-//! {
-//!     let folder = cab_builder.add_folder(cab::CompressionType::MsZip);
-//!     folder.add_file("documents/README.txt");
-//!     folder.add_file("documents/license.txt");
-//!     // We can also specify metadata on individual files:
-//!     {
+//! cab_builder
+//!     // Add a single file in its own folder:
+//!     .folder(cab::CompressionType::None, |folder| {
+//!         folder.add_file("img/foo.jpg");
+//!     })
+//!     // Add several more files, compressed together in a second folder:
+//!     .folder(cab::CompressionType::MsZip, |folder| {
+//!         folder.add_file("documents/README.txt");
+//!         folder.add_file("documents/license.txt");
+//!         // We can also specify metadata on individual files:
 //!         let file = folder.add_file("documents/hidden.txt");
 //!         file.set_is_hidden(true);
 //!         file.set_is_read_only(true);
-//!     }
-//! }
+//!     });
 //! // Now, we'll actually construct the cabinet file on disk:
 //! let cab_file = fs::File::create("path/to/cabinet.cab").unwrap();
 //! let mut cab_writer = cab_builder.build(cab_file).unwrap();
@@ -89,29 +89,109 @@
 //! let mut cab_file = cab_writer.finish().unwrap();
 //! println!("Cabinet size: {} B", cab_file.metadata().unwrap().len());
 //! ```
+//!
+//! When the cabinet lives in a file on disk, [`open`] and [`create`] take
+//! care of opening the file and buffering reads and writes to it.
+//!
+//! For the common case of a cabinet holding just one file, the
+//! [`compress_single`] and [`decompress_single`] functions do all of this in
+//! one call.
+//!
+//! To extract or check every file at once, use [`Cabinet::extract_all`] or
+//! [`Cabinet::verify_all`], which carry on past per-file failures and return
+//! an [`ExtractReport`] describing the outcome for each file.
 
 #![warn(missing_docs)]
+// Parsing and decompressing cabinets involves no unsafe code.  The only
+// exception is the optional C API, which by its nature has to deal in raw
+// pointers.
+#![cfg_attr(not(feature = "capi"), forbid(unsafe_code))]
+#![cfg_attr(feature = "capi", deny(unsafe_code))]
 
 pub use lzxd::WindowSize;
 
 pub use builder::{
-    CabinetBuilder, CabinetWriter, FileBuilder, FileWriter, FolderBuilder,
+    CabinetAppender, CabinetBuilder, CabinetWriter, CheckpointToken,
+    DuplicateNames, FileBuilder, FileWriter, FinishedCabinet, FolderBuilder,
+};
+pub use cabinet::{Cabinet, DataBlocks, ReadSeek};
+pub use cancel::{CancelToken, Cancelled};
+pub use ctype::{
+    CompressionEffort, CompressionType, InvalidWindowSize, WindowSizeExt,
+};
+pub use dump::DumpFormat;
+pub use extract::{ExtractReport, FileReport, FileStatus, SkipReason};
+pub use file::{
+    FileEntries, FileEntry, FileInfoRef, FileReader, PathComponents,
+};
+pub use folder::{
+    BlockError, BlockSpan, Continuation, DataBlockInfo, FolderEntries,
+    FolderEntry, FolderInfoRef, FolderReader, FolderResumeToken,
+};
+pub use open::{create, open};
+pub use options::{ReadOptions, SizeMismatch};
+pub use plan::{
+    CabinetPlan, PlannedCabinet, PlannedCabinets, PlannedFile, PlannedFolder,
+};
+pub use readers::{FileReaders, SharedFileReader};
+pub use report::{CabinetReport, FileSpan, FolderReport, GroupingSuggestion};
+pub use set::CabinetSet;
+pub use single::{
+    compress_single, compress_underscored, decompress_single,
+    expand_underscored, single_file_name, underscored_name,
 };
-pub use cabinet::Cabinet;
-pub use ctype::CompressionType;
-pub use file::{FileEntries, FileEntry, FileReader};
-pub use folder::{FolderEntries, FolderEntry};
+pub use split::{split_stream, SplitStream, StreamSegment};
+pub use spool::{Spool, SpoolOptions};
+pub use string::{NameDecoding, NameLocation, NameTooLongError};
+pub use throttle::Throttle;
+pub use transform::BlockTransform;
+pub use tree::{TreeDirectory, TreeNode};
+pub use warning::Warning;
 
 #[macro_use]
 mod macros;
 
+#[cfg(feature = "time")]
+pub mod archive;
 mod builder;
 mod cabinet;
+mod cache;
+mod cancel;
+#[cfg(feature = "capi")]
+pub mod capi;
 mod checksum;
+pub mod codec;
 mod consts;
+#[cfg(feature = "zip")]
+pub mod convert;
 mod ctype;
 mod datetime;
+pub mod ddf;
+mod digest;
+mod dump;
+mod extract;
 mod file;
 mod folder;
+mod index;
+#[cfg(feature = "interop")]
+pub mod interop;
 mod mszip;
+mod open;
+mod options;
+#[cfg(feature = "pipeline")]
+mod pipeline;
+mod plan;
+mod readers;
+mod report;
+mod set;
+mod single;
+mod slice;
+mod split;
+mod spool;
 mod string;
+#[cfg(feature = "test-support")]
+pub mod test_support;
+mod throttle;
+mod transform;
+mod tree;
+mod warning;
@@ -6,28 +6,66 @@ use flate2::Compression;
 const MSZIP_SIGNATURE: u16 = 0x4B43; // "CK" stored little-endian
 const MSZIP_SIGNATURE_LEN: usize = 2;
 const MSZIP_BLOCK_TERMINATOR: u16 = 0x0003;
-const DEFLATE_MAX_DICT_LEN: usize = 0x8000;
+pub(crate) const DEFLATE_MAX_DICT_LEN: usize = 0x8000;
 
+/// The maximum number of uncompressed bytes in one MSZIP block.
+pub const MAX_BLOCK_SIZE: usize = 0x8000;
+
+/// Compresses a sequence of data into MSZIP blocks.
+///
+/// Each block is a `CK` signature followed by deflate data.  By default,
+/// each block may refer back to data from earlier blocks, so the blocks must
+/// later be decompressed in order by a single [`MsZipDecompressor`].
 pub struct MsZipCompressor {
     compressor: flate2::Compress,
+    shared_history: bool,
 }
 
 impl MsZipCompressor {
+    /// Creates a compressor using the best (slowest) compression level.
     pub fn new() -> MsZipCompressor {
+        MsZipCompressor::with_level(Compression::best().level())
+    }
+
+    /// Creates a compressor using the given deflate compression level, from
+    /// 0 (no compression) to 9 (best compression).
+    pub fn with_level(level: u32) -> MsZipCompressor {
         MsZipCompressor {
-            compressor: flate2::Compress::new(Compression::best(), false),
+            compressor: flate2::Compress::new(Compression::new(level), false),
+            shared_history: true,
         }
     }
 
+    /// Sets whether later blocks may refer back to data from earlier blocks
+    /// (the default).  If not, each block is compressed as a separate,
+    /// complete deflate stream.
+    pub fn set_shared_history(&mut self, shared_history: bool) {
+        self.shared_history = shared_history;
+    }
+
+    /// Compresses the next block of data, which must be at most
+    /// [`MAX_BLOCK_SIZE`] bytes, and returns the compressed block
+    /// (including its signature).  `is_last_block` should be true for the
+    /// final block of the sequence.
+    ///
+    /// If the data doesn't compress, the returned block stores it
+    /// uncompressed, so the result is never more than 7 bytes larger than
+    /// the input.
     pub fn compress_block(
         &mut self,
         data: &[u8],
         is_last_block: bool,
     ) -> io::Result<Vec<u8>> {
-        debug_assert!(data.len() <= 0x8000);
+        if data.len() > MAX_BLOCK_SIZE {
+            invalid_input!(
+                "MSZIP block too large ({} bytes, maximum is {})",
+                data.len(),
+                MAX_BLOCK_SIZE
+            );
+        }
         let mut out = Vec::<u8>::with_capacity(0xffff);
         out.write_u16::<LittleEndian>(MSZIP_SIGNATURE)?;
-        let flush = if is_last_block {
+        let flush = if is_last_block || !self.shared_history {
             flate2::FlushCompress::Finish
         } else {
             flate2::FlushCompress::Sync
@@ -36,7 +74,9 @@ impl MsZipCompressor {
             Ok(_) => {}
             Err(error) => invalid_data!("MSZIP compression failed: {}", error),
         }
-        if !is_last_block {
+        if !self.shared_history {
+            self.compressor.reset();
+        } else if !is_last_block {
             out.write_u16::<LittleEndian>(MSZIP_BLOCK_TERMINATOR)?;
         }
         let max_out_len = data.len() + 7;
@@ -54,12 +94,23 @@ impl MsZipCompressor {
     }
 }
 
+impl Default for MsZipCompressor {
+    fn default() -> Self {
+        MsZipCompressor::new()
+    }
+}
+
+/// Decompresses a sequence of MSZIP blocks.
+///
+/// Since each block may refer back to data from earlier blocks, the blocks
+/// of a sequence must be decompressed in order, by the same decompressor.
 pub struct MsZipDecompressor {
     decompressor: flate2::Decompress,
     dictionary: Vec<u8>,
 }
 
 impl MsZipDecompressor {
+    /// Creates a decompressor, ready for the first block of a sequence.
     pub fn new() -> MsZipDecompressor {
         MsZipDecompressor {
             decompressor: flate2::Decompress::new(false),
@@ -67,11 +118,59 @@ impl MsZipDecompressor {
         }
     }
 
+    /// Discards the history of previous blocks, so that the decompressor is
+    /// ready for the first block of a new sequence.
     pub fn reset(&mut self) {
         self.decompressor.reset(true);
         self.dictionary = Vec::with_capacity(DEFLATE_MAX_DICT_LEN);
     }
 
+    /// Puts the decompressor in the state it would be in after
+    /// decompressing blocks whose data ended with `window` (which must hold
+    /// the last `DEFLATE_MAX_DICT_LEN` bytes of that data, or all of it if
+    /// there was less).
+    pub(crate) fn set_window(&mut self, window: &[u8]) {
+        debug_assert!(window.len() <= DEFLATE_MAX_DICT_LEN);
+        self.reset();
+        self.dictionary.extend_from_slice(window);
+    }
+
+    /// Feeds the decompressor the current dictionary, followed by `extra`
+    /// (data that has already been decompressed from the current block), as
+    /// an uncompressed deflate block, so that subsequent compressed data can
+    /// refer back to it.
+    fn prime_dictionary(&mut self, extra: &[u8]) -> io::Result<()> {
+        // TODO: Avoid doing extra allocations/copies here.
+        let total = self.dictionary.len() + extra.len();
+        let skip = total.saturating_sub(DEFLATE_MAX_DICT_LEN);
+        let mut window = Vec::with_capacity(total - skip);
+        if skip < self.dictionary.len() {
+            window.extend_from_slice(&self.dictionary[skip..]);
+            window.extend_from_slice(extra);
+        } else {
+            window.extend_from_slice(&extra[(skip - self.dictionary.len())..]);
+        }
+        if window.is_empty() {
+            return Ok(());
+        }
+        debug_assert!(window.len() <= DEFLATE_MAX_DICT_LEN);
+        let length = window.len() as u16;
+        let mut chunk: Vec<u8> = vec![0];
+        chunk.write_u16::<LittleEndian>(length)?;
+        chunk.write_u16::<LittleEndian>(!length)?;
+        chunk.extend_from_slice(&window);
+        let mut out = Vec::with_capacity(window.len());
+        let flush = flate2::FlushDecompress::Sync;
+        match self.decompressor.decompress_vec(&chunk, &mut out, flush) {
+            Ok(flate2::Status::Ok) => {}
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    /// Decompresses the next block (including its signature) of the
+    /// sequence, which must decompress to exactly `uncompressed_size` bytes
+    /// (normally at most [`MAX_BLOCK_SIZE`]).
     pub fn decompress_block(
         &mut self,
         data: &[u8],
@@ -85,31 +184,42 @@ impl MsZipDecompressor {
                 "MSZIP decompression failed: Invalid block signature"
             );
         }
-        let data = &data[MSZIP_SIGNATURE_LEN..];
+        let mut data = &data[MSZIP_SIGNATURE_LEN..];
         // Reset decompressor with appropriate dictionary:
         self.decompressor.reset(false);
-        if !self.dictionary.is_empty() {
-            // TODO: Avoid doing extra allocations/copies here.
-            debug_assert!(self.dictionary.len() <= DEFLATE_MAX_DICT_LEN);
-            let length = self.dictionary.len() as u16;
-            let mut chunk: Vec<u8> = vec![0];
-            chunk.write_u16::<LittleEndian>(length)?;
-            chunk.write_u16::<LittleEndian>(!length)?;
-            chunk.extend_from_slice(&self.dictionary);
-            let mut out = Vec::with_capacity(self.dictionary.len());
+        self.prime_dictionary(&[])?;
+        // Decompress data.  To match the leniency of the Windows decoder, we
+        // don't require the deflate stream to end with a final block, we
+        // ignore any data (or errors) beyond the expected uncompressed size,
+        // and if the deflate stream ends early we treat any remaining input
+        // as another deflate stream continuing from the same window.
+        let mut out = Vec::<u8>::with_capacity(uncompressed_size);
+        loop {
+            let in_before = self.decompressor.total_in();
+            let out_before = out.len();
             let flush = flate2::FlushDecompress::Sync;
-            match self.decompressor.decompress_vec(&chunk, &mut out, flush) {
-                Ok(flate2::Status::Ok) => {}
-                _ => unreachable!(),
+            let result =
+                self.decompressor.decompress_vec(data, &mut out, flush);
+            let consumed = (self.decompressor.total_in() - in_before) as usize;
+            data = &data[consumed..];
+            if out.len() >= uncompressed_size {
+                break;
             }
-        }
-        // Decompress data:
-        let mut out = Vec::<u8>::with_capacity(uncompressed_size);
-        let flush = flate2::FlushDecompress::Finish;
-        match self.decompressor.decompress_vec(data, &mut out, flush) {
-            Ok(_) => {}
-            Err(error) => {
-                invalid_data!("MSZIP decompression failed: {}", error);
+            match result {
+                Ok(flate2::Status::StreamEnd) if !data.is_empty() => {
+                    self.decompressor.reset(false);
+                    self.prime_dictionary(&out)?;
+                }
+                Ok(_) => {
+                    if data.is_empty()
+                        || (consumed == 0 && out.len() == out_before)
+                    {
+                        break;
+                    }
+                }
+                Err(error) => {
+                    invalid_data!("MSZIP decompression failed: {}", error);
+                }
             }
         }
         if out.len() != uncompressed_size {
@@ -136,11 +246,20 @@ impl MsZipDecompressor {
     }
 }
 
+impl Default for MsZipDecompressor {
+    fn default() -> Self {
+        MsZipDecompressor::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use rand::RngCore;
 
-    use super::{MsZipCompressor, MsZipDecompressor, DEFLATE_MAX_DICT_LEN};
+    use super::{
+        MsZipCompressor, MsZipDecompressor, DEFLATE_MAX_DICT_LEN,
+        MAX_BLOCK_SIZE,
+    };
 
     #[test]
     fn read_compressed_data() {
@@ -155,10 +274,80 @@ mod tests {
         assert!(input.len() < expected.len());
         let mut decompressor = MsZipDecompressor::new();
         let output =
-            decompressor.decompress_block(&input, expected.len()).unwrap();
+            decompressor.decompress_block(input, expected.len()).unwrap();
         assert_eq!(output, expected);
     }
 
+    #[test]
+    fn read_block_without_terminator() {
+        let original = repeating_data(5000);
+        let mut compressor = MsZipCompressor::new();
+        let mut block = compressor.compress_block(&original, false).unwrap();
+        block.truncate(block.len() - 2);
+        let mut decompressor = MsZipDecompressor::new();
+        let output = decompressor.decompress_block(&block, 5000).unwrap();
+        assert_eq!(output, original);
+    }
+
+    #[test]
+    fn read_block_with_trailing_garbage() {
+        let original = repeating_data(5000);
+        let mut compressor = MsZipCompressor::new();
+        let mut block = compressor.compress_block(&original, false).unwrap();
+        block.truncate(block.len() - 2);
+        block.extend_from_slice(&[0xff; 8]);
+        let mut decompressor = MsZipDecompressor::new();
+        let output = decompressor.decompress_block(&block, 5000).unwrap();
+        assert_eq!(output, original);
+    }
+
+    #[test]
+    fn read_block_with_two_deflate_streams() {
+        let original = repeating_data(3000);
+        let mut compressor = MsZipCompressor::new();
+        let mut block =
+            compressor.compress_block(&original[..2000], true).unwrap();
+        // Append a second deflate stream, consisting of a single final stored
+        // block with the rest of the data:
+        block.extend_from_slice(&[0x01, 0xe8, 0x03, 0x17, 0xfc]);
+        block.extend_from_slice(&original[2000..]);
+        let mut decompressor = MsZipDecompressor::new();
+        let output = decompressor.decompress_block(&block, 3000).unwrap();
+        assert_eq!(output, original);
+    }
+
+    #[test]
+    fn independent_blocks_decode_without_history() {
+        let original = repeating_data(3 * DEFLATE_MAX_DICT_LEN);
+        let blocks = do_lib_compress(&original, false);
+        assert_eq!(blocks.len(), 3);
+        for (index, (size, compressed)) in blocks.into_iter().enumerate() {
+            let mut decompressor = MsZipDecompressor::new();
+            let output =
+                decompressor.decompress_block(&compressed, size).unwrap();
+            let start = index * DEFLATE_MAX_DICT_LEN;
+            assert_eq!(output, &original[start..(start + size)]);
+        }
+    }
+
+    #[test]
+    fn read_truncated_block() {
+        let original = random_data(5000);
+        let mut compressor = MsZipCompressor::new();
+        let block = compressor.compress_block(&original, true).unwrap();
+        let mut decompressor = MsZipDecompressor::new();
+        assert!(decompressor.decompress_block(&block[..1000], 5000).is_err());
+    }
+
+    #[test]
+    fn compress_oversized_block() {
+        let mut compressor = MsZipCompressor::new();
+        let error = compressor
+            .compress_block(&repeating_data(MAX_BLOCK_SIZE + 1), true)
+            .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
     fn repeating_data(size: usize) -> Vec<u8> {
         let modulus = 251; // a prime number no bigger than u8::MAX
         (0..size).map(|index| (index % modulus) as u8).collect::<Vec<u8>>()
@@ -173,152 +362,20 @@ mod tests {
     }
 
     #[cfg(target_env = "msvc")]
-    /// Wrappers for the Microsoft compression API so that on Windows we can
-    /// test interop with the system implementation.  This code comes from
-    /// https://github.com/luser/rust-makecab; thanks to Ted Mielczarek for
-    /// sharing it.
     mod sys {
-        #![allow(non_camel_case_types)]
-
-        use std::mem;
-        use std::ptr;
-
-        use winapi::shared::basetsd::{PSIZE_T, SIZE_T};
-        use winapi::shared::minwindef::{BOOL, DWORD, FALSE, LPVOID, TRUE};
-        use winapi::um::winnt::{HANDLE, PVOID};
-
-        use super::super::DEFLATE_MAX_DICT_LEN;
-
-        const COMPRESS_ALGORITHM_MSZIP: DWORD = 2;
-        const COMPRESS_RAW: DWORD = 1 << 29;
-        type PCOMPRESS_ALLOCATION_ROUTINES = LPVOID;
-        type COMPRESSOR_HANDLE = HANDLE;
-        type DECOMPRESSOR_HANDLE = HANDLE;
-        type PCOMPRESSOR_HANDLE = *mut COMPRESSOR_HANDLE;
-        type PDECOMPRESSOR_HANDLE = *mut DECOMPRESSOR_HANDLE;
-
-        #[rustfmt::skip]
-        #[link(name = "cabinet")]
-        extern "system" {
-            fn CreateCompressor(
-                Algorithm: DWORD,
-                AllocationRoutines: LPVOID,
-                CompressorHandle: PCOMPRESSOR_HANDLE)
-                -> BOOL;
-            fn CloseCompressor(
-                CompressorHandle: COMPRESSOR_HANDLE)
-                -> BOOL;
-            fn Compress(
-                CompressorHandle: COMPRESSOR_HANDLE,
-                UncompressedData: PVOID,
-                UncompressedDataSize: SIZE_T,
-                CompressedBuffer: PVOID,
-                CompressedBufferSize: SIZE_T,
-                CompressedDataSize: PSIZE_T)
-                -> BOOL;
-
-            fn CreateDecompressor(
-                Algorithm: DWORD,
-                AllocationRoutines: PCOMPRESS_ALLOCATION_ROUTINES,
-                DecompressorHandle: PDECOMPRESSOR_HANDLE)
-                -> BOOL;
-            fn CloseDecompressor(
-                DecompressorHandle: DECOMPRESSOR_HANDLE)
-                -> BOOL;
-            fn Decompress(
-                DecompressorHandle: DECOMPRESSOR_HANDLE,
-                CompressedData: PVOID,
-                CompressedDataSize: SIZE_T,
-                UncompressedBuffer: PVOID,
-                UncompressedBufferSize: SIZE_T,
-                UncompressedDataSize: PSIZE_T)
-                -> BOOL;
-        }
-
-        /// Compress `data` with the Microsoft compression API.
-        pub fn do_system_compress(data: &[u8]) -> Vec<(usize, Vec<u8>)> {
-            let handle = unsafe {
-                let mut handle = mem::MaybeUninit::uninit();
-                if CreateCompressor(
-                    COMPRESS_ALGORITHM_MSZIP | COMPRESS_RAW,
-                    ptr::null_mut(),
-                    handle.as_mut_ptr(),
-                ) != TRUE
-                {
-                    panic!("CreateCompressor failed");
-                }
-                handle.assume_init()
-            };
-            let mut blocks = Vec::<(usize, Vec<u8>)>::new();
-            for slice in data.chunks(DEFLATE_MAX_DICT_LEN) {
-                let mut buffer = vec![0; 0xffff];
-                unsafe {
-                    let mut compressed_size = mem::MaybeUninit::uninit();
-                    if Compress(
-                        handle,
-                        slice.as_ptr() as PVOID,
-                        slice.len() as SIZE_T,
-                        buffer.as_ptr() as PVOID,
-                        buffer.len() as SIZE_T,
-                        compressed_size.as_mut_ptr(),
-                    ) == FALSE
-                    {
-                        panic!("Compress failed");
-                    }
-                    buffer.resize(compressed_size.assume_init() as usize, 0);
-                }
-                blocks.push((slice.len(), buffer));
-            }
-            unsafe {
-                CloseCompressor(handle);
-            }
-            blocks
-        }
-
-        pub fn do_system_decompress(blocks: Vec<(usize, Vec<u8>)>) -> Vec<u8> {
-            let handle = unsafe {
-                let mut handle = mem::MaybeUninit::uninit();
-                if CreateDecompressor(
-                    COMPRESS_ALGORITHM_MSZIP | COMPRESS_RAW,
-                    ptr::null_mut(),
-                    handle.as_mut_ptr(),
-                ) != TRUE
-                {
-                    panic!("CreateDecompressor failed");
-                }
-                handle.assume_init()
-            };
-            let mut buffer = Vec::<u8>::new();
-            // Decompress each chunk in turn.
-            for (original_size, ref block) in blocks.into_iter() {
-                assert!(original_size <= DEFLATE_MAX_DICT_LEN);
-                // Make space in the output buffer.
-                let last = buffer.len();
-                buffer.resize(last + original_size, 0);
-                unsafe {
-                    if Decompress(
-                        handle,
-                        block.as_ptr() as PVOID,
-                        block.len() as SIZE_T,
-                        buffer[last..].as_mut_ptr() as PVOID,
-                        original_size as SIZE_T,
-                        ptr::null_mut(),
-                    ) == FALSE
-                    {
-                        panic!("Decompress failed");
-                    }
-                }
-            }
-            unsafe {
-                CloseDecompressor(handle);
-            }
-            buffer
-        }
+        pub use cab_interop_sys::{
+            mszip_compress as do_system_compress,
+            mszip_decompress as do_system_decompress,
+        };
     }
 
-    fn do_lib_compress(mut data: &[u8]) -> Vec<(usize, Vec<u8>)> {
+    fn do_lib_compress(
+        mut data: &[u8],
+        shared_history: bool,
+    ) -> Vec<(usize, Vec<u8>)> {
         let mut blocks = Vec::<(usize, Vec<u8>)>::new();
         let mut compressor = MsZipCompressor::new();
+        compressor.set_shared_history(shared_history);
         while data.len() > DEFLATE_MAX_DICT_LEN {
             let slice = &data[0..DEFLATE_MAX_DICT_LEN];
             let compressed = compressor.compress_block(slice, false).unwrap();
@@ -349,7 +406,17 @@ mod tests {
                 #[test]
                 fn lib_to_lib() {
                     let original: &[u8] = $data;
-                    let compressed = do_lib_compress(original);
+                    let compressed = do_lib_compress(original, true);
+                    assert_eq!(
+                        do_lib_decompress(compressed).as_slice(),
+                        original
+                    );
+                }
+
+                #[test]
+                fn lib_to_lib_independent_blocks() {
+                    let original: &[u8] = $data;
+                    let compressed = do_lib_compress(original, false);
                     assert_eq!(
                         do_lib_decompress(compressed).as_slice(),
                         original
@@ -360,7 +427,18 @@ mod tests {
                 #[test]
                 fn lib_to_sys() {
                     let original: &[u8] = $data;
-                    let compressed = do_lib_compress(original);
+                    let compressed = do_lib_compress(original, true);
+                    assert_eq!(
+                        sys::do_system_decompress(compressed).as_slice(),
+                        original
+                    );
+                }
+
+                #[cfg(target_env = "msvc")]
+                #[test]
+                fn lib_to_sys_independent_blocks() {
+                    let original: &[u8] = $data;
+                    let compressed = do_lib_compress(original, false);
                     assert_eq!(
                         sys::do_system_decompress(compressed).as_slice(),
                         original
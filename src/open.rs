@@ -0,0 +1,52 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+
+use crate::builder::{CabinetBuilder, CabinetWriter};
+use crate::cabinet::Cabinet;
+use crate::options::ReadOptions;
+
+/// How many bytes [`open`] reads from the file at a time when loading data
+/// blocks (see [`ReadOptions::io_batch_size`]).
+const READ_BATCH_SIZE: usize = 0x10000;
+/// The size of the buffer [`create`] puts in front of the file.
+const WRITE_BUFFER_SIZE: usize = 0x10000;
+
+/// Opens the cabinet file at the given path, reading it through a buffer
+/// and (when loading data blocks) in batches of 64 KiB, so that parsing the
+/// cabinet's directory and reading through its folders doesn't take a
+/// system call for every small read.  To open a file with other options,
+/// use [`ReadOptions::open_path`].
+///
+/// ```no_run
+/// let mut cabinet = cab::open("foo.cab")?;
+/// let mut reader = cabinet.read_file("hi.txt")?;
+/// std::io::copy(&mut reader, &mut std::io::stdout())?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Cabinet<BufReader<File>>> {
+    ReadOptions::new().io_batch_size(READ_BATCH_SIZE).open_path(path)
+}
+
+/// Creates (or truncates) the file at the given path, and starts writing
+/// the cabinet laid out by `builder` into it through a buffer.  The buffer
+/// is flushed by [`CabinetWriter::finish`].
+///
+/// ```no_run
+/// use std::io::Write;
+/// let mut builder = cab::CabinetBuilder::new();
+/// builder.add_folder(cab::CompressionType::MsZip).add_file("hi.txt");
+/// let mut cab_writer = cab::create("foo.cab", builder)?;
+/// while let Some(mut file_writer) = cab_writer.next_file()? {
+///     file_writer.write_all(b"Hello, world!\n")?;
+/// }
+/// cab_writer.finish()?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn create<P: AsRef<Path>>(
+    path: P,
+    builder: CabinetBuilder,
+) -> io::Result<CabinetWriter<BufWriter<File>>> {
+    let file = File::create(path)?;
+    builder.build(BufWriter::with_capacity(WRITE_BUFFER_SIZE, file))
+}
@@ -0,0 +1,307 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek};
+use std::path::Path;
+
+#[cfg(feature = "time")]
+use time::PrimitiveDateTime;
+
+use crate::cabinet::Cabinet;
+use crate::cancel::CancelToken;
+use crate::consts;
+
+/// Options for opening a cabinet file, in the style of
+/// [`std::fs::OpenOptions`].
+///
+/// ```no_run
+/// let file = std::fs::File::open("foo.cab")?;
+/// let cabinet = cab::ReadOptions::new().io_batch_size(1 << 20).open(file)?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ReadOptions {
+    pub(crate) io_batch_size: usize,
+    pub(crate) detect_data_reserve: bool,
+    pub(crate) max_name_len: usize,
+    pub(crate) lenient_names: bool,
+    pub(crate) lenient_layout: bool,
+    pub(crate) cancel: Option<CancelToken>,
+    pub(crate) defer_file_entries: bool,
+    pub(crate) file_crc32: bool,
+    pub(crate) block_cache_size: usize,
+    #[cfg(feature = "time")]
+    pub(crate) default_datetime: Option<PrimitiveDateTime>,
+    pub(crate) size_mismatch: SizeMismatch,
+    pub(crate) skip_reserve_data: bool,
+    pub(crate) max_file_size: u64,
+    pub(crate) check_block_sizes: bool,
+    #[cfg(feature = "pipeline")]
+    pub(crate) pipeline: bool,
+    /// The actual data reserve size, if already known (e.g. from an index),
+    /// in which case detection is skipped.
+    pub(crate) known_data_reserve: Option<u8>,
+}
+
+/// How to read a file whose size (as recorded in its file entry) runs past
+/// the end of its folder's data, as happens in some truncated or otherwise
+/// corrupt cabinets.  See [`ReadOptions::size_mismatch`].
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum SizeMismatch {
+    /// Fail with an [`UnexpectedEof`](io::ErrorKind::UnexpectedEof) error
+    /// wrapping a [`BlockError`](crate::BlockError) once the folder's data
+    /// runs out.
+    #[default]
+    Error,
+    /// End the file early, where the folder's data runs out.
+    Truncate,
+    /// Pad the file with zeros, from where the folder's data runs out up to
+    /// its recorded size.
+    ZeroFill,
+}
+
+impl ReadOptions {
+    /// Creates a new set of options, with all options set to their defaults.
+    pub fn new() -> ReadOptions {
+        ReadOptions {
+            io_batch_size: 0,
+            detect_data_reserve: false,
+            max_name_len: consts::MAX_STRING_SIZE,
+            lenient_names: false,
+            lenient_layout: false,
+            cancel: None,
+            defer_file_entries: false,
+            file_crc32: false,
+            block_cache_size: 0,
+            #[cfg(feature = "time")]
+            default_datetime: None,
+            size_mismatch: SizeMismatch::Error,
+            skip_reserve_data: false,
+            max_file_size: u64::MAX,
+            check_block_sizes: false,
+            #[cfg(feature = "pipeline")]
+            pipeline: false,
+            known_data_reserve: None,
+        }
+    }
+
+    /// Sets the minimum number of bytes to read from the underlying reader
+    /// at a time when loading data blocks.  Bytes read beyond the current
+    /// block are kept and used to serve the following block(s), so larger
+    /// values mean fewer (but bigger) reads, which helps when each read is
+    /// expensive (e.g. a `Read + Seek` adapter issuing HTTP range requests).
+    ///
+    /// Regardless of this setting, each block's payload is read together
+    /// with the header of the block after it, in a single read.  Defaults to
+    /// zero (no additional read-ahead).
+    pub fn io_batch_size(&mut self, size: usize) -> &mut ReadOptions {
+        self.io_batch_size = size;
+        self
+    }
+
+    /// Sets whether to work out the size of the reserve data in each data
+    /// block header from the blocks themselves, rather than trusting the
+    /// size declared in the cabinet header.  Some malformed cabinets declare
+    /// a data reserve that their blocks don't actually include (or vice
+    /// versa), which otherwise makes every folder unreadable.
+    ///
+    /// When enabled, opening the cabinet tries each possible reserve size
+    /// against the data blocks of every folder, ruling out sizes under which
+    /// a block would have an invalid size, overrun the next folder's data or
+    /// the end of the file, or fail its checksum, and picks the size that
+    /// best fits (preferring the declared size in case of a tie).  Any
+    /// discrepancy is reported by
+    /// [`Cabinet::data_reserve_discrepancy`](crate::Cabinet::data_reserve_discrepancy).
+    /// Defaults to false.
+    pub fn detect_data_reserve(&mut self, detect: bool) -> &mut ReadOptions {
+        self.detect_data_reserve = detect;
+        self
+    }
+
+    /// Sets the maximum length, in bytes, of the names stored in the cabinet
+    /// (file names, and the names of the previous/next cabinet and disk).
+    /// The cabinet format limits names to 255 bytes, but some real cabinets
+    /// contain longer names anyway, which Windows still reads.  Opening a
+    /// cabinet with a name longer than this fails with a
+    /// [`NameTooLongError`](crate::NameTooLongError) identifying the
+    /// offending entry.  Defaults to 255.
+    pub fn max_name_len(&mut self, len: usize) -> &mut ReadOptions {
+        self.max_name_len = len;
+        self
+    }
+
+    /// Sets whether to detect file names stored as UTF-16LE, which the CAB
+    /// spec doesn't allow but some cabinet writers produce anyway.  Without
+    /// this, such names are cut off at their first NUL byte (and the rest of
+    /// the directory is likely misread).  A name is taken to be UTF-16LE if
+    /// it starts with a byte order mark, or if its first two characters are
+    /// each followed by a NUL byte; such names are reported as
+    /// [`NameDecoding::Utf16`](crate::NameDecoding::Utf16).  Defaults to
+    /// false.
+    pub fn lenient_names(&mut self, lenient: bool) -> &mut ReadOptions {
+        self.lenient_names = lenient;
+        self
+    }
+
+    /// Sets whether to accept cabinets whose header places the file entries
+    /// before the end of the folder entries (i.e. overlapping the folder
+    /// entries or the header itself), as some broken generators do.
+    /// Normally such cabinets are rejected with an
+    /// [`InvalidData`](io::ErrorKind::InvalidData) error; when lenient, the
+    /// folder entries are parsed first, from just after the header, and the
+    /// file entries are then parsed from where the folder entries end
+    /// (ignoring the offset given in the header), and a
+    /// [`Warning::MisplacedFileEntries`](crate::Warning::MisplacedFileEntries)
+    /// is recorded.  Defaults to false.
+    pub fn lenient_layout(&mut self, lenient: bool) -> &mut ReadOptions {
+        self.lenient_layout = lenient;
+        self
+    }
+
+    /// Sets a token that can be used to cancel reads of the cabinet's file
+    /// data while they are in progress (see [`CancelToken`]).  Once the
+    /// token is cancelled, reading fails with a
+    /// [`Cancelled`](crate::Cancelled) error before the next data block is
+    /// loaded.  By default, reads can't be cancelled.
+    pub fn cancel(&mut self, token: CancelToken) -> &mut ReadOptions {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Sets whether to put off parsing the cabinet's file entries until they
+    /// are first needed, rather than parsing them when the cabinet is
+    /// opened.  The header and folder entries are still parsed up front, so
+    /// for a cabinet with a huge file directory this makes opening much
+    /// faster when only folder-level information (or a raw data block) is
+    /// needed.
+    ///
+    /// Until the file entries are loaded (by
+    /// [`Cabinet::load_file_entries`](crate::Cabinet::load_file_entries), or
+    /// automatically by methods such as
+    /// [`Cabinet::read_file`](crate::Cabinet::read_file)), the cabinet
+    /// appears to contain no files, folder
+    /// [continuation](crate::FolderEntry::continuation) isn't known, and
+    /// warnings about the file entries aren't reported.  Defaults to false.
+    pub fn defer_file_entries(&mut self, defer: bool) -> &mut ReadOptions {
+        self.defer_file_entries = defer;
+        self
+    }
+
+    /// Sets whether [`Cabinet::extract_all`](crate::Cabinet::extract_all) and
+    /// [`Cabinet::verify_all`](crate::Cabinet::verify_all) should compute the
+    /// CRC-32 of each file's decompressed data as it goes, reporting it in
+    /// [`FileReport::crc32`](crate::FileReport::crc32).  Defaults to false.
+    pub fn file_crc32(&mut self, enable: bool) -> &mut ReadOptions {
+        self.file_crc32 = enable;
+        self
+    }
+
+    /// Sets the maximum number of bytes of decompressed block data to keep
+    /// in a cache shared by all the readers created from the cabinet.  In a
+    /// compressed folder, reading a file means decompressing every block
+    /// before it in the folder, so when reads alternate between files in the
+    /// same folder (e.g. a manifest and the payload it describes), caching
+    /// the blocks saves decompressing the same prefix of the folder over and
+    /// over.  When the cache is full, the least recently used blocks are
+    /// evicted first.  Defaults to zero (no caching).
+    pub fn block_cache_size(&mut self, max_bytes: usize) -> &mut ReadOptions {
+        self.block_cache_size = max_bytes;
+        self
+    }
+
+    /// Sets a datetime to report (from
+    /// [`FileEntry::datetime`](crate::FileEntry::datetime), and so when
+    /// extracting or converting the cabinet) for files whose stored datetime
+    /// isn't valid.  The stored value is still available from
+    /// [`FileEntry::raw_datetime`](crate::FileEntry::raw_datetime).  By
+    /// default, such files have no datetime.  Requires the `time` feature
+    /// (enabled by default).
+    #[cfg(feature = "time")]
+    pub fn default_datetime(
+        &mut self,
+        datetime: PrimitiveDateTime,
+    ) -> &mut ReadOptions {
+        self.default_datetime = Some(datetime);
+        self
+    }
+
+    /// Sets how to read files whose recorded size runs past the end of their
+    /// folder's data (see [`SizeMismatch`]).  With `Truncate` or `ZeroFill`,
+    /// the missing part of such a file is reported by
+    /// [`FileReader::damaged_ranges`](crate::FileReader::damaged_ranges)
+    /// once it has been reached, and so marks the file as
+    /// [`Recovered`](crate::FileStatus::Recovered) in an
+    /// [`ExtractReport`](crate::ExtractReport).  Defaults to
+    /// [`SizeMismatch::Error`].
+    pub fn size_mismatch(&mut self, policy: SizeMismatch) -> &mut ReadOptions {
+        self.size_mismatch = policy;
+        self
+    }
+
+    /// Sets whether to skip over the application-defined reserve data in the
+    /// cabinet header when opening the cabinet, rather than keeping it in
+    /// memory.  The reserve can be up to 60,000 bytes (signed cabinets often
+    /// use most of that), which adds up when opening thousands of cabinets
+    /// at once.  When skipped,
+    /// [`Cabinet::reserve_data`](crate::Cabinet::reserve_data) returns an
+    /// empty slice, but the reserve can still be read from the underlying
+    /// reader with
+    /// [`Cabinet::reserve_data_reader`](crate::Cabinet::reserve_data_reader).
+    /// Defaults to false.
+    pub fn skip_reserve_data(&mut self, skip: bool) -> &mut ReadOptions {
+        self.skip_reserve_data = skip;
+        self
+    }
+
+    /// Sets the largest file (by its recorded uncompressed size) that may be
+    /// opened for reading.  Opening a bigger file, whether with
+    /// [`Cabinet::read_file`](crate::Cabinet::read_file) or any of the other
+    /// ways of reading files, fails with an
+    /// [`InvalidData`](io::ErrorKind::InvalidData) error instead, which
+    /// guards against a small cabinet claiming to hold huge files.
+    /// Regardless of this option, a file reader never produces more than
+    /// the file's recorded size, however much data its folder decompresses
+    /// to.  Defaults to no limit.
+    pub fn max_file_size(&mut self, max_bytes: u64) -> &mut ReadOptions {
+        self.max_file_size = max_bytes;
+        self
+    }
+
+    /// Sets whether to check that each data block decompresses to exactly
+    /// the size recorded in its header, reporting any block that doesn't as
+    /// an [`InvalidData`](io::ErrorKind::InvalidData) error (or, when
+    /// continuing on error, as damaged).  Either way, a block's data is
+    /// never allowed to grow past its recorded size; any excess is dropped.
+    /// Defaults to false.
+    pub fn check_block_sizes(&mut self, check: bool) -> &mut ReadOptions {
+        self.check_block_sizes = check;
+        self
+    }
+
+    /// Sets whether to decompress each folder's next data block on a
+    /// background thread while the current one is being read, which hides
+    /// decompression time behind the caller's own work (such as writing out
+    /// the data) when reading through a compressed folder, especially an
+    /// LZX one.  The compressed data is still read on the calling thread,
+    /// so the underlying reader needn't be `Send`.  Each folder reader that
+    /// needs it gets its own thread, and may read and decompress one block
+    /// more than it ends up needing.  Requires the `pipeline` feature.
+    /// Defaults to false.
+    #[cfg(feature = "pipeline")]
+    pub fn pipeline(&mut self, enable: bool) -> &mut ReadOptions {
+        self.pipeline = enable;
+        self
+    }
+
+    /// Opens a cabinet file with these options.
+    pub fn open<R: Read + Seek>(&self, reader: R) -> io::Result<Cabinet<R>> {
+        Cabinet::with_options(reader, self.clone())
+    }
+
+    /// Opens the cabinet file at the given path with these options, reading
+    /// it through a buffer.  See also [`open`](crate::open).
+    pub fn open_path<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> io::Result<Cabinet<BufReader<File>>> {
+        self.open(BufReader::new(File::open(path)?))
+    }
+}
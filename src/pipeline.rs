@@ -0,0 +1,127 @@
+use std::io;
+use std::mem;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+use crate::ctype::Decompressor;
+
+/// The outcome of preparing a data block ahead of time: the outer result is
+/// from reading and checking the block's payload, and the inner one is from
+/// decompressing it.
+pub(crate) type Prefetched = io::Result<io::Result<Vec<u8>>>;
+
+struct Job {
+    decompressor: Decompressor,
+    data: Vec<u8>,
+    uncompressed_size: usize,
+}
+
+struct Done {
+    decompressor: Decompressor,
+    result: io::Result<Vec<u8>>,
+}
+
+/// Decompresses one data block at a time on a background thread, so that
+/// the next block of a folder can be decompressed while the caller is still
+/// consuming the current one (see `ReadOptions::pipeline`).
+///
+/// Since each block's decompression depends on the blocks before it, the
+/// folder's decompressor is handed to the worker thread along with the
+/// block, and handed back along with the result.
+pub(crate) struct Pipeline {
+    worker: Option<(Sender<Job>, Receiver<Done>)>,
+    /// The index of the block being decompressed on the worker thread, if
+    /// any (in which case the folder's decompressor is out on loan).
+    in_flight: Option<usize>,
+    /// The index and outcome of the block that was prepared ahead.
+    ready: Option<(usize, Prefetched)>,
+}
+
+impl Pipeline {
+    pub(crate) fn new() -> Pipeline {
+        Pipeline { worker: None, in_flight: None, ready: None }
+    }
+
+    /// Starts decompressing the given block's (already read and checked)
+    /// payload on the worker thread, spawning the thread if needed.
+    pub(crate) fn start(
+        &mut self,
+        index: usize,
+        decompressor: &mut Decompressor,
+        data: Vec<u8>,
+        uncompressed_size: usize,
+    ) {
+        debug_assert!(self.in_flight.is_none() && self.ready.is_none());
+        let (jobs, _) = self.worker.get_or_insert_with(spawn_worker);
+        let lent = mem::replace(decompressor, Decompressor::Uncompressed);
+        let job = Job { decompressor: lent, data, uncompressed_size };
+        match jobs.send(job) {
+            Ok(()) => self.in_flight = Some(index),
+            Err(mpsc::SendError(mut job)) => {
+                // The worker thread is gone, so decompress the block here.
+                let result =
+                    job.decompressor.decompress(job.data, uncompressed_size);
+                *decompressor = job.decompressor;
+                self.ready = Some((index, Ok(result)));
+            }
+        }
+    }
+
+    /// Records that the given block couldn't be prepared ahead, so that the
+    /// error is reported if and when the block is actually needed.
+    pub(crate) fn fail(&mut self, index: usize, error: io::Error) {
+        debug_assert!(self.in_flight.is_none() && self.ready.is_none());
+        self.ready = Some((index, Err(error)));
+    }
+
+    /// Takes the outcome of preparing the given block ahead, if that's the
+    /// block that was prepared, waiting for the worker thread to finish
+    /// with it if necessary.
+    pub(crate) fn take(
+        &mut self,
+        index: usize,
+        decompressor: &mut Decompressor,
+    ) -> Option<Prefetched> {
+        self.settle(decompressor);
+        match self.ready.take() {
+            Some((ready_index, prefetched)) if ready_index == index => {
+                Some(prefetched)
+            }
+            _ => None,
+        }
+    }
+
+    /// Throws away any block prepared ahead (e.g. because the decompressor
+    /// is about to be reset), getting the decompressor back first.
+    pub(crate) fn discard(&mut self, decompressor: &mut Decompressor) {
+        self.settle(decompressor);
+        self.ready = None;
+    }
+
+    /// Waits for the block on the worker thread (if any) to finish, and
+    /// puts the decompressor back.
+    fn settle(&mut self, decompressor: &mut Decompressor) {
+        if let Some(index) = self.in_flight.take() {
+            let (_, done) = self.worker.as_ref().unwrap();
+            let done = done.recv().expect("block decompression thread died");
+            *decompressor = done.decompressor;
+            self.ready = Some((index, Ok(done.result)));
+        }
+    }
+}
+
+fn spawn_worker() -> (Sender<Job>, Receiver<Done>) {
+    let (job_sender, job_receiver) = mpsc::channel::<Job>();
+    let (done_sender, done_receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for mut job in job_receiver {
+            let result =
+                job.decompressor.decompress(job.data, job.uncompressed_size);
+            let done = Done { decompressor: job.decompressor, result };
+            if done_sender.send(done).is_err() {
+                break;
+            }
+        }
+    });
+    (job_sender, done_receiver)
+}
@@ -0,0 +1,655 @@
+use std::fs::{self, File};
+use std::io::{self, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::slice;
+
+use crate::builder::{split_extension, CabinetBuilder};
+use crate::ctype::CompressionType;
+
+/// The approximate per-file overhead in a cabinet, not counting the file name.
+pub(crate) const FILE_ENTRY_OVERHEAD: u64 = 16;
+
+/// A plan for creating one or more cabinet files out of files on disk.
+///
+/// A plan can be assembled by hand, or parsed from a manifest with
+/// [`CabinetPlan::from_manifest`].  Each cabinet in the plan can then be
+/// written out with [`PlannedCabinet::write`].
+#[derive(Clone, Debug, Default)]
+pub struct CabinetPlan {
+    pub(crate) cabinets: Vec<PlannedCabinet>,
+}
+
+/// A single cabinet file within a [`CabinetPlan`].
+#[derive(Clone, Debug)]
+pub struct PlannedCabinet {
+    name: Option<String>,
+    pub(crate) folders: Vec<PlannedFolder>,
+    set_index: u16,
+    prev_cabinet: Option<String>,
+    next_cabinet: Option<String>,
+}
+
+/// A folder within a [`PlannedCabinet`].
+#[derive(Clone, Debug)]
+pub struct PlannedFolder {
+    compression_type: CompressionType,
+    files: Vec<PlannedFile>,
+}
+
+/// A file within a [`PlannedFolder`], mapping a source path on disk to the
+/// name it will be stored under in the cabinet.
+#[derive(Clone, Debug)]
+pub struct PlannedFile {
+    source: PathBuf,
+    name: String,
+}
+
+/// An iterator over the cabinets in a [`CabinetPlan`].
+#[derive(Clone)]
+pub struct PlannedCabinets<'a> {
+    iter: slice::Iter<'a, PlannedCabinet>,
+}
+
+impl CabinetPlan {
+    /// Creates a new, empty plan.
+    pub fn new() -> CabinetPlan {
+        CabinetPlan { cabinets: Vec::new() }
+    }
+
+    /// Parses a plan from the text of a manifest.
+    ///
+    /// A manifest is a line-oriented text format loosely modeled on makecab's
+    /// directive files.  Blank lines and lines starting with `#` or `;` are
+    /// ignored.  Each remaining line is one of:
+    ///
+    /// * `.cabinet NAME` starts a new cabinet, to be written to `NAME`.
+    /// * `.folder COMPRESSION` starts a new folder within the current cabinet,
+    ///   using the given compression type (`none`, `mszip`, `lzx:N`, or any
+    ///   other form accepted by [`CompressionType`]'s `FromStr` impl).
+    /// * `.maxsize SIZE` limits each cabinet from then on to `SIZE` bytes of
+    ///   file data (counting a small overhead per file), or lifts the limit
+    ///   if `SIZE` is zero.  When the next file would take the current
+    ///   cabinet over the limit, a new cabinet is started, with a new folder
+    ///   using the same compression type.
+    /// * `SOURCE [DEST]` adds the file at path `SOURCE` to the current folder,
+    ///   stored under the name `DEST` (defaulting to the file name of
+    ///   `SOURCE`).
+    ///
+    /// Tokens containing whitespace can be enclosed in double quotes.  Files
+    /// listed before any `.folder` directive are placed in an MSZIP folder,
+    /// and folders listed before any `.cabinet` directive are placed in an
+    /// unnamed cabinet.
+    ///
+    /// The cabinets that a `.cabinet` directive is split into under
+    /// `.maxsize` form a cabinet set, each recording the names of the
+    /// previous and next ones in the set.  A `*` in the `NAME` is replaced
+    /// by each cabinet's number within the set (starting from 1); otherwise,
+    /// the first cabinet is named `NAME`, and the number is inserted before
+    /// the extension for the rest (so `disk.cab` is followed by `disk2.cab`).
+    /// An unnamed cabinet can't be split.  Splitting requires reading the
+    /// size of each source file from disk; use
+    /// [`from_manifest_with`](CabinetPlan::from_manifest_with) to supply the
+    /// sizes some other way.
+    pub fn from_manifest(text: &str) -> io::Result<CabinetPlan> {
+        CabinetPlan::from_manifest_with(text, CompressionType::MsZip, |path| {
+            Ok(fs::metadata(path)?.len())
+        })
+    }
+
+    /// Parses a plan from the text of a manifest, as with
+    /// [`from_manifest`](CabinetPlan::from_manifest), but placing files
+    /// listed before any `.folder` directive in a folder using
+    /// `default_ctype`, and using `size_of` to determine the size of each
+    /// source file when `.maxsize` is in effect.
+    pub fn from_manifest_with<F>(
+        text: &str,
+        default_ctype: CompressionType,
+        size_of: F,
+    ) -> io::Result<CabinetPlan>
+    where
+        F: FnMut(&Path) -> io::Result<u64>,
+    {
+        let mut parser = ManifestParser::new(default_ctype, size_of);
+        for (index, line) in text.lines().enumerate() {
+            parser.parse_line(index + 1, line)?;
+        }
+        parser.finish_set();
+        Ok(parser.plan)
+    }
+
+    /// Adds a new cabinet to the plan, to be written to a file with the given
+    /// name.
+    pub fn add_cabinet<S: Into<String>>(
+        &mut self,
+        name: S,
+    ) -> &mut PlannedCabinet {
+        self.cabinets.push(PlannedCabinet::new(Some(name.into())));
+        self.cabinets.last_mut().unwrap()
+    }
+
+    /// Returns an iterator over the cabinets in this plan.
+    pub fn cabinets(&self) -> PlannedCabinets<'_> {
+        PlannedCabinets { iter: self.cabinets.iter() }
+    }
+
+    /// Resolves all relative source paths in the plan against the given base
+    /// directory (typically the directory containing the manifest).
+    pub fn resolve_sources<P: AsRef<Path>>(&mut self, base: P) {
+        let base = base.as_ref();
+        for cabinet in self.cabinets.iter_mut() {
+            for folder in cabinet.folders.iter_mut() {
+                for file in folder.files.iter_mut() {
+                    if file.source.is_relative() {
+                        file.source = base.join(&file.source);
+                    }
+                }
+            }
+        }
+    }
+
+    fn current_cabinet(&mut self) -> &mut PlannedCabinet {
+        if self.cabinets.is_empty() {
+            self.cabinets.push(PlannedCabinet::new(None));
+        }
+        self.cabinets.last_mut().unwrap()
+    }
+}
+
+struct ManifestParser<F> {
+    plan: CabinetPlan,
+    default_ctype: CompressionType,
+    size_of: F,
+    /// The name given by the current `.cabinet` directive, if any.
+    template: Option<String>,
+    /// The index within the plan of the first cabinet split from the
+    /// current `.cabinet` directive.
+    set_start: usize,
+    max_size: u64,
+    cabinet_size: u64,
+}
+
+impl<F> ManifestParser<F>
+where
+    F: FnMut(&Path) -> io::Result<u64>,
+{
+    fn new(default_ctype: CompressionType, size_of: F) -> ManifestParser<F> {
+        ManifestParser {
+            plan: CabinetPlan::new(),
+            default_ctype,
+            size_of,
+            template: None,
+            set_start: 0,
+            max_size: 0,
+            cabinet_size: 0,
+        }
+    }
+
+    fn parse_line(
+        &mut self,
+        line_number: usize,
+        line: &str,
+    ) -> io::Result<()> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            return Ok(());
+        }
+        let tokens = match tokenize_line(line) {
+            Some(tokens) => tokens,
+            None => invalid_data!(
+                "Unterminated quote on manifest line {}",
+                line_number
+            ),
+        };
+        match tokens[0].as_str() {
+            ".cabinet" => {
+                if tokens.len() != 2 {
+                    invalid_data!(
+                        "Expected `.cabinet NAME` on manifest line {}",
+                        line_number
+                    );
+                }
+                self.finish_set();
+                let template = tokens[1].clone();
+                let name = part_name(&template, 1);
+                self.plan.cabinets.push(PlannedCabinet::new(Some(name)));
+                self.template = Some(template);
+                self.set_start = self.plan.cabinets.len() - 1;
+                self.cabinet_size = 0;
+            }
+            ".folder" => {
+                if tokens.len() != 2 {
+                    invalid_data!(
+                        "Expected `.folder COMPRESSION` on manifest line {}",
+                        line_number
+                    );
+                }
+                let ctype = match tokens[1].parse::<CompressionType>() {
+                    Ok(ctype) => ctype,
+                    Err(_) => invalid_data!(
+                        "Invalid compression type {:?} on manifest line {}",
+                        tokens[1],
+                        line_number
+                    ),
+                };
+                self.plan.current_cabinet().add_folder(ctype);
+            }
+            ".maxsize" => {
+                let size = match tokens.get(1).map(|size| size.parse()) {
+                    Some(Ok(size)) if tokens.len() == 2 => size,
+                    _ => invalid_data!(
+                        "Expected `.maxsize SIZE` on manifest line {}",
+                        line_number
+                    ),
+                };
+                self.max_size = size;
+            }
+            directive if directive.starts_with('.') => {
+                invalid_data!(
+                    "Unknown directive {:?} on manifest line {}",
+                    directive,
+                    line_number
+                );
+            }
+            _ => {
+                if tokens.len() > 2 {
+                    invalid_data!(
+                        "Too many fields on manifest line {}",
+                        line_number
+                    );
+                }
+                let source = PathBuf::from(&tokens[0]);
+                let name = match tokens.get(1) {
+                    Some(name) => name.clone(),
+                    None => match source.file_name() {
+                        Some(name) => name.to_string_lossy().into_owned(),
+                        None => invalid_data!(
+                            "Cannot determine file name for {:?} on \
+                             manifest line {}",
+                            tokens[0],
+                            line_number
+                        ),
+                    },
+                };
+                if self.max_size > 0 {
+                    let size = (self.size_of)(&source)?
+                        + FILE_ENTRY_OVERHEAD
+                        + name.len() as u64;
+                    if self.cabinet_size > 0
+                        && self.cabinet_size + size > self.max_size
+                    {
+                        self.split_cabinet(line_number)?;
+                    }
+                    self.cabinet_size += size;
+                }
+                let default_ctype = self.default_ctype;
+                self.plan
+                    .current_cabinet()
+                    .current_folder(default_ctype)
+                    .add_file(source, name);
+            }
+        }
+        Ok(())
+    }
+
+    /// Starts a new cabinet in the current set, continuing the current
+    /// folder's compression type.
+    fn split_cabinet(&mut self, line_number: usize) -> io::Result<()> {
+        let template = match self.template {
+            Some(ref template) => template,
+            None => invalid_data!(
+                "Cannot split an unnamed cabinet on manifest line {} (add a \
+                 `.cabinet NAME` directive)",
+                line_number
+            ),
+        };
+        let part = self.plan.cabinets.len() - self.set_start + 1;
+        let name = part_name(template, part);
+        let ctype = self
+            .plan
+            .current_cabinet()
+            .folders
+            .last()
+            .map_or(self.default_ctype, |folder| folder.compression_type);
+        let mut cabinet = PlannedCabinet::new(Some(name));
+        cabinet.add_folder(ctype);
+        self.plan.cabinets.push(cabinet);
+        self.cabinet_size = 0;
+        Ok(())
+    }
+
+    /// Links together the cabinets split from the current `.cabinet`
+    /// directive, if there is more than one.
+    fn finish_set(&mut self) {
+        link_cabinets(&mut self.plan.cabinets[self.set_start..]);
+    }
+}
+
+/// Returns the name of the given (one-based) part of a cabinet set, for a
+/// `.cabinet` directive with the given name.
+fn part_name(template: &str, part: usize) -> String {
+    if template.contains('*') {
+        template.replace('*', &part.to_string())
+    } else if part == 1 {
+        template.to_string()
+    } else {
+        let (stem, extension) = split_extension(template);
+        format!("{}{}{}", stem, part, extension)
+    }
+}
+
+/// Records the previous and next cabinets of each of the given cabinets, as
+/// consecutive members of one cabinet set.  Does nothing if there is only
+/// one cabinet, or if any of them is unnamed.
+pub(crate) fn link_cabinets(cabinets: &mut [PlannedCabinet]) {
+    if cabinets.len() < 2 || cabinets.iter().any(|cab| cab.name.is_none()) {
+        return;
+    }
+    let names: Vec<String> = cabinets
+        .iter()
+        .map(|cabinet| {
+            let name = cabinet.name.as_deref().unwrap_or_default();
+            match Path::new(name).file_name() {
+                Some(file_name) => file_name.to_string_lossy().into_owned(),
+                None => name.to_string(),
+            }
+        })
+        .collect();
+    for (index, cabinet) in cabinets.iter_mut().enumerate() {
+        cabinet.set_index = index as u16;
+        cabinet.prev_cabinet =
+            index.checked_sub(1).map(|prev| names[prev].clone());
+        cabinet.next_cabinet = names.get(index + 1).cloned();
+    }
+}
+
+impl PlannedCabinet {
+    fn new(name: Option<String>) -> PlannedCabinet {
+        PlannedCabinet {
+            name,
+            folders: Vec::new(),
+            set_index: 0,
+            prev_cabinet: None,
+            next_cabinet: None,
+        }
+    }
+
+    /// Returns the file name this cabinet should be written to, or `None` if
+    /// the plan did not specify one.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns the file name of the previous cabinet in this cabinet's set,
+    /// if any.
+    pub fn prev_cabinet_name(&self) -> Option<&str> {
+        self.prev_cabinet.as_deref()
+    }
+
+    /// Returns the file name of the next cabinet in this cabinet's set, if
+    /// any.
+    pub fn next_cabinet_name(&self) -> Option<&str> {
+        self.next_cabinet.as_deref()
+    }
+
+    /// Returns the folders in this cabinet.
+    pub fn folders(&self) -> &[PlannedFolder] {
+        &self.folders
+    }
+
+    /// Adds a new folder to the cabinet, using the given compression type.
+    pub fn add_folder(
+        &mut self,
+        ctype: CompressionType,
+    ) -> &mut PlannedFolder {
+        self.folders
+            .push(PlannedFolder { compression_type: ctype, files: vec![] });
+        self.folders.last_mut().unwrap()
+    }
+
+    /// Creates a `CabinetBuilder` for this cabinet.  File datetimes are taken
+    /// from the modification times of the source files, where available.
+    pub fn to_builder(&self) -> CabinetBuilder {
+        let mut builder = CabinetBuilder::new();
+        builder.set_cabinet_set(0, self.set_index);
+        if let Some(ref prev) = self.prev_cabinet {
+            builder.set_prev_cabinet(prev.as_str(), "");
+        }
+        if let Some(ref next) = self.next_cabinet {
+            builder.set_next_cabinet(next.as_str(), "");
+        }
+        for folder in self.folders.iter() {
+            let folder_builder = builder.add_folder(folder.compression_type);
+            for file in folder.files.iter() {
+                let file_builder = folder_builder.add_file(file.name.as_str());
+                if let Ok(modified) =
+                    fs::metadata(&file.source).and_then(|meta| meta.modified())
+                {
+                    file_builder.set_system_time(modified);
+                }
+            }
+        }
+        builder
+    }
+
+    /// Writes this cabinet into the given writer, reading each file's data
+    /// from its source path, and returns the underlying writer.
+    pub fn write<W: Write + Seek>(&self, writer: W) -> io::Result<W> {
+        let mut sources =
+            self.folders.iter().flat_map(|folder| folder.files.iter());
+        let mut cab_writer = self.to_builder().build(writer)?;
+        while let Some(mut file_writer) = cab_writer.next_file()? {
+            let file = sources.next().unwrap();
+            let mut reader = File::open(&file.source)?;
+            io::copy(&mut reader, &mut file_writer)?;
+        }
+        cab_writer.finish()
+    }
+
+    fn current_folder(
+        &mut self,
+        default_ctype: CompressionType,
+    ) -> &mut PlannedFolder {
+        if self.folders.is_empty() {
+            self.add_folder(default_ctype);
+        }
+        self.folders.last_mut().unwrap()
+    }
+}
+
+impl PlannedFolder {
+    /// Returns the scheme that will be used to compress this folder's data.
+    pub fn compression_type(&self) -> CompressionType {
+        self.compression_type
+    }
+
+    /// Returns the files in this folder.
+    pub fn files(&self) -> &[PlannedFile] {
+        &self.files
+    }
+
+    /// Adds the file at the given source path to this folder, to be stored in
+    /// the cabinet under the given name.
+    pub fn add_file<P: Into<PathBuf>, S: Into<String>>(
+        &mut self,
+        source: P,
+        name: S,
+    ) -> &mut PlannedFile {
+        self.files
+            .push(PlannedFile { source: source.into(), name: name.into() });
+        self.files.last_mut().unwrap()
+    }
+}
+
+impl PlannedFile {
+    /// Returns the path of the file on disk.
+    pub fn source(&self) -> &Path {
+        &self.source
+    }
+
+    /// Returns the name the file will be stored under in the cabinet.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl<'a> Iterator for PlannedCabinets<'a> {
+    type Item = &'a PlannedCabinet;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl ExactSizeIterator for PlannedCabinets<'_> {}
+
+/// Splits a manifest line into whitespace-separated tokens, honoring double
+/// quotes.  Returns `None` if a quote is left unterminated.
+pub(crate) fn tokenize_line(line: &str) -> Option<Vec<String>> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|chr| chr.is_whitespace()).is_some() {}
+        let mut token = String::new();
+        match chars.peek() {
+            None => return Some(tokens),
+            Some('"') => {
+                chars.next();
+                loop {
+                    match chars.next() {
+                        None => return None,
+                        Some('"') => break,
+                        Some(chr) => token.push(chr),
+                    }
+                }
+            }
+            Some(_) => {
+                while let Some(chr) = chars.next_if(|chr| !chr.is_whitespace())
+                {
+                    token.push(chr);
+                }
+            }
+        }
+        tokens.push(token);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::CabinetPlan;
+    use crate::ctype::CompressionType;
+
+    #[test]
+    fn parse_manifest() {
+        let manifest = "# A comment\n\
+                        .cabinet first.cab\n\
+                        a.txt\n\
+                        .folder none\n\
+                        dir/b.txt \"docs\\b file.txt\"\n\
+                        \n\
+                        .cabinet second.cab\n\
+                        .folder lzx:16\n\
+                        c.bin data\\c.bin\n";
+        let plan = CabinetPlan::from_manifest(manifest).unwrap();
+        let cabinets: Vec<_> = plan.cabinets().collect();
+        assert_eq!(cabinets.len(), 2);
+
+        assert_eq!(cabinets[0].name(), Some("first.cab"));
+        let folders = cabinets[0].folders();
+        assert_eq!(folders.len(), 2);
+        assert_eq!(folders[0].compression_type(), CompressionType::MsZip);
+        assert_eq!(folders[0].files()[0].source(), Path::new("a.txt"));
+        assert_eq!(folders[0].files()[0].name(), "a.txt");
+        assert_eq!(folders[1].compression_type(), CompressionType::None);
+        assert_eq!(folders[1].files()[0].source(), Path::new("dir/b.txt"));
+        assert_eq!(folders[1].files()[0].name(), "docs\\b file.txt");
+
+        assert_eq!(cabinets[1].name(), Some("second.cab"));
+        let folders = cabinets[1].folders();
+        assert_eq!(
+            folders[0].compression_type(),
+            CompressionType::Lzx(lzxd::WindowSize::KB64)
+        );
+        assert_eq!(folders[0].files()[0].name(), "data\\c.bin");
+    }
+
+    #[test]
+    fn parse_invalid_manifests() {
+        assert!(CabinetPlan::from_manifest(".folder zstd").is_err());
+        assert!(CabinetPlan::from_manifest(".bogus").is_err());
+        assert!(CabinetPlan::from_manifest("a b c").is_err());
+        assert!(CabinetPlan::from_manifest("\"a.txt").is_err());
+    }
+
+    #[test]
+    fn split_manifest_by_max_size() {
+        let manifest = ".cabinet one.cab\n\
+                        a\n\
+                        .cabinet disk.cab\n\
+                        .maxsize 1000\n\
+                        .folder none\n\
+                        b\nc\nd\n\
+                        .cabinet part*.cab\n\
+                        e\nf\ng\n";
+        let plan = CabinetPlan::from_manifest_with(
+            manifest,
+            CompressionType::None,
+            |_| Ok(400),
+        )
+        .unwrap();
+        let cabinets: Vec<_> = plan
+            .cabinets()
+            .map(|cabinet| {
+                let files: Vec<_> = cabinet
+                    .folders()
+                    .iter()
+                    .flat_map(|folder| folder.files())
+                    .map(|file| file.name())
+                    .collect();
+                (
+                    cabinet.name().unwrap(),
+                    cabinet.prev_cabinet_name(),
+                    cabinet.next_cabinet_name(),
+                    files,
+                )
+            })
+            .collect();
+        assert_eq!(
+            cabinets,
+            vec![
+                ("one.cab", None, None, vec!["a"]),
+                ("disk.cab", None, Some("disk2.cab"), vec!["b", "c"]),
+                ("disk2.cab", Some("disk.cab"), None, vec!["d"]),
+                ("part1.cab", None, Some("part2.cab"), vec!["e", "f"]),
+                ("part2.cab", Some("part1.cab"), None, vec!["g"]),
+            ]
+        );
+        let folders = plan.cabinets().nth(2).unwrap().folders();
+        assert_eq!(folders[0].compression_type(), CompressionType::None);
+        let folders = plan.cabinets().nth(3).unwrap().folders();
+        assert_eq!(folders[0].compression_type(), CompressionType::None);
+
+        // An unnamed cabinet can't be split:
+        let sizes = |_: &Path| Ok(600);
+        let ctype = CompressionType::MsZip;
+        assert!(CabinetPlan::from_manifest_with(
+            ".maxsize 1000\na\nb",
+            ctype,
+            sizes
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn unnamed_default_cabinet() {
+        let plan = CabinetPlan::from_manifest("a.txt\n").unwrap();
+        let cabinet = plan.cabinets().next().unwrap();
+        assert_eq!(cabinet.name(), None);
+        assert_eq!(cabinet.folders().len(), 1);
+    }
+}
@@ -0,0 +1,168 @@
+use std::cell::RefCell;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::rc::Rc;
+
+use crate::cabinet::{check_extractable, CabinetInner, ReadSeek};
+use crate::file::FileEntry;
+use crate::folder::{BlockSource, FolderReader};
+use crate::options::SizeMismatch;
+
+/// An iterator over readers for every file in a cabinet, as returned by
+/// [`Cabinet::file_reader_iter`](crate::Cabinet::file_reader_iter).
+///
+/// Files are yielded folder by folder, in the order their data is stored
+/// within each folder, and all the readers for a folder share a single
+/// decompressor.  So as long as each file is read through before moving on
+/// to the next one, the whole cabinet is decompressed exactly once (whereas
+/// opening each file with [`Cabinet::read_file`](crate::Cabinet::read_file)
+/// decompresses each folder from its start again for every file).  Reading
+/// the files in some other order still works, but may mean decompressing
+/// parts of a folder more than once.
+pub struct FileReaders<'a, R> {
+    inner: &'a CabinetInner<dyn ReadSeek + 'a>,
+    folder_index: usize,
+    next_folder: usize,
+    /// The current folder's files, in the order of their data.
+    files: Vec<&'a FileEntry>,
+    next_file: usize,
+    folder: Option<Rc<RefCell<FolderReader<'a, R>>>>,
+}
+
+/// A reader for one file's decompressed data, as yielded by
+/// [`FileReaders`], which shares its folder's decompressor with the readers
+/// for the other files in that folder.
+pub struct SharedFileReader<'a, R> {
+    entry: &'a FileEntry,
+    folder: Rc<RefCell<FolderReader<'a, R>>>,
+    file_start_in_folder: u64,
+    offset: u64,
+    size: u64,
+}
+
+impl<'a, R: Read + Seek> FileReaders<'a, R> {
+    pub(crate) fn new(
+        inner: &'a CabinetInner<dyn ReadSeek + 'a>,
+    ) -> FileReaders<'a, R> {
+        FileReaders {
+            inner,
+            folder_index: 0,
+            next_folder: 0,
+            files: Vec::new(),
+            next_file: 0,
+            folder: None,
+        }
+    }
+
+    /// Returns the reader for the current folder, creating it if needed.
+    fn folder_reader(
+        &mut self,
+    ) -> io::Result<Rc<RefCell<FolderReader<'a, R>>>> {
+        if let Some(ref folder) = self.folder {
+            return Ok(folder.clone());
+        }
+        let source = BlockSource::Cabinet(self.inner);
+        let folder = FolderReader::new(source, self.folder_index)?;
+        let folder = Rc::new(RefCell::new(folder));
+        self.folder = Some(folder.clone());
+        Ok(folder)
+    }
+}
+
+impl<'a, R: Read + Seek> Iterator for FileReaders<'a, R> {
+    type Item = io::Result<SharedFileReader<'a, R>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.next_file >= self.files.len() {
+            let folder = self.inner.folders.get(self.next_folder)?;
+            self.folder_index = self.next_folder;
+            self.next_folder += 1;
+            self.files = folder.files.iter().collect();
+            self.files.sort_by_key(|file| file.uncompressed_offset);
+            self.next_file = 0;
+            self.folder = None;
+        }
+        let entry = self.files[self.next_file];
+        self.next_file += 1;
+        if let Err(error) = check_extractable(self.inner, entry) {
+            return Some(Err(error));
+        }
+        let folder = match self.folder_reader() {
+            Ok(folder) => folder,
+            Err(error) => return Some(Err(error)),
+        };
+        Some(Ok(SharedFileReader {
+            entry,
+            folder,
+            file_start_in_folder: entry.uncompressed_offset as u64,
+            offset: 0,
+            size: entry.uncompressed_size() as u64,
+        }))
+    }
+}
+
+impl<'a, R> SharedFileReader<'a, R> {
+    /// Returns the entry for the file being read.
+    pub fn entry(&self) -> &'a FileEntry {
+        self.entry
+    }
+
+    /// Returns the total (uncompressed) size of the file, in bytes.
+    pub fn len(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns true if the file is empty.
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+}
+
+impl<'a, R: Read + Seek> Read for SharedFileReader<'a, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.size - self.offset;
+        if buf.is_empty() || remaining == 0 {
+            return Ok(0);
+        }
+        let max_bytes = buf.len().min(remaining as usize);
+        let mut folder = self.folder.borrow_mut();
+        let position = self.file_start_in_folder + self.offset;
+        let mut bytes_read = 0;
+        if folder.uncompressed_offset() == position
+            || folder.try_seek_to_uncompressed_offset(position)?
+        {
+            bytes_read = folder.read(&mut buf[..max_bytes])?;
+        }
+        if bytes_read == 0 {
+            // The folder's data has run out before the end of the file.
+            match folder.size_mismatch() {
+                SizeMismatch::Error => return Err(folder.past_end_error()),
+                SizeMismatch::Truncate => return Ok(0),
+                SizeMismatch::ZeroFill => {
+                    buf[..max_bytes].fill(0);
+                    bytes_read = max_bytes;
+                }
+            }
+        }
+        self.offset += bytes_read as u64;
+        Ok(bytes_read)
+    }
+}
+
+impl<'a, R: Read + Seek> Seek for SharedFileReader<'a, R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_offset = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::Current(delta) => self.offset as i64 + delta,
+            SeekFrom::End(delta) => self.size as i64 + delta,
+        };
+        if new_offset < 0 || (new_offset as u64) > self.size {
+            invalid_input!(
+                "Cannot seek to {}, file length is {}",
+                new_offset,
+                self.size
+            );
+        }
+        self.offset = new_offset as u64;
+        Ok(self.offset)
+    }
+}
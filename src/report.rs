@@ -0,0 +1,314 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Read, Seek};
+
+use crate::cabinet::Cabinet;
+use crate::ctype::CompressionType;
+use crate::mszip::MsZipCompressor;
+
+const MAX_UNCOMPRESSED_BLOCK_SIZE: usize = 0x8000;
+
+/// A summary of the folders written by a
+/// [`CabinetWriter`](crate::CabinetWriter), returned by
+/// [`CabinetWriter::finish_with_report`](crate::CabinetWriter::finish_with_report).
+#[derive(Clone, Debug, Default)]
+pub struct CabinetReport {
+    pub(crate) folders: Vec<FolderReport>,
+}
+
+/// The sizes of a single folder within a [`CabinetReport`].
+#[derive(Clone, Debug)]
+pub struct FolderReport {
+    pub(crate) index: usize,
+    pub(crate) compression_type: CompressionType,
+    pub(crate) uncompressed_size: u64,
+    pub(crate) compressed_size: u64,
+    pub(crate) num_data_blocks: u16,
+    pub(crate) stored_blocks: u16,
+    pub(crate) files: Vec<FileSpan>,
+}
+
+/// Where a single file's data lies within its folder's uncompressed data.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FileSpan {
+    pub(crate) name: String,
+    pub(crate) offset: u32,
+    pub(crate) size: u32,
+}
+
+/// A suggestion for regrouping the files of a folder to improve compression,
+/// returned by [`Cabinet::suggest_groupings`](crate::Cabinet::suggest_groupings).
+#[derive(Clone, Debug)]
+pub struct GroupingSuggestion {
+    folder_index: usize,
+    current_size: u64,
+    suggested_size: u64,
+    extensions: Vec<String>,
+}
+
+impl CabinetReport {
+    /// Returns the reports for each folder written, in order.  When resuming
+    /// or appending to a cabinet, only the folders written by this writer are
+    /// included.
+    pub fn folders(&self) -> &[FolderReport] {
+        &self.folders
+    }
+
+    /// Returns the total uncompressed size of all the folders written.
+    pub fn total_uncompressed_size(&self) -> u64 {
+        self.folders.iter().map(|folder| folder.uncompressed_size).sum()
+    }
+
+    /// Returns the total compressed size (including data block headers) of
+    /// all the folders written.
+    pub fn total_compressed_size(&self) -> u64 {
+        self.folders.iter().map(|folder| folder.compressed_size).sum()
+    }
+}
+
+impl FolderReport {
+    /// Returns the index of this folder within the cabinet.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the compression type used for this folder.  This is
+    /// [`CompressionType::None`] for folders that
+    /// [`FolderBuilder::set_store_if_incompressible`](crate::FolderBuilder::set_store_if_incompressible)
+    /// switched to being stored uncompressed.
+    pub fn compression_type(&self) -> CompressionType {
+        self.compression_type
+    }
+
+    /// Returns the total size of the folder's uncompressed data, in bytes.
+    pub fn uncompressed_size(&self) -> u64 {
+        self.uncompressed_size
+    }
+
+    /// Returns the total size of the folder's data blocks (including their
+    /// headers), in bytes.
+    pub fn compressed_size(&self) -> u64 {
+        self.compressed_size
+    }
+
+    /// Returns the number of data blocks in this folder.
+    pub fn num_data_blocks(&self) -> u16 {
+        self.num_data_blocks
+    }
+
+    /// Returns the number of data blocks in this (MSZIP) folder whose data
+    /// didn't compress, and so was stored uncompressed within the MSZIP
+    /// stream instead.  This is always zero for other compression types.
+    pub fn stored_blocks(&self) -> u16 {
+        self.stored_blocks
+    }
+
+    /// Returns the compressed size divided by the uncompressed size, or
+    /// `None` if the folder is empty.
+    pub fn ratio(&self) -> Option<f64> {
+        if self.uncompressed_size == 0 {
+            None
+        } else {
+            Some(self.compressed_size as f64 / self.uncompressed_size as f64)
+        }
+    }
+
+    /// Returns where each file in this folder lies within the folder's
+    /// uncompressed data.
+    pub fn files(&self) -> &[FileSpan] {
+        &self.files
+    }
+}
+
+impl FileSpan {
+    /// Returns the name of the file.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the offset of the file's data within its folder's
+    /// uncompressed data.
+    pub fn offset(&self) -> u32 {
+        self.offset
+    }
+
+    /// Returns the size of the file, in bytes.
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+}
+
+impl GroupingSuggestion {
+    /// Returns the index of the folder that this suggestion applies to.
+    pub fn folder_index(&self) -> usize {
+        self.folder_index
+    }
+
+    /// Returns the estimated compressed size of the folder as it is now.
+    pub fn current_size(&self) -> u64 {
+        self.current_size
+    }
+
+    /// Returns the estimated total compressed size of the folders that the
+    /// folder would be split into.
+    pub fn suggested_size(&self) -> u64 {
+        self.suggested_size
+    }
+
+    /// Returns the file extensions (lowercased, without the dot, and empty
+    /// for files with no extension) that the folder would be split by, one
+    /// per new folder.
+    pub fn extensions(&self) -> &[String] {
+        &self.extensions
+    }
+
+    /// Returns the estimated size reduction, as a percentage of the current
+    /// size.
+    pub fn improvement_percent(&self) -> f64 {
+        let saved = self.current_size - self.suggested_size;
+        100.0 * saved as f64 / self.current_size as f64
+    }
+}
+
+impl fmt::Display for GroupingSuggestion {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "folder {} would compress {:.0}% better split by extension",
+            self.folder_index,
+            self.improvement_percent()
+        )
+    }
+}
+
+/// Estimates the MSZIP-compressed size of a stream of data.
+struct SizeEstimator {
+    compressor: MsZipCompressor,
+    buffer: Vec<u8>,
+    total: u64,
+}
+
+impl SizeEstimator {
+    fn new() -> SizeEstimator {
+        SizeEstimator {
+            compressor: MsZipCompressor::new(),
+            buffer: Vec::with_capacity(MAX_UNCOMPRESSED_BLOCK_SIZE),
+            total: 0,
+        }
+    }
+
+    fn write(&mut self, mut data: &[u8]) -> io::Result<()> {
+        while !data.is_empty() {
+            if self.buffer.len() == MAX_UNCOMPRESSED_BLOCK_SIZE {
+                self.compress_block(false)?;
+            }
+            let room = MAX_UNCOMPRESSED_BLOCK_SIZE - self.buffer.len();
+            let (head, tail) = data.split_at(data.len().min(room));
+            self.buffer.extend_from_slice(head);
+            data = tail;
+        }
+        Ok(())
+    }
+
+    fn compress_block(&mut self, is_last_block: bool) -> io::Result<()> {
+        let compressed =
+            self.compressor.compress_block(&self.buffer, is_last_block)?;
+        // Each data block also has an 8-byte header:
+        self.total += 8 + compressed.len() as u64;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<u64> {
+        if !self.buffer.is_empty() {
+            self.compress_block(true)?;
+        }
+        Ok(self.total)
+    }
+}
+
+/// Returns the lowercased extension of a file name within a cabinet.
+fn extension(name: &str) -> String {
+    let base = name.rsplit('\\').next().unwrap_or(name);
+    match base.rfind('.') {
+        Some(index) if index > 0 => base[index + 1..].to_lowercase(),
+        _ => String::new(),
+    }
+}
+
+pub(crate) fn suggest_groupings<R: Read + Seek>(
+    cabinet: &mut Cabinet<R>,
+) -> io::Result<Vec<GroupingSuggestion>> {
+    let folders: Vec<Vec<(u32, u32, String)>> = cabinet
+        .folder_entries()
+        .map(|folder| {
+            if folder.continuation().is_from_prev() {
+                return Vec::new();
+            }
+            let mut files: Vec<(u32, u32, String)> = folder
+                .file_entries()
+                .filter(|file| !file.is_continued())
+                .map(|file| {
+                    (
+                        file.uncompressed_offset,
+                        file.uncompressed_size(),
+                        extension(file.name()),
+                    )
+                })
+                .collect();
+            files.sort();
+            files.dedup_by_key(|&mut (offset, size, _)| (offset, size));
+            files
+        })
+        .collect();
+    let mut suggestions = Vec::new();
+    for (folder_index, files) in folders.into_iter().enumerate() {
+        let mut groups = BTreeMap::<String, SizeEstimator>::new();
+        for (_, _, extension) in files.iter() {
+            groups.entry(extension.clone()).or_insert_with(SizeEstimator::new);
+        }
+        if groups.len() < 2 {
+            continue;
+        }
+        let mut whole = SizeEstimator::new();
+        let mut reader = cabinet.read_folder(folder_index)?;
+        let mut data = Vec::new();
+        for (offset, size, extension) in files.iter() {
+            reader.seek_to_uncompressed_offset(*offset as u64)?;
+            data.clear();
+            (&mut reader).take(*size as u64).read_to_end(&mut data)?;
+            whole.write(&data)?;
+            groups.get_mut(extension).unwrap().write(&data)?;
+        }
+        let current_size = whole.finish()?;
+        let mut suggested_size = 0;
+        let mut extensions = Vec::with_capacity(groups.len());
+        for (extension, estimator) in groups {
+            suggested_size += estimator.finish()?;
+            extensions.push(extension);
+        }
+        if suggested_size < current_size {
+            suggestions.push(GroupingSuggestion {
+                folder_index,
+                current_size,
+                suggested_size,
+                extensions,
+            });
+        }
+    }
+    Ok(suggestions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::extension;
+
+    #[test]
+    fn file_extensions() {
+        assert_eq!(extension("setup.INF"), "inf");
+        assert_eq!(extension("drivers\\x64\\foo.sys"), "sys");
+        assert_eq!(extension("dir.d\\README"), "");
+        assert_eq!(extension(".hidden"), "");
+        assert_eq!(extension("archive.tar.gz"), "gz");
+    }
+}
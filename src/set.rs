@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Seek};
+
+use crate::cabinet::Cabinet;
+
+/// A plan for which cabinet files of a multi-cabinet set are needed in order
+/// to extract everything in it, and which of those are not yet available.
+///
+/// This is meant for install tooling that knows the names of some cabinet
+/// files (e.g. from an MSI `Media` table) and wants to fetch exactly the
+/// parts it is missing.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CabinetSet {
+    parts: Vec<String>,
+    missing: Vec<String>,
+}
+
+impl CabinetSet {
+    /// Plans a cabinet set from the given cabinet file paths, the first of
+    /// which should be the first cabinet to extract from.  Starting from
+    /// that cabinet, the set's previous- and next-cabinet chains are
+    /// followed through the other given files, which are matched against
+    /// the cabinet names recorded in the chain by their file name
+    /// (case-insensitively).
+    pub fn plan_from_names(names: &[String]) -> io::Result<CabinetSet> {
+        CabinetSet::plan_with_opener(names, |name| File::open(name))
+    }
+
+    /// Like [`plan_from_names`](Self::plan_from_names), but uses the given
+    /// function to open each cabinet file rather than reading it from the
+    /// filesystem.
+    pub fn plan_with_opener<R, F>(
+        names: &[String],
+        mut open: F,
+    ) -> io::Result<CabinetSet>
+    where
+        R: Read + Seek,
+        F: FnMut(&str) -> io::Result<R>,
+    {
+        let first = match names.first() {
+            Some(first) => first,
+            None => invalid_input!("No cabinet names given"),
+        };
+        let mut available = HashMap::<String, &String>::new();
+        for name in names {
+            available.entry(chain_key(name)).or_insert(name);
+        }
+        let cabinet = Cabinet::new(open(first)?)?;
+        let mut prev = cabinet.prev_cabinet_name().map(str::to_string);
+        let mut next = cabinet.next_cabinet_name().map(str::to_string);
+        let mut set =
+            CabinetSet { parts: vec![first.clone()], missing: Vec::new() };
+        while let Some(prev_name) = prev.take() {
+            let name = set.find_part(&available, &prev_name)?;
+            if let Some(name) = name {
+                let cabinet = Cabinet::new(open(name)?)?;
+                prev = cabinet.prev_cabinet_name().map(str::to_string);
+                set.parts.push(name.clone());
+            }
+        }
+        // So far, the parts are the first cabinet followed by its
+        // predecessors, nearest first.
+        set.parts.reverse();
+        while let Some(next_name) = next.take() {
+            let name = set.find_part(&available, &next_name)?;
+            if let Some(name) = name {
+                let cabinet = Cabinet::new(open(name)?)?;
+                next = cabinet.next_cabinet_name().map(str::to_string);
+                set.parts.push(name.clone());
+            }
+        }
+        Ok(set)
+    }
+
+    /// Looks up the given cabinet name from a prev/next chain among the
+    /// available files, recording it as missing if it isn't there.  Returns
+    /// an error if the chain loops back on itself.
+    fn find_part<'a>(
+        &mut self,
+        available: &HashMap<String, &'a String>,
+        chain_name: &str,
+    ) -> io::Result<Option<&'a String>> {
+        let key = chain_key(chain_name);
+        if self.parts.iter().any(|part| chain_key(part) == key) {
+            invalid_data!("Cabinet set chain loops back to {:?}", chain_name);
+        }
+        match available.get(&key) {
+            Some(&name) => Ok(Some(name)),
+            None => {
+                self.missing.push(chain_name.to_string());
+                Ok(None)
+            }
+        }
+    }
+
+    /// Returns the given cabinet files that are part of the set, in set
+    /// order.
+    pub fn parts(&self) -> &[String] {
+        &self.parts
+    }
+
+    /// Returns the names (as recorded in the prev/next chain) of the cabinet
+    /// files that are needed but were not among the given files.  Since the
+    /// chain can only be followed through cabinets that are available, the
+    /// set may turn out to need still more parts once these are fetched, so
+    /// the set should be planned again until this is empty.
+    pub fn missing_parts(&self) -> &[String] {
+        &self.missing
+    }
+
+    /// Returns true if every part of the set is available.
+    pub fn is_complete(&self) -> bool {
+        self.missing.is_empty()
+    }
+}
+
+/// Returns the key used to match a cabinet name from a prev/next chain
+/// against a cabinet file path.
+fn chain_key(name: &str) -> String {
+    let file_name = name.rsplit(['/', '\\']).next().unwrap_or(name);
+    file_name.to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Cursor, Write};
+
+    use super::CabinetSet;
+    use crate::builder::CabinetBuilder;
+    use crate::ctype::CompressionType;
+
+    /// Builds a one-file cabinet, then patches it to refer to the given
+    /// previous and next cabinets.
+    fn build_part(prev: Option<&str>, next: Option<&str>) -> Vec<u8> {
+        let mut builder = CabinetBuilder::new();
+        builder.add_folder(CompressionType::None).add_file("a.txt");
+        let mut writer = builder.build(Cursor::new(Vec::new())).unwrap();
+        while let Some(mut file) = writer.next_file().unwrap() {
+            file.write_all(b"data").unwrap();
+        }
+        let mut binary = writer.finish().unwrap().into_inner();
+
+        let mut names = Vec::new();
+        for (flag, name) in [(0x1, prev), (0x2, next)] {
+            if let Some(name) = name {
+                binary[30] |= flag;
+                names.extend_from_slice(name.as_bytes());
+                names.extend_from_slice(b"\0disk\0");
+            }
+        }
+        let shift = names.len() as u32;
+        for offset in [8, 16, 36] {
+            let bytes: [u8; 4] = binary[offset..][..4].try_into().unwrap();
+            let value = u32::from_le_bytes(bytes) + shift;
+            binary[offset..][..4].copy_from_slice(&value.to_le_bytes());
+        }
+        binary.splice(36..36, names);
+        binary
+    }
+
+    fn plan(names: &[&str]) -> io::Result<CabinetSet> {
+        let names: Vec<String> =
+            names.iter().map(|&n| n.to_string()).collect();
+        CabinetSet::plan_with_opener(&names, |name| {
+            let binary = match name.rsplit('/').next().unwrap() {
+                "one.cab" => build_part(None, Some("TWO.CAB")),
+                "two.cab" => build_part(Some("one.cab"), Some("three.cab")),
+                "three.cab" => build_part(Some("two.cab"), None),
+                "loop.cab" => build_part(None, Some("loop.cab")),
+                _ => return Err(io::ErrorKind::NotFound.into()),
+            };
+            Ok(Cursor::new(binary))
+        })
+    }
+
+    #[test]
+    fn plan_complete_set() {
+        let set = plan(&["media/one.cab", "media/three.cab", "media/two.cab"])
+            .unwrap();
+        assert!(set.is_complete());
+        assert_eq!(
+            set.parts(),
+            &["media/one.cab", "media/two.cab", "media/three.cab"]
+        );
+    }
+
+    #[test]
+    fn plan_set_with_missing_parts() {
+        let set = plan(&["one.cab"]).unwrap();
+        assert_eq!(set.parts(), &["one.cab"]);
+        assert_eq!(set.missing_parts(), &["TWO.CAB"]);
+
+        let set = plan(&["two.cab", "unrelated.cab"]).unwrap();
+        assert_eq!(set.parts(), &["two.cab"]);
+        assert_eq!(set.missing_parts(), &["one.cab", "three.cab"]);
+
+        let set = plan(&["three.cab", "one.cab"]).unwrap();
+        assert_eq!(set.parts(), &["three.cab"]);
+        assert_eq!(set.missing_parts(), &["two.cab"]);
+    }
+
+    #[test]
+    fn plan_set_with_chain_loop() {
+        let error = plan(&["loop.cab"]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        let error = plan(&[]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,161 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+use crate::builder::CabinetBuilder;
+use crate::cabinet::Cabinet;
+use crate::ctype::CompressionType;
+use crate::file::FileEntry;
+
+/// Builds a cabinet containing a single file, named `name`, whose contents
+/// are read from `reader`; returns the underlying writer once the cabinet
+/// is finished.
+///
+/// This covers the common case of compressing one file into its own
+/// cabinet (such as the `foo.ex_` files served by symbol servers) without
+/// going through [`CabinetBuilder`].
+///
+/// ```no_run
+/// use std::fs::File;
+/// let input = File::open("foo.exe")?;
+/// let output = File::create("foo.ex_")?;
+/// cab::compress_single("foo.exe", input, output, cab::CompressionType::MsZip)?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn compress_single<R: Read, W: Write + Seek>(
+    name: &str,
+    mut reader: R,
+    writer: W,
+    ctype: CompressionType,
+) -> io::Result<W> {
+    let mut builder = CabinetBuilder::new();
+    builder.add_folder(ctype).add_file(name);
+    let mut cabinet = builder.build(writer)?;
+    if let Some(mut file_writer) = cabinet.next_file()? {
+        io::copy(&mut reader, &mut file_writer)?;
+    }
+    cabinet.finish()
+}
+
+/// Decompresses the file named `name` from the cabinet read from
+/// `cab_reader`, writing its contents to `writer`; returns the number of
+/// bytes written.
+///
+/// ```no_run
+/// use std::fs::File;
+/// let input = File::open("foo.ex_")?;
+/// let output = File::create("foo.exe")?;
+/// cab::decompress_single(input, "foo.exe", output)?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn decompress_single<R: Read + Seek, W: Write>(
+    cab_reader: R,
+    name: &str,
+    mut writer: W,
+) -> io::Result<u64> {
+    let mut cabinet = Cabinet::new(cab_reader)?;
+    let mut file_reader = cabinet.read_file(name)?;
+    let size = io::copy(&mut file_reader, &mut writer)?;
+    writer.flush()?;
+    Ok(size)
+}
+
+/// Returns the name that `makecab` gives a cabinet holding just the file
+/// `name`: the last character of the file's extension is replaced with an
+/// underscore (e.g. `driver.sys` becomes `driver.sy_`), or, if the extension
+/// is shorter than three characters, an underscore is appended (`foo.c`
+/// becomes `foo.c_`, and `README` becomes `README._`).
+///
+/// ```
+/// assert_eq!(cab::underscored_name("driver.sys"), "driver.sy_");
+/// assert_eq!(cab::underscored_name("bin/foo.c"), "bin/foo.c_");
+/// assert_eq!(cab::underscored_name("README"), "README._");
+/// ```
+pub fn underscored_name(name: &str) -> String {
+    let base_start = name.rfind(['/', '\\']).map_or(0, |index| index + 1);
+    let base = &name[base_start..];
+    match base.rfind('.') {
+        Some(dot) if base.len() - dot > 3 => {
+            let mut underscored = name.to_string();
+            underscored.pop();
+            underscored.push('_');
+            underscored
+        }
+        Some(_) => format!("{}_", name),
+        None => format!("{}._", name),
+    }
+}
+
+/// Returns the name of the one file in a single-file cabinet (such as a
+/// `foo.ex_` file), which is the name it should be restored to when
+/// expanded.  Any directory components of the stored name are dropped, so
+/// the result is always a plain file name.
+///
+/// Returns an error if the cabinet doesn't contain exactly one file, or if
+/// the file's name is empty once directory components are removed.
+pub fn single_file_name<R: Read + Seek>(
+    cabinet: &Cabinet<R>,
+) -> io::Result<&str> {
+    let name = single_file(cabinet)?.name();
+    let base = name.rsplit(['/', '\\']).next().unwrap_or(name);
+    if base.is_empty() || base == "." || base == ".." {
+        invalid_data!("Invalid file name in single-file cabinet: {:?}", name);
+    }
+    Ok(base)
+}
+
+fn single_file<R: Read + Seek>(
+    cabinet: &Cabinet<R>,
+) -> io::Result<&FileEntry> {
+    let mut files =
+        cabinet.folder_entries().flat_map(|folder| folder.file_entries());
+    match (files.next(), files.next()) {
+        (Some(file), None) => Ok(file),
+        (None, _) => invalid_input!("Cabinet contains no files"),
+        (Some(_), Some(_)) => {
+            invalid_input!("Cabinet contains more than one file")
+        }
+    }
+}
+
+/// Compresses the file at `path` into a single-file cabinet next to it,
+/// named as by [`underscored_name`] (e.g. `driver.sys` is compressed into
+/// `driver.sy_`), and returns the path of the new cabinet.  The file is
+/// stored under its own file name and modification time, as `makecab`
+/// does.
+pub fn compress_underscored(
+    path: &Path,
+    ctype: CompressionType,
+) -> io::Result<PathBuf> {
+    let name = match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name,
+        None => invalid_input!("Invalid file name: {:?}", path),
+    };
+    let mut reader = File::open(path)?;
+    let mut builder = CabinetBuilder::new();
+    let file = builder.add_folder(ctype).add_file(name);
+    if let Ok(modified) = reader.metadata().and_then(|meta| meta.modified()) {
+        file.set_system_time(modified);
+    }
+    let cab_path = path.with_file_name(underscored_name(name));
+    let mut cabinet = builder.build(File::create(&cab_path)?)?;
+    if let Some(mut file_writer) = cabinet.next_file()? {
+        io::copy(&mut reader, &mut file_writer)?;
+    }
+    cabinet.finish()?;
+    Ok(cab_path)
+}
+
+/// Expands a single-file cabinet (such as a `foo.ex_` file) into `out_dir`,
+/// restoring the original file name from the entry stored in the cabinet
+/// (see [`single_file_name`]), and returns the path of the expanded file.
+pub fn expand_underscored(path: &Path, out_dir: &Path) -> io::Result<PathBuf> {
+    let mut cabinet = Cabinet::new(File::open(path)?)?;
+    let out_path = out_dir.join(single_file_name(&cabinet)?);
+    let stored_name = single_file(&cabinet)?.name().to_string();
+    let mut reader = cabinet.read_file(&stored_name)?;
+    let mut writer = File::create(&out_path)?;
+    io::copy(&mut reader, &mut writer)?;
+    writer.flush()?;
+    Ok(out_path)
+}
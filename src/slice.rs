@@ -0,0 +1,112 @@
+use std::io::{self, Cursor};
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::cabinet::Cabinet;
+use crate::checksum::Checksum;
+use crate::ctype::CompressionType;
+
+impl<'a> Cabinet<Cursor<&'a [u8]>> {
+    /// Opens a cabinet file that is already in memory (e.g. memory-mapped,
+    /// or embedded with `include_bytes!`), without copying it.
+    pub fn from_slice(
+        data: &'a [u8],
+    ) -> io::Result<Cabinet<Cursor<&'a [u8]>>> {
+        Cabinet::new(Cursor::new(data))
+    }
+
+    /// Returns the data for the named file as a list of slices borrowed
+    /// directly from the cabinet's in-memory data, one per data block that
+    /// the file spans, so that nothing needs to be copied.  Each block's
+    /// checksum is verified.
+    ///
+    /// This only works for files in uncompressed
+    /// ([`CompressionType::None`]) folders, and only if no
+    /// [block transform](Cabinet::set_block_transform) is set; for other
+    /// files, an error is returned, and [`read_file`](Cabinet::read_file)
+    /// must be used instead.
+    pub fn file_slices(&mut self, name: &str) -> io::Result<Vec<&'a [u8]>> {
+        self.load_file_entries()?;
+        let (folder_index, start, size) = self.locate_file(name)?;
+        let folder = &self.inner.folders[folder_index];
+        if folder.compression_type() != CompressionType::None {
+            invalid_input!(
+                "File {:?} is in a compressed ({}) folder, so its data can't \
+                 be borrowed",
+                name,
+                folder.compression_type()
+            );
+        }
+        if self.inner.block_transform.is_some() {
+            invalid_input!(
+                "File {:?} can't be borrowed while a block transform is set",
+                name
+            );
+        }
+        let data: &'a [u8] = self.inner.reader.borrow().get_ref();
+        let reserve_size = self.inner.data_reserve_size as usize;
+        let end = start + size;
+        let mut slices = Vec::new();
+        let mut offset = folder.first_data_block_offset as usize;
+        let mut block_start = 0u64;
+        for block_index in 0..folder.num_data_blocks() as usize {
+            if block_start >= end {
+                break;
+            }
+            let header_end = offset + 8 + reserve_size;
+            let header = match data.get(offset..header_end) {
+                Some(header) => header,
+                None => return Err(io::ErrorKind::UnexpectedEof.into()),
+            };
+            let checksum = LittleEndian::read_u32(&header[0..4]);
+            let compressed_size = LittleEndian::read_u16(&header[4..6]);
+            let uncompressed_size = LittleEndian::read_u16(&header[6..8]);
+            if compressed_size != uncompressed_size {
+                invalid_data!(
+                    "Uncompressed data block {} has compressed size {} but \
+                     uncompressed size {}",
+                    block_index,
+                    compressed_size,
+                    uncompressed_size
+                );
+            }
+            let payload_end = header_end + compressed_size as usize;
+            let payload = match data.get(header_end..payload_end) {
+                Some(payload) => payload,
+                None => return Err(io::ErrorKind::UnexpectedEof.into()),
+            };
+            let block_end = block_start + uncompressed_size as u64;
+            if block_end > start {
+                if checksum != 0 {
+                    let mut actual = Checksum::new();
+                    actual.update(&header[8..]);
+                    actual.update(payload);
+                    let actual = actual
+                        .data_block_value(compressed_size, uncompressed_size);
+                    if actual != checksum {
+                        invalid_data!(
+                            "Checksum error in data block {} \
+                             (expected {:08x}, actual {:08x})",
+                            block_index,
+                            checksum,
+                            actual
+                        );
+                    }
+                }
+                let from = (start.max(block_start) - block_start) as usize;
+                let to = (end.min(block_end) - block_start) as usize;
+                slices.push(&payload[from..to]);
+            }
+            block_start = block_end;
+            offset = payload_end;
+        }
+        if block_start < end {
+            invalid_data!(
+                "Folder {} ends before the end of file {:?}",
+                folder_index,
+                name
+            );
+        }
+        Ok(slices)
+    }
+}
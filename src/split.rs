@@ -0,0 +1,230 @@
+use std::cell::RefCell;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::rc::Rc;
+
+use byteorder::{LittleEndian, ReadBytesExt};
+
+use crate::cabinet::Cabinet;
+use crate::consts;
+
+/// Splits a stream holding several cabinet files back-to-back (as some
+/// firmware bundles do) into those cabinets, using the total size recorded
+/// in each cabinet's header to find where the next one starts.  The first
+/// cabinet must start at the reader's current position.
+///
+/// Each cabinet reads from its own [`StreamSegment`] of the shared reader,
+/// so the cabinets can be kept open and used in any order.  Iteration stops
+/// at the end of the stream, or after the first error (e.g. if the data
+/// after a cabinet isn't another cabinet).
+///
+/// ```no_run
+/// let file = std::fs::File::open("bundle.bin")?;
+/// for cabinet in cab::split_stream(file) {
+///     let cabinet = cabinet?;
+///     for file in cabinet.file_entries_in_directory_order() {
+///         println!("{}", file.name());
+///     }
+/// }
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn split_stream<R: Read + Seek>(reader: R) -> SplitStream<R> {
+    SplitStream {
+        reader: Rc::new(RefCell::new(reader)),
+        offset: None,
+        end: 0,
+        done: false,
+    }
+}
+
+/// An iterator over the cabinets in a stream of concatenated cabinet files,
+/// as returned by [`split_stream`].
+pub struct SplitStream<R> {
+    reader: Rc<RefCell<R>>,
+    /// Where the next cabinet starts, once the stream has been measured.
+    offset: Option<u64>,
+    end: u64,
+    done: bool,
+}
+
+/// A reader for the part of a shared stream that holds one cabinet file, as
+/// read by the cabinets yielded from [`SplitStream`].  Offsets are relative
+/// to the start of the cabinet.
+pub struct StreamSegment<R> {
+    reader: Rc<RefCell<R>>,
+    start: u64,
+    len: u64,
+    position: u64,
+}
+
+impl<R: Read + Seek> SplitStream<R> {
+    /// Finds the extent of the next cabinet in the stream, if there is one.
+    fn next_segment(&mut self) -> io::Result<Option<StreamSegment<R>>> {
+        let mut reader = self.reader.borrow_mut();
+        let offset = match self.offset {
+            Some(offset) => offset,
+            None => {
+                let start = reader.stream_position()?;
+                self.end = reader.seek(SeekFrom::End(0))?;
+                start
+            }
+        };
+        if offset >= self.end {
+            return Ok(None);
+        }
+        reader.seek(SeekFrom::Start(offset))?;
+        let signature = reader.read_u32::<LittleEndian>()?;
+        if signature != consts::FILE_SIGNATURE {
+            invalid_data!(
+                "Expected a cabinet file at offset {} of the stream \
+                 (invalid file signature)",
+                offset
+            );
+        }
+        let _reserved1 = reader.read_u32::<LittleEndian>()?;
+        let total_size = reader.read_u32::<LittleEndian>()? as u64;
+        if total_size < 36 {
+            invalid_data!(
+                "Cabinet at offset {} of the stream has an invalid total \
+                 size of {} bytes",
+                offset,
+                total_size
+            );
+        }
+        if total_size > self.end - offset {
+            invalid_data!(
+                "Cabinet at offset {} of the stream claims to be {} bytes, \
+                 but only {} bytes remain",
+                offset,
+                total_size,
+                self.end - offset
+            );
+        }
+        self.offset = Some(offset + total_size);
+        reader.seek(SeekFrom::Start(offset))?;
+        Ok(Some(StreamSegment {
+            reader: self.reader.clone(),
+            start: offset,
+            len: total_size,
+            position: 0,
+        }))
+    }
+}
+
+impl<R: Read + Seek> Iterator for SplitStream<R> {
+    type Item = io::Result<Cabinet<StreamSegment<R>>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = match self.next_segment() {
+            Ok(Some(segment)) => Cabinet::new(segment),
+            Ok(None) => {
+                self.done = true;
+                return None;
+            }
+            Err(error) => Err(error),
+        };
+        self.done = result.is_err();
+        Some(result)
+    }
+}
+
+impl<R> StreamSegment<R> {
+    /// Returns the offset within the whole stream at which this segment
+    /// starts.
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    /// Returns the length of this segment, in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns true if this segment is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<R: Read + Seek> Read for StreamSegment<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.len.saturating_sub(self.position);
+        let max_bytes = (buf.len() as u64).min(remaining) as usize;
+        if max_bytes == 0 {
+            return Ok(0);
+        }
+        let mut reader = self.reader.borrow_mut();
+        reader.seek(SeekFrom::Start(self.start + self.position))?;
+        let bytes_read = reader.read(&mut buf[..max_bytes])?;
+        self.position += bytes_read as u64;
+        Ok(bytes_read)
+    }
+}
+
+impl<R> Seek for StreamSegment<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_position = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::Current(delta) => self.position as i64 + delta,
+            SeekFrom::End(delta) => self.len as i64 + delta,
+        };
+        if new_position < 0 {
+            invalid_input!("Cannot seek to {}", new_position);
+        }
+        self.position = new_position as u64;
+        Ok(self.position)
+    }
+
+    fn stream_position(&mut self) -> io::Result<u64> {
+        Ok(self.position)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read, Write};
+
+    use super::split_stream;
+    use crate::builder::CabinetBuilder;
+    use crate::ctype::CompressionType;
+
+    fn build_cabinet(name: &str, data: &[u8]) -> Vec<u8> {
+        let mut builder = CabinetBuilder::new();
+        builder.add_folder(CompressionType::MsZip).add_file(name);
+        let mut cab_writer = builder.build(Cursor::new(Vec::new())).unwrap();
+        while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+            file_writer.write_all(data).unwrap();
+        }
+        cab_writer.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn split_concatenated_cabinets() {
+        let mut stream = build_cabinet("a.txt", b"Hello, world!\n");
+        stream.extend(build_cabinet("b.txt", b"See you later!\n"));
+        let cabinets: Vec<_> = split_stream(Cursor::new(stream.clone()))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(cabinets.len(), 2);
+        // Read the cabinets in reverse order, since they share the reader:
+        for (mut cabinet, (name, data)) in cabinets.into_iter().rev().zip([
+            ("b.txt", &b"See you later!\n"[..]),
+            ("a.txt", &b"Hello, world!\n"[..]),
+        ]) {
+            let mut output = Vec::new();
+            cabinet.read_file(name).unwrap().read_to_end(&mut output).unwrap();
+            assert_eq!(output, data);
+        }
+
+        // Trailing data that isn't a cabinet is reported, after the
+        // cabinets before it:
+        stream.extend_from_slice(b"garbage");
+        let mut cabinets = split_stream(Cursor::new(stream));
+        assert!(cabinets.next().unwrap().is_ok());
+        assert!(cabinets.next().unwrap().is_ok());
+        assert!(cabinets.next().unwrap().is_err());
+        assert!(cabinets.next().is_none());
+    }
+}
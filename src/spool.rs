@@ -0,0 +1,247 @@
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const DEFAULT_MEMORY_THRESHOLD: usize = 8 << 20;
+
+static NEXT_SPOOL_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Options for creating a [`Spool`], in the style of
+/// [`std::fs::OpenOptions`].
+///
+/// ```no_run
+/// let spool = cab::SpoolOptions::new()
+///     .dir("/var/tmp")
+///     .memory_threshold(1 << 20)
+///     .create();
+/// let mut cab_builder = cab::CabinetBuilder::new();
+/// cab_builder.add_folder(cab::CompressionType::MsZip).add_file("foo.txt");
+/// let mut cab_writer = cab_builder.build(spool)?;
+/// while let Some(mut writer) = cab_writer.next_file()? {
+///     let mut reader = std::fs::File::open(writer.file_name())?;
+///     std::io::copy(&mut reader, &mut writer)?;
+/// }
+/// cab_writer.finish_to(std::io::stdout().lock())?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct SpoolOptions {
+    dir: Option<PathBuf>,
+    memory_threshold: usize,
+}
+
+impl SpoolOptions {
+    /// Creates a new set of options, with all options set to their defaults.
+    pub fn new() -> SpoolOptions {
+        SpoolOptions { dir: None, memory_threshold: DEFAULT_MEMORY_THRESHOLD }
+    }
+
+    /// Sets the directory in which to create the temporary file, once the
+    /// spooled data no longer fits in memory.  Defaults to
+    /// [`std::env::temp_dir`].
+    pub fn dir<P: Into<PathBuf>>(&mut self, dir: P) -> &mut SpoolOptions {
+        self.dir = Some(dir.into());
+        self
+    }
+
+    /// Sets the maximum number of bytes to keep in memory before moving the
+    /// spooled data into a temporary file.  Defaults to 8 MiB; zero means
+    /// that the data always goes straight to a temporary file.
+    pub fn memory_threshold(&mut self, bytes: usize) -> &mut SpoolOptions {
+        self.memory_threshold = bytes;
+        self
+    }
+
+    /// Creates a new, empty spool with these options.  No temporary file is
+    /// created until the memory threshold is exceeded.
+    pub fn create(&self) -> Spool {
+        Spool {
+            storage: Storage::Memory(Cursor::new(Vec::new())),
+            options: self.clone(),
+        }
+    }
+}
+
+impl Default for SpoolOptions {
+    fn default() -> Self {
+        SpoolOptions::new()
+    }
+}
+
+/// A seekable scratch buffer that keeps its data in memory up to a threshold,
+/// and in a temporary file beyond that.
+///
+/// A `CabinetWriter` needs to seek within its output, so it cannot write
+/// directly to a pipe or socket.  Building the cabinet into a `Spool`
+/// instead, and then copying it to the real destination with
+/// [`CabinetWriter::finish_to`](crate::CabinetWriter::finish_to), avoids
+/// having to hold the whole cabinet in memory.  The temporary file (if any)
+/// is deleted when the spool is dropped.
+pub struct Spool {
+    storage: Storage,
+    options: SpoolOptions,
+}
+
+enum Storage {
+    Memory(Cursor<Vec<u8>>),
+    File(TempFile),
+}
+
+/// A temporary file that is deleted when dropped.
+struct TempFile {
+    file: File,
+    path: PathBuf,
+}
+
+impl Spool {
+    /// Creates a new, empty spool with the default options.
+    pub fn new() -> Spool {
+        SpoolOptions::new().create()
+    }
+
+    /// Returns the path of the temporary file holding the spooled data, or
+    /// `None` if the data is still held in memory.
+    pub fn temp_path(&self) -> Option<&Path> {
+        match self.storage {
+            Storage::Memory(_) => None,
+            Storage::File(ref temp) => Some(&temp.path),
+        }
+    }
+
+    /// Copies the entire contents of the spool (regardless of the current
+    /// position) into the given writer, and returns the number of bytes
+    /// copied.  Afterwards, the spool is positioned at its end.
+    pub fn copy_to<W: Write>(&mut self, sink: &mut W) -> io::Result<u64> {
+        self.seek(SeekFrom::Start(0))?;
+        io::copy(self, sink)
+    }
+
+    /// Moves the data held in memory into a new temporary file.
+    fn spill(&mut self) -> io::Result<()> {
+        if let Storage::Memory(ref cursor) = self.storage {
+            let mut temp = TempFile::create(self.options.dir.as_deref())?;
+            temp.file.write_all(cursor.get_ref())?;
+            temp.file.seek(SeekFrom::Start(cursor.position()))?;
+            self.storage = Storage::File(temp);
+        }
+        Ok(())
+    }
+}
+
+impl Default for Spool {
+    fn default() -> Self {
+        Spool::new()
+    }
+}
+
+impl Read for Spool {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.storage {
+            Storage::Memory(ref mut cursor) => cursor.read(buf),
+            Storage::File(ref mut temp) => temp.file.read(buf),
+        }
+    }
+}
+
+impl Write for Spool {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Storage::Memory(ref cursor) = self.storage {
+            let end = cursor.position().saturating_add(buf.len() as u64);
+            if end > self.options.memory_threshold as u64 {
+                self.spill()?;
+            }
+        }
+        match self.storage {
+            Storage::Memory(ref mut cursor) => cursor.write(buf),
+            Storage::File(ref mut temp) => temp.file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.storage {
+            Storage::Memory(_) => Ok(()),
+            Storage::File(ref mut temp) => temp.file.flush(),
+        }
+    }
+}
+
+impl Seek for Spool {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self.storage {
+            Storage::Memory(ref mut cursor) => cursor.seek(pos),
+            Storage::File(ref mut temp) => temp.file.seek(pos),
+        }
+    }
+}
+
+impl TempFile {
+    fn create(dir: Option<&Path>) -> io::Result<TempFile> {
+        let dir = match dir {
+            Some(dir) => dir.to_path_buf(),
+            None => env::temp_dir(),
+        };
+        loop {
+            let id = NEXT_SPOOL_ID.fetch_add(1, Ordering::Relaxed);
+            let name = format!("cab-spool-{}-{}.tmp", process::id(), id);
+            let path = dir.join(name);
+            match OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(&path)
+            {
+                Ok(file) => return Ok(TempFile { file, path }),
+                Err(error) if error.kind() == io::ErrorKind::AlreadyExists => {
+                    continue;
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SpoolOptions;
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    #[test]
+    fn spool_stays_in_memory_below_threshold() {
+        let mut spool = SpoolOptions::new().memory_threshold(16).create();
+        spool.write_all(b"Hello, world!").unwrap();
+        assert!(spool.temp_path().is_none());
+        let mut output = Vec::new();
+        assert_eq!(spool.copy_to(&mut output).unwrap(), 13);
+        assert_eq!(output, b"Hello, world!");
+    }
+
+    #[test]
+    fn spool_moves_to_temp_file_above_threshold() {
+        let dir = std::env::temp_dir();
+        let mut spool =
+            SpoolOptions::new().dir(&dir).memory_threshold(8).create();
+        spool.write_all(b"Hello").unwrap();
+        assert!(spool.temp_path().is_none());
+        spool.write_all(b", world!").unwrap();
+        let path = spool.temp_path().unwrap().to_path_buf();
+        assert!(path.starts_with(&dir));
+        assert!(path.exists());
+        spool.seek(SeekFrom::Start(7)).unwrap();
+        spool.write_all(b"W").unwrap();
+        spool.seek(SeekFrom::Start(0)).unwrap();
+        let mut output = String::new();
+        spool.read_to_string(&mut output).unwrap();
+        assert_eq!(output, "Hello, World!");
+        drop(spool);
+        assert!(!path.exists());
+    }
+}
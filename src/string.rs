@@ -1,26 +1,262 @@
-use std::io::{self, Read};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
 
 use byteorder::ReadBytesExt;
 
 use crate::consts;
 
-pub(crate) fn read_null_terminated_string<R: Read>(
+/// Describes how a file name was decoded from the raw bytes stored in the
+/// cabinet file.  See [`FileEntry::name_decoding`](crate::FileEntry::name_decoding).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NameDecoding {
+    /// The name was valid UTF-8 (which includes plain ASCII), and was decoded
+    /// exactly.
+    Utf8,
+    /// The name was not valid UTF-8, and invalid byte sequences were replaced
+    /// with U+FFFD.
+    Lossy,
+    /// The name was not valid UTF-8, and was decoded with a character
+    /// encoding guessed from all the non-UTF-8 names in the cabinet.  This is
+    /// only ever used when the `chardet` feature is enabled.
+    Detected {
+        /// The name of the guessed encoding (e.g. `"Shift_JIS"`).
+        encoding: &'static str,
+        /// False if the guess was a low-confidence one, in which case the
+        /// decoded name may still be garbled.
+        confident: bool,
+    },
+    /// The name was stored as UTF-16LE (which the CAB spec doesn't allow,
+    /// but some cabinet writers do anyway), and any unpaired surrogates were
+    /// replaced with U+FFFD.  This is only ever used when the cabinet is
+    /// opened with
+    /// [`ReadOptions::lenient_names`](crate::ReadOptions::lenient_names).
+    Utf16,
+}
+
+/// Identifies where in a cabinet file a name was stored.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NameLocation {
+    /// The name of the file entry with the given index (counting from zero,
+    /// in the order the entries are stored in the cabinet).
+    FileEntry(usize),
+    /// The name of the previous cabinet in the set, from the cabinet header.
+    PrevCabinet,
+    /// The name of the previous disk in the set, from the cabinet header.
+    PrevDisk,
+    /// The name of the next cabinet in the set, from the cabinet header.
+    NextCabinet,
+    /// The name of the next disk in the set, from the cabinet header.
+    NextDisk,
+}
+
+impl fmt::Display for NameLocation {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            NameLocation::FileEntry(index) => {
+                write!(formatter, "file entry {}", index)
+            }
+            NameLocation::PrevCabinet => formatter.write_str("prev cabinet"),
+            NameLocation::PrevDisk => formatter.write_str("prev disk"),
+            NameLocation::NextCabinet => formatter.write_str("next cabinet"),
+            NameLocation::NextDisk => formatter.write_str("next disk"),
+        }
+    }
+}
+
+/// The error returned (wrapped in an [`io::Error`] of kind
+/// [`InvalidData`](io::ErrorKind::InvalidData)) when a name stored in a
+/// cabinet is longer than
+/// [`ReadOptions::max_name_len`](crate::ReadOptions::max_name_len) allows.
+/// Use [`io::Error::get_ref`] and `downcast_ref` to get at it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NameTooLongError {
+    location: NameLocation,
+    max_len: usize,
+}
+
+impl NameTooLongError {
+    /// Returns where the offending name was stored.
+    pub fn location(&self) -> NameLocation {
+        self.location
+    }
+
+    /// Returns the maximum name length that was exceeded, in bytes.
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+}
+
+impl fmt::Display for NameTooLongError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            formatter,
+            "Name in {} is longer than maximum of {} bytes",
+            self.location, self.max_len
+        )
+    }
+}
+
+impl Error for NameTooLongError {}
+
+/// Reads a NUL-terminated string, returning its raw bytes (without the
+/// terminator).  Decoding the bytes is left to the caller, so that names that
+/// are never looked at don't need to be decoded at all.
+pub(crate) fn read_null_terminated_bytes<R: Read>(
+    reader: &mut R,
+    max_len: usize,
+    location: NameLocation,
+) -> io::Result<Vec<u8>> {
+    let capacity = max_len.min(consts::MAX_STRING_SIZE);
+    let mut bytes = Vec::<u8>::with_capacity(capacity);
+    read_null_terminated_bytes_into(reader, max_len, location, &mut bytes)?;
+    Ok(bytes)
+}
+
+/// Like `read_null_terminated_bytes`, but reads the string into `bytes`
+/// (replacing its previous contents), so that one buffer can be reused for
+/// many strings.
+pub(crate) fn read_null_terminated_bytes_into<R: Read>(
     reader: &mut R,
-    _is_utf8: bool,
-) -> io::Result<(String, Vec<u8>)> {
-    let mut bytes = Vec::<u8>::with_capacity(consts::MAX_STRING_SIZE);
+    max_len: usize,
+    location: NameLocation,
+    bytes: &mut Vec<u8>,
+) -> io::Result<()> {
+    bytes.clear();
     loop {
         let byte = reader.read_u8()?;
         if byte == 0 {
             break;
-        } else if bytes.len() == consts::MAX_STRING_SIZE {
-            invalid_data!(
-                "String longer than maximum of {} bytes",
-                consts::MAX_STRING_SIZE
-            );
+        } else if bytes.len() == max_len {
+            let error = NameTooLongError { location, max_len };
+            return Err(io::Error::new(io::ErrorKind::InvalidData, error));
         }
         bytes.push(byte);
     }
-    // TODO: Handle decoding differently depending on `_is_utf8`.
-    Ok((String::from_utf8_lossy(&bytes).to_string(), bytes.clone()))
+    Ok(())
+}
+
+/// Returns true if the reader is positioned at what looks like a UTF-16LE
+/// string: one that starts with a byte order mark, or whose first two
+/// characters are each followed by a NUL byte.  (A one-character UTF-16LE
+/// string without a byte order mark can't be told apart from a one-byte
+/// string followed by other data, so it is not detected.)  The reader's
+/// position is left unchanged.
+pub(crate) fn looks_like_utf16<R: Read + Seek>(
+    reader: &mut R,
+) -> io::Result<bool> {
+    let mut bytes = [0u8; 4];
+    let mut num_read = 0;
+    while num_read < bytes.len() {
+        match reader.read(&mut bytes[num_read..]) {
+            Ok(0) => break,
+            Ok(count) => num_read += count,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+    reader.seek(SeekFrom::Current(-(num_read as i64)))?;
+    if num_read < bytes.len() {
+        return Ok(false);
+    }
+    Ok(bytes[..2] == [0xff, 0xfe]
+        || (bytes[0] != 0 && bytes[1] == 0 && bytes[2] != 0 && bytes[3] == 0))
+}
+
+/// Reads a UTF-16LE string terminated by a NUL character into `bytes`
+/// (replacing its previous contents), leaving out the terminator.  Use
+/// [`decode_utf16`] to decode the raw bytes.
+pub(crate) fn read_null_terminated_utf16_into<R: Read>(
+    reader: &mut R,
+    max_len: usize,
+    location: NameLocation,
+    bytes: &mut Vec<u8>,
+) -> io::Result<()> {
+    bytes.clear();
+    loop {
+        let mut unit = [0u8; 2];
+        reader.read_exact(&mut unit)?;
+        if unit == [0, 0] {
+            break;
+        } else if bytes.len() + 2 > max_len {
+            let error = NameTooLongError { location, max_len };
+            return Err(io::Error::new(io::ErrorKind::InvalidData, error));
+        }
+        bytes.extend_from_slice(&unit);
+    }
+    Ok(())
+}
+
+/// Decodes the raw bytes of a UTF-16LE string, dropping any byte order mark.
+pub(crate) fn decode_utf16(bytes: &[u8]) -> String {
+    let mut units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+        .collect();
+    if units.first() == Some(&0xfeff) {
+        units.remove(0);
+    }
+    String::from_utf16_lossy(&units)
+}
+
+/// Guesses the character encoding used for the given (non-UTF-8) names,
+/// returning the encoding and whether the guess is a confident one.
+#[cfg(feature = "chardet")]
+pub(crate) fn detect_encoding<'a, I>(
+    names: I,
+) -> (&'static encoding_rs::Encoding, bool)
+where
+    I: IntoIterator<Item = &'a [u8]>,
+{
+    let mut detector = chardetng::EncodingDetector::new();
+    for name in names {
+        detector.feed(name, false);
+        // Separate names so that they aren't treated as one long run.
+        detector.feed(b"\n", false);
+    }
+    detector.feed(b"", true);
+    detector.guess_assess(None, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use super::{
+        decode_utf16, looks_like_utf16, read_null_terminated_utf16_into,
+        NameLocation,
+    };
+
+    #[test]
+    fn detect_and_read_utf16_names() {
+        let location = NameLocation::FileEntry(0);
+        let mut reader = Cursor::new(b"\xff\xfeh\0\xe9\0\0\0rest".to_vec());
+        assert!(looks_like_utf16(&mut reader).unwrap());
+        assert_eq!(reader.position(), 0);
+        let mut raw = Vec::new();
+        read_null_terminated_utf16_into(&mut reader, 255, location, &mut raw)
+            .unwrap();
+        assert_eq!(decode_utf16(&raw), "h\u{e9}");
+        assert_eq!(raw, b"\xff\xfeh\0\xe9\0");
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"rest");
+
+        let mut reader = Cursor::new(b"h\0i\0\0\0".to_vec());
+        assert!(looks_like_utf16(&mut reader).unwrap());
+        read_null_terminated_utf16_into(&mut reader, 255, location, &mut raw)
+            .unwrap();
+        assert_eq!(decode_utf16(&raw), "hi");
+        assert!(read_null_terminated_utf16_into(
+            &mut Cursor::new(b"h\0i\0\0\0".to_vec()),
+            3,
+            location,
+            &mut raw
+        )
+        .is_err());
+
+        assert!(!looks_like_utf16(&mut Cursor::new(b"hi\0\0")).unwrap());
+        assert!(!looks_like_utf16(&mut Cursor::new(b"h\0\0\0")).unwrap());
+        assert!(!looks_like_utf16(&mut Cursor::new(b"h\0")).unwrap());
+    }
 }
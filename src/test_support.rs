@@ -0,0 +1,338 @@
+//! Helpers for property-testing code that generates or consumes cabinets,
+//! enabled by the `test-support` feature.
+//!
+//! [`CabinetLayout::random`] generates a random cabinet layout (folders,
+//! compression types, file sizes and contents, datetimes, and attributes),
+//! which can be written with [`CabinetLayout::write`] (or fed through code
+//! under test via [`CabinetLayout::builder`]), and then checked against the
+//! cabinet that comes out with [`assert_round_trip`]:
+//!
+//! ```
+//! use cab::test_support::{assert_round_trip, CabinetLayout, LayoutOptions};
+//! use rand::SeedableRng;
+//!
+//! let mut rng = rand::rngs::SmallRng::seed_from_u64(1);
+//! for _ in 0..10 {
+//!     let layout = CabinetLayout::random(&mut rng, &LayoutOptions::new());
+//!     let cab_file = layout.write()?;
+//!     assert_round_trip(&layout, &cab_file);
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::io::{self, Cursor, Read, Write};
+
+use rand::Rng;
+use time::PrimitiveDateTime;
+
+use crate::builder::CabinetBuilder;
+use crate::cabinet::Cabinet;
+use crate::ctype::CompressionType;
+use crate::datetime::datetime_from_bits;
+
+/// Limits on the cabinet layouts generated by [`CabinetLayout::random`].
+#[derive(Clone, Debug)]
+pub struct LayoutOptions {
+    /// The maximum number of folders (at least one is always generated).
+    pub max_folders: usize,
+    /// The maximum number of files in each folder.
+    pub max_files_per_folder: usize,
+    /// The maximum size of each file, in bytes.
+    pub max_file_size: usize,
+    /// The compression types to choose from for each folder.  Defaults to
+    /// [`CompressionType::None`] and [`CompressionType::MsZip`] (the types
+    /// that this crate can write).
+    pub compression_types: Vec<CompressionType>,
+}
+
+impl LayoutOptions {
+    /// Creates a new set of options, with all options set to their defaults
+    /// (up to 4 folders, of up to 8 files each, of up to 100,000 bytes
+    /// each).
+    pub fn new() -> LayoutOptions {
+        LayoutOptions {
+            max_folders: 4,
+            max_files_per_folder: 8,
+            max_file_size: 100_000,
+            compression_types: vec![
+                CompressionType::None,
+                CompressionType::MsZip,
+            ],
+        }
+    }
+}
+
+impl Default for LayoutOptions {
+    fn default() -> LayoutOptions {
+        LayoutOptions::new()
+    }
+}
+
+/// The layout and contents of a cabinet, for property tests.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CabinetLayout {
+    /// The cabinet's folders, in order.
+    pub folders: Vec<FolderLayout>,
+}
+
+/// A folder within a [`CabinetLayout`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FolderLayout {
+    /// The folder's compression type.
+    pub compression_type: CompressionType,
+    /// The folder's files, in order.
+    pub files: Vec<FileLayout>,
+}
+
+/// A file within a [`FolderLayout`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FileLayout {
+    /// The file's name (unique within the cabinet).
+    pub name: String,
+    /// The file's contents.
+    pub data: Vec<u8>,
+    /// The file's datetime, which is always representable in a cabinet.
+    pub datetime: PrimitiveDateTime,
+    /// Whether the file is read-only.
+    pub is_read_only: bool,
+    /// Whether the file is hidden.
+    pub is_hidden: bool,
+    /// Whether the file is a system file.
+    pub is_system: bool,
+    /// Whether the file has the archive attribute.
+    pub is_archive: bool,
+    /// Whether the file is executable.
+    pub is_exec: bool,
+}
+
+impl CabinetLayout {
+    /// Generates a random cabinet layout within the given limits.  File
+    /// contents are a mix of empty files, highly compressible text, and
+    /// incompressible random bytes.
+    pub fn random<R: Rng + ?Sized>(
+        rng: &mut R,
+        options: &LayoutOptions,
+    ) -> CabinetLayout {
+        let num_folders = rng.gen_range(1..=options.max_folders.max(1));
+        let mut folders = Vec::with_capacity(num_folders);
+        for folder_index in 0..num_folders {
+            let compression_type = if options.compression_types.is_empty() {
+                CompressionType::None
+            } else {
+                let index = rng.gen_range(0..options.compression_types.len());
+                options.compression_types[index]
+            };
+            let num_files = rng.gen_range(0..=options.max_files_per_folder);
+            let files = (0..num_files)
+                .map(|file_index| {
+                    let name = format!(
+                        "folder{}\\file{}.dat",
+                        folder_index, file_index
+                    );
+                    random_file(rng, name, options.max_file_size)
+                })
+                .collect();
+            folders.push(FolderLayout { compression_type, files });
+        }
+        CabinetLayout { folders }
+    }
+
+    /// Returns a `CabinetBuilder` set up with this layout's folders and
+    /// files (but not their contents, which must be written through the
+    /// resulting `CabinetWriter`).
+    pub fn builder(&self) -> CabinetBuilder {
+        let mut builder = CabinetBuilder::new();
+        for folder in self.folders.iter() {
+            let folder_builder = builder.add_folder(folder.compression_type);
+            for file in folder.files.iter() {
+                let file_builder = folder_builder.add_file(file.name.as_str());
+                file_builder.set_datetime(file.datetime);
+                file_builder.set_is_read_only(file.is_read_only);
+                file_builder.set_is_hidden(file.is_hidden);
+                file_builder.set_is_system(file.is_system);
+                file_builder.set_is_archive(file.is_archive);
+                file_builder.set_is_exec(file.is_exec);
+            }
+        }
+        builder
+    }
+
+    /// Writes this layout as a cabinet file in memory.
+    pub fn write(&self) -> io::Result<Vec<u8>> {
+        let mut writer = self.builder().build(Cursor::new(Vec::new()))?;
+        let mut files = self.files();
+        while let Some(mut file_writer) = writer.next_file()? {
+            match files.next() {
+                Some(file) => file_writer.write_all(&file.data)?,
+                None => invalid_input!("Cabinet has more files than layout"),
+            }
+        }
+        Ok(writer.finish()?.into_inner())
+    }
+
+    /// Returns an iterator over all the files in the layout, in order.
+    pub fn files(&self) -> impl Iterator<Item = &FileLayout> {
+        self.folders.iter().flat_map(|folder| folder.files.iter())
+    }
+}
+
+fn random_file<R: Rng + ?Sized>(
+    rng: &mut R,
+    name: String,
+    max_size: usize,
+) -> FileLayout {
+    let size = rng.gen_range(0..=max_size);
+    let data = match rng.gen_range(0..3) {
+        0 => Vec::new(),
+        1 => {
+            let mut data = Vec::with_capacity(size);
+            while data.len() < size {
+                let word = ["cab", "folder", "block", "data", " ", "\n"]
+                    [rng.gen_range(0..6)];
+                data.extend_from_slice(word.as_bytes());
+            }
+            data.truncate(size);
+            data
+        }
+        _ => {
+            let mut data = vec![0u8; size];
+            rng.fill_bytes(&mut data);
+            data
+        }
+    };
+    let date = ((rng.gen_range(0..128) as u16) << 9)
+        | ((rng.gen_range(1..=12) as u16) << 5)
+        | rng.gen_range(1..=28) as u16;
+    let time = ((rng.gen_range(0..24) as u16) << 11)
+        | ((rng.gen_range(0..60) as u16) << 5)
+        | rng.gen_range(0..30) as u16;
+    FileLayout {
+        name,
+        data,
+        datetime: datetime_from_bits(date, time).unwrap(),
+        is_read_only: rng.gen_bool(0.25),
+        is_hidden: rng.gen_bool(0.25),
+        is_system: rng.gen_bool(0.25),
+        is_archive: rng.gen_bool(0.5),
+        is_exec: rng.gen_bool(0.25),
+    }
+}
+
+/// Checks that the given cabinet file matches the given layout: the same
+/// folders with the same compression types, and the same files with the
+/// same contents, datetimes, and attributes.  Returns an error describing
+/// the first mismatch found, if any.
+pub fn check_round_trip(
+    layout: &CabinetLayout,
+    cab_file: &[u8],
+) -> io::Result<()> {
+    let mut cabinet = Cabinet::new(Cursor::new(cab_file))?;
+    let num_folders = cabinet.folder_entries().len();
+    if num_folders != layout.folders.len() {
+        invalid_data!(
+            "Cabinet has {} folders, but layout has {}",
+            num_folders,
+            layout.folders.len()
+        );
+    }
+    for (index, (folder, expected)) in
+        cabinet.folder_entries().zip(layout.folders.iter()).enumerate()
+    {
+        if folder.compression_type() != expected.compression_type {
+            invalid_data!(
+                "Folder {} has compression type {}, but layout has {}",
+                index,
+                folder.compression_type(),
+                expected.compression_type
+            );
+        }
+        let names: Vec<&str> =
+            folder.file_entries().map(|file| file.name()).collect();
+        let expected_names: Vec<&str> =
+            expected.files.iter().map(|file| file.name.as_str()).collect();
+        if names != expected_names {
+            invalid_data!(
+                "Folder {} has files {:?}, but layout has {:?}",
+                index,
+                names,
+                expected_names
+            );
+        }
+    }
+    for expected in layout.files() {
+        let file = match cabinet.get_file_entry(&expected.name) {
+            Some(file) => file,
+            None => invalid_data!("Cabinet has no file {:?}", expected.name),
+        };
+        let attributes = [
+            ("datetime", file.datetime() == Some(expected.datetime)),
+            ("read-only", file.is_read_only() == expected.is_read_only),
+            ("hidden", file.is_hidden() == expected.is_hidden),
+            ("system", file.is_system() == expected.is_system),
+            ("archive", file.is_archive() == expected.is_archive),
+            ("exec", file.is_exec() == expected.is_exec),
+        ];
+        for (attribute, matches) in attributes {
+            if !matches {
+                invalid_data!(
+                    "File {:?} has the wrong {} attribute",
+                    expected.name,
+                    attribute
+                );
+            }
+        }
+        let mut data = Vec::new();
+        cabinet.read_file(&expected.name)?.read_to_end(&mut data)?;
+        if data != expected.data {
+            invalid_data!(
+                "File {:?} has the wrong contents ({} bytes; expected {})",
+                expected.name,
+                data.len(),
+                expected.data.len()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Panics if the given cabinet file doesn't match the given layout (see
+/// [`check_round_trip`]).
+pub fn assert_round_trip(layout: &CabinetLayout, cab_file: &[u8]) {
+    if let Err(error) = check_round_trip(layout, cab_file) {
+        panic!("Cabinet doesn't match layout: {}", error);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::SmallRng;
+    use rand::SeedableRng;
+
+    use super::LayoutOptions;
+    use super::{assert_round_trip, check_round_trip, CabinetLayout};
+
+    #[test]
+    fn random_layouts_round_trip() {
+        let mut rng = SmallRng::seed_from_u64(0x2186);
+        let options = LayoutOptions::new();
+        for _ in 0..20 {
+            let layout = CabinetLayout::random(&mut rng, &options);
+            assert_round_trip(&layout, &layout.write().unwrap());
+        }
+    }
+
+    #[test]
+    fn detect_mismatched_contents() {
+        let mut rng = SmallRng::seed_from_u64(0x2186);
+        let mut layout =
+            CabinetLayout::random(&mut rng, &LayoutOptions::new());
+        layout.folders[0].files.push(super::random_file(
+            &mut rng,
+            "extra.txt".to_string(),
+            10,
+        ));
+        let cab_file = layout.write().unwrap();
+        layout.folders[0].files.last_mut().unwrap().data.push(b'!');
+        assert!(check_round_trip(&layout, &cab_file).is_err());
+    }
+}
@@ -0,0 +1,38 @@
+/// A caller-provided hook that is told about each data block as it is read
+/// or written, e.g. so that a backup agent can rate-limit cabinet I/O by
+/// sleeping (or waiting on a token bucket) inside
+/// [`throttle`](Throttle::throttle).
+///
+/// Unlike wrapping the underlying reader or writer, the hook sees both the
+/// compressed size of each block (the bytes actually moved to or from
+/// storage) and its uncompressed size (the file data produced or consumed),
+/// so either can be used for accounting.
+///
+/// When reading (see
+/// [`Cabinet::set_throttle`](crate::Cabinet::set_throttle)), the hook is
+/// called after each block's payload has been read and before it is
+/// decompressed.  When writing (see
+/// [`CabinetBuilder::set_throttle`](crate::CabinetBuilder::set_throttle)),
+/// it is called after each block has been written.  Any closure taking the
+/// two sizes can be used as a `Throttle`:
+///
+/// ```
+/// use std::sync::Arc;
+/// let mut builder = cab::CabinetBuilder::new();
+/// builder.set_throttle(Some(Arc::new(|compressed: usize, _: usize| {
+///     // Limit output to roughly 1 MB/s.
+///     let nanos = compressed as u64 * 1000;
+///     std::thread::sleep(std::time::Duration::from_nanos(nanos));
+/// })));
+/// ```
+pub trait Throttle: Send + Sync {
+    /// Called once for each data block, with the size of the block's
+    /// (compressed) payload and the size of its uncompressed data, in bytes.
+    fn throttle(&self, compressed_size: usize, uncompressed_size: usize);
+}
+
+impl<F: Fn(usize, usize) + Send + Sync> Throttle for F {
+    fn throttle(&self, compressed_size: usize, uncompressed_size: usize) {
+        self(compressed_size, uncompressed_size)
+    }
+}
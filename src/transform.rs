@@ -0,0 +1,42 @@
+use std::io;
+
+/// A caller-provided transformation applied to the (compressed) payload of
+/// each data block, e.g. to implement encrypted or obfuscated cabinet
+/// schemes while keeping the CAB structure itself standard.
+///
+/// When writing a cabinet (see
+/// [`CabinetBuilder::set_block_transform`](crate::CabinetBuilder::set_block_transform)),
+/// [`encode`](BlockTransform::encode) is applied to each block's payload
+/// after compression and before the block is written; the block's checksum
+/// covers the encoded bytes.  When reading (see
+/// [`Cabinet::set_block_transform`](crate::Cabinet::set_block_transform)),
+/// [`decode`](BlockTransform::decode) is applied to each block's payload after
+/// the checksum is verified and before decompression.
+///
+/// A data block's reserve area (whose size is declared in the cabinet
+/// header) is never transformed, since schemes that use it typically keep
+/// per-block metadata there (such as a nonce) that is needed to decode the
+/// payload; instead, it is passed to `decode` alongside the payload.
+/// `CabinetBuilder` doesn't write per-block reserve data, so `encode` only
+/// gets the payload.
+pub trait BlockTransform: Send + Sync {
+    /// Transforms the payload of the given data block before it is written.
+    /// The result must be no more than 65535 bytes long.
+    fn encode(
+        &self,
+        folder_index: usize,
+        block_index: usize,
+        payload: Vec<u8>,
+    ) -> io::Result<Vec<u8>>;
+
+    /// Reverses [`encode`](BlockTransform::encode) for the payload of the
+    /// given data block after it is read.  `reserve` is the block's reserve
+    /// data (which is empty for cabinets written by `CabinetBuilder`).
+    fn decode(
+        &self,
+        folder_index: usize,
+        block_index: usize,
+        reserve: &[u8],
+        payload: Vec<u8>,
+    ) -> io::Result<Vec<u8>>;
+}
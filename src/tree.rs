@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+
+use crate::file::FileEntry;
+
+/// A directory in the hierarchical view of a cabinet's contents returned by
+/// [`Cabinet::tree`](crate::Cabinet::tree).
+///
+/// Cabinet files don't store directories explicitly; instead, file names may
+/// contain backslash-separated path components.  This view groups files into
+/// directories according to those components.
+#[derive(Clone, Debug)]
+pub struct TreeDirectory<'a> {
+    name: &'a str,
+    children: Vec<TreeNode<'a>>,
+    /// The index within `children` of each subdirectory, by name.
+    subdirectories: HashMap<&'a str, usize>,
+}
+
+/// One entry in a [`TreeDirectory`].
+#[derive(Clone, Debug)]
+pub enum TreeNode<'a> {
+    /// A subdirectory.
+    Directory(TreeDirectory<'a>),
+    /// A file, whose name is the last component of the file entry's path.
+    File(&'a FileEntry),
+}
+
+impl<'a> TreeDirectory<'a> {
+    pub(crate) fn build<I>(files: I) -> TreeDirectory<'a>
+    where
+        I: IntoIterator<Item = &'a FileEntry>,
+    {
+        let mut root = TreeDirectory::new("");
+        for file in files {
+            let components: Vec<&'a str> = file.path_components().collect();
+            let Some((_, dirs)) = components.split_last() else {
+                continue;
+            };
+            let mut directory = &mut root;
+            for &dir_name in dirs {
+                directory = directory.subdirectory_mut(dir_name);
+            }
+            directory.children.push(TreeNode::File(file));
+        }
+        root
+    }
+
+    fn new(name: &'a str) -> TreeDirectory<'a> {
+        TreeDirectory {
+            name,
+            children: Vec::new(),
+            subdirectories: HashMap::new(),
+        }
+    }
+
+    fn subdirectory_mut(&mut self, name: &'a str) -> &mut TreeDirectory<'a> {
+        let index = *self.subdirectories.entry(name).or_insert_with(|| {
+            self.children.push(TreeNode::Directory(TreeDirectory::new(name)));
+            self.children.len() - 1
+        });
+        match self.children[index] {
+            TreeNode::Directory(ref mut dir) => dir,
+            TreeNode::File(_) => unreachable!(),
+        }
+    }
+
+    /// Returns the name of this directory (not including its parents'
+    /// names).  The root directory's name is the empty string.
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+
+    /// Returns the files and subdirectories directly within this directory,
+    /// in the order in which they first appear in the cabinet.
+    pub fn children(&self) -> &[TreeNode<'a>] {
+        &self.children
+    }
+
+    /// Returns an iterator over the subdirectories directly within this
+    /// directory.
+    pub fn directories(&self) -> impl Iterator<Item = &TreeDirectory<'a>> {
+        self.children.iter().filter_map(|child| match child {
+            TreeNode::Directory(dir) => Some(dir),
+            TreeNode::File(_) => None,
+        })
+    }
+
+    /// Returns an iterator over the files directly within this directory.
+    pub fn files(&self) -> impl Iterator<Item = &'a FileEntry> + '_ {
+        self.children.iter().filter_map(|child| match *child {
+            TreeNode::Directory(_) => None,
+            TreeNode::File(file) => Some(file),
+        })
+    }
+}
+
+impl<'a> TreeNode<'a> {
+    /// Returns the name of this node (the last component of its path).
+    pub fn name(&self) -> &'a str {
+        match *self {
+            TreeNode::Directory(ref dir) => dir.name,
+            TreeNode::File(file) => {
+                file.path_components().last().unwrap_or("")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use super::TreeNode;
+    use crate::builder::CabinetBuilder;
+    use crate::cabinet::Cabinet;
+    use crate::ctype::CompressionType;
+
+    #[test]
+    fn build_tree_from_paths() {
+        let mut builder = CabinetBuilder::new();
+        let folder = builder.add_folder(CompressionType::None);
+        folder.add_file("readme.txt");
+        folder.add_file("bin\\x86\\tool.exe");
+        folder.add_file("bin\\readme.txt");
+        folder.add_file("bin\\x64\\tool.exe");
+        let mut writer = builder.build(Cursor::new(Vec::new())).unwrap();
+        while let Some(mut file) = writer.next_file().unwrap() {
+            file.write_all(b"data").unwrap();
+        }
+        let binary = writer.finish().unwrap().into_inner();
+        let cabinet = Cabinet::new(Cursor::new(binary)).unwrap();
+
+        let root = cabinet.tree();
+        assert_eq!(root.name(), "");
+        let names: Vec<&str> =
+            root.children().iter().map(TreeNode::name).collect();
+        assert_eq!(names, vec!["readme.txt", "bin"]);
+        let bin = root.directories().next().unwrap();
+        let names: Vec<&str> =
+            bin.children().iter().map(TreeNode::name).collect();
+        assert_eq!(names, vec!["x86", "readme.txt", "x64"]);
+        let x64 = bin.directories().nth(1).unwrap();
+        let files: Vec<&str> = x64.files().map(|file| file.name()).collect();
+        assert_eq!(files, vec!["bin\\x64\\tool.exe"]);
+    }
+}
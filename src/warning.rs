@@ -0,0 +1,209 @@
+use std::fmt;
+
+use crate::consts;
+use crate::file::FileEntry;
+use crate::folder::FolderEntry;
+
+/// A non-fatal anomaly found while opening a cabinet file.  See
+/// [`Cabinet::warnings`](crate::Cabinet::warnings).
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum Warning {
+    /// The cabinet header has flag bits set that this library doesn't know
+    /// about (given here with the known bits masked out).
+    UnknownFlags(u16),
+    /// The cabinet's format version is older than 1.3, the only version
+    /// described by the CAB spec.
+    OldVersion {
+        /// The major version number.
+        major: u8,
+        /// The minor version number.
+        minor: u8,
+    },
+    /// The data reserve size declared in the cabinet header didn't match
+    /// the cabinet's data blocks (only detected when opened with
+    /// [`ReadOptions::detect_data_reserve`](crate::ReadOptions::detect_data_reserve)).
+    DataReserveMismatch {
+        /// The data reserve size declared in the cabinet header.
+        declared: u8,
+        /// The data reserve size actually used to read the data blocks.
+        actual: u8,
+    },
+    /// A folder with no data blocks has files with data in it.  Such files
+    /// are still listed, but reading them fails with an
+    /// [`UnexpectedEof`](std::io::ErrorKind::UnexpectedEof) error wrapping a
+    /// [`BlockError`](crate::BlockError) that names the folder and files.
+    EmptyFolderWithFiles {
+        /// The index of the folder.
+        folder_index: usize,
+        /// The number of non-empty files in the folder.
+        num_files: usize,
+    },
+    /// Two files in the same folder have partially overlapping data ranges.
+    /// (Files with identical ranges, as written for duplicate files, are not
+    /// reported.)
+    OverlappingFiles {
+        /// The index of the folder.
+        folder_index: usize,
+        /// The name of the file that starts first.
+        first: String,
+        /// The name of the file that starts within the first one.
+        second: String,
+    },
+    /// The file entries aren't sorted by folder, as the CAB spec requires.
+    /// Such cabinets can still be read, but some other implementations may
+    /// not handle them.  See
+    /// [`Cabinet::file_entries_in_directory_order`](crate::Cabinet::file_entries_in_directory_order).
+    UnsortedFileEntries {
+        /// The index (in directory order) of the first file entry whose
+        /// folder comes before the previous entry's folder.
+        index: usize,
+    },
+    /// The header places the file entries before the end of the folder
+    /// entries (only accepted when opened with
+    /// [`ReadOptions::lenient_layout`](crate::ReadOptions::lenient_layout)).
+    MisplacedFileEntries {
+        /// The offset of the file entries, as given in the header.
+        first_file_offset: u32,
+        /// The offset at which the folder entries end.
+        folder_entries_end: u64,
+    },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Warning::UnknownFlags(flags) => {
+                write!(formatter, "Unknown header flags 0x{:04x}", flags)
+            }
+            Warning::OldVersion { major, minor } => write!(
+                formatter,
+                "Old cabinet format version {}.{} (expected {}.{})",
+                major,
+                minor,
+                consts::VERSION_MAJOR,
+                consts::VERSION_MINOR
+            ),
+            Warning::DataReserveMismatch { declared, actual } => write!(
+                formatter,
+                "Header declares {} bytes of data block reserve, but blocks \
+                 have {}",
+                declared, actual
+            ),
+            Warning::EmptyFolderWithFiles { folder_index, num_files } => {
+                write!(
+                    formatter,
+                    "Folder {} has no data blocks, but {} non-empty file(s)",
+                    folder_index, num_files
+                )
+            }
+            Warning::OverlappingFiles {
+                folder_index,
+                ref first,
+                ref second,
+            } => {
+                write!(
+                    formatter,
+                    "Files {:?} and {:?} in folder {} overlap",
+                    first, second, folder_index
+                )
+            }
+            Warning::UnsortedFileEntries { index } => write!(
+                formatter,
+                "File entries are not sorted by folder (starting at entry {})",
+                index
+            ),
+            Warning::MisplacedFileEntries {
+                first_file_offset,
+                folder_entries_end,
+            } => write!(
+                formatter,
+                "File entries start at offset {}, before the end of the \
+                 folder entries at offset {}",
+                first_file_offset, folder_entries_end
+            ),
+        }
+    }
+}
+
+/// Collects a warning if the given file entries (in directory order) aren't
+/// sorted by folder index.
+pub(crate) fn check_file_order(
+    files: &[FileEntry],
+    warnings: &mut Vec<Warning>,
+) {
+    let unsorted = files
+        .windows(2)
+        .position(|pair| pair[1].folder_index < pair[0].folder_index);
+    if let Some(position) = unsorted {
+        warnings.push(Warning::UnsortedFileEntries { index: position + 1 });
+    }
+}
+
+/// Collects warnings about the header fields of a cabinet.
+pub(crate) fn check_header(
+    flags: u16,
+    version: (u8, u8),
+    data_reserve_sizes: (u8, u8),
+    warnings: &mut Vec<Warning>,
+) {
+    let known_flags = consts::FLAG_PREV_CABINET
+        | consts::FLAG_NEXT_CABINET
+        | consts::FLAG_RESERVE_PRESENT;
+    if flags & !known_flags != 0 {
+        warnings.push(Warning::UnknownFlags(flags & !known_flags));
+    }
+    let (major, minor) = version;
+    if (major, minor) < (consts::VERSION_MAJOR, consts::VERSION_MINOR) {
+        warnings.push(Warning::OldVersion { major, minor });
+    }
+    let (declared, actual) = data_reserve_sizes;
+    if declared != actual {
+        warnings.push(Warning::DataReserveMismatch { declared, actual });
+    }
+}
+
+/// Collects warnings about the files within each folder of a cabinet.
+pub(crate) fn check_folders(
+    folders: &[FolderEntry],
+    warnings: &mut Vec<Warning>,
+) {
+    for (folder_index, folder) in folders.iter().enumerate() {
+        let mut files: Vec<(u64, u64, &str)> = folder
+            .file_entries()
+            .filter(|file| {
+                !file.is_continued() && file.uncompressed_size() > 0
+            })
+            .map(|file| {
+                let start = file.uncompressed_offset as u64;
+                let end = start + file.uncompressed_size() as u64;
+                (start, end, file.name())
+            })
+            .collect();
+        if folder.num_data_blocks() == 0 && !files.is_empty() {
+            warnings.push(Warning::EmptyFolderWithFiles {
+                folder_index,
+                num_files: files.len(),
+            });
+            continue;
+        }
+        files.sort();
+        files.dedup_by_key(|&mut (start, end, _)| (start, end));
+        // Compare each file with the furthest-reaching file before it:
+        let mut furthest: Option<(u64, u64, &str)> = None;
+        for &(start, end, name) in files.iter() {
+            if let Some((_, furthest_end, furthest_name)) = furthest {
+                if start < furthest_end {
+                    warnings.push(Warning::OverlappingFiles {
+                        folder_index,
+                        first: furthest_name.to_string(),
+                        second: name.to_string(),
+                    });
+                }
+            }
+            if furthest.is_none_or(|(_, furthest_end, _)| end > furthest_end) {
+                furthest = Some((start, end, name));
+            }
+        }
+    }
+}
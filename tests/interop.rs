@@ -0,0 +1,80 @@
+#![cfg(feature = "interop")]
+
+use std::io::{Cursor, Write};
+
+use cab::interop::verify_with_available;
+
+// These tests need at least one reference extractor (cabextract, gcab, or
+// expand.exe) to be installed, so they're ignored by default; run them with
+// `cargo test --features interop -- --ignored`.
+
+// ========================================================================= //
+
+/// Checks the cabinet with every available extractor, failing if there are
+/// none (rather than passing without checking anything).
+fn verify(cab_file: &[u8], files: &[(&str, &[u8])]) {
+    let extractors = verify_with_available(cab_file, files).unwrap();
+    assert!(!extractors.is_empty(), "No reference extractor is installed");
+}
+
+fn build_cabinet(
+    ctype: cab::CompressionType,
+    files: &[(&str, &[u8])],
+) -> Vec<u8> {
+    build_cabinet_with(ctype, files, |_| {})
+}
+
+fn build_cabinet_with(
+    ctype: cab::CompressionType,
+    files: &[(&str, &[u8])],
+    configure: impl FnOnce(&mut cab::FolderBuilder),
+) -> Vec<u8> {
+    let mut cab_builder = cab::CabinetBuilder::new();
+    {
+        let folder_builder = cab_builder.add_folder(ctype);
+        configure(folder_builder);
+        for &(name, _) in files.iter() {
+            folder_builder.add_file(name);
+        }
+    }
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    let mut index = 0;
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        file_writer.write_all(files[index].1).unwrap();
+        index += 1;
+    }
+    cab_writer.finish().unwrap().into_inner()
+}
+
+#[test]
+#[ignore = "needs a reference extractor"]
+fn uncompressed_cabinet_extracts_with_reference_tools() {
+    let files: &[(&str, &[u8])] =
+        &[("hi.txt", b"Hello, world!\n"), ("bye.txt", b"See you later!\n")];
+    let cab_file = build_cabinet(cab::CompressionType::None, files);
+    verify(&cab_file, files);
+}
+
+#[test]
+#[ignore = "needs a reference extractor"]
+fn mszipped_cabinet_extracts_with_reference_tools() {
+    let original = lipsum::lipsum(30000);
+    let files: &[(&str, &[u8])] =
+        &[("lorem_ipsum.txt", original.as_bytes()), ("docs\\empty.txt", b"")];
+    let cab_file = build_cabinet(cab::CompressionType::MsZip, files);
+    verify(&cab_file, files);
+}
+
+#[test]
+#[ignore = "needs a reference extractor"]
+fn mszipped_cabinet_without_shared_history_extracts_with_reference_tools() {
+    let original = lipsum::lipsum(30000);
+    let files: &[(&str, &[u8])] = &[("lorem_ipsum.txt", original.as_bytes())];
+    let cab_file =
+        build_cabinet_with(cab::CompressionType::MsZip, files, |folder| {
+            folder.set_mszip_shared_history(false)
+        });
+    verify(&cab_file, files);
+}
+
+// ========================================================================= //
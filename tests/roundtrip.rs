@@ -1,9 +1,11 @@
 use std::io::{Cursor, Read, Write};
+#[cfg(feature = "time")]
 use time::macros::datetime;
 
 // ========================================================================= //
 
 #[test]
+#[cfg(feature = "time")]
 fn cabinet_with_one_small_uncompressed_text_file() {
     let original = lipsum::lipsum(500);
     let datetime = datetime!(2063-04-05 23:14:38);
@@ -55,7 +57,7 @@ fn cabinet_with_one_small_mszipped_text_file() {
 
     let mut cabinet = cab::Cabinet::new(Cursor::new(cab_file)).unwrap();
     assert_eq!(
-        cabinet.folder_entries().nth(0).unwrap().compression_type(),
+        cabinet.folder_entries().next().unwrap().compression_type(),
         cab::CompressionType::MsZip
     );
     let mut output = Vec::new();
@@ -81,10 +83,10 @@ fn cabinet_with_one_big_uncompressed_text_file() {
 
     let mut cabinet = cab::Cabinet::new(Cursor::new(cab_file)).unwrap();
     {
-        let folder = cabinet.folder_entries().nth(0).unwrap();
+        let folder = cabinet.folder_entries().next().unwrap();
         assert_eq!(folder.compression_type(), cab::CompressionType::None);
         assert!(folder.num_data_blocks() > 1);
-        let file = folder.file_entries().nth(0).unwrap();
+        let file = folder.file_entries().next().unwrap();
         assert_eq!(file.uncompressed_size() as usize, original.len());
     }
     let mut output = Vec::new();
@@ -111,9 +113,9 @@ fn cabinet_with_one_big_mszipped_text_file() {
 
     let mut cabinet = cab::Cabinet::new(Cursor::new(cab_file)).unwrap();
     {
-        let folder = cabinet.folder_entries().nth(0).unwrap();
+        let folder = cabinet.folder_entries().next().unwrap();
         assert_eq!(folder.compression_type(), cab::CompressionType::MsZip);
-        let file = folder.file_entries().nth(0).unwrap();
+        let file = folder.file_entries().next().unwrap();
         assert_eq!(file.uncompressed_size() as usize, original.len());
     }
     let mut output = Vec::new();
@@ -141,10 +143,10 @@ fn random_data_roundtrip(num_bytes: usize, ctype: cab::CompressionType) {
 
     let mut cabinet = cab::Cabinet::new(Cursor::new(cab_file)).unwrap();
     {
-        let folder = cabinet.folder_entries().nth(0).unwrap();
+        let folder = cabinet.folder_entries().next().unwrap();
         assert_eq!(folder.compression_type(), ctype);
         assert!((folder.num_data_blocks() as usize) >= (num_bytes / 0x8000));
-        let file = folder.file_entries().nth(0).unwrap();
+        let file = folder.file_entries().next().unwrap();
         assert_eq!(file.name(), "binary");
         assert_eq!(file.uncompressed_size() as usize, original.len());
     }
@@ -175,3 +177,2146 @@ fn cabinet_with_one_big_mszipped_binary_file() {
 }
 
 // ========================================================================= //
+
+fn append_roundtrip(num_new_folders: usize) {
+    let original = lipsum::lipsum(5000);
+    let mut cab_builder = cab::CabinetBuilder::new();
+    cab_builder.add_folder(cab::CompressionType::MsZip).add_file("a");
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        file_writer.write_all(original.as_bytes()).unwrap();
+    }
+    let mut cab_file = cab_writer.finish().unwrap();
+    cab_file.set_position(0);
+
+    let cabinet = cab::Cabinet::new(cab_file).unwrap();
+    let mut appender = cabinet.into_appender();
+    for index in 0..num_new_folders {
+        appender
+            .add_folder(cab::CompressionType::None)
+            .add_file(format!("new{}.txt", index));
+    }
+    let mut cab_writer = appender.build().unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        let name = file_writer.file_name().to_string();
+        file_writer.write_all(name.as_bytes()).unwrap();
+    }
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+
+    let mut cabinet = cab::Cabinet::new(Cursor::new(cab_file)).unwrap();
+    assert_eq!(cabinet.folder_entries().count(), 1 + num_new_folders);
+    let mut output = String::new();
+    cabinet.read_file("a").unwrap().read_to_string(&mut output).unwrap();
+    assert_eq!(output, original);
+    for index in 0..num_new_folders {
+        let name = format!("new{}.txt", index);
+        let mut output = String::new();
+        cabinet.read_file(&name).unwrap().read_to_string(&mut output).unwrap();
+        assert_eq!(output, name);
+    }
+}
+
+#[test]
+fn append_folder_into_free_space() {
+    append_roundtrip(1);
+}
+
+#[test]
+fn append_folders_shifting_existing_data() {
+    append_roundtrip(4);
+}
+
+#[test]
+fn append_rejects_split_incompressible_folder() {
+    let mut cab_builder = cab::CabinetBuilder::new();
+    cab_builder.add_folder(cab::CompressionType::None).add_file("o");
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        file_writer.write_all(b"original").unwrap();
+    }
+    let mut cab_file = cab_writer.finish().unwrap();
+    cab_file.set_position(0);
+
+    // The folder could be split into up to four folders, but only one
+    // folder entry would be reserved for it:
+    let cabinet = cab::Cabinet::new(cab_file).unwrap();
+    let mut appender = cabinet.into_appender();
+    let folder = appender.add_folder(cab::CompressionType::MsZip);
+    folder.set_split_incompressible(true);
+    for name in ["rand1.bin", "text.txt", "rand2.bin", "text2.txt"] {
+        folder.add_file(name);
+    }
+    let error = appender.build().err().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    assert_eq!(
+        error.to_string(),
+        "Folder compressed size limits and incompressible splitting are not \
+         supported when appending to a cabinet"
+    );
+}
+
+// ========================================================================= //
+
+#[test]
+fn cabinet_with_set_links() {
+    let mut cab_builder = cab::CabinetBuilder::new();
+    cab_builder.set_cabinet_set(0x1234, 1);
+    cab_builder.set_prev_cabinet("disk1.cab", "Disk 1");
+    cab_builder.set_next_cabinet("disk3.cab", "");
+    cab_builder.set_reserve_data(b"reserved".to_vec());
+    cab_builder.add_folder(cab::CompressionType::MsZip).add_file("hi.txt");
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        file_writer.write_all(b"Hello, world!\n").unwrap();
+    }
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+
+    let mut cabinet = cab::Cabinet::new(Cursor::new(cab_file)).unwrap();
+    assert_eq!(cabinet.cabinet_set_id(), 0x1234);
+    assert_eq!(cabinet.cabinet_set_index(), 1);
+    assert!(cabinet.is_multi_part());
+    assert_eq!(cabinet.prev_cabinet_name(), Some("disk1.cab"));
+    assert_eq!(cabinet.prev_disk_name(), Some("Disk 1"));
+    assert_eq!(cabinet.next_cabinet_name(), Some("disk3.cab"));
+    assert_eq!(cabinet.next_disk_name(), Some(""));
+    assert_eq!(cabinet.reserve_data(), b"reserved");
+    let mut data = String::new();
+    cabinet.read_file("hi.txt").unwrap().read_to_string(&mut data).unwrap();
+    assert_eq!(data, "Hello, world!\n");
+
+    let mut cab_builder = cab::CabinetBuilder::new();
+    cab_builder.set_next_cabinet("disk\0.cab", "");
+    assert_eq!(
+        cab_builder.build(Cursor::new(Vec::new())).err().unwrap().kind(),
+        std::io::ErrorKind::InvalidInput
+    );
+}
+
+// ========================================================================= //
+
+#[test]
+fn folders_split_at_max_compressed_size() {
+    use rand::{RngCore, SeedableRng};
+
+    let mut rng = rand::rngs::SmallRng::from_entropy();
+    let originals: Vec<Vec<u8>> = (0..5)
+        .map(|_| {
+            let mut data = vec![0; 50_000];
+            rng.fill_bytes(&mut data);
+            data
+        })
+        .collect();
+
+    let mut cab_builder = cab::CabinetBuilder::new();
+    {
+        let folder = cab_builder.add_folder(cab::CompressionType::MsZip);
+        folder.set_max_compressed_size(Some(90_000));
+        for index in 0..originals.len() {
+            folder.add_file(format!("file{}.bin", index));
+        }
+    }
+    cab_builder.add_folder(cab::CompressionType::None).add_file("last.txt");
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        let data = match file_writer.file_name() {
+            "last.txt" => b"The end.".to_vec(),
+            name => originals[name[4..5].parse::<usize>().unwrap()].clone(),
+        };
+        file_writer.write_all(&data).unwrap();
+    }
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+
+    let mut cabinet = cab::Cabinet::new(Cursor::new(cab_file)).unwrap();
+    let folder_files: Vec<Vec<String>> = cabinet
+        .folder_entries()
+        .map(|folder| {
+            folder.file_entries().map(|file| file.name().to_string()).collect()
+        })
+        .collect();
+    assert_eq!(
+        folder_files,
+        vec![
+            vec!["file0.bin", "file1.bin"],
+            vec!["file2.bin", "file3.bin"],
+            vec!["file4.bin"],
+            vec!["last.txt"],
+        ]
+    );
+    for (index, original) in originals.iter().enumerate() {
+        let mut output = Vec::new();
+        let name = format!("file{}.bin", index);
+        cabinet.read_file(&name).unwrap().read_to_end(&mut output).unwrap();
+        assert_eq!(&output, original);
+    }
+    let mut output = Vec::new();
+    cabinet.read_file("last.txt").unwrap().read_to_end(&mut output).unwrap();
+    assert_eq!(output, b"The end.");
+}
+
+// ========================================================================= //
+
+/// A writer into a shared buffer, which can be made to start failing (to
+/// simulate an interruption).
+struct SharedWriter {
+    data: std::rc::Rc<std::cell::RefCell<Vec<u8>>>,
+    position: u64,
+    failing: std::rc::Rc<std::cell::Cell<bool>>,
+}
+
+impl Write for SharedWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.failing.get() {
+            return Err(std::io::Error::other("interrupted"));
+        }
+        let mut data = self.data.borrow_mut();
+        let start = self.position as usize;
+        if data.len() < start + buf.len() {
+            data.resize(start + buf.len(), 0);
+        }
+        data[start..(start + buf.len())].copy_from_slice(buf);
+        self.position += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl std::io::Seek for SharedWriter {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        let mut cursor = Cursor::new(&[][..]);
+        cursor.set_position(self.position);
+        let len = self.data.borrow().len() as i64;
+        self.position = match pos {
+            std::io::SeekFrom::End(offset) => (len + offset) as u64,
+            pos => cursor.seek(pos)?,
+        };
+        Ok(self.position)
+    }
+}
+
+#[test]
+fn resume_interrupted_cabinet() {
+    use rand::{RngCore, SeedableRng};
+    use std::io::Seek;
+
+    let mut rng = rand::rngs::SmallRng::seed_from_u64(0x2124);
+    let mut random = vec![0; 40_000];
+    rng.fill_bytes(&mut random);
+    let text = lipsum::lipsum(3000);
+    let make_builder = || {
+        let mut cab_builder = cab::CabinetBuilder::new();
+        let folder = cab_builder.add_folder(cab::CompressionType::MsZip);
+        folder.set_max_compressed_size(Some(30_000));
+        folder.add_file("random1.bin");
+        folder.add_file("random2.bin");
+        cab_builder.add_folder(cab::CompressionType::None).add_file("a.txt");
+        cab_builder.add_folder(cab::CompressionType::MsZip).add_file("b.txt");
+        cab_builder
+    };
+    let contents = |name: &str| -> Vec<u8> {
+        if name.ends_with(".bin") {
+            random.clone()
+        } else {
+            text.as_bytes().to_vec()
+        }
+    };
+
+    // Write the first folder (which gets split in two), then get
+    // interrupted partway through the next one:
+    let data = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+    let failing = std::rc::Rc::new(std::cell::Cell::new(false));
+    let writer = SharedWriter {
+        data: data.clone(),
+        position: 0,
+        failing: failing.clone(),
+    };
+    let mut cab_writer = make_builder().build(writer).unwrap();
+    for _ in 0..2 {
+        let mut file_writer = cab_writer.next_file().unwrap().unwrap();
+        let name = file_writer.file_name().to_string();
+        file_writer.write_all(&contents(&name)).unwrap();
+    }
+    let token = cab_writer.checkpoint().unwrap();
+    assert_eq!(token.num_completed_folders(), 2);
+    {
+        let mut file_writer = cab_writer.next_file().unwrap().unwrap();
+        assert_eq!(file_writer.file_name(), "a.txt");
+        file_writer.write_all(b"partial").unwrap();
+    }
+    failing.set(true);
+    drop(cab_writer);
+
+    // Resume writing from the checkpoint:
+    let token = cab::CheckpointToken::from_bytes(&token.to_bytes()).unwrap();
+    let mut partial = data.borrow().clone();
+    partial.truncate(token.data_offset() as usize);
+    let mut cursor = Cursor::new(partial);
+    cursor.seek(std::io::SeekFrom::End(0)).unwrap();
+    let mut cab_writer = make_builder().resume(cursor, &token).unwrap();
+    let mut names = Vec::new();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        let name = file_writer.file_name().to_string();
+        file_writer.write_all(&contents(&name)).unwrap();
+        names.push(name);
+    }
+    assert_eq!(names, vec!["a.txt", "b.txt"]);
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+
+    let mut cabinet = cab::Cabinet::new(Cursor::new(cab_file)).unwrap();
+    assert_eq!(cabinet.folder_entries().len(), 4);
+    for name in ["random1.bin", "random2.bin", "a.txt", "b.txt"] {
+        let mut output = Vec::new();
+        cabinet.read_file(name).unwrap().read_to_end(&mut output).unwrap();
+        assert_eq!(output, contents(name), "{}", name);
+    }
+
+    // Resuming with a different builder should fail:
+    let mut other_builder = make_builder();
+    other_builder.add_folder(cab::CompressionType::None).add_file("c.txt");
+    assert!(other_builder.resume(Cursor::new(Vec::new()), &token).is_err());
+    // Even if it only swaps two files with equal-length names:
+    let mut other_builder = cab::CabinetBuilder::new();
+    let folder = other_builder.add_folder(cab::CompressionType::MsZip);
+    folder.set_max_compressed_size(Some(30_000));
+    folder.add_file("random2.bin");
+    folder.add_file("random1.bin");
+    other_builder.add_folder(cab::CompressionType::None).add_file("a.txt");
+    other_builder.add_folder(cab::CompressionType::MsZip).add_file("b.txt");
+    assert!(other_builder.resume(Cursor::new(Vec::new()), &token).is_err());
+}
+
+// ========================================================================= //
+
+struct XorTransform;
+
+impl XorTransform {
+    fn apply(folder: usize, block: usize, mut payload: Vec<u8>) -> Vec<u8> {
+        for (index, byte) in payload.iter_mut().enumerate() {
+            *byte ^= (folder * 31 + block * 7 + index) as u8 | 0x80;
+        }
+        payload
+    }
+}
+
+impl cab::BlockTransform for XorTransform {
+    fn encode(
+        &self,
+        folder: usize,
+        block: usize,
+        payload: Vec<u8>,
+    ) -> std::io::Result<Vec<u8>> {
+        Ok(XorTransform::apply(folder, block, payload))
+    }
+
+    fn decode(
+        &self,
+        folder: usize,
+        block: usize,
+        _reserve: &[u8],
+        payload: Vec<u8>,
+    ) -> std::io::Result<Vec<u8>> {
+        Ok(XorTransform::apply(folder, block, payload))
+    }
+}
+
+#[test]
+fn cabinet_with_block_transform() {
+    let original = lipsum::lipsum(20000);
+    let transform: std::sync::Arc<dyn cab::BlockTransform> =
+        std::sync::Arc::new(XorTransform);
+
+    let mut cab_builder = cab::CabinetBuilder::new();
+    cab_builder.set_block_transform(Some(transform.clone()));
+    cab_builder.add_folder(cab::CompressionType::None).add_file("plain.txt");
+    cab_builder.add_folder(cab::CompressionType::MsZip).add_file("zipped.txt");
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        file_writer.write_all(original.as_bytes()).unwrap();
+    }
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+    let needle = &original.as_bytes()[..100];
+    assert!(!cab_file.windows(needle.len()).any(|window| window == needle));
+
+    let mut cabinet = cab::Cabinet::new(Cursor::new(cab_file)).unwrap();
+    let mut output = Vec::new();
+    assert!(cabinet
+        .read_file("zipped.txt")
+        .and_then(|mut reader| reader.read_to_end(&mut output))
+        .is_err());
+    cabinet.set_block_transform(Some(transform));
+    for name in ["plain.txt", "zipped.txt"] {
+        let mut output = String::new();
+        cabinet.read_file(name).unwrap().read_to_string(&mut output).unwrap();
+        assert_eq!(output, original);
+    }
+}
+
+/// A transform whose `decode` forgets to undo what `encode` did.
+struct BrokenTransform;
+
+impl cab::BlockTransform for BrokenTransform {
+    fn encode(
+        &self,
+        folder: usize,
+        block: usize,
+        payload: Vec<u8>,
+    ) -> std::io::Result<Vec<u8>> {
+        Ok(XorTransform::apply(folder, block, payload))
+    }
+
+    fn decode(
+        &self,
+        _folder: usize,
+        _block: usize,
+        _reserve: &[u8],
+        payload: Vec<u8>,
+    ) -> std::io::Result<Vec<u8>> {
+        Ok(payload)
+    }
+}
+
+fn write_verified_cabinet(
+    transform: std::sync::Arc<dyn cab::BlockTransform>,
+    data: &[u8],
+) -> std::io::Result<Vec<u8>> {
+    let mut cab_builder = cab::CabinetBuilder::new();
+    cab_builder.set_verify_blocks(true);
+    cab_builder.set_block_transform(Some(transform));
+    cab_builder.add_folder(cab::CompressionType::None).add_file("plain.txt");
+    cab_builder.add_folder(cab::CompressionType::MsZip).add_file("zipped.txt");
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new()))?;
+    while let Some(mut file_writer) = cab_writer.next_file()? {
+        file_writer.write_all(data)?;
+    }
+    Ok(cab_writer.finish_and_verify()?.into_inner())
+}
+
+#[test]
+fn verify_cabinet_after_write() {
+    let original = lipsum::lipsum(20000);
+    let cab_file = write_verified_cabinet(
+        std::sync::Arc::new(XorTransform),
+        original.as_bytes(),
+    )
+    .unwrap();
+    assert!(cab::Cabinet::new(Cursor::new(cab_file)).is_ok());
+
+    let error = write_verified_cabinet(
+        std::sync::Arc::new(BrokenTransform),
+        original.as_bytes(),
+    )
+    .unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    assert!(error.to_string().contains("failed verification"));
+}
+
+// ========================================================================= //
+
+#[test]
+fn deduplicate_identical_files() {
+    use rand::{RngCore, SeedableRng};
+
+    let mut rng = rand::rngs::SmallRng::from_entropy();
+    let mut driver = vec![0; 40_000];
+    rng.fill_bytes(&mut driver);
+    let mut other = vec![0; 40_000];
+    rng.fill_bytes(&mut other);
+
+    let mut cab_builder = cab::CabinetBuilder::new();
+    {
+        let folder = cab_builder.add_folder(cab::CompressionType::MsZip);
+        folder.set_deduplicate(true);
+        folder.add_file("x64\\driver.sys");
+        folder.add_file("other.bin");
+        folder.add_file("x86\\driver.sys");
+        folder.add_duplicate_file("arm64\\driver.sys", "x64\\driver.sys");
+    }
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    let mut names = Vec::new();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        names.push(file_writer.file_name().to_string());
+        let data = match file_writer.file_name() {
+            "other.bin" => &other,
+            _ => &driver,
+        };
+        file_writer.write_all(data).unwrap();
+    }
+    assert_eq!(names, vec!["x64\\driver.sys", "other.bin", "x86\\driver.sys"]);
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+    assert!(cab_file.len() < 90_000);
+
+    let mut cabinet = cab::Cabinet::new(Cursor::new(cab_file)).unwrap();
+    for name in ["x64\\driver.sys", "x86\\driver.sys", "arm64\\driver.sys"] {
+        let mut output = Vec::new();
+        cabinet.read_file(name).unwrap().read_to_end(&mut output).unwrap();
+        assert_eq!(output, driver);
+    }
+    let mut output = Vec::new();
+    cabinet.read_file("other.bin").unwrap().read_to_end(&mut output).unwrap();
+    assert_eq!(output, other);
+}
+
+#[test]
+fn duplicate_file_must_follow_original() {
+    let mut cab_builder = cab::CabinetBuilder::new();
+    {
+        let folder = cab_builder.add_folder(cab::CompressionType::None);
+        folder.add_duplicate_file("copy.txt", "original.txt");
+        folder.add_file("original.txt");
+    }
+    let error = cab_builder.build(Cursor::new(Vec::new())).err().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+// ========================================================================= //
+
+#[test]
+fn report_folder_sizes_and_suggest_groupings() {
+    use rand::{RngCore, SeedableRng};
+
+    // Files with the same extension have identical contents, but are spread
+    // too far apart for MSZIP to notice when the extensions are interleaved.
+    let mut rng = rand::rngs::SmallRng::from_entropy();
+    let mut text = vec![0; 20_000];
+    rng.fill_bytes(&mut text);
+    let mut binary = vec![0; 20_000];
+    rng.fill_bytes(&mut binary);
+
+    let mut cab_builder = cab::CabinetBuilder::new();
+    {
+        let folder = cab_builder.add_folder(cab::CompressionType::MsZip);
+        for index in 0..4 {
+            folder.add_file(format!("file{}.txt", index));
+            folder.add_file(format!("file{}.dat", index));
+        }
+    }
+    cab_builder.add_folder(cab::CompressionType::None).add_file("end.txt");
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        let data = match file_writer.file_name() {
+            "end.txt" => b"The end.".as_slice(),
+            name if name.ends_with(".txt") => &text,
+            _ => &binary,
+        };
+        file_writer.write_all(data).unwrap();
+    }
+    let (cursor, report) = cab_writer.finish_with_report().unwrap();
+    let cab_file = cursor.into_inner();
+
+    let folders = report.folders();
+    assert_eq!(folders.len(), 2);
+    assert_eq!(folders[0].index(), 0);
+    assert_eq!(folders[0].compression_type(), cab::CompressionType::MsZip);
+    assert_eq!(folders[0].uncompressed_size(), 160_000);
+    assert_eq!(folders[0].num_data_blocks(), 5);
+    assert_eq!(folders[0].files().len(), 8);
+    assert_eq!(folders[0].files()[3].name(), "file1.dat");
+    assert_eq!(folders[0].files()[3].offset(), 60_000);
+    assert_eq!(folders[0].files()[3].size(), 20_000);
+    assert_eq!(folders[1].uncompressed_size(), 8);
+    assert_eq!(folders[1].compressed_size(), 16);
+    assert_eq!(folders[1].ratio(), Some(2.0));
+    assert_eq!(
+        report.total_compressed_size(),
+        folders[0].compressed_size() + 16
+    );
+    assert!(report.total_compressed_size() < cab_file.len() as u64);
+
+    let mut cabinet = cab::Cabinet::new(Cursor::new(cab_file)).unwrap();
+    let suggestions = cabinet.suggest_groupings().unwrap();
+    assert_eq!(suggestions.len(), 1);
+    let suggestion = &suggestions[0];
+    assert_eq!(suggestion.folder_index(), 0);
+    assert_eq!(suggestion.extensions(), &["dat", "txt"]);
+    assert!(suggestion.improvement_percent() > 50.0);
+    assert!(suggestion.to_string().starts_with("folder 0 would compress"));
+}
+
+// ========================================================================= //
+
+/// Compresses the given files into a single MSZIP folder elsewhere, and
+/// returns the (payload, uncompressed size) of each of its data blocks.
+fn compress_folder_elsewhere(files: &[&[u8]]) -> Vec<(Vec<u8>, u16)> {
+    let mut cab_builder = cab::CabinetBuilder::new();
+    {
+        let folder = cab_builder.add_folder(cab::CompressionType::MsZip);
+        for index in 0..files.len() {
+            folder.add_file(format!("{}", index));
+        }
+    }
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        let index: usize = file_writer.file_name().parse().unwrap();
+        file_writer.write_all(files[index]).unwrap();
+    }
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+    let mut cabinet = cab::Cabinet::new(Cursor::new(&cab_file)).unwrap();
+    let blocks: Vec<cab::DataBlockInfo> =
+        cabinet.data_blocks(0).unwrap().map(Result::unwrap).collect();
+    blocks
+        .iter()
+        .map(|block| {
+            let start = block.offset() as usize + 8;
+            let end = start + block.compressed_size() as usize;
+            (cab_file[start..end].to_vec(), block.uncompressed_size())
+        })
+        .collect()
+}
+
+#[test]
+fn cabinet_with_precompressed_folder() {
+    let first = lipsum::lipsum(10_000);
+    let second = lipsum::lipsum(3000);
+    let blocks =
+        compress_folder_elsewhere(&[first.as_bytes(), second.as_bytes()]);
+    assert!(blocks.len() > 1);
+
+    let mut cab_builder = cab::CabinetBuilder::new();
+    cab_builder.add_folder(cab::CompressionType::None).add_file("readme.txt");
+    {
+        let folder = cab_builder.add_folder(cab::CompressionType::MsZip);
+        folder.set_precompressed(true);
+        folder.add_file("first.txt").set_uncompressed_size(first.len() as u32);
+        folder
+            .add_file("second.txt")
+            .set_uncompressed_size(second.len() as u32);
+        folder.add_duplicate_file("copy.txt", "second.txt");
+    }
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    let mut file_writer = cab_writer.next_file().unwrap().unwrap();
+    assert_eq!(file_writer.file_name(), "readme.txt");
+    file_writer.write_all(b"Hello!").unwrap();
+    for (payload, uncompressed_size) in blocks.iter() {
+        cab_writer
+            .write_precompressed_block(payload, *uncompressed_size)
+            .unwrap();
+    }
+    assert!(cab_writer.next_file().unwrap().is_none());
+    let cab_file = cab_writer.finish_and_verify().unwrap().into_inner();
+
+    let mut cabinet = cab::Cabinet::new(Cursor::new(cab_file)).unwrap();
+    for (name, expected) in [
+        ("readme.txt", "Hello!"),
+        ("first.txt", first.as_str()),
+        ("second.txt", second.as_str()),
+        ("copy.txt", second.as_str()),
+    ] {
+        let mut output = String::new();
+        cabinet.read_file(name).unwrap().read_to_string(&mut output).unwrap();
+        assert_eq!(output, expected);
+    }
+}
+
+#[test]
+fn precompressed_folder_size_mismatch() {
+    let data = lipsum::lipsum(1000);
+    let blocks = compress_folder_elsewhere(&[data.as_bytes()]);
+
+    let mut cab_builder = cab::CabinetBuilder::new();
+    {
+        let folder = cab_builder.add_folder(cab::CompressionType::MsZip);
+        folder.set_precompressed(true);
+        folder
+            .add_file("lorem.txt")
+            .set_uncompressed_size(data.len() as u32 + 1);
+    }
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    for (payload, uncompressed_size) in blocks.iter() {
+        cab_writer
+            .write_precompressed_block(payload, *uncompressed_size)
+            .unwrap();
+    }
+    let error = cab_writer.finish().unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn precompressed_folder_with_too_many_blocks() {
+    let mut cab_builder = cab::CabinetBuilder::new();
+    {
+        let folder = cab_builder.add_folder(cab::CompressionType::None);
+        folder.set_precompressed(true);
+        folder.add_file("ones.bin").set_uncompressed_size(0x10000);
+    }
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    for _ in 0..u16::MAX {
+        cab_writer.write_precompressed_block(b"1", 1).unwrap();
+    }
+    let error = cab_writer.write_precompressed_block(b"1", 1).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+// ========================================================================= //
+
+#[test]
+fn build_cabinet_through_spool() {
+    let original = lipsum::lipsum(30_000);
+    let mut cab_builder = cab::CabinetBuilder::new();
+    cab_builder.add_folder(cab::CompressionType::None).add_file("lorem.txt");
+    let spool = cab::SpoolOptions::new().memory_threshold(1000).create();
+    let mut cab_writer = cab_builder.build(spool).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        file_writer.write_all(original.as_bytes()).unwrap();
+    }
+    // Write into a sink that doesn't implement Seek:
+    let cab_file = cab_writer.finish_to(Vec::new()).unwrap();
+
+    let mut cabinet = cab::Cabinet::new(Cursor::new(cab_file)).unwrap();
+    let mut output = String::new();
+    cabinet
+        .read_file("lorem.txt")
+        .unwrap()
+        .read_to_string(&mut output)
+        .unwrap();
+    assert_eq!(output, original);
+}
+
+// ========================================================================= //
+
+#[test]
+fn compression_effort_levels() {
+    let original = lipsum::lipsum(20_000);
+    let mut sizes = Vec::new();
+    for effort in [cab::CompressionEffort::Fast, cab::CompressionEffort::Max] {
+        let mut cab_builder = cab::CabinetBuilder::new();
+        let folder = cab_builder.add_folder(cab::CompressionType::MsZip);
+        folder.set_compression_effort(effort);
+        folder.add_file("lorem.txt");
+        let mut cab_writer =
+            cab_builder.build(Cursor::new(Vec::new())).unwrap();
+        while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+            file_writer.write_all(original.as_bytes()).unwrap();
+        }
+        let cab_file = cab_writer.finish_and_verify().unwrap().into_inner();
+        sizes.push(cab_file.len());
+
+        let mut cabinet = cab::Cabinet::new(Cursor::new(cab_file)).unwrap();
+        let mut output = String::new();
+        let mut reader = cabinet.read_file("lorem.txt").unwrap();
+        reader.read_to_string(&mut output).unwrap();
+        assert_eq!(output, original);
+    }
+    assert!(sizes[0] >= sizes[1]);
+}
+
+#[test]
+fn mszip_folders_with_and_without_shared_history() {
+    let original = lipsum::lipsum(40_000);
+    let mut sizes = Vec::new();
+    for shared_history in [true, false] {
+        let mut cab_builder = cab::CabinetBuilder::new();
+        let folder = cab_builder.add_folder(cab::CompressionType::MsZip);
+        folder.set_mszip_shared_history(shared_history);
+        folder.add_file("lorem.txt");
+        let mut cab_writer =
+            cab_builder.build(Cursor::new(Vec::new())).unwrap();
+        while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+            file_writer.write_all(original.as_bytes()).unwrap();
+        }
+        let cab_file = cab_writer.finish_and_verify().unwrap().into_inner();
+        sizes.push(cab_file.len());
+
+        let mut cabinet = cab::Cabinet::new(Cursor::new(cab_file)).unwrap();
+        let mut output = String::new();
+        let mut reader = cabinet.read_file("lorem.txt").unwrap();
+        reader.read_to_string(&mut output).unwrap();
+        assert_eq!(output, original);
+    }
+    assert!(sizes[0] < sizes[1]);
+}
+
+#[test]
+fn detect_missing_data_reserve() {
+    let original = lipsum::lipsum(20_000);
+    let mut cab_builder = cab::CabinetBuilder::new();
+    cab_builder.set_reserve_data(vec![1, 2, 3]);
+    cab_builder.add_folder(cab::CompressionType::MsZip).add_file("lorem.txt");
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        file_writer.write_all(original.as_bytes()).unwrap();
+    }
+    let mut cab_file = cab_writer.finish().unwrap().into_inner();
+    // Claim that each data block has four bytes of reserve data, even though
+    // none of them actually do:
+    assert_eq!(cab_file[39], 0);
+    cab_file[39] = 4;
+
+    let mut cabinet = cab::Cabinet::new(Cursor::new(&cab_file)).unwrap();
+    let mut output = Vec::new();
+    let result = cabinet
+        .read_file("lorem.txt")
+        .and_then(|mut reader| reader.read_to_end(&mut output));
+    assert!(result.is_err());
+
+    let mut cabinet = cab::ReadOptions::new()
+        .detect_data_reserve(true)
+        .open(Cursor::new(&cab_file))
+        .unwrap();
+    assert_eq!(cabinet.data_reserve_discrepancy(), Some((4, 0)));
+    let mut output = String::new();
+    let mut reader = cabinet.read_file("lorem.txt").unwrap();
+    reader.read_to_string(&mut output).unwrap();
+    assert_eq!(output, original);
+}
+
+#[test]
+fn skip_large_header_reserve() {
+    let reserve: Vec<u8> = (0..60_000).map(|i| (i % 251) as u8).collect();
+    let mut cab_builder = cab::CabinetBuilder::new();
+    cab_builder.set_reserve_data(reserve.clone());
+    cab_builder.add_folder(cab::CompressionType::MsZip).add_file("hi.txt");
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        file_writer.write_all(b"Hello, world!").unwrap();
+    }
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+
+    let cabinet = cab::Cabinet::new(Cursor::new(&cab_file)).unwrap();
+    assert_eq!(cabinet.reserve_data(), reserve.as_slice());
+    let mut output = Vec::new();
+    cabinet.reserve_data_reader().unwrap().read_to_end(&mut output).unwrap();
+    assert_eq!(output, reserve);
+
+    let mut cabinet = cab::ReadOptions::new()
+        .skip_reserve_data(true)
+        .open(Cursor::new(&cab_file))
+        .unwrap();
+    assert_eq!(cabinet.reserve_data(), &[]);
+    assert_eq!(cabinet.reserve_data_size(), 60_000);
+    let mut output = Vec::new();
+    cabinet.reserve_data_reader().unwrap().read_to_end(&mut output).unwrap();
+    assert_eq!(output, reserve);
+    let mut output = String::new();
+    let mut reader = cabinet.read_file("hi.txt").unwrap();
+    reader.read_to_string(&mut output).unwrap();
+    assert_eq!(output, "Hello, world!");
+}
+
+#[test]
+fn read_names_longer_than_spec_allows() {
+    let long_name = "x".repeat(300);
+    let mut cab_builder = cab::CabinetBuilder::new();
+    {
+        let folder = cab_builder.add_folder(cab::CompressionType::None);
+        folder.add_file("short.txt");
+        folder.add_file(long_name.as_str());
+    }
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        file_writer.write_all(b"Hello!").unwrap();
+    }
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+
+    let error = cab::Cabinet::new(Cursor::new(&cab_file)).err().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    let error = error
+        .get_ref()
+        .and_then(|error| error.downcast_ref::<cab::NameTooLongError>())
+        .unwrap();
+    assert_eq!(error.location(), cab::NameLocation::FileEntry(1));
+    assert_eq!(error.max_len(), 255);
+
+    let mut cabinet = cab::ReadOptions::new()
+        .max_name_len(300)
+        .open(Cursor::new(&cab_file))
+        .unwrap();
+    let mut output = Vec::new();
+    let mut reader = cabinet.read_file(&long_name).unwrap();
+    reader.read_to_end(&mut output).unwrap();
+    assert_eq!(output, b"Hello!");
+}
+
+#[test]
+#[cfg(feature = "time")]
+fn patch_entries_in_place() {
+    let mut cab_builder = cab::CabinetBuilder::new();
+    {
+        let folder = cab_builder.add_folder(cab::CompressionType::MsZip);
+        folder.set_reserve_data(vec![1, 2, 3]);
+        folder.add_file("hi.txt").set_datetime(datetime!(2000-01-01 0:00));
+        folder.add_file("bye.txt").set_is_hidden(true);
+    }
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        file_writer.write_all(b"Hello!").unwrap();
+    }
+    let mut cab_file = Cursor::new(cab_writer.finish().unwrap().into_inner());
+
+    let cabinet = cab::Cabinet::new(cab_file.clone()).unwrap();
+    let folder = cabinet.folder_entries().next().unwrap();
+    assert_eq!(folder.entry_offset(), 40);
+    let hi = cabinet.get_file_entry("hi.txt").unwrap();
+    let bye = cabinet.get_file_entry("bye.txt").unwrap();
+    assert_eq!(hi.entry_offset(), cabinet.first_file_offset() as u64);
+    assert_eq!(bye.entry_offset(), hi.entry_offset() + 17 + 6);
+
+    let new_datetime = datetime!(2024-06-30 12:34:56);
+    hi.patch_datetime(&mut cab_file, new_datetime).unwrap();
+    bye.patch_attributes(&mut cab_file, bye.attributes() & !0x02).unwrap();
+    folder.patch_reserve_data(&mut cab_file, &[4, 5, 6]).unwrap();
+    assert!(folder.patch_reserve_data(&mut cab_file, &[7]).is_err());
+    // The folder entry no longer matches what's in the file:
+    assert!(folder.patch_reserve_data(&mut cab_file, &[7, 8, 9]).is_err());
+    // Nor does an entry from a different cabinet:
+    assert!(hi
+        .patch_datetime(&mut Cursor::new(vec![0; 1000]), new_datetime)
+        .is_err());
+
+    cab_file.set_position(0);
+    let mut cabinet = cab::Cabinet::new(cab_file).unwrap();
+    let folder = cabinet.folder_entries().next().unwrap();
+    assert_eq!(folder.reserve_data(), &[4, 5, 6]);
+    let hi = cabinet.get_file_entry("hi.txt").unwrap();
+    assert_eq!(hi.datetime(), Some(new_datetime));
+    assert!(!cabinet.get_file_entry("bye.txt").unwrap().is_hidden());
+    let mut output = Vec::new();
+    let mut reader = cabinet.read_file("bye.txt").unwrap();
+    reader.read_to_end(&mut output).unwrap();
+    assert_eq!(output, b"Hello!");
+}
+
+#[test]
+#[cfg(feature = "time")]
+fn update_entry_metadata_through_cabinet() {
+    let mut cab_builder = cab::CabinetBuilder::new();
+    {
+        let folder = cab_builder.add_folder(cab::CompressionType::MsZip);
+        folder.add_file("hi.txt").set_datetime(datetime!(2000-01-01 0:00));
+        folder.add_file("bye.txt").set_is_hidden(true);
+    }
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        file_writer.write_all(b"Hello!").unwrap();
+    }
+    let cab_file = Cursor::new(cab_writer.finish().unwrap().into_inner());
+
+    let mut cabinet = cab::Cabinet::new(cab_file).unwrap();
+    let new_datetime = datetime!(2024-06-30 12:34:56);
+    cabinet.update_entry_metadata("hi.txt", Some(new_datetime), None).unwrap();
+    cabinet.update_entry_metadata("bye.txt", None, Some(0x01)).unwrap();
+    assert!(cabinet
+        .update_entry_metadata("bye.txt", None, Some(0x80))
+        .is_err());
+    assert!(cabinet.update_entry_metadata("nope.txt", None, None).is_err());
+    // The cabinet's own entries are updated, as well as the file:
+    let hi = cabinet.get_file_entry("hi.txt").unwrap();
+    assert_eq!(hi.datetime(), Some(new_datetime));
+    let folder = cabinet.folder_entries().next().unwrap();
+    let bye = folder.file_entries().nth(1).unwrap();
+    assert!(bye.is_read_only() && !bye.is_hidden());
+
+    let mut cab_file =
+        cabinet.into_appender().build().unwrap().finish().unwrap();
+    cab_file.set_position(0);
+    let cabinet = cab::Cabinet::new(cab_file).unwrap();
+    let hi = cabinet.get_file_entry("hi.txt").unwrap();
+    assert_eq!(hi.datetime(), Some(new_datetime));
+    assert_eq!(cabinet.get_file_entry("bye.txt").unwrap().attributes(), 0x01);
+}
+
+// ========================================================================= //
+
+#[test]
+fn compress_and_decompress_single_file() {
+    let original = lipsum::lipsum(2000);
+    for ctype in [cab::CompressionType::None, cab::CompressionType::MsZip] {
+        let cab_file = cab::compress_single(
+            "lorem.txt",
+            original.as_bytes(),
+            Cursor::new(Vec::new()),
+            ctype,
+        )
+        .unwrap()
+        .into_inner();
+
+        let cabinet = cab::Cabinet::new(Cursor::new(&cab_file)).unwrap();
+        assert_eq!(cabinet.folder_entries().len(), 1);
+        let folder = cabinet.folder_entries().next().unwrap();
+        assert_eq!(folder.compression_type(), ctype);
+        assert_eq!(folder.file_entries().len(), 1);
+
+        let mut output = Vec::new();
+        let size = cab::decompress_single(
+            Cursor::new(&cab_file),
+            "lorem.txt",
+            &mut output,
+        )
+        .unwrap();
+        assert_eq!(size, original.len() as u64);
+        assert_eq!(output, original.as_bytes());
+
+        let error = cab::decompress_single(
+            Cursor::new(&cab_file),
+            "missing.txt",
+            &mut output,
+        )
+        .unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+    }
+}
+
+// ========================================================================= //
+
+#[test]
+fn compress_and_expand_underscored_file() {
+    assert_eq!(cab::underscored_name("foo.exe"), "foo.ex_");
+    assert_eq!(cab::underscored_name("foo.tar.gz"), "foo.tar.gz_");
+    assert_eq!(cab::underscored_name("v1.0\\notes"), "v1.0\\notes._");
+
+    let dir = std::env::temp_dir()
+        .join(format!("cab-underscored-test-{}", std::process::id()));
+    let out_dir = dir.join("out");
+    std::fs::create_dir_all(&out_dir).unwrap();
+    let original = lipsum::lipsum(1000);
+    let path = dir.join("driver.sys");
+    std::fs::write(&path, &original).unwrap();
+
+    let cab_path =
+        cab::compress_underscored(&path, cab::CompressionType::MsZip).unwrap();
+    assert_eq!(cab_path, dir.join("driver.sy_"));
+    let cabinet =
+        cab::Cabinet::new(std::fs::File::open(&cab_path).unwrap()).unwrap();
+    assert_eq!(cab::single_file_name(&cabinet).unwrap(), "driver.sys");
+
+    let out_path = cab::expand_underscored(&cab_path, &out_dir).unwrap();
+    assert_eq!(out_path, out_dir.join("driver.sys"));
+    assert_eq!(std::fs::read_to_string(&out_path).unwrap(), original);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // Directory components of the stored name are not restored:
+    let mut cab_builder = cab::CabinetBuilder::new();
+    cab_builder
+        .add_folder(cab::CompressionType::None)
+        .add_file("..\\system32\\evil.dll");
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        file_writer.write_all(b"MZ").unwrap();
+    }
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+    let cabinet = cab::Cabinet::new(Cursor::new(cab_file)).unwrap();
+    assert_eq!(cab::single_file_name(&cabinet).unwrap(), "evil.dll");
+
+    let mut cab_builder = cab::CabinetBuilder::new();
+    let folder = cab_builder.add_folder(cab::CompressionType::None);
+    folder.add_file("a.txt");
+    folder.add_file("b.txt");
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while cab_writer.next_file().unwrap().is_some() {}
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+    let cabinet = cab::Cabinet::new(Cursor::new(cab_file)).unwrap();
+    assert!(cab::single_file_name(&cabinet).is_err());
+}
+
+// ========================================================================= //
+
+#[test]
+#[cfg(feature = "time")]
+fn copy_between_archives_through_traits() {
+    use cab::archive::{ArchiveReader, ArchiveWriter, CabArchiveWriter};
+
+    // A generic "backup" routine that doesn't know which format it writes.
+    fn backup<W: ArchiveWriter>(
+        files: &[(&str, &[u8])],
+        mut writer: W,
+    ) -> std::io::Result<W::Output> {
+        let modified = Some(datetime!(2020-02-29 12:00:00));
+        for &(name, mut data) in files {
+            writer.add_entry(name, modified, &mut data)?;
+        }
+        writer.finish()
+    }
+
+    let lorem = lipsum::lipsum(3000);
+    let files: &[(&str, &[u8])] = &[
+        ("hi.txt", b"Hello, world!\n"),
+        ("empty.txt", b""),
+        ("docs/lorem.txt", lorem.as_bytes()),
+    ];
+    let mut spool_options = cab::SpoolOptions::new();
+    spool_options.memory_threshold(1000);
+    let mut writer = CabArchiveWriter::new(
+        Cursor::new(Vec::new()),
+        cab::CompressionType::MsZip,
+    );
+    writer.set_spool_options(spool_options);
+    let cab_file = backup(files, writer).unwrap().into_inner();
+
+    let mut cabinet = cab::Cabinet::new(Cursor::new(cab_file)).unwrap();
+    let entries = cabinet.entries();
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[2].name, "docs/lorem.txt");
+    assert_eq!(entries[2].size, lorem.len() as u64);
+    assert_eq!(entries[0].modified, Some(datetime!(2020-02-29 12:00:00)));
+    for &(name, data) in files {
+        let mut output = Vec::new();
+        cabinet.open_entry(name).unwrap().read_to_end(&mut output).unwrap();
+        assert_eq!(output, data);
+    }
+
+    let mut writer = CabArchiveWriter::new(
+        Cursor::new(Vec::new()),
+        cab::CompressionType::None,
+    );
+    let copied = cab::archive::copy_archive(&mut cabinet, &mut writer);
+    assert_eq!(copied.unwrap(), 3);
+    let copy = writer.finish().unwrap().into_inner();
+    let mut copy = cab::Cabinet::new(Cursor::new(copy)).unwrap();
+    assert_eq!(copy.entries(), entries);
+    assert_eq!(
+        copy.open_entry("missing.txt").err().unwrap().kind(),
+        std::io::ErrorKind::NotFound
+    );
+}
+
+// ========================================================================= //
+
+#[test]
+fn duplicate_file_names() {
+    fn make_builder() -> cab::CabinetBuilder {
+        let mut cab_builder = cab::CabinetBuilder::new();
+        let folder = cab_builder.add_folder(cab::CompressionType::None);
+        folder.add_file("hi.txt");
+        folder.add_file("bye.txt");
+        let folder = cab_builder.add_folder(cab::CompressionType::MsZip);
+        folder.add_file("HI.TXT");
+        folder.add_file("hi (2).txt");
+        folder.add_file("hi.txt");
+        cab_builder
+    }
+
+    let error = make_builder().build(Cursor::new(Vec::new())).err().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(error.to_string().contains("\"HI.TXT\", \"hi.txt\""));
+
+    let mut cab_builder = make_builder();
+    cab_builder.set_duplicate_names(cab::DuplicateNames::Allow);
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    assert!(cab_writer.renamed_files().is_empty());
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        assert_eq!(file_writer.original_name(), file_writer.file_name());
+        file_writer.write_all(b"data").unwrap();
+    }
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+    let cabinet = cab::Cabinet::new(Cursor::new(cab_file)).unwrap();
+    let names: Vec<&str> = cabinet
+        .folder_entries()
+        .flat_map(|folder| folder.file_entries())
+        .map(|file| file.name())
+        .collect();
+    assert_eq!(names, ["hi.txt", "bye.txt", "HI.TXT", "hi (2).txt", "hi.txt"]);
+
+    let mut cab_builder = make_builder();
+    cab_builder.set_duplicate_names(cab::DuplicateNames::Rename);
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    assert_eq!(
+        cab_writer.renamed_files(),
+        [
+            ("HI.TXT".to_string(), "HI (3).TXT".to_string()),
+            ("hi.txt".to_string(), "hi (4).txt".to_string()),
+        ]
+    );
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        let data = file_writer.original_name().to_string();
+        file_writer.write_all(data.as_bytes()).unwrap();
+    }
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+    let mut cabinet = cab::Cabinet::new(Cursor::new(cab_file)).unwrap();
+    let names: Vec<String> = cabinet
+        .folder_entries()
+        .flat_map(|folder| folder.file_entries())
+        .map(|file| file.name().to_string())
+        .collect();
+    assert_eq!(
+        names,
+        ["hi.txt", "bye.txt", "HI (3).TXT", "hi (2).txt", "hi (4).txt"]
+    );
+    let mut data = String::new();
+    cabinet
+        .read_file("HI (3).TXT")
+        .unwrap()
+        .read_to_string(&mut data)
+        .unwrap();
+    assert_eq!(data, "HI.TXT");
+
+    // Duplicate files follow the file they refer to when it is renamed:
+    let mut cab_builder = cab::CabinetBuilder::new();
+    cab_builder.set_duplicate_names(cab::DuplicateNames::Rename);
+    cab_builder.add_folder(cab::CompressionType::None).add_file("a.txt");
+    let folder = cab_builder.add_folder(cab::CompressionType::MsZip);
+    folder.add_file("a.txt");
+    folder.add_duplicate_file("b.txt", "a.txt");
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        let data = file_writer.file_name().to_string();
+        file_writer.write_all(data.as_bytes()).unwrap();
+    }
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+    let mut cabinet = cab::Cabinet::new(Cursor::new(cab_file)).unwrap();
+    for (name, expected) in [
+        ("a.txt", "a.txt"),
+        ("a (2).txt", "a (2).txt"),
+        ("b.txt", "a (2).txt"),
+    ] {
+        let mut data = String::new();
+        cabinet.read_file(name).unwrap().read_to_string(&mut data).unwrap();
+        assert_eq!(data, expected, "{}", name);
+    }
+}
+
+// ========================================================================= //
+
+#[test]
+fn empty_cabinet() {
+    let cab_builder = cab::CabinetBuilder::new();
+    let cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+    assert_eq!(cab_file.len(), 36);
+
+    let mut cabinet = cab::Cabinet::new(Cursor::new(cab_file)).unwrap();
+    assert_eq!(cabinet.folder_entries().len(), 0);
+    assert_eq!(cabinet.total_size(), 36);
+    assert!(cabinet.warnings().is_empty());
+    let error = cabinet.read_file("foo.txt").err().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+}
+
+#[test]
+fn cabinet_with_empty_folders() {
+    let mut cab_builder = cab::CabinetBuilder::new();
+    cab_builder.add_folder(cab::CompressionType::MsZip);
+    cab_builder.add_folder(cab::CompressionType::None).add_file("hi.txt");
+    cab_builder.add_folder(cab::CompressionType::None);
+    cab_builder.add_folder(cab::CompressionType::MsZip).add_file("bye.txt");
+    cab_builder.add_folder(cab::CompressionType::MsZip);
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        let data = file_writer.file_name().to_uppercase();
+        file_writer.write_all(data.as_bytes()).unwrap();
+    }
+    let (cab_file, report) = cab_writer.finish_with_report().unwrap();
+    assert_eq!(report.folders().len(), 5);
+    assert_eq!(report.folders()[0].num_data_blocks(), 0);
+    assert_eq!(report.folders()[0].uncompressed_size(), 0);
+
+    let mut cabinet =
+        cab::Cabinet::new(Cursor::new(cab_file.into_inner())).unwrap();
+    assert!(cabinet.warnings().is_empty());
+    let folders: Vec<(u16, usize)> = cabinet
+        .folder_entries()
+        .map(|folder| (folder.num_data_blocks(), folder.file_entries().len()))
+        .collect();
+    assert_eq!(folders, [(0, 0), (1, 1), (0, 0), (1, 1), (0, 0)]);
+    assert_eq!(cabinet.data_blocks(0).unwrap().count(), 0);
+    assert_eq!(cabinet.data_blocks(4).unwrap().count(), 0);
+    let mut data = String::new();
+    cabinet.read_file("bye.txt").unwrap().read_to_string(&mut data).unwrap();
+    assert_eq!(data, "BYE.TXT");
+}
+
+// ========================================================================= //
+
+#[test]
+fn files_with_shared_data() {
+    let lorem = lipsum::lipsum(10000);
+    assert!(lorem.len() > 0x10000);
+    let mut cab_builder = cab::CabinetBuilder::new();
+    let folder = cab_builder.add_folder(cab::CompressionType::MsZip);
+    folder.add_file("a.txt");
+    folder.add_duplicate_file("copy.txt", "a.txt");
+    folder.add_file("b.txt");
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        if file_writer.file_name() == "a.txt" {
+            file_writer.write_all(lorem.as_bytes()).unwrap();
+        } else {
+            file_writer.write_all(b"Goodbye").unwrap();
+        }
+    }
+    let mut cab_file = cab_writer.finish().unwrap().into_inner();
+
+    // Point b.txt at a range in the middle of a.txt's data, spanning a data
+    // block boundary:
+    let b_offset = {
+        let cabinet = cab::Cabinet::new(Cursor::new(&cab_file)).unwrap();
+        cabinet.get_file_entry("b.txt").unwrap().entry_offset() as usize
+    };
+    let b_start = 0x8000 - 3;
+    cab_file[(b_offset + 4)..(b_offset + 8)]
+        .copy_from_slice(&(b_start as u32).to_le_bytes());
+
+    let mut cabinet = cab::Cabinet::new(Cursor::new(cab_file)).unwrap();
+    let a = cabinet.get_file_entry("a.txt").unwrap();
+    let copy = cabinet.get_file_entry("copy.txt").unwrap();
+    let b = cabinet.get_file_entry("b.txt").unwrap();
+    assert_eq!(a.uncompressed_offset(), 0);
+    assert_eq!(copy.uncompressed_offset(), 0);
+    assert_eq!(b.uncompressed_offset(), b_start as u32);
+    assert!(a.shares_data_with(copy));
+    assert!(copy.shares_data_with(a));
+    assert!(b.shares_data_with(a));
+    assert_eq!(
+        cabinet.warnings(),
+        [cab::Warning::OverlappingFiles {
+            folder_index: 0,
+            first: "a.txt".to_string(),
+            second: "b.txt".to_string(),
+        }]
+    );
+
+    // Read the files in an order that requires seeking backwards:
+    for (name, expected) in [
+        ("b.txt", &lorem.as_bytes()[b_start..(b_start + 7)]),
+        ("a.txt", lorem.as_bytes()),
+        ("b.txt", &lorem.as_bytes()[b_start..(b_start + 7)]),
+        ("copy.txt", lorem.as_bytes()),
+    ] {
+        let mut data = Vec::new();
+        cabinet.read_file(name).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, expected, "contents of {}", name);
+    }
+}
+
+// ========================================================================= //
+
+#[test]
+fn cabinet_with_shuffled_file_entries() {
+    let mut cab_builder = cab::CabinetBuilder::new();
+    for (index, ctype) in [
+        cab::CompressionType::None,
+        cab::CompressionType::MsZip,
+        cab::CompressionType::MsZip,
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let folder = cab_builder.add_folder(ctype);
+        folder.add_file(format!("{}a.txt", index));
+        folder.add_file(format!("{}b.txt", index));
+    }
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        let data = lipsum::lipsum_words(file_writer.file_name().len() * 50);
+        file_writer.write_all(data.as_bytes()).unwrap();
+    }
+    let mut cab_file = cab_writer.finish().unwrap().into_inner();
+
+    // Rewrite the file directory in a different (unsorted) order:
+    let cabinet = cab::Cabinet::new(Cursor::new(&cab_file)).unwrap();
+    let entries: Vec<(String, Vec<u8>)> = cabinet
+        .file_entries_in_directory_order()
+        .map(|file| {
+            let start = file.entry_offset() as usize;
+            let end = start + 17 + file.name().len();
+            (file.name().to_string(), cab_file[start..end].to_vec())
+        })
+        .collect();
+    let start = cabinet.first_file_offset() as usize;
+    drop(cabinet);
+    let order = [3, 0, 5, 1, 4, 2];
+    let mut offset = start;
+    for &index in order.iter() {
+        let bytes = &entries[index].1;
+        cab_file[offset..(offset + bytes.len())].copy_from_slice(bytes);
+        offset += bytes.len();
+    }
+
+    let mut cabinet = cab::Cabinet::new(Cursor::new(cab_file)).unwrap();
+    assert_eq!(
+        cabinet.warnings(),
+        [cab::Warning::UnsortedFileEntries { index: 1 }]
+    );
+    let names: Vec<&str> = cabinet
+        .file_entries_in_directory_order()
+        .map(|file| file.name())
+        .collect();
+    assert_eq!(
+        names,
+        ["1b.txt", "0a.txt", "2b.txt", "0b.txt", "2a.txt", "1a.txt"]
+    );
+    let folders: Vec<Vec<&str>> = cabinet
+        .folder_entries()
+        .map(|folder| folder.file_entries().map(|file| file.name()).collect())
+        .collect();
+    assert_eq!(
+        folders,
+        [["0a.txt", "0b.txt"], ["1b.txt", "1a.txt"], ["2b.txt", "2a.txt"]]
+    );
+    for file in cabinet.file_entries_in_directory_order() {
+        assert_eq!(&file.name()[..1], file.folder_index().to_string());
+    }
+    let names: Vec<String> =
+        names.iter().map(|name| name.to_string()).collect();
+    for name in names {
+        let mut data = String::new();
+        cabinet.read_file(&name).unwrap().read_to_string(&mut data).unwrap();
+        assert_eq!(data, lipsum::lipsum_words(name.len() * 50));
+    }
+}
+
+// ========================================================================= //
+
+#[test]
+fn extract_all_files_with_report() {
+    let lorem = lipsum::lipsum(2000);
+    let mut cab_builder = cab::CabinetBuilder::new();
+    {
+        let folder = cab_builder.add_folder(cab::CompressionType::None);
+        folder.add_file("docs\\readme.txt");
+        folder.add_file("..\\evil.txt");
+    }
+    cab_builder.add_folder(cab::CompressionType::MsZip).add_file("lorem.txt");
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        if file_writer.file_name() == "lorem.txt" {
+            file_writer.write_all(lorem.as_bytes()).unwrap();
+        } else {
+            file_writer.write_all(b"Hello, world!\n").unwrap();
+        }
+    }
+    let mut cab_file = cab_writer.finish().unwrap().into_inner();
+
+    let dir = std::env::temp_dir()
+        .join(format!("cab-extract-all-test-{}", std::process::id()));
+    let out_dir = dir.join("out");
+    let mut cabinet = cab::Cabinet::new(Cursor::new(&cab_file)).unwrap();
+    let report = cabinet.extract_all(&out_dir).unwrap();
+    let statuses: Vec<(&str, &cab::FileStatus)> = report
+        .files()
+        .iter()
+        .map(|file| (file.name(), file.status()))
+        .collect();
+    assert_eq!(
+        statuses,
+        vec![
+            ("docs\\readme.txt", &cab::FileStatus::Ok),
+            (
+                "..\\evil.txt",
+                &cab::FileStatus::Skipped(cab::SkipReason::UnsafePath)
+            ),
+            ("lorem.txt", &cab::FileStatus::Ok),
+        ]
+    );
+    assert!(!report.is_success());
+    assert_eq!(report.total_bytes(), 14 + lorem.len() as u64);
+    assert_eq!(
+        report.files()[0].path(),
+        Some(out_dir.join("docs").join("readme.txt").as_path())
+    );
+    assert_eq!(
+        std::fs::read_to_string(out_dir.join("docs").join("readme.txt"))
+            .unwrap(),
+        "Hello, world!\n"
+    );
+    assert_eq!(
+        std::fs::read_to_string(out_dir.join("lorem.txt")).unwrap(),
+        lorem
+    );
+    assert!(!dir.join("evil.txt").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let report = cabinet.verify_all();
+    assert!(report.is_success());
+    assert!(report.files().iter().all(|file| file.path().is_none()));
+
+    // Clear the MSZIP folder's data block checksum, and corrupt its MSZIP
+    // signature:
+    let folder_offset = 36 + 8;
+    let offset = u32::from_le_bytes(
+        cab_file[folder_offset..(folder_offset + 4)].try_into().unwrap(),
+    ) as usize;
+    cab_file[offset..(offset + 4)].copy_from_slice(&[0, 0, 0, 0]);
+    cab_file[offset + 8] = b'X';
+    let mut cabinet = cab::Cabinet::new(Cursor::new(&cab_file)).unwrap();
+    let report = cabinet.verify_all();
+    assert_eq!(report.files()[0].status(), &cab::FileStatus::Ok);
+    assert_eq!(report.failures().count(), 1);
+    let failure = report.failures().next().unwrap();
+    assert_eq!(failure.name(), "lorem.txt");
+    assert_eq!(failure.bytes(), 0);
+    match failure.status() {
+        cab::FileStatus::Failed { message, .. } => {
+            assert!(!message.is_empty())
+        }
+        status => panic!("unexpected status: {:?}", status),
+    }
+
+    cabinet.set_continue_on_error(true);
+    let report = cabinet.verify_all();
+    assert_eq!(report.failures().count(), 0);
+    match report.files()[2].status() {
+        cab::FileStatus::Recovered { damaged_ranges } => {
+            assert_eq!(damaged_ranges.len(), 1);
+            assert_eq!(damaged_ranges[0], 0..(lorem.len() as u64));
+        }
+        status => panic!("unexpected status: {:?}", status),
+    }
+    assert_eq!(report.files()[2].bytes(), lorem.len() as u64);
+}
+
+// ========================================================================= //
+
+#[test]
+fn cancel_reading_and_writing() {
+    let original = lipsum::lipsum(30000);
+    let token = cab::CancelToken::new();
+    let mut cab_builder = cab::CabinetBuilder::new();
+    {
+        let folder = cab_builder.add_folder(cab::CompressionType::MsZip);
+        folder.add_file("lorem.txt");
+        folder.add_file("hi.txt");
+    }
+    cab_builder.set_cancel_token(Some(token.clone()));
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        if file_writer.file_name() == "lorem.txt" {
+            file_writer.write_all(original.as_bytes()).unwrap();
+        } else {
+            file_writer.write_all(b"Hello, world!\n").unwrap();
+        }
+    }
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+
+    let mut cabinet = cab::ReadOptions::new()
+        .cancel(token.clone())
+        .open(Cursor::new(&cab_file))
+        .unwrap();
+    let mut file_reader = cabinet.read_file("lorem.txt").unwrap();
+    let mut data = vec![0u8; 40000];
+    file_reader.read_exact(&mut data).unwrap();
+    assert_eq!(&data[..], &original.as_bytes()[..40000]);
+    token.cancel();
+    let error = file_reader.read_to_end(&mut data).unwrap_err();
+    assert!(cab::Cancelled::is_cancelled(&error));
+    drop(file_reader);
+
+    let report = cabinet.verify_all();
+    let statuses: Vec<&cab::FileStatus> =
+        report.files().iter().map(|file| file.status()).collect();
+    let cancelled = cab::FileStatus::Skipped(cab::SkipReason::Cancelled);
+    assert_eq!(statuses, vec![&cancelled, &cancelled]);
+
+    // Writing with the cancelled token fails before any data block is
+    // written:
+    let mut cab_builder = cab::CabinetBuilder::new();
+    cab_builder.add_folder(cab::CompressionType::None).add_file("hi.txt");
+    cab_builder.set_cancel_token(Some(token));
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    let mut file_writer = cab_writer.next_file().unwrap().unwrap();
+    file_writer.write_all(b"Hello, world!\n").unwrap();
+    let error = cab_writer.finish().unwrap_err();
+    assert!(cab::Cancelled::is_cancelled(&error));
+}
+
+// ========================================================================= //
+
+#[test]
+fn throttle_reading_and_writing() {
+    use std::sync::{Arc, Mutex};
+
+    let original = lipsum::lipsum(30000);
+    let written = Arc::new(Mutex::new(Vec::<(usize, usize)>::new()));
+    let mut cab_builder = cab::CabinetBuilder::new();
+    cab_builder.add_folder(cab::CompressionType::MsZip).add_file("lorem.txt");
+    let log = written.clone();
+    cab_builder.set_throttle(Some(Arc::new(
+        move |compressed: usize, uncompressed: usize| {
+            log.lock().unwrap().push((compressed, uncompressed));
+        },
+    )));
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        file_writer.write_all(original.as_bytes()).unwrap();
+    }
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+    let written = written.lock().unwrap().clone();
+    assert_eq!(written.len(), original.len().div_ceil(0x8000));
+    let total: usize = written.iter().map(|&(_, size)| size).sum();
+    assert_eq!(total, original.len());
+    assert!(written.iter().all(|&(compressed, size)| compressed < size));
+
+    let read = Arc::new(Mutex::new(Vec::<(usize, usize)>::new()));
+    let mut cabinet = cab::Cabinet::new(Cursor::new(cab_file)).unwrap();
+    let log = read.clone();
+    cabinet.set_throttle(Some(Arc::new(
+        move |compressed: usize, uncompressed: usize| {
+            log.lock().unwrap().push((compressed, uncompressed));
+        },
+    )));
+    let mut output = String::new();
+    cabinet
+        .read_file("lorem.txt")
+        .unwrap()
+        .read_to_string(&mut output)
+        .unwrap();
+    assert_eq!(output, original);
+    assert_eq!(*read.lock().unwrap(), written);
+}
+
+// ========================================================================= //
+
+#[test]
+fn store_incompressible_folders_uncompressed() {
+    use rand::{RngCore, SeedableRng};
+    let mut random = vec![0u8; 100_000];
+    rand::rngs::SmallRng::from_entropy().fill_bytes(&mut random);
+    let lorem = lipsum::lipsum(30000);
+
+    let mut cab_builder = cab::CabinetBuilder::new();
+    let folder = cab_builder.add_folder(cab::CompressionType::MsZip);
+    folder.set_store_if_incompressible(true);
+    folder.add_file("random1.bin");
+    let folder = cab_builder.add_folder(cab::CompressionType::MsZip);
+    folder.set_store_if_incompressible(true);
+    folder.add_file("lorem.txt");
+    cab_builder
+        .add_folder(cab::CompressionType::MsZip)
+        .add_file("random2.bin");
+    cab_builder.set_verify_blocks(true);
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        if file_writer.file_name() == "lorem.txt" {
+            file_writer.write_all(lorem.as_bytes()).unwrap();
+        } else {
+            file_writer.write_all(&random).unwrap();
+        }
+    }
+    let (cab_file, report) = cab_writer.finish_with_report().unwrap();
+    let folders = report.folders();
+    assert_eq!(folders[0].compression_type(), cab::CompressionType::None);
+    assert_eq!(folders[0].num_data_blocks(), 4);
+    assert_eq!(folders[0].stored_blocks(), 0);
+    assert_eq!(folders[1].compression_type(), cab::CompressionType::MsZip);
+    assert!(folders[1].ratio().unwrap() < 0.5);
+    assert_eq!(folders[2].compression_type(), cab::CompressionType::MsZip);
+    assert_eq!(folders[2].stored_blocks(), 4);
+    assert!(folders[0].compressed_size() < folders[2].compressed_size());
+
+    let mut cabinet =
+        cab::Cabinet::new(Cursor::new(cab_file.into_inner())).unwrap();
+    let ctypes: Vec<cab::CompressionType> = cabinet
+        .folder_entries()
+        .map(|folder| folder.compression_type())
+        .collect();
+    assert_eq!(
+        ctypes,
+        vec![
+            cab::CompressionType::None,
+            cab::CompressionType::MsZip,
+            cab::CompressionType::MsZip
+        ]
+    );
+    for name in ["random1.bin", "random2.bin"] {
+        let mut data = Vec::new();
+        cabinet.read_file(name).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, random);
+    }
+    let mut output = String::new();
+    cabinet
+        .read_file("lorem.txt")
+        .unwrap()
+        .read_to_string(&mut output)
+        .unwrap();
+    assert_eq!(output, lorem);
+}
+
+// ========================================================================= //
+
+#[test]
+fn split_folders_on_incompressible_files() {
+    use rand::{RngCore, SeedableRng};
+    let mut rng = rand::rngs::SmallRng::from_entropy();
+    let mut random1 = vec![0u8; 100_000];
+    rng.fill_bytes(&mut random1);
+    let mut random2 = vec![0u8; 100_000];
+    rng.fill_bytes(&mut random2);
+    let lorem = lipsum::lipsum(20000).into_bytes();
+    let files: Vec<(&str, &[u8])> = vec![
+        ("a.txt", &lorem[..100_000]),
+        ("b.jpg", &random1),
+        ("c.zip", &random2),
+        ("d.txt", &lorem[..100_000]),
+        ("e.txt", b"Hello, world!\n"),
+    ];
+
+    let mut cab_builder = cab::CabinetBuilder::new();
+    let folder = cab_builder.add_folder(cab::CompressionType::MsZip);
+    folder.set_split_incompressible(true);
+    for &(name, _) in files.iter() {
+        folder.add_file(name);
+    }
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        let name = file_writer.file_name().to_string();
+        let data = files.iter().find(|&&(n, _)| n == name).unwrap().1;
+        file_writer.write_all(data).unwrap();
+    }
+    let (cab_file, report) = cab_writer.finish_with_report().unwrap();
+    let layout: Vec<(cab::CompressionType, Vec<&str>)> = report
+        .folders()
+        .iter()
+        .map(|folder| {
+            let names = folder.files().iter().map(|f| f.name()).collect();
+            (folder.compression_type(), names)
+        })
+        .collect();
+    assert_eq!(
+        layout,
+        vec![
+            (cab::CompressionType::MsZip, vec!["a.txt", "b.jpg"]),
+            (cab::CompressionType::None, vec!["c.zip", "d.txt"]),
+            (cab::CompressionType::MsZip, vec!["e.txt"]),
+        ]
+    );
+
+    let mut cabinet =
+        cab::Cabinet::new(Cursor::new(cab_file.into_inner())).unwrap();
+    assert_eq!(cabinet.folder_entries().len(), 3);
+    for &(name, original) in files.iter() {
+        let mut data = Vec::new();
+        cabinet.read_file(name).unwrap().read_to_end(&mut data).unwrap();
+        assert_eq!(data, original);
+    }
+}
+
+// ========================================================================= //
+
+#[test]
+fn dump_cabinet_structure() {
+    let mut cab_builder = cab::CabinetBuilder::new();
+    cab_builder.add_folder(cab::CompressionType::MsZip).add_file("a\"b.txt");
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        file_writer.write_all(lipsum::lipsum(5000).as_bytes()).unwrap();
+    }
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+    let mut cabinet = cab::Cabinet::new(Cursor::new(cab_file)).unwrap();
+
+    let mut text = Vec::new();
+    cabinet.dump_structure(&mut text, cab::DumpFormat::Text).unwrap();
+    let text = String::from_utf8(text).unwrap();
+    assert!(text.starts_with("header:\n  signature: 4d534346\n"));
+    assert!(text.contains("    compression_type: \"mszip\"\n"));
+    assert!(text.contains("\n    blocks:\n      [0]\n        index: 0\n"));
+    assert!(text.contains("    name: \"a\\\"b.txt\"\n"));
+
+    let mut json = Vec::new();
+    cabinet.dump_structure(&mut json, cab::DumpFormat::Json).unwrap();
+    let json = String::from_utf8(json).unwrap();
+    assert!(json.starts_with("{\"header\":{\"signature\":\"4d534346\","));
+    assert!(json.contains("\"num_folders\":1,\"num_files\":1,"));
+    assert!(json.contains("\"name\":\"a\\\"b.txt\""));
+    assert!(json.contains("\"blocks_error\":null"));
+    assert!(json.ends_with("\"warnings\":[]}\n"));
+}
+
+// ========================================================================= //
+
+#[test]
+fn defer_parsing_file_entries() {
+    let mut cab_builder = cab::CabinetBuilder::new();
+    let folder = cab_builder.add_folder(cab::CompressionType::MsZip);
+    folder.add_file("a.txt");
+    folder.add_file("b.txt");
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        let name = file_writer.file_name().to_string();
+        file_writer.write_all(name.as_bytes()).unwrap();
+    }
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+
+    let mut options = cab::ReadOptions::new();
+    options.defer_file_entries(true);
+    let mut cabinet = options.open(Cursor::new(cab_file.clone())).unwrap();
+    assert!(!cabinet.file_entries_loaded());
+    assert_eq!(cabinet.folder_entries().len(), 1);
+    assert_eq!(cabinet.folder_entries().next().unwrap().num_data_blocks(), 1);
+    assert_eq!(cabinet.file_entries_in_directory_order().len(), 0);
+    let mut output = String::new();
+    cabinet.read_file("b.txt").unwrap().read_to_string(&mut output).unwrap();
+    assert_eq!(output, "b.txt");
+    assert!(cabinet.file_entries_loaded());
+    let names: Vec<&str> = cabinet
+        .file_entries_in_directory_order()
+        .map(|file| file.name())
+        .collect();
+    assert_eq!(names, vec!["a.txt", "b.txt"]);
+
+    // Appending works without ever loading the file entries.
+    let cabinet = options.open(Cursor::new(cab_file)).unwrap();
+    let mut appender = cabinet.into_appender();
+    appender.add_folder(cab::CompressionType::None).add_file("c.txt");
+    let mut cab_writer = appender.build().unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        file_writer.write_all(b"c").unwrap();
+    }
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+    let mut cabinet = cab::Cabinet::new(Cursor::new(cab_file)).unwrap();
+    assert_eq!(cabinet.file_entries_in_directory_order().len(), 3);
+    let mut output = String::new();
+    cabinet.read_file("a.txt").unwrap().read_to_string(&mut output).unwrap();
+    assert_eq!(output, "a.txt");
+}
+
+// ========================================================================= //
+
+#[test]
+fn verify_all_files_with_crc32() {
+    let mut cab_builder = cab::CabinetBuilder::new();
+    let folder = cab_builder.add_folder(cab::CompressionType::MsZip);
+    folder.add_file("digits.txt");
+    folder.add_file("empty.txt");
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        if file_writer.file_name() == "digits.txt" {
+            file_writer.write_all(b"123456789").unwrap();
+        }
+    }
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+
+    let mut cabinet =
+        cab::Cabinet::new(Cursor::new(cab_file.clone())).unwrap();
+    let report = cabinet.verify_all();
+    assert!(report.is_success());
+    assert_eq!(report.files()[0].crc32(), None);
+
+    let mut cabinet = cab::ReadOptions::new()
+        .file_crc32(true)
+        .open(Cursor::new(cab_file))
+        .unwrap();
+    let report = cabinet.verify_all();
+    assert!(report.is_success());
+    assert_eq!(report.files()[0].crc32(), Some(0xcbf43926));
+    assert_eq!(report.files()[1].crc32(), Some(0));
+
+    // Files that share a name are each read from their own data:
+    let mut cab_builder = cab::CabinetBuilder::new();
+    cab_builder.set_duplicate_names(cab::DuplicateNames::Allow);
+    let folder = cab_builder.add_folder(cab::CompressionType::MsZip);
+    folder.add_file("dup.txt");
+    folder.add_file("dup.txt");
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    let mut contents = [&b"123456789"[..], b""].into_iter();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        file_writer.write_all(contents.next().unwrap()).unwrap();
+    }
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+    let mut cabinet = cab::ReadOptions::new()
+        .file_crc32(true)
+        .open(Cursor::new(cab_file))
+        .unwrap();
+    let report = cabinet.verify_all();
+    assert!(report.is_success());
+    assert_eq!(report.files()[0].crc32(), Some(0xcbf43926));
+    assert_eq!(report.files()[1].crc32(), Some(0));
+    assert_eq!(report.total_bytes(), 9);
+}
+
+// ========================================================================= //
+
+#[test]
+fn borrow_uncompressed_file_data_from_slice() {
+    let original: Vec<u8> = (0..100_000).map(|i| (i % 253) as u8).collect();
+    let mut cab_builder = cab::CabinetBuilder::new();
+    let folder = cab_builder.add_folder(cab::CompressionType::None);
+    folder.add_file("head.txt");
+    folder.add_file("big.bin");
+    cab_builder.add_folder(cab::CompressionType::MsZip).add_file("zip.txt");
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        match file_writer.file_name() {
+            "big.bin" => file_writer.write_all(&original).unwrap(),
+            _ => file_writer.write_all(b"Hello!").unwrap(),
+        }
+    }
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+
+    let mut cabinet = cab::Cabinet::from_slice(&cab_file).unwrap();
+    let slices = cabinet.file_slices("big.bin").unwrap();
+    assert_eq!(slices.len(), 4);
+    assert_eq!(slices.concat(), original);
+    let range = cab_file.as_ptr_range();
+    assert!(slices.iter().all(|slice| range.contains(&slice.as_ptr())));
+    assert_eq!(cabinet.file_slices("head.txt").unwrap(), vec![b"Hello!"]);
+    let error = cabinet.file_slices("zip.txt").unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+// ========================================================================= //
+
+#[test]
+fn block_errors_identify_damaged_files() {
+    let original = lipsum::lipsum(20000);
+    let mut cab_builder = cab::CabinetBuilder::new();
+    let folder = cab_builder.add_folder(cab::CompressionType::MsZip);
+    folder.add_file("a.txt");
+    folder.add_file("b.txt");
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        file_writer.write_all(original.as_bytes()).unwrap();
+    }
+    let mut cab_file = cab_writer.finish().unwrap().into_inner();
+    let mut cabinet = cab::Cabinet::new(Cursor::new(&cab_file)).unwrap();
+    let blocks: Vec<cab::DataBlockInfo> =
+        cabinet.data_blocks(0).unwrap().map(Result::unwrap).collect();
+    assert!(blocks.len() > 3);
+    let a_size = original.len() as u64;
+    let target = (0..blocks.len())
+        .find(|&index| {
+            let start: u64 = blocks[..index]
+                .iter()
+                .map(|block| block.uncompressed_size() as u64)
+                .sum();
+            let end = start + blocks[index].uncompressed_size() as u64;
+            start < a_size && end > a_size
+        })
+        .unwrap();
+    let offset = blocks[target].offset() as usize + 8 + 20;
+    cab_file[offset] ^= 0xff;
+
+    let mut cabinet = cab::Cabinet::new(Cursor::new(&cab_file)).unwrap();
+    let mut data = Vec::new();
+    let error = cabinet
+        .read_file("b.txt")
+        .and_then(|mut file_reader| file_reader.read_to_end(&mut data))
+        .unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    let block_error = error
+        .get_ref()
+        .and_then(|error| error.downcast_ref::<cab::BlockError>())
+        .unwrap();
+    assert_eq!(block_error.folder_index(), 0);
+    assert_eq!(block_error.block_index(), target);
+    assert_eq!(block_error.files(), &["a.txt", "b.txt"]);
+    assert!(error.to_string().starts_with(&format!(
+        "Error in data block {} of folder 0 (affecting [\"a.txt\", \"b.txt\"]): \
+         Checksum error",
+        target
+    )));
+}
+
+// ========================================================================= //
+
+#[test]
+fn preserve_raw_compression_bits() {
+    let mut cab_builder = cab::CabinetBuilder::new();
+    let folder = cab_builder.add_folder(cab::CompressionType::MsZip);
+    folder.set_raw_compression_bits(Some(0x8001));
+    folder.add_file("hi.txt");
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        file_writer.write_all(b"Hello, world!\n").unwrap();
+    }
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+
+    let mut cabinet = cab::Cabinet::new(Cursor::new(cab_file)).unwrap();
+    let folder = cabinet.folder_entries().next().unwrap();
+    assert_eq!(folder.compression_type(), cab::CompressionType::MsZip);
+    assert_eq!(folder.raw_compression_bits(), 0x8001);
+    let mut data = String::new();
+    cabinet.read_file("hi.txt").unwrap().read_to_string(&mut data).unwrap();
+    assert_eq!(data, "Hello, world!\n");
+
+    // The bits must match the folder's compression type:
+    let mut cab_builder = cab::CabinetBuilder::new();
+    let folder = cab_builder.add_folder(cab::CompressionType::None);
+    folder.set_raw_compression_bits(Some(0x8001));
+    let error = cab_builder.build(Cursor::new(Vec::new())).err().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+// ========================================================================= //
+
+#[test]
+fn preserve_invalid_raw_datetime() {
+    let mut cab_builder = cab::CabinetBuilder::new();
+    let folder = cab_builder.add_folder(cab::CompressionType::None);
+    folder.add_file("hi.txt").set_raw_datetime(0x220c, 0xffff);
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        file_writer.write_all(b"Hello, world!\n").unwrap();
+    }
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+
+    let cabinet = cab::Cabinet::new(Cursor::new(cab_file)).unwrap();
+    let file = cabinet.get_file_entry("hi.txt").unwrap();
+    #[cfg(feature = "time")]
+    assert_eq!(file.datetime(), None);
+    assert_eq!(file.raw_datetime(), (0x220c, 0xffff));
+}
+
+// ========================================================================= //
+
+#[test]
+fn create_and_open_cabinet_file_by_path() {
+    let dir = std::env::temp_dir()
+        .join(format!("cab-open-path-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("lorem.cab");
+    let lorem = lipsum::lipsum(10000);
+
+    let mut cab_builder = cab::CabinetBuilder::new();
+    let folder = cab_builder.add_folder(cab::CompressionType::MsZip);
+    folder.add_file("hi.txt");
+    folder.add_file("lorem.txt");
+    let mut cab_writer = cab::create(&path, cab_builder).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        if file_writer.file_name() == "lorem.txt" {
+            file_writer.write_all(lorem.as_bytes()).unwrap();
+        } else {
+            file_writer.write_all(b"Hello, world!\n").unwrap();
+        }
+    }
+    cab_writer.finish().unwrap();
+
+    let mut cabinet = cab::open(&path).unwrap();
+    assert_eq!(
+        cabinet.total_size() as u64,
+        std::fs::metadata(&path).unwrap().len()
+    );
+    let mut output = String::new();
+    cabinet
+        .read_file("lorem.txt")
+        .unwrap()
+        .read_to_string(&mut output)
+        .unwrap();
+    assert_eq!(output, lorem);
+    let cabinet = cab::ReadOptions::new()
+        .defer_file_entries(true)
+        .open_path(&path)
+        .unwrap();
+    assert!(!cabinet.file_entries_loaded());
+    drop(cabinet);
+    assert!(cab::open(dir.join("missing.cab")).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+// ========================================================================= //
+
+#[test]
+fn write_all_files_from_provider() {
+    let lorem = lipsum::lipsum(5000);
+    let mut cab_builder = cab::CabinetBuilder::new();
+    cab_builder
+        .folder(cab::CompressionType::MsZip, |folder| {
+            folder.add_file("hi.txt");
+            folder.add_file("lorem.txt");
+        })
+        .folder(cab::CompressionType::None, |folder| {
+            folder.add_file("empty.txt");
+        });
+    let cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    let mut requested = Vec::new();
+    let cab_file = cab_writer
+        .write_all_files(|name| -> std::io::Result<Box<dyn Read>> {
+            requested.push(name.to_string());
+            Ok(match name {
+                "hi.txt" => Box::new(&b"Hello, world!\n"[..]),
+                "lorem.txt" => Box::new(Cursor::new(lorem.clone())),
+                _ => Box::new(std::io::empty()),
+            })
+        })
+        .unwrap()
+        .into_inner();
+    assert_eq!(requested, ["hi.txt", "lorem.txt", "empty.txt"]);
+
+    let mut cabinet = cab::Cabinet::new(Cursor::new(cab_file)).unwrap();
+    let mut output = String::new();
+    cabinet
+        .read_file("lorem.txt")
+        .unwrap()
+        .read_to_string(&mut output)
+        .unwrap();
+    assert_eq!(output, lorem);
+    assert_eq!(
+        cabinet.get_file_entry("hi.txt").unwrap().uncompressed_size(),
+        14
+    );
+    assert_eq!(
+        cabinet.get_file_entry("empty.txt").unwrap().uncompressed_size(),
+        0
+    );
+
+    // Errors from the provider are passed along:
+    let mut cab_builder = cab::CabinetBuilder::new();
+    cab_builder.add_folder(cab::CompressionType::None).add_file("missing.txt");
+    let cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    let error = cab_writer
+        .write_all_files(|name| std::fs::File::open(name))
+        .unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+}
+
+// ========================================================================= //
+
+#[test]
+fn read_cabinet_through_boxed_trait_object() {
+    let mut cab_builder = cab::CabinetBuilder::new();
+    cab_builder.add_folder(cab::CompressionType::MsZip).add_file("hi.txt");
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        file_writer.write_all(b"Hello, world!\n").unwrap();
+    }
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+
+    let reader: Box<dyn cab::ReadSeek + Send> =
+        Box::new(Cursor::new(cab_file));
+    let mut cabinet = cab::Cabinet::new(reader).unwrap();
+    // The cabinet can be moved to another thread:
+    let output = std::thread::spawn(move || {
+        let mut output = Vec::new();
+        cabinet.read_file("hi.txt").unwrap().read_to_end(&mut output).unwrap();
+        output
+    })
+    .join()
+    .unwrap();
+    assert_eq!(output, b"Hello, world!\n");
+}
+
+// ========================================================================= //
+
+#[test]
+fn sorted_view_and_case_insensitive_lookup() {
+    let names = ["b.txt", "Docs\\Readme.txt", "a.txt", "C.TXT", "A.txt"];
+    let mut cab_builder = cab::CabinetBuilder::new();
+    cab_builder.set_duplicate_names(cab::DuplicateNames::Allow);
+    cab_builder.folder(cab::CompressionType::None, |folder| {
+        for name in names {
+            folder.add_file(name);
+        }
+    });
+    let cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    let cab_file = cab_writer
+        .write_all_files(|name| Ok(Cursor::new(name.as_bytes().to_vec())))
+        .unwrap()
+        .into_inner();
+
+    let mut cabinet = cab::Cabinet::new(Cursor::new(cab_file)).unwrap();
+    let sorted: Vec<&str> =
+        cabinet.sorted_file_entries().map(|file| file.name()).collect();
+    assert_eq!(
+        sorted,
+        ["A.txt", "a.txt", "b.txt", "C.TXT", "Docs\\Readme.txt"]
+    );
+    assert_eq!(cabinet.sorted_file_entries().len(), 5);
+
+    // Exact matches are preferred:
+    assert_eq!(cabinet.lookup("a.txt").unwrap().index(), 2);
+    assert_eq!(cabinet.lookup("A.txt").unwrap().index(), 4);
+    assert_eq!(cabinet.lookup("A.TXT").unwrap().name(), "A.txt");
+    assert_eq!(cabinet.lookup("c.txt").unwrap().name(), "C.TXT");
+    assert_eq!(cabinet.lookup("DOCS\\README.TXT").unwrap().index(), 1);
+    assert!(cabinet.lookup("d.txt").is_none());
+    assert!(cabinet.lookup("").is_none());
+
+    // Exact lookups still require the case to match:
+    assert!(cabinet.get_file_entry("c.txt").is_none());
+    assert_eq!(cabinet.get_file_entry("C.TXT").unwrap().index(), 3);
+    let mut output = String::new();
+    cabinet.read_file("A.txt").unwrap().read_to_string(&mut output).unwrap();
+    assert_eq!(output, "A.txt");
+    assert!(cabinet.read_file("B.TXT").is_err());
+}
+
+// ========================================================================= //
@@ -1,7 +1,7 @@
 extern crate cab;
 extern crate lipsum;
 
-use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::io::{Cursor, ErrorKind, Read, Seek, SeekFrom, Write};
 
 // ========================================================================= //
 
@@ -89,4 +89,658 @@ fn seek_within_empty_file() {
     assert!(data.is_empty());
 }
 
+#[test]
+fn file_reader_size_accessors() {
+    let original_string = lipsum::lipsum(5000);
+    let original_bytes = original_string.as_bytes();
+
+    let mut cab_builder = cab::CabinetBuilder::new();
+    {
+        let folder = cab_builder.add_folder(cab::CompressionType::MsZip);
+        folder.add_file("empty.txt");
+        folder.add_file("lorem_ipsum.txt");
+    }
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        if file_writer.file_name() == "lorem_ipsum.txt" {
+            file_writer.write_all(original_bytes).unwrap();
+        }
+    }
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+
+    let mut cabinet = cab::Cabinet::new(Cursor::new(cab_file)).unwrap();
+    {
+        let file_reader = cabinet.read_file("empty.txt").unwrap();
+        assert!(file_reader.is_empty());
+        assert_eq!(file_reader.len(), 0);
+        assert_eq!(file_reader.remaining(), 0);
+    }
+    let mut file_reader = cabinet.read_file("lorem_ipsum.txt").unwrap();
+    let len = original_bytes.len() as u64;
+    assert!(!file_reader.is_empty());
+    assert_eq!(file_reader.len(), len);
+    assert_eq!(file_reader.stream_len(), len);
+    assert_eq!(file_reader.remaining(), len);
+    file_reader.seek(SeekFrom::Start(1000)).unwrap();
+    assert_eq!(file_reader.position(), 1000);
+    assert_eq!(file_reader.stream_position().unwrap(), 1000);
+    assert_eq!(file_reader.remaining(), len - 1000);
+    let mut output = Vec::with_capacity(file_reader.remaining() as usize);
+    file_reader.read_to_end(&mut output).unwrap();
+    assert_eq!(&output as &[u8], &original_bytes[1000..]);
+    assert_eq!(file_reader.remaining(), 0);
+}
+
+#[test]
+fn read_lines_from_file_in_multi_block_folder() {
+    use std::io::BufRead;
+
+    let lines: Vec<String> =
+        (0..5000).map(|index| format!("line number {}", index)).collect();
+    let text = lines.join("\r\n") + "\r\n";
+
+    let mut cab_builder = cab::CabinetBuilder::new();
+    {
+        let folder = cab_builder.add_folder(cab::CompressionType::MsZip);
+        folder.add_file("before.bin");
+        folder.add_file("setup.inf");
+        folder.add_file("after.bin");
+    }
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        match file_writer.file_name() {
+            "setup.inf" => file_writer.write_all(text.as_bytes()).unwrap(),
+            _ => file_writer.write_all(&[0xff; 40_000]).unwrap(),
+        }
+    }
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+
+    let mut cabinet = cab::Cabinet::new(Cursor::new(cab_file)).unwrap();
+    let file_reader = cabinet.read_file("setup.inf").unwrap();
+    let read_lines: Vec<String> = file_reader
+        .lines()
+        .map(|line| line.unwrap().trim_end_matches('\r').to_string())
+        .collect();
+    assert_eq!(read_lines, lines);
+
+    let mut file_reader = cabinet.read_file("setup.inf").unwrap();
+    file_reader.seek(SeekFrom::End(-4)).unwrap();
+    assert_eq!(file_reader.fill_buf().unwrap(), b"99\r\n");
+    file_reader.consume(2);
+    assert_eq!(file_reader.fill_buf().unwrap(), b"\r\n");
+    file_reader.consume(10);
+    assert!(file_reader.fill_buf().unwrap().is_empty());
+}
+
+// ========================================================================= //
+
+#[test]
+fn file_block_spans() {
+    let mut cab_builder = cab::CabinetBuilder::new();
+    {
+        let folder = cab_builder.add_folder(cab::CompressionType::None);
+        folder.add_file("a.bin");
+        folder.add_file("b.bin");
+        folder.add_file("empty.bin");
+    }
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        match file_writer.file_name() {
+            "a.bin" => file_writer.write_all(&[b'a'; 40_000]).unwrap(),
+            "b.bin" => file_writer.write_all(&[b'b'; 30_000]).unwrap(),
+            _ => {}
+        }
+    }
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+
+    let mut cabinet =
+        cab::Cabinet::new(Cursor::new(cab_file.clone())).unwrap();
+    let blocks: Vec<cab::DataBlockInfo> =
+        cabinet.data_blocks(0).unwrap().map(Result::unwrap).collect();
+    assert_eq!(blocks.len(), 3);
+
+    let span = cabinet.file_block_span("a.bin").unwrap();
+    assert_eq!(span.folder_index(), 0);
+    assert_eq!((span.first_block(), span.last_block()), (0, 1));
+    assert_eq!(span.start_within_first_block(), 0);
+    assert_eq!(span.end_within_last_block(), 40_000 - 32_768);
+    assert_eq!(span.byte_range(), blocks[0].offset()..blocks[2].offset());
+
+    let span = cabinet.file_block_span("b.bin").unwrap();
+    assert_eq!((span.first_block(), span.last_block()), (1, 2));
+    assert_eq!(span.start_within_first_block(), 40_000 - 32_768);
+    assert_eq!(span.end_within_last_block(), 4464);
+    assert_eq!(span.byte_range(), blocks[1].offset()..cab_file.len() as u64);
+    let range = span.byte_range();
+    let last_payload = &cab_file[(range.end as usize - 4464)..];
+    assert!(last_payload.iter().all(|&byte| byte == b'b'));
+
+    let span = cabinet.file_block_span("empty.bin").unwrap();
+    assert_eq!((span.first_block(), span.last_block()), (2, 2));
+    assert_eq!(span.start_within_first_block(), 4464);
+    assert_eq!(span.end_within_last_block(), 4464);
+
+    let error = cabinet.file_block_span("missing.bin").unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::NotFound);
+}
+
+// ========================================================================= //
+
+/// A reader that counts how many times it gets seeked.
+struct SeekCounter {
+    inner: Cursor<Vec<u8>>,
+    seeks: std::rc::Rc<std::cell::Cell<usize>>,
+}
+
+impl Read for SeekCounter {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl Seek for SeekCounter {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.seeks.set(self.seeks.get() + 1);
+        self.inner.seek(pos)
+    }
+}
+
+impl Write for SeekCounter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[test]
+fn batch_reads_of_data_blocks() {
+    let original: Vec<u8> = (0..300_000).map(|i| (i % 251) as u8).collect();
+    let mut cab_builder = cab::CabinetBuilder::new();
+    cab_builder.add_folder(cab::CompressionType::MsZip).add_file("big.bin");
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        file_writer.write_all(&original).unwrap();
+    }
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+    let num_blocks = original.len().div_ceil(0x8000);
+
+    let count_seeks = |options: &cab::ReadOptions| {
+        let seeks = std::rc::Rc::new(std::cell::Cell::new(0));
+        let reader = SeekCounter {
+            inner: Cursor::new(cab_file.clone()),
+            seeks: seeks.clone(),
+        };
+        let mut cabinet = options.open(reader).unwrap();
+        seeks.set(0);
+        let mut output = Vec::new();
+        cabinet
+            .read_file("big.bin")
+            .unwrap()
+            .read_to_end(&mut output)
+            .unwrap();
+        assert_eq!(output, original);
+        seeks.get()
+    };
+    // One seek for the first block's header, then one per block:
+    assert_eq!(count_seeks(&cab::ReadOptions::new()), 1 + num_blocks);
+    // With batching, the first block's header is read along with everything
+    // after it:
+    assert_eq!(count_seeks(cab::ReadOptions::new().io_batch_size(1 << 20)), 1);
+}
+
+#[test]
+fn read_uncompressed_folder_directly() {
+    let original: Vec<u8> = (0..300_000).map(|i| (i % 251) as u8).collect();
+    let mut cab_builder = cab::CabinetBuilder::new();
+    let folder = cab_builder.add_folder(cab::CompressionType::None);
+    folder.add_file("big.bin");
+    folder.add_file("small.txt");
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        if file_writer.file_name() == "big.bin" {
+            file_writer.write_all(&original).unwrap();
+        } else {
+            file_writer.write_all(b"Hello, world!").unwrap();
+        }
+    }
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+
+    let seeks = std::rc::Rc::new(std::cell::Cell::new(0));
+    let reader =
+        SeekCounter { inner: Cursor::new(cab_file), seeks: seeks.clone() };
+    let mut cabinet = cab::Cabinet::new(reader).unwrap();
+    seeks.set(0);
+    let mut output = Vec::new();
+    cabinet.read_file("big.bin").unwrap().read_to_end(&mut output).unwrap();
+    assert_eq!(output, original);
+    // The block headers and payloads are read straight through, so only the
+    // first block's header needs a seek:
+    assert_eq!(seeks.get(), 1);
+
+    // Seeking within the file only reads block headers up to the target:
+    let mut file_reader = cabinet.read_file("big.bin").unwrap();
+    file_reader.seek(SeekFrom::Start(250_000)).unwrap();
+    let mut output = Vec::new();
+    file_reader.read_to_end(&mut output).unwrap();
+    assert_eq!(output, &original[250_000..]);
+}
+
+// ========================================================================= //
+
+#[test]
+fn reopen_cabinet_with_index() {
+    let original: Vec<u8> = (0..300_000).map(|i| (i % 251) as u8).collect();
+    let mut cab_builder = cab::CabinetBuilder::new();
+    let folder = cab_builder.add_folder(cab::CompressionType::MsZip);
+    folder.add_file("big.bin");
+    folder.add_file("small.txt");
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        if file_writer.file_name() == "big.bin" {
+            file_writer.write_all(&original).unwrap();
+        } else {
+            file_writer.write_all(b"Hello, world!").unwrap();
+        }
+    }
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+    let num_blocks = (original.len() + 13).div_ceil(0x8000);
+
+    let mut index = Vec::new();
+    let mut cabinet =
+        cab::Cabinet::new(Cursor::new(cab_file.clone())).unwrap();
+    cabinet.write_index(&mut index).unwrap();
+
+    let seeks = std::rc::Rc::new(std::cell::Cell::new(0));
+    let reader = SeekCounter {
+        inner: Cursor::new(cab_file.clone()),
+        seeks: seeks.clone(),
+    };
+    let mut cabinet =
+        cab::Cabinet::open_with_index(reader, index.as_slice()).unwrap();
+    assert_eq!(seeks.get(), 1);
+    let names: Vec<&str> = cabinet
+        .file_entries_in_directory_order()
+        .map(|file| file.name())
+        .collect();
+    assert_eq!(names, vec!["big.bin", "small.txt"]);
+    // Jumping to the second file reads no block headers beyond those of the
+    // blocks that need decompressing.
+    seeks.set(0);
+    let mut output = String::new();
+    cabinet
+        .read_file("small.txt")
+        .unwrap()
+        .read_to_string(&mut output)
+        .unwrap();
+    assert_eq!(output, "Hello, world!");
+    assert_eq!(seeks.get(), num_blocks);
+    let mut output = Vec::new();
+    cabinet.read_file("big.bin").unwrap().read_to_end(&mut output).unwrap();
+    assert_eq!(output, original);
+
+    // An index for a different cabinet is rejected.
+    let mut other = cab_file;
+    other[8] ^= 1;
+    assert!(cab::Cabinet::open_with_index(
+        Cursor::new(other),
+        index.as_slice()
+    )
+    .is_err());
+}
+
+// ========================================================================= //
+
+#[test]
+fn file_readers_read_nothing_until_used() {
+    let mut cab_builder = cab::CabinetBuilder::new();
+    let folder = cab_builder.add_folder(cab::CompressionType::MsZip);
+    folder.add_file("a.txt");
+    folder.add_file("b.txt");
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        file_writer.write_all(lipsum::lipsum(20000).as_bytes()).unwrap();
+    }
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+
+    let seeks = std::rc::Rc::new(std::cell::Cell::new(0));
+    let reader =
+        SeekCounter { inner: Cursor::new(cab_file), seeks: seeks.clone() };
+    let mut cabinet = cab::Cabinet::new(reader).unwrap();
+    seeks.set(0);
+    for name in ["a.txt", "b.txt"] {
+        let mut file_reader = cabinet.read_file(name).unwrap();
+        assert!(!file_reader.is_empty());
+        file_reader.seek(SeekFrom::End(-10)).unwrap();
+        assert_eq!(file_reader.position(), file_reader.len() - 10);
+    }
+    assert_eq!(seeks.get(), 0);
+
+    let mut file_reader = cabinet.read_file("b.txt").unwrap();
+    file_reader.seek(SeekFrom::End(-10)).unwrap();
+    let mut tail = String::new();
+    file_reader.read_to_string(&mut tail).unwrap();
+    assert_eq!(tail.len(), 10);
+    assert!(seeks.get() > 0);
+}
+
+// ========================================================================= //
+
+#[test]
+fn cache_blocks_shared_between_files() {
+    let original: Vec<u8> = (0..200_000).map(|i| (i % 251) as u8).collect();
+    let mut cab_builder = cab::CabinetBuilder::new();
+    let folder = cab_builder.add_folder(cab::CompressionType::MsZip);
+    folder.add_file("payload.bin");
+    folder.add_file("manifest.txt");
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        if file_writer.file_name() == "payload.bin" {
+            file_writer.write_all(&original).unwrap();
+        } else {
+            file_writer.write_all(b"Hello, world!").unwrap();
+        }
+    }
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+
+    let count_seeks = |options: &cab::ReadOptions| {
+        let seeks = std::rc::Rc::new(std::cell::Cell::new(0));
+        let reader = SeekCounter {
+            inner: Cursor::new(cab_file.clone()),
+            seeks: seeks.clone(),
+        };
+        let mut cabinet = options.open(reader).unwrap();
+        seeks.set(0);
+        for _ in 0..3 {
+            let mut output = String::new();
+            cabinet
+                .read_file("manifest.txt")
+                .unwrap()
+                .read_to_string(&mut output)
+                .unwrap();
+            assert_eq!(output, "Hello, world!");
+            let mut output = Vec::new();
+            cabinet
+                .read_file("payload.bin")
+                .unwrap()
+                .read_to_end(&mut output)
+                .unwrap();
+            assert_eq!(output, original);
+        }
+        seeks.get()
+    };
+    let num_blocks = (original.len() + 13).div_ceil(0x8000);
+    // Without a cache, every read decompresses the folder from the start:
+    assert_eq!(count_seeks(&cab::ReadOptions::new()), 6 * (1 + num_blocks));
+    // With a big enough cache, each block is only read once:
+    let mut options = cab::ReadOptions::new();
+    options.block_cache_size(1 << 20);
+    assert_eq!(count_seeks(&options), 1 + num_blocks);
+    // With a tiny cache, the results are still right:
+    options.block_cache_size(0x8000);
+    assert!(count_seeks(&options) > 1 + num_blocks);
+}
+
+// ========================================================================= //
+
+#[test]
+fn file_reader_iter_decodes_each_folder_once() {
+    let files: Vec<(&str, Vec<u8>)> = vec![
+        ("a.bin", (0..70_000).map(|i| (i % 251) as u8).collect()),
+        ("b.txt", b"Hello, world!".to_vec()),
+        ("c.bin", (0..90_000).map(|i| (i % 241) as u8).collect()),
+        ("d.bin", (0..50_000).map(|i| (i % 239) as u8).collect()),
+    ];
+    let mut cab_builder = cab::CabinetBuilder::new();
+    for chunk in files.chunks(2) {
+        let folder = cab_builder.add_folder(cab::CompressionType::MsZip);
+        for (name, _) in chunk {
+            folder.add_file(*name);
+        }
+    }
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    let mut contents = files.iter();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        file_writer.write_all(&contents.next().unwrap().1).unwrap();
+    }
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+
+    let seeks = std::rc::Rc::new(std::cell::Cell::new(0));
+    let reader =
+        SeekCounter { inner: Cursor::new(cab_file), seeks: seeks.clone() };
+    let mut cabinet = cab::Cabinet::new(reader).unwrap();
+    let num_blocks: usize = cabinet
+        .folder_entries()
+        .map(|folder| folder.num_data_blocks() as usize)
+        .sum();
+    seeks.set(0);
+    let mut names = Vec::new();
+    for (reader, (name, data)) in
+        cabinet.file_reader_iter().unwrap().zip(files.iter())
+    {
+        let mut reader = reader.unwrap();
+        assert_eq!(reader.entry().name(), *name);
+        assert_eq!(reader.len(), data.len() as u64);
+        let mut output = Vec::new();
+        reader.read_to_end(&mut output).unwrap();
+        assert_eq!(&output, data);
+        names.push(reader.entry().name());
+    }
+    assert_eq!(names, ["a.bin", "b.txt", "c.bin", "d.bin"]);
+    // Each folder's first block header takes a seek, as does each block:
+    assert_eq!(seeks.get(), 2 + num_blocks);
+
+    // Readers for the same folder can still be read out of order:
+    let mut readers = cabinet.file_reader_iter().unwrap();
+    let mut first = readers.next().unwrap().unwrap();
+    let mut second = readers.next().unwrap().unwrap();
+    let mut output = String::new();
+    second.read_to_string(&mut output).unwrap();
+    assert_eq!(output, "Hello, world!");
+    first.seek(SeekFrom::Start(60_000)).unwrap();
+    let mut output = Vec::new();
+    first.read_to_end(&mut output).unwrap();
+    assert_eq!(output, &files[0].1[60_000..]);
+}
+
+// ========================================================================= //
+
+#[test]
+fn read_and_seek_within_folder() {
+    let big: Vec<u8> = (0..100_000).map(|i| (i % 251) as u8).collect();
+    let mut cab_builder = cab::CabinetBuilder::new();
+    let folder = cab_builder.add_folder(cab::CompressionType::MsZip);
+    folder.add_file("big.bin");
+    folder.add_file("hello.txt");
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        if file_writer.file_name() == "big.bin" {
+            file_writer.write_all(&big).unwrap();
+        } else {
+            file_writer.write_all(b"Hello, world!").unwrap();
+        }
+    }
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+    let mut expected = big.clone();
+    expected.extend_from_slice(b"Hello, world!");
+
+    let mut cabinet = cab::Cabinet::new(Cursor::new(cab_file)).unwrap();
+    let mut folder_reader = cabinet.read_folder(0).unwrap();
+    let mut output = Vec::new();
+    folder_reader.read_to_end(&mut output).unwrap();
+    assert_eq!(output, expected);
+
+    assert_eq!(folder_reader.seek(SeekFrom::End(-13)).unwrap(), 100_000);
+    let mut output = String::new();
+    folder_reader.read_to_string(&mut output).unwrap();
+    assert_eq!(output, "Hello, world!");
+
+    folder_reader.seek(SeekFrom::Start(10)).unwrap();
+    assert_eq!(folder_reader.seek(SeekFrom::Current(50_000)).unwrap(), 50_010);
+    let mut output = vec![0u8; 100];
+    folder_reader.read_exact(&mut output).unwrap();
+    assert_eq!(output, &expected[50_010..][..100]);
+    assert_eq!(folder_reader.stream_position().unwrap(), 50_110);
+
+    assert!(folder_reader.seek(SeekFrom::Current(-60_000)).is_err());
+    assert!(folder_reader.seek(SeekFrom::Start(100_014)).is_err());
+    drop(folder_reader);
+    assert!(cabinet.read_folder(1).is_err());
+}
+
+// ========================================================================= //
+
+#[test]
+fn pause_and_resume_folder_reads() {
+    let original = lipsum::lipsum(30000);
+    let original = original.as_bytes();
+    assert!(original.len() > 120_000);
+    for &ctype in &[cab::CompressionType::None, cab::CompressionType::MsZip] {
+        let mut cab_builder = cab::CabinetBuilder::new();
+        cab_builder.add_folder(ctype).add_file("lorem_ipsum.txt");
+        let mut cab_writer =
+            cab_builder.build(Cursor::new(Vec::new())).unwrap();
+        while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+            file_writer.write_all(original).unwrap();
+        }
+        let mut cab_file = cab_writer.finish().unwrap().into_inner();
+
+        let mut cabinet = cab::Cabinet::new(Cursor::new(&cab_file)).unwrap();
+        let first_block_offset =
+            cabinet.folder_entries().next().unwrap().first_data_block_offset();
+        let mut folder_reader = cabinet.read_folder(0).unwrap();
+        let mut output = vec![0u8; 100_000];
+        folder_reader.read_exact(&mut output).unwrap();
+        let token = folder_reader.resume_token();
+        assert_eq!(token.folder_index(), 0);
+        assert_eq!(token.uncompressed_offset(), 100_000);
+        assert_eq!(token.reset_offset(), 3 * 0x8000);
+        let bytes = token.to_bytes();
+        drop(folder_reader);
+
+        // A reader that seeked past the earlier blocks can still be resumed
+        // from, but (for MSZIP) only by decoding the folder from the start:
+        let mut folder_reader = cabinet.read_folder(0).unwrap();
+        folder_reader.seek(SeekFrom::Start(100_000)).unwrap();
+        let seeked_token = folder_reader.resume_token();
+        drop(folder_reader);
+        drop(cabinet);
+        if ctype == cab::CompressionType::None {
+            assert_eq!(seeked_token.reset_offset(), 3 * 0x8000);
+        } else {
+            assert_eq!(seeked_token.reset_offset(), 0);
+        }
+
+        // Resuming from the first token only decodes the block it was taken
+        // in, so damage to the first block doesn't matter:
+        cab_file[first_block_offset as usize + 20] ^= 0xff;
+        let token = cab::FolderResumeToken::from_bytes(&bytes).unwrap();
+        assert_eq!(token.to_bytes(), bytes);
+        let mut cabinet = cab::Cabinet::new(Cursor::new(&cab_file)).unwrap();
+        let mut folder_reader = cabinet.resume_folder(&token).unwrap();
+        let mut output = Vec::new();
+        folder_reader.read_to_end(&mut output).unwrap();
+        assert_eq!(output, &original[100_000..]);
+        drop(folder_reader);
+        let resumed = cabinet.resume_folder(&seeked_token);
+        assert_eq!(resumed.is_ok(), ctype == cab::CompressionType::None);
+        drop(resumed);
+
+        // Tampered tokens are rejected rather than misread:
+        let mut tampered = bytes.clone();
+        tampered[20..24].copy_from_slice(&0x7fff_fff0u32.to_le_bytes());
+        let error = cab::FolderResumeToken::from_bytes(&tampered).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        let mut tampered = bytes.clone();
+        tampered[20..24].copy_from_slice(&40_000u32.to_le_bytes());
+        let reset_offset = 3 * 0x8000u64;
+        tampered[24..32]
+            .copy_from_slice(&(reset_offset + 40_000).to_le_bytes());
+        let token = cab::FolderResumeToken::from_bytes(&tampered).unwrap();
+        let error = cabinet.resume_folder(&token).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+    assert!(cab::FolderResumeToken::from_bytes(b"CABRSUM1").is_err());
+}
+
+// ========================================================================= //
+
+#[cfg(feature = "pipeline")]
+#[test]
+fn pipelined_reads_and_seeks() {
+    let original = lipsum::lipsum(60000);
+    let original = original.as_bytes();
+    let mut cab_builder = cab::CabinetBuilder::new();
+    let folder = cab_builder.add_folder(cab::CompressionType::MsZip);
+    folder.add_file("first.txt");
+    folder.add_file("second.txt");
+    let mut cab_writer = cab_builder.build(Cursor::new(Vec::new())).unwrap();
+    while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+        file_writer.write_all(original).unwrap();
+    }
+    let cab_file = cab_writer.finish().unwrap().into_inner();
+
+    let mut cabinet = cab::ReadOptions::new()
+        .pipeline(true)
+        .open(Cursor::new(cab_file))
+        .unwrap();
+    let mut output = Vec::new();
+    let mut file_reader = cabinet.read_file("second.txt").unwrap();
+    file_reader.read_to_end(&mut output).unwrap();
+    assert_eq!(output, original);
+    // Seeking backwards resets the decompressor, throwing away whatever
+    // block was being decompressed ahead:
+    for &offset in &[100_000, 5, 200_000, 0] {
+        file_reader.seek(SeekFrom::Start(offset)).unwrap();
+        let mut output = vec![0u8; 1000];
+        file_reader.read_exact(&mut output).unwrap();
+        let offset = offset as usize;
+        assert_eq!(output, &original[offset..][..1000]);
+    }
+    drop(file_reader);
+    let mut output = Vec::new();
+    cabinet.read_file("first.txt").unwrap().read_to_end(&mut output).unwrap();
+    assert_eq!(output, original);
+}
+
+// ========================================================================= //
+
+#[test]
+fn write_entries_with_expected_sizes_up_front() {
+    let files: [(&str, &[u8]); 3] =
+        [("a.txt", b"Hello"), ("b.txt", b", world"), ("c.txt", b"!\n")];
+    let write_cabinet = |declare_sizes: bool| {
+        let mut cab_builder = cab::CabinetBuilder::new();
+        let folder = cab_builder.add_folder(cab::CompressionType::MsZip);
+        for &(name, data) in files.iter() {
+            let file = folder.add_file(name);
+            file.set_raw_datetime(0x5042, 0xa28a);
+            if declare_sizes {
+                file.set_expected_size(data.len() as u64);
+            }
+        }
+        let seeks = std::rc::Rc::new(std::cell::Cell::new(0));
+        let writer = SeekCounter {
+            inner: Cursor::new(Vec::new()),
+            seeks: seeks.clone(),
+        };
+        let mut cab_writer = cab_builder.build(writer).unwrap();
+        let mut index = 0;
+        while let Some(mut file_writer) = cab_writer.next_file().unwrap() {
+            file_writer.write_all(files[index].1).unwrap();
+            index += 1;
+        }
+        let output = cab_writer.finish().unwrap().inner.into_inner();
+        (output, seeks.get())
+    };
+    let (patched, patched_seeks) = write_cabinet(false);
+    let (declared, declared_seeks) = write_cabinet(true);
+    assert_eq!(declared, patched);
+    // The file entries no longer need seeking back to once the folder is
+    // done:
+    assert_eq!(declared_seeks + files.len(), patched_seeks);
+}
+
 // ========================================================================= //